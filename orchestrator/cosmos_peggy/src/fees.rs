@@ -0,0 +1,256 @@
+//! Fees for claim transactions. By default claims pay a fixed fee, on chains with a dynamic fee
+//! market the fee can instead follow the node's minimum gas price so claims are neither stuck
+//! below it nor overpaying when it drops.
+//!
+//! The gas limit comes from simulating the claims tx through the API server's gRPC gateway, the
+//! same server that hosts the legacy REST routes. Nodes that don't serve a route answer 404 and
+//! the static fee is paid instead. The minimum gas price route only exists from Cosmos SDK 0.46,
+//! on older chains the simulated gas limit is paid for with the static fee amount.
+
+use crate::messages::PeggyMsg;
+use awc::http::StatusCode;
+use contact::client::Contact;
+use contact::utils::maybe_get_optional_tx_info;
+use deep_space::address::Address;
+use deep_space::coin::Coin;
+use deep_space::stdfee::StdFee;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// The gas limit claims are sent with when paying a static fee
+pub const STATIC_CLAIMS_GAS: u64 = 500_000_000;
+
+const GAS_PRICE_QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+const SIMULATE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A claims fee that does not depend on the chain's gas price
+pub fn static_claims_fee(fee: Coin) -> StdFee {
+    StdFee {
+        amount: vec![fee],
+        gas: STATIC_CLAIMS_GAS.into(),
+    }
+}
+
+/// Finds the price for `denom` in a minimum gas prices string such as "0.025ustake,0.1uatom"
+pub fn parse_min_gas_price(min_gas_prices: &str, denom: &str) -> Option<f64> {
+    min_gas_prices.split(',').find_map(|price| {
+        let price = price.trim();
+        let amount = price.strip_suffix(denom)?;
+        // guard against one denom being a suffix of another, such as stake and ustake
+        if amount.ends_with(|c: char| c.is_ascii_alphabetic()) {
+            return None;
+        }
+        amount
+            .parse()
+            .ok()
+            .filter(|p: &f64| p.is_finite() && *p >= 0f64)
+    })
+}
+
+#[derive(Deserialize, Debug)]
+struct NodeConfigResponse {
+    minimum_gas_price: String,
+}
+
+/// Queries the node's minimum gas price for `denom` through the node config endpoint, None if the
+/// chain does not expose it, as before Cosmos SDK 0.46, or has no price for the denom
+pub async fn query_min_gas_price(legacy_rpc_url: &str, denom: &str) -> Option<f64> {
+    let url = format!(
+        "{}/cosmos/base/node/v1beta1/config",
+        legacy_rpc_url.trim_end_matches('/')
+    );
+    let client = awc::Client::default();
+    let mut response = match client
+        .get(&url)
+        .timeout(GAS_PRICE_QUERY_TIMEOUT)
+        .send()
+        .await
+    {
+        Ok(response) if response.status().is_success() => response,
+        Ok(response) => {
+            trace!("Gas price query returned {}", response.status());
+            return None;
+        }
+        Err(e) => {
+            trace!("Gas price query failed {:?}", e);
+            return None;
+        }
+    };
+    let config: NodeConfigResponse = response.json().await.ok()?;
+    parse_min_gas_price(&config.minimum_gas_price, denom)
+}
+
+/// Converts an amino JSON claim message into the protobuf JSON the gRPC gateway expects
+fn simulation_msg(msg: &PeggyMsg) -> Option<Value> {
+    let amino = serde_json::to_value(msg).ok()?;
+    let type_url = format!(
+        "/peggy.v1.{}",
+        amino["type"].as_str()?.strip_prefix("peggy/")?
+    );
+    let mut value = amino["value"].as_object()?.clone();
+    // protobuf JSON encodes bytes as base64 rather than a list of numbers
+    if let Some(Value::Array(bytes)) = value.get("invalidation_id") {
+        let bytes: Vec<u8> = bytes
+            .iter()
+            .filter_map(|b| b.as_u64())
+            .map(|b| b as u8)
+            .collect();
+        value.insert("invalidation_id".to_string(), base64::encode(bytes).into());
+    }
+    value.insert("@type".to_string(), type_url.into());
+    Some(Value::Object(value))
+}
+
+#[derive(Deserialize, Debug)]
+struct GasInfo {
+    gas_used: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct SimulateResponse {
+    gas_info: GasInfo,
+}
+
+/// Simulates a tx carrying `msgs` from an account at `sequence` and returns the gas it used, None
+/// if the node does not serve the simulate route or the simulation failed
+pub async fn simulate_claims_gas(
+    legacy_rpc_url: &str,
+    msgs: &[PeggyMsg],
+    sequence: &str,
+) -> Option<u64> {
+    let messages: Vec<Value> = msgs.iter().map(simulation_msg).collect::<Option<_>>()?;
+    let request = json!({
+        "tx": {
+            "body": {"messages": messages, "memo": ""},
+            "auth_info": {
+                "signer_infos": [{
+                    "mode_info": {"single": {"mode": "SIGN_MODE_LEGACY_AMINO_JSON"}},
+                    "sequence": sequence,
+                }],
+                "fee": {"amount": [], "gas_limit": "0"},
+            },
+            // signatures are only counted when simulating, never verified
+            "signatures": [""],
+        },
+    });
+    let url = format!(
+        "{}/cosmos/tx/v1beta1/simulate",
+        legacy_rpc_url.trim_end_matches('/')
+    );
+    let client = awc::Client::default();
+    let mut response = match client
+        .post(&url)
+        .timeout(SIMULATE_TIMEOUT)
+        .send_json(&request)
+        .await
+    {
+        Ok(response) if response.status().is_success() => response,
+        Ok(response) if response.status() == StatusCode::NOT_FOUND => {
+            trace!("Node does not serve tx simulation");
+            return None;
+        }
+        Ok(response) => {
+            warn!("Claims tx simulation returned {}", response.status());
+            return None;
+        }
+        Err(e) => {
+            warn!("Claims tx simulation failed {:?}", e);
+            return None;
+        }
+    };
+    let simulated: SimulateResponse = response.json().await.ok()?;
+    simulated.gas_info.gas_used.parse().ok()
+}
+
+/// A fee paying `gas_price` for the simulated gas scaled by `multiplier`, which is also used as
+/// the gas limit so the fee always covers the limit at that price
+pub fn dynamic_claims_fee(gas_price: f64, gas: u64, multiplier: f64, denom: &str) -> StdFee {
+    let gas_limit = (gas as f64 * multiplier).ceil() as u64;
+    let amount = (gas_limit as f64 * gas_price).ceil() as u128;
+    StdFee {
+        amount: vec![Coin {
+            denom: denom.to_string(),
+            amount: amount.into(),
+        }],
+        gas: gas_limit.into(),
+    }
+}
+
+/// The fee for a claims transaction carrying `msgs` sent by `our_address`. The gas limit is the
+/// simulated gas scaled by `multiplier`, paid for at the node's minimum gas price in the static
+/// fee's denom, or with the static fee amount when the node doesn't report one. The static fee is
+/// paid unchanged when the tx can't be simulated
+pub async fn claims_fee(
+    contact: &Contact,
+    our_address: Address,
+    msgs: &[PeggyMsg],
+    static_fee: Coin,
+    legacy_rpc_url: &str,
+    multiplier: f64,
+) -> StdFee {
+    let sequence = match maybe_get_optional_tx_info(our_address, None, None, None, contact).await {
+        Ok(tx_info) => tx_info.sequence.to_string(),
+        Err(e) => {
+            warn!(
+                "Could not get our account sequence, paying the static claims fee {:?}",
+                e
+            );
+            return static_claims_fee(static_fee);
+        }
+    };
+    let gas = match simulate_claims_gas(legacy_rpc_url, msgs, &sequence).await {
+        Some(gas) => gas,
+        None => {
+            debug!("Could not simulate the claims tx, paying the static claims fee");
+            return static_claims_fee(static_fee);
+        }
+    };
+    let fee = match query_min_gas_price(legacy_rpc_url, &static_fee.denom).await {
+        Some(gas_price) => dynamic_claims_fee(gas_price, gas, multiplier, &static_fee.denom),
+        None => StdFee {
+            gas: ((gas as f64 * multiplier).ceil() as u64).into(),
+            amount: vec![static_fee],
+        },
+    };
+    debug!(
+        "Claims fee {} {} for simulated gas {}, gas limit {}",
+        fee.amount[0].amount, fee.amount[0].denom, gas, fee.gas
+    );
+    fee
+}
+
+#[test]
+fn test_parse_min_gas_price() {
+    assert_eq!(parse_min_gas_price("0.025ustake", "ustake"), Some(0.025));
+    assert_eq!(
+        parse_min_gas_price("0.1uatom, 0.025ustake", "ustake"),
+        Some(0.025)
+    );
+    assert_eq!(parse_min_gas_price("0.025ustake", "stake"), None);
+    assert_eq!(parse_min_gas_price("", "ustake"), None);
+    assert_eq!(parse_min_gas_price("fooustake", "ustake"), None);
+}
+
+#[test]
+fn test_simulation_msg() {
+    use crate::messages::LogicCallExecutedClaim;
+    let msg = PeggyMsg::LogicCallExecutedClaim(LogicCallExecutedClaim {
+        event_nonce: 9u8.into(),
+        block_height: 100u8.into(),
+        invalidation_id: vec![1, 2, 3],
+        invalidation_nonce: 2u8.into(),
+        orchestrator: Address::default(),
+    });
+    let value = simulation_msg(&msg).unwrap();
+    assert_eq!(value["@type"], "/peggy.v1.MsgLogicCallExecutedClaim");
+    assert_eq!(value["invalidation_id"], "AQID");
+    assert!(value.get("type").is_none());
+}
+
+#[test]
+fn test_dynamic_claims_fee() {
+    let fee = dynamic_claims_fee(0.25, 500_000, 1.5, "ustake");
+    assert_eq!(fee.gas, 750_000u64.into());
+    assert_eq!(fee.amount[0].amount, 187_500u64.into());
+    assert_eq!(fee.amount[0].denom, "ustake");
+}
@@ -8,6 +8,7 @@ extern crate serde_derive;
 #[macro_use]
 extern crate log;
 
+pub mod fees;
 pub mod messages;
 pub mod query;
 pub mod send;
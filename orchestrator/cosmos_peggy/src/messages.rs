@@ -148,12 +148,16 @@ pub struct DepositClaimMsg {
     pub token_contract: EthAddress,
     pub amount: Uint256,
     pub ethereum_sender: EthAddress,
-    pub cosmos_receiver: Address,
+    /// The bech32 destination, encoded with the configured prefix rather than deep_space's
+    pub cosmos_receiver: String,
     pub orchestrator: Address,
 }
 
 impl DepositClaimMsg {
-    pub fn from_event(input: SendToCosmosEvent, sender: Address) -> Self {
+    pub fn from_event(input: SendToCosmosEvent, sender: Address, cosmos_prefix: &str) -> Self {
+        let cosmos_receiver = input
+            .destination_bech32(cosmos_prefix)
+            .expect("Invalid Cosmos prefix!");
         DepositClaimMsg {
             event_nonce: downcast_uint256(input.event_nonce)
                 .expect("Event nonce overflow! Bridge Halt!")
@@ -164,7 +168,7 @@ impl DepositClaimMsg {
             amount: input.amount,
             token_contract: input.erc20,
             ethereum_sender: input.sender,
-            cosmos_receiver: input.destination,
+            cosmos_receiver,
             orchestrator: sender,
         }
     }
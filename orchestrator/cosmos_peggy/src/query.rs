@@ -1,6 +1,7 @@
 use clarity::Address as EthAddress;
 use deep_space::address::Address;
 use peggy_proto::peggy::query_client::QueryClient as PeggyQueryClient;
+use peggy_proto::peggy::Params;
 use peggy_proto::peggy::QueryBatchConfirmsRequest;
 use peggy_proto::peggy::QueryCurrentValsetRequest;
 use peggy_proto::peggy::QueryLastEventNonceByAddrRequest;
@@ -11,6 +12,7 @@ use peggy_proto::peggy::QueryLastValsetRequestsRequest;
 use peggy_proto::peggy::QueryLogicConfirmsRequest;
 use peggy_proto::peggy::QueryOutgoingLogicCallsRequest;
 use peggy_proto::peggy::QueryOutgoingTxBatchesRequest;
+use peggy_proto::peggy::QueryParamsRequest;
 use peggy_proto::peggy::QueryValsetConfirmsByNonceRequest;
 use peggy_proto::peggy::QueryValsetRequestRequest;
 use peggy_utils::error::PeggyError;
@@ -130,6 +132,33 @@ pub async fn get_latest_transaction_batches(
     Ok(out)
 }
 
+/// gets the Peggy module parameters
+pub async fn get_peggy_params(
+    client: &mut PeggyQueryClient<Channel>,
+) -> Result<Params, PeggyError> {
+    let request = client.params(QueryParamsRequest {}).await?;
+    match request.into_inner().params {
+        Some(params) => Ok(params),
+        None => Err(PeggyError::InvalidBridgeStateError(
+            "Peggy params are unset!".to_string(),
+        )),
+    }
+}
+
+/// gets the number of Ethereum blocks between a batch being created and timing out, as targeted
+/// by the chain's batch timeout parameters
+pub async fn get_batch_timeout_blocks(
+    client: &mut PeggyQueryClient<Channel>,
+) -> Result<u64, PeggyError> {
+    let params = get_peggy_params(client).await?;
+    if params.average_ethereum_block_time == 0 {
+        return Err(PeggyError::InvalidBridgeStateError(
+            "Invalid batch timeout params!".to_string(),
+        ));
+    }
+    Ok(params.target_batch_timeout / params.average_ethereum_block_time)
+}
+
 /// get all batch confirmations for a given nonce and denom
 pub async fn get_transaction_batch_signatures(
     client: &mut PeggyQueryClient<Channel>,
@@ -164,6 +193,25 @@ pub async fn get_last_event_nonce(
     Ok(request.into_inner().event_nonce)
 }
 
+/// Returns true if the provided event nonce has already been claimed by the given orchestrator
+pub async fn is_event_nonce_claimed(
+    client: &mut PeggyQueryClient<Channel>,
+    address: Address,
+    event_nonce: u64,
+) -> Result<bool, PeggyError> {
+    let last_event_nonce = get_last_event_nonce(client, address).await?;
+    Ok(event_nonce <= last_event_nonce)
+}
+
+/// Gets the next event nonce the given orchestrator has yet to claim
+pub async fn next_unclaimed_nonce(
+    client: &mut PeggyQueryClient<Channel>,
+    address: Address,
+) -> Result<u64, PeggyError> {
+    let last_event_nonce = get_last_event_nonce(client, address).await?;
+    Ok(last_event_nonce + 1)
+}
+
 /// Gets the 100 latest logic calls for a relayer to consider relaying
 pub async fn get_latest_logic_calls(
     client: &mut PeggyQueryClient<Channel>,
@@ -14,7 +14,6 @@ use peggy_utils::message_signatures::{
     encode_logic_call_confirm, encode_tx_batch_confirm, encode_valset_confirm,
 };
 use peggy_utils::types::*;
-use std::collections::HashMap;
 
 /// Send a transaction updating the eth address for the sending
 /// Cosmos address. The sending Cosmos address should be a validator
@@ -212,6 +211,24 @@ pub async fn send_logic_call_confirm(
     contact.retry_on_block(tx).await
 }
 
+/// Submits the observed events as claims in a single tx. The tx is signed in amino JSON
+/// (SIGN_MODE_LEGACY_AMINO_JSON) by deep_space and broadcast to the legacy REST /txs endpoint,
+/// the only encoding deep_space 0.2 can produce. That maps to Cosmos SDK versions as follows
+///
+/// v0.39 and earlier - amino only, supported
+/// v0.40 to v0.45 - protobuf native, amino JSON still accepted through the legacy REST server,
+/// supported as long as the node runs it with api.enable
+/// v0.46 and later - the legacy REST server is removed, protobuf (SIGN_MODE_DIRECT) over gRPC
+/// is required and not supported
+///
+/// The fee is always paid by `private_key`'s account. Fee grants (x/feegrant, v0.43 and later)
+/// need a granter on the fee, which deep_space's StdFee has no field for
+///
+/// A claims tx stuck in the mempool on too low a fee is not re-signed with a higher one. The
+/// sequence is read from committed account state, so a resubmission reuses the stuck tx's
+/// sequence and is rejected with a sequence mismatch, and the Tendermint mempool has no replace
+/// by fee. Expiring the stuck tx first would need a timeout_height, which deep_space's StdSignMsg
+/// has no field for. The claims are submitted again on a later poll once the node evicts it
 pub async fn send_ethereum_claims(
     contact: &Contact,
     private_key: PrivateKey,
@@ -219,7 +236,8 @@ pub async fn send_ethereum_claims(
     withdraws: Vec<TransactionBatchExecutedEvent>,
     erc20_deploys: Vec<ERC20DeployedEvent>,
     logic_calls: Vec<LogicCallExecutedEvent>,
-    fee: Coin,
+    fee: StdFee,
+    cosmos_prefix: &str,
 ) -> Result<TXSendResponse, JsonRpcError> {
     let our_address = private_key
         .to_public_key()
@@ -228,67 +246,75 @@ pub async fn send_ethereum_claims(
 
     let tx_info = maybe_get_optional_tx_info(our_address, None, None, None, contact).await?;
 
-    // This sorts oracle messages by event nonce before submitting them. It's not a pretty implementation because
-    // we're missing an intermediary layer of abstraction. We could implement 'EventTrait' and then implement sort
-    // for it, but then when we go to transform 'EventTrait' objects into PeggyMsg enum values we'll have all sorts
-    // of issues extracting the inner object from the TraitObject. Likewise we could implement sort of PeggyMsg but that
-    // would require a truly horrendous (nearly 100 line) match statement to deal with all combinations. That match statement
-    // could be reduced by adding two traits to sort against but really this is the easiest option.
-    //
-    // We index the events by event nonce in an unordered hashmap and then play them back in order into a vec
-    let mut unordered_msgs = HashMap::new();
+    let msgs = order_claim_msgs(
+        our_address,
+        deposits,
+        withdraws,
+        erc20_deploys,
+        logic_calls,
+        cosmos_prefix,
+    );
+
+    let std_sign_msg = StdSignMsg {
+        chain_id: tx_info.chain_id,
+        account_number: tx_info.account_number,
+        sequence: tx_info.sequence,
+        fee,
+        msgs,
+        memo: String::new(),
+    };
+
+    let tx = private_key
+        .sign_std_msg(std_sign_msg, TransactionSendType::Block)
+        .unwrap();
+
+    contact.retry_on_block(tx).await
+}
+
+/// Converts the observed events into claim messages ordered strictly by event nonce ascending,
+/// regardless of type, so that the chain processes them in the exact order Ethereum emitted them.
+/// It's not a pretty implementation because we're missing an intermediary layer of abstraction, we could
+/// implement 'EventTrait' and sort on that, but then we'd have issues extracting the inner object from the
+/// TraitObject when transforming it into a PeggyMsg. Instead we pair each message with its nonce and sort those.
+pub fn order_claim_msgs(
+    our_address: Address,
+    deposits: Vec<SendToCosmosEvent>,
+    withdraws: Vec<TransactionBatchExecutedEvent>,
+    erc20_deploys: Vec<ERC20DeployedEvent>,
+    logic_calls: Vec<LogicCallExecutedEvent>,
+    cosmos_prefix: &str,
+) -> Vec<PeggyMsg> {
+    let mut unordered_msgs = Vec::new();
     for deposit in deposits {
-        unordered_msgs.insert(
+        unordered_msgs.push((
             deposit.event_nonce.clone(),
-            PeggyMsg::DepositClaimMsg(DepositClaimMsg::from_event(deposit, our_address)),
-        );
+            PeggyMsg::DepositClaimMsg(DepositClaimMsg::from_event(
+                deposit,
+                our_address,
+                cosmos_prefix,
+            )),
+        ));
     }
     for withdraw in withdraws {
-        unordered_msgs.insert(
+        unordered_msgs.push((
             withdraw.event_nonce.clone(),
             PeggyMsg::WithdrawClaimMsg(WithdrawClaimMsg::from_event(withdraw, our_address)),
-        );
+        ));
     }
     for deploy in erc20_deploys {
-        unordered_msgs.insert(
+        unordered_msgs.push((
             deploy.event_nonce.clone(),
             PeggyMsg::ERC20DeployedClaimMsg(ERC20DeployedClaimMsg::from_event(deploy, our_address)),
-        );
+        ));
     }
     for call in logic_calls {
-        unordered_msgs.insert(
+        unordered_msgs.push((
             call.event_nonce.clone(),
             PeggyMsg::LogicCallExecutedClaim(LogicCallExecutedClaim::from_event(call, our_address)),
-        );
-    }
-    let mut keys = Vec::new();
-    for (key, _) in unordered_msgs.iter() {
-        keys.push(key);
+        ));
     }
-    keys.sort();
-
-    let mut msgs = Vec::new();
-    for i in keys {
-        msgs.push(unordered_msgs[i].clone());
-    }
-
-    let std_sign_msg = StdSignMsg {
-        chain_id: tx_info.chain_id,
-        account_number: tx_info.account_number,
-        sequence: tx_info.sequence,
-        fee: StdFee {
-            amount: vec![fee],
-            gas: 500_000_000u64.into(),
-        },
-        msgs,
-        memo: String::new(),
-    };
-
-    let tx = private_key
-        .sign_std_msg(std_sign_msg, TransactionSendType::Block)
-        .unwrap();
-
-    contact.retry_on_block(tx).await
+    unordered_msgs.sort_by(|a, b| a.0.cmp(&b.0));
+    unordered_msgs.into_iter().map(|(_, msg)| msg).collect()
 }
 
 /// Sends tokens from Cosmos to Ethereum. These tokens will not be sent immediately instead
@@ -389,3 +415,45 @@ pub async fn send_request_batch(
 
     contact.retry_on_block(tx).await
 }
+
+#[test]
+fn test_order_claim_msgs() {
+    use clarity::Uint256;
+    let address = Address::default();
+    let deposit = |nonce: u8| SendToCosmosEvent {
+        event_nonce: nonce.into(),
+        ..Default::default()
+    };
+    let withdraw = |nonce: u8| TransactionBatchExecutedEvent {
+        event_nonce: nonce.into(),
+        ..Default::default()
+    };
+    let deploy = |nonce: u8| ERC20DeployedEvent {
+        event_nonce: nonce.into(),
+        ..Default::default()
+    };
+    let call = |nonce: u8| LogicCallExecutedEvent {
+        event_nonce: nonce.into(),
+        ..Default::default()
+    };
+    let msgs = order_claim_msgs(
+        address,
+        vec![deposit(6), deposit(1), deposit(4)],
+        vec![withdraw(5), withdraw(2)],
+        vec![deploy(7)],
+        vec![call(3)],
+        DEFAULT_COSMOS_PREFIX,
+    );
+    let nonces: Vec<Uint256> = msgs
+        .iter()
+        .map(|msg| match msg {
+            PeggyMsg::DepositClaimMsg(m) => m.event_nonce.clone(),
+            PeggyMsg::WithdrawClaimMsg(m) => m.event_nonce.clone(),
+            PeggyMsg::ERC20DeployedClaimMsg(m) => m.event_nonce.clone(),
+            PeggyMsg::LogicCallExecutedClaim(m) => m.event_nonce.clone(),
+            _ => panic!("Unexpected message type"),
+        })
+        .collect();
+    let expected: Vec<Uint256> = (1u8..=7).map(|n| n.into()).collect();
+    assert_eq!(nonces, expected);
+}
@@ -26,3 +26,110 @@ pub async fn wait_for_cosmos_online(contact: &Contact, timeout: Duration) {
     wait_for_next_cosmos_block(contact, timeout).await;
     wait_for_next_cosmos_block(contact, timeout).await;
 }
+
+/// How often a transaction is looked up while waiting for it to be included
+const TX_INCLUSION_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether a transaction has made it into a block, see wait_for_tx_inclusion
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxInclusion {
+    /// Included in a block, a non zero code means the transaction failed and none
+    /// of its messages took effect
+    Included {
+        height: u64,
+        code: u32,
+        raw_log: String,
+    },
+    /// Not found, most likely still waiting in the mempool
+    Pending,
+}
+
+/// The fields we need from the legacy REST /txs/{hash} response
+#[derive(Deserialize, Debug)]
+struct TxLookupResponse {
+    height: String,
+    /// omitted when the transaction succeeded
+    #[serde(default)]
+    code: u32,
+    #[serde(default)]
+    raw_log: String,
+}
+
+impl TxLookupResponse {
+    fn into_inclusion(self) -> TxInclusion {
+        match self.height.parse() {
+            Ok(height) if height > 0 => TxInclusion::Included {
+                height,
+                code: self.code,
+                raw_log: self.raw_log,
+            },
+            _ => TxInclusion::Pending,
+        }
+    }
+}
+
+/// Polls the legacy REST endpoint for a transaction by hash until it is included in a block or the
+/// timeout elapses. Lookup failures are treated as the transaction not being included yet, since
+/// nodes answer with an error for transactions they have not indexed
+pub async fn wait_for_tx_inclusion(
+    legacy_rpc_url: &str,
+    txhash: &str,
+    timeout: Duration,
+) -> TxInclusion {
+    let url = format!("{}/txs/{}", legacy_rpc_url.trim_end_matches('/'), txhash);
+    let client = awc::Client::default();
+    let start = Instant::now();
+    loop {
+        let response = client
+            .get(&url)
+            .timeout(TX_INCLUSION_POLL_INTERVAL * 5)
+            .send()
+            .await;
+        if let Ok(mut response) = response {
+            if response.status().is_success() {
+                match response.json::<TxLookupResponse>().await {
+                    Ok(tx) => {
+                        let inclusion = tx.into_inclusion();
+                        if inclusion != TxInclusion::Pending {
+                            return inclusion;
+                        }
+                    }
+                    Err(e) => trace!("Unexpected tx lookup response for {} {:?}", txhash, e),
+                }
+            }
+        }
+        if Instant::now() - start >= timeout {
+            return TxInclusion::Pending;
+        }
+        delay_for(TX_INCLUSION_POLL_INTERVAL).await;
+    }
+}
+
+#[test]
+fn test_tx_lookup_response() {
+    let failed: TxLookupResponse = serde_json::from_str(
+        r#"{"height":"1042","txhash":"ABCD","code":4,"raw_log":"unauthorized","gas_wanted":"200000"}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        failed.into_inclusion(),
+        TxInclusion::Included {
+            height: 1042,
+            code: 4,
+            raw_log: "unauthorized".to_string(),
+        }
+    );
+    // successful transactions omit the code
+    let succeeded: TxLookupResponse =
+        serde_json::from_str(r#"{"height":"1043","txhash":"ABCD","raw_log":"[]"}"#).unwrap();
+    assert_eq!(
+        succeeded.into_inclusion(),
+        TxInclusion::Included {
+            height: 1043,
+            code: 0,
+            raw_log: "[]".to_string(),
+        }
+    );
+    let pending: TxLookupResponse = serde_json::from_str(r#"{"height":"0"}"#).unwrap();
+    assert_eq!(pending.into_inclusion(), TxInclusion::Pending);
+}
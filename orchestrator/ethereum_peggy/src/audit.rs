@@ -0,0 +1,64 @@
+//! An audit record of every Ethereum transaction sent to the Peggy contract. Each transaction
+//! type is logged through the same struct so the resulting log lines share a single schema.
+
+use clarity::Uint256;
+use json_logger::LOGGING;
+use peggy_utils::rpc_endpoint::eth_rpc_endpoint;
+use slog::debug as sdebug;
+use slog::info as sinfo;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EthTxType {
+    ValsetUpdate,
+    TransactionBatch,
+    LogicCall,
+}
+
+impl fmt::Display for EthTxType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EthTxType::ValsetUpdate => write!(f, "valset_update"),
+            EthTxType::TransactionBatch => write!(f, "transaction_batch"),
+            EthTxType::LogicCall => write!(f, "logic_call"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EthTxAudit {
+    pub tx_hash: Uint256,
+    pub tx_type: EthTxType,
+    /// The Ethereum nonce of the transaction, None when it was left for the node to fill
+    pub eth_nonce: Option<Uint256>,
+    /// None when the transaction was sent at the node's gas price
+    pub gas_price: Option<Uint256>,
+    pub gas_limit: Uint256,
+    /// The valset nonce, batch nonce, or logic call invalidation nonce this transaction submits
+    pub cosmos_nonce: u64,
+}
+
+impl EthTxAudit {
+    /// Writes this record as an ETH_TX_AUDIT line, fields that are unknown are left empty
+    /// rather than omitted so every line has the same keys
+    pub fn log(&self) {
+        let optional = |v: &Option<Uint256>| v.as_ref().map(|v| v.to_string()).unwrap_or_default();
+        info!(
+            "Audit: sent {} transaction {:#066x} for nonce {}",
+            self.tx_type, self.tx_hash, self.cosmos_nonce
+        );
+        sinfo!(&LOGGING.logger, "ETH_TX_AUDIT";
+            "tx_hash" => format!("{:#066x}",self.tx_hash),
+            "tx_type" => format!("{}",self.tx_type),
+            "eth_nonce" => optional(&self.eth_nonce),
+            "gas_price" => optional(&self.gas_price),
+            "gas_limit" => format!("{}",self.gas_limit),
+            "cosmos_nonce" => format!("{}",self.cosmos_nonce),
+        );
+        sdebug!(&LOGGING.logger, "ETH_TX_RPC_ENDPOINT";
+            "tx_hash" => format!("{:#066x}",self.tx_hash),
+            "tx_type" => format!("{}",self.tx_type),
+            "rpc_endpoint" => eth_rpc_endpoint(),
+        );
+    }
+}
@@ -0,0 +1,89 @@
+//! Verifies the runtime bytecode deployed at the Peggy contract address against a hash the
+//! operator expects for their deployment, so a wrong or malicious contract address fails at
+//! startup rather than producing a stream of confusing reverts.
+
+use crate::utils::raw_rpc_call;
+use clarity::utils::{bytes_to_hex_str, hex_str_to_bytes};
+use clarity::Address as EthAddress;
+use peggy_utils::error::PeggyError;
+use serde_json::json;
+use sha3::{Digest, Keccak256};
+use web30::client::Web3;
+
+fn rpc_error(cause: String) -> PeggyError {
+    PeggyError::RpcUnavailable {
+        endpoint: "Ethereum RPC".to_string(),
+        cause,
+    }
+}
+
+/// Fetches the runtime bytecode at an address with eth_getCode, empty if there is no contract
+pub async fn get_contract_code(web3: &Web3, contract: EthAddress) -> Result<Vec<u8>, PeggyError> {
+    let code = raw_rpc_call(web3, "eth_getCode", json!([contract.to_string(), "latest"]))
+        .await
+        .map_err(|e| rpc_error(format!("{:?}", e)))?;
+    match code.as_str() {
+        Some(code) => {
+            hex_str_to_bytes(code).map_err(|e| rpc_error(format!("invalid code {:?}", e)))
+        }
+        None => Err(rpc_error("empty eth_getCode response".to_string())),
+    }
+}
+
+/// The keccak256 hash of a contract's runtime bytecode
+pub fn bytecode_hash(code: &[u8]) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&Keccak256::digest(code));
+    hash
+}
+
+/// Parses an expected bytecode hash, a 32 byte hex string with or without the 0x prefix
+pub fn parse_bytecode_hash(input: &str) -> Result<[u8; 32], String> {
+    let bytes = hex_str_to_bytes(input).map_err(|e| format!("invalid hex {:?}", e))?;
+    if bytes.len() != 32 {
+        return Err(format!("expected 32 bytes, got {}", bytes.len()));
+    }
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&bytes);
+    Ok(hash)
+}
+
+/// Checks that the keccak256 of the runtime bytecode at `contract` is `expected_keccak`
+pub async fn verify_contract_bytecode(
+    web3: &Web3,
+    contract: EthAddress,
+    expected_keccak: [u8; 32],
+) -> Result<(), PeggyError> {
+    let code = get_contract_code(web3, contract).await?;
+    let actual = bytecode_hash(&code);
+    if code.is_empty() || actual != expected_keccak {
+        return Err(PeggyError::ContractBytecodeMismatch {
+            contract,
+            expected: bytes_to_hex_str(&expected_keccak),
+            actual: if code.is_empty() {
+                "no code".to_string()
+            } else {
+                bytes_to_hex_str(&actual)
+            },
+        });
+    }
+    info!(
+        "Contract {} bytecode matches the expected hash {}",
+        contract,
+        bytes_to_hex_str(&expected_keccak)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_bytecode_hash() {
+    // keccak256 of empty input, what an address without a contract hashes to
+    let empty = "0xc5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470";
+    assert_eq!(bytecode_hash(&[]), parse_bytecode_hash(empty).unwrap());
+    assert_eq!(
+        parse_bytecode_hash(&empty[2..]).unwrap(),
+        parse_bytecode_hash(empty).unwrap()
+    );
+    assert!(parse_bytecode_hash("0xc5d2").is_err());
+    assert!(parse_bytecode_hash("not hex").is_err());
+}
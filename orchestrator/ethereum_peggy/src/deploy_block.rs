@@ -0,0 +1,64 @@
+//! Locates the block the Peggy contract was deployed in, so that an oracle joining an existing
+//! bridge can start observing events from the contract's genesis rather than scanning backwards
+//! through the entire history of the chain.
+
+use crate::utils::raw_rpc_call;
+use clarity::utils::hex_str_to_bytes;
+use clarity::{Address as EthAddress, Uint256};
+use peggy_utils::error::PeggyError;
+use serde_json::json;
+use web30::client::Web3;
+use web30::jsonrpc::error::Web3Error;
+
+/// Gets the code stored at `address` as of `block`. Web30 does not expose eth_getCode so
+/// it goes through raw_rpc_call. Historical blocks require an archive node.
+pub async fn eth_get_code(
+    web3: &Web3,
+    address: EthAddress,
+    block: Uint256,
+) -> Result<Vec<u8>, PeggyError> {
+    let code = raw_rpc_call(
+        web3,
+        "eth_getCode",
+        json!([address.to_string(), format!("{:#x}", block)]),
+    )
+    .await?;
+    match code.as_str() {
+        Some(code) => hex_str_to_bytes(code)
+            .map_err(|e| PeggyError::EthereumRestError(Web3Error::BadResponse(format!("{:?}", e)))),
+        None => Err(PeggyError::EthereumRestError(Web3Error::BadResponse(
+            format!("No code returned for {} at block {}", address, block),
+        ))),
+    }
+}
+
+/// Binary searches the block history for the earliest block at which `contract` has code,
+/// which is the block it was deployed in.
+pub async fn find_contract_deploy_block(
+    web3: &Web3,
+    contract: EthAddress,
+) -> Result<Uint256, PeggyError> {
+    let latest_block = web3.eth_block_number().await?;
+    if eth_get_code(web3, contract, latest_block.clone())
+        .await?
+        .is_empty()
+    {
+        return Err(PeggyError::InvalidBridgeStateError(format!(
+            "No contract is deployed at {}",
+            contract
+        )));
+    }
+
+    let mut low: Uint256 = 0u8.into();
+    let mut high = latest_block;
+    while low < high {
+        let mid: Uint256 = (low.clone() + high.clone()) / 2u8.into();
+        trace!("Searching for deploy block of {} at {}", contract, mid);
+        if eth_get_code(web3, contract, mid.clone()).await?.is_empty() {
+            low = mid + 1u8.into();
+        } else {
+            high = mid;
+        }
+    }
+    Ok(low)
+}
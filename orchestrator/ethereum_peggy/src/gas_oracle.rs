@@ -0,0 +1,106 @@
+//! Gas price oracles provide a gas price for a requested speed tier along with an estimate of how long
+//! a transaction at that price will wait before being included. Relayers can use this to trade off cost
+//! against speed rather than blindly using whatever gas price the Ethereum node reports.
+
+use async_trait::async_trait;
+use clarity::Uint256;
+use peggy_utils::error::PeggyError;
+use peggy_utils::types::GasPriceTier;
+use std::time::Duration;
+use web30::client::Web3;
+
+/// EthGasStation reports prices in tenths of a gwei
+const ETH_GAS_STATION_UNIT: u64 = 100_000_000;
+const ORACLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A gas price along with the expected wait time for inclusion, if the source of
+/// the price can predict one
+#[derive(Debug, Clone)]
+pub struct GasPriceQuote {
+    pub gas_price: Uint256,
+    pub expected_wait: Option<Duration>,
+}
+
+#[async_trait(?Send)]
+pub trait GasOracle {
+    async fn get_gas_price(&self, tier: GasPriceTier) -> Result<GasPriceQuote, PeggyError>;
+}
+
+/// The response format of the EthGasStation api, also used by a number of compatible services
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct EthGasStationResponse {
+    fast: f64,
+    average: f64,
+    safe_low: f64,
+    fast_wait: f64,
+    avg_wait: f64,
+    safe_low_wait: f64,
+}
+
+/// A gas oracle for any api that returns responses in the EthGasStation format
+pub struct EthGasStationOracle {
+    pub url: String,
+}
+
+impl EthGasStationOracle {
+    pub fn new(url: String) -> Self {
+        EthGasStationOracle { url }
+    }
+}
+
+#[async_trait(?Send)]
+impl GasOracle for EthGasStationOracle {
+    async fn get_gas_price(&self, tier: GasPriceTier) -> Result<GasPriceQuote, PeggyError> {
+        let client = awc::Client::default();
+        let mut response = client
+            .get(&self.url)
+            .timeout(ORACLE_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| PeggyError::GasOracleError(format!("{:?}", e)))?;
+        let response: EthGasStationResponse = response
+            .json()
+            .await
+            .map_err(|e| PeggyError::GasOracleError(format!("{:?}", e)))?;
+        trace!("Gas oracle response {:?}", response);
+
+        let (price, wait_minutes) = match tier {
+            GasPriceTier::Fast => (response.fast, response.fast_wait),
+            GasPriceTier::Standard => (response.average, response.avg_wait),
+            GasPriceTier::Slow => (response.safe_low, response.safe_low_wait),
+        };
+        if price.is_nan() || price <= 0f64 || wait_minutes.is_nan() || wait_minutes < 0f64 {
+            return Err(PeggyError::GasOracleError(format!(
+                "Invalid gas price {} or wait {} from oracle",
+                price, wait_minutes
+            )));
+        }
+        let gas_price: Uint256 = (price as u64).into();
+        Ok(GasPriceQuote {
+            gas_price: gas_price * ETH_GAS_STATION_UNIT.into(),
+            expected_wait: Some(Duration::from_secs_f64(wait_minutes * 60f64)),
+        })
+    }
+}
+
+/// Gets a gas price from the provided oracle, when no oracle is configured the baseline is used,
+/// see GasPriceTracker::baseline, falling back to the gas price reported by the Ethereum node
+pub async fn resolve_gas_price(
+    oracle: Option<&dyn GasOracle>,
+    tier: GasPriceTier,
+    web3: &Web3,
+    baseline: Option<Uint256>,
+) -> Result<GasPriceQuote, PeggyError> {
+    match (oracle, baseline) {
+        (Some(oracle), _) => oracle.get_gas_price(tier).await,
+        (None, Some(baseline)) => Ok(GasPriceQuote {
+            gas_price: baseline,
+            expected_wait: None,
+        }),
+        (None, None) => Ok(GasPriceQuote {
+            gas_price: web3.eth_gas_price().await?,
+            expected_wait: None,
+        }),
+    }
+}
@@ -0,0 +1,85 @@
+//! Tracks an exponential moving average of the gas price reported by the Ethereum node. Submitting
+//! at the average rather than the spot price avoids overpaying when a poll lands on a short spike,
+//! at the cost of slower inclusion while prices are rising, so urgent submissions bypass it.
+
+use clarity::Uint256;
+use peggy_utils::metrics::GAS_PRICE_EMA;
+use web30::client::Web3;
+use web30::jsonrpc::error::Web3Error;
+
+#[derive(Debug, Clone)]
+pub struct GasPriceTracker {
+    /// the weight of each new sample, between 0 and 1, higher values follow the spot price more closely
+    smoothing: f64,
+    ema: Option<f64>,
+}
+
+impl GasPriceTracker {
+    pub fn new(smoothing: f64) -> Self {
+        assert!(
+            smoothing > 0f64 && smoothing <= 1f64,
+            "Gas price smoothing must be in (0, 1]"
+        );
+        GasPriceTracker {
+            smoothing,
+            ema: None,
+        }
+    }
+
+    /// Adds a spot price sample to the average, the first sample becomes the average
+    pub fn record(&mut self, spot: &Uint256) {
+        let spot: f64 = spot.to_string().parse().unwrap();
+        let ema = match self.ema {
+            Some(ema) => self.smoothing * spot + (1f64 - self.smoothing) * ema,
+            None => spot,
+        };
+        self.ema = Some(ema);
+        if let Some(ema) = self.ema() {
+            GAS_PRICE_EMA.set(ema);
+        }
+    }
+
+    /// Seeds the average with one carried over from a previous run, replacing any samples so far
+    pub fn restore(&mut self, ema: &Uint256) {
+        self.ema = Some(ema.to_string().parse().unwrap());
+        GAS_PRICE_EMA.set(ema.clone());
+    }
+
+    /// Samples the node's current gas price, returning the spot price
+    pub async fn sample(&mut self, web3: &Web3) -> Result<Uint256, Web3Error> {
+        let spot = web3.eth_gas_price().await?;
+        self.record(&spot);
+        Ok(spot)
+    }
+
+    /// The current average in wei, None until the first sample
+    pub fn ema(&self) -> Option<Uint256> {
+        self.ema.map(|ema| (ema.round() as u128).into())
+    }
+
+    /// The gas price to use as a baseline for a submission, None means the spot price should
+    /// be used because the submission is urgent or there is no average yet
+    pub fn baseline(&self, urgent: bool) -> Option<Uint256> {
+        if urgent {
+            None
+        } else {
+            self.ema()
+        }
+    }
+}
+
+#[test]
+fn test_gas_price_tracker() {
+    let mut tracker = GasPriceTracker::new(0.5);
+    assert_eq!(tracker.ema(), None);
+    tracker.record(&100u8.into());
+    assert_eq!(tracker.ema(), Some(100u8.into()));
+    // a spike only moves the average part of the way
+    tracker.record(&200u8.into());
+    assert_eq!(tracker.ema(), Some(150u8.into()));
+    tracker.record(&150u8.into());
+    assert_eq!(tracker.baseline(false), Some(150u8.into()));
+    assert_eq!(tracker.baseline(true), None);
+    tracker.restore(&40u8.into());
+    assert_eq!(tracker.ema(), Some(40u8.into()));
+}
@@ -2,13 +2,27 @@
 
 use clarity::Uint256;
 
+#[macro_use]
+extern crate lazy_static;
 #[macro_use]
 extern crate log;
+#[macro_use]
+extern crate serde_derive;
 
+pub mod audit;
+pub mod bytecode;
+pub mod deploy_block;
 pub mod deploy_erc20;
+pub mod gas_oracle;
+pub mod gas_price_tracker;
 pub mod logic_call;
+pub mod nonce_manager;
+pub mod price_feed;
+pub mod recovery_log;
+pub mod revert_reason;
 pub mod send_to_cosmos;
 pub mod submit_batch;
+pub mod token_metadata;
 pub mod utils;
 pub mod valset_update;
 
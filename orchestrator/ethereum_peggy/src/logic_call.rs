@@ -1,10 +1,16 @@
+use crate::audit::{EthTxAudit, EthTxType};
+use crate::nonce_manager::NonceManager;
+use crate::recovery_log::{record_outgoing_transaction, RecoveryRecord};
 use crate::utils::{get_logic_call_nonce, GasCost};
 use clarity::{abi::Token, utils::bytes_to_hex_str, PrivateKey as EthPrivateKey};
 use clarity::{Address as EthAddress, Uint256};
 use peggy_utils::types::*;
 use peggy_utils::{error::PeggyError, message_signatures::encode_logic_call_confirm_hashed};
 use std::{cmp::min, time::Duration};
-use web30::{client::Web3, types::TransactionRequest};
+use web30::{
+    client::Web3,
+    types::{SendTxOption, TransactionRequest},
+};
 
 /// this function generates an appropriate Ethereum transaction
 /// to submit the provided logic call
@@ -18,6 +24,8 @@ pub async fn send_eth_logic_call(
     peggy_contract_address: EthAddress,
     peggy_id: String,
     our_eth_key: EthPrivateKey,
+    gas_limit: Uint256,
+    nonce_manager: &NonceManager,
 ) -> Result<(), PeggyError> {
     let new_call_nonce = call.invalidation_nonce;
     let eth_address = our_eth_key.to_public_key().unwrap();
@@ -52,6 +60,17 @@ pub async fn send_eth_logic_call(
 
     let payload = encode_logic_call_payload(current_valset, &call, confirms, peggy_id)?;
 
+    let nonce = nonce_manager.next_nonce(web3).await?;
+    record_outgoing_transaction(&RecoveryRecord {
+        tx_type: EthTxType::LogicCall.to_string(),
+        contract: peggy_contract_address,
+        from: eth_address,
+        eth_nonce: Some(nonce.clone()),
+        gas_price: None,
+        gas_limit: gas_limit.clone(),
+        cosmos_nonce: new_call_nonce,
+        payload: bytes_to_hex_str(&payload),
+    });
     let tx = web3
         .send_transaction(
             peggy_contract_address,
@@ -59,9 +78,26 @@ pub async fn send_eth_logic_call(
             0u32.into(),
             eth_address,
             our_eth_key,
-            vec![],
+            vec![
+                SendTxOption::GasLimit(gas_limit.clone()),
+                SendTxOption::Nonce(nonce.clone()),
+            ],
         )
-        .await?;
+        .await;
+    if tx.is_err() {
+        nonce_manager.reset().await;
+    }
+    let tx = tx?;
+    nonce_manager.mark_sent().await;
+    EthTxAudit {
+        tx_hash: tx.clone(),
+        tx_type: EthTxType::LogicCall,
+        eth_nonce: Some(nonce),
+        gas_price: None,
+        gas_limit,
+        cosmos_nonce: new_call_nonce,
+    }
+    .log();
     info!("Sent batch update with txid {:#066x}", tx);
 
     web3.wait_for_transaction(tx.clone(), timeout, None).await?;
@@ -0,0 +1,183 @@
+//! The valset, batch and logic call relayers may share a single Ethereum account, if they each
+//! query `eth_get_transaction_count` before the others' transactions are mined they will build
+//! transactions with the same nonce and one of them will be dropped. The NonceManager tracks
+//! the next nonce locally and hands out monotonic nonces to every submission path, only
+//! reconciling against the chain periodically or after a failed submission.
+//!
+//! Reconciling uses the node's pending nonce, which counts transactions still in the mempool.
+//! It is a floor for the local nonce, and if the local nonce is ahead of it while none of our
+//! nonces are waiting to be sent then transactions were dropped and the local nonce is reset
+//! to fill the gap, otherwise every later transaction would be stuck behind the missing nonce.
+
+use crate::utils::raw_rpc_call;
+use clarity::{Address as EthAddress, Uint256};
+use futures::lock::Mutex;
+use json_logger::LOGGING;
+use serde_json::json;
+use slog::warn as swarn;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use web30::client::Web3;
+use web30::jsonrpc::error::Web3Error;
+
+/// How often the locally tracked nonce is checked against the chain
+pub const NONCE_RECONCILE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Default)]
+struct NonceState {
+    next_nonce: Option<Uint256>,
+    last_reconciled: Option<Instant>,
+    /// nonces handed out whose transaction has not yet been accepted or rejected by the node,
+    /// the node can't know about these so they don't count as a gap
+    unsent: usize,
+}
+
+/// The nonce of the next transaction from `address` counting those still in the node's
+/// mempool, web30's eth_get_transaction_count only counts mined transactions
+pub async fn get_pending_nonce(web3: &Web3, address: EthAddress) -> Result<Uint256, Web3Error> {
+    let count = raw_rpc_call(
+        web3,
+        "eth_getTransactionCount",
+        json!([address.to_string(), "pending"]),
+    )
+    .await?;
+    let count = count.as_str().ok_or_else(|| {
+        Web3Error::BadResponse(format!("Invalid eth_getTransactionCount result {}", count))
+    })?;
+    u64::from_str_radix(count.trim_start_matches("0x"), 16)
+        .map(|count| count.into())
+        .map_err(|e| Web3Error::BadResponse(format!("Invalid nonce {} {:?}", count, e)))
+}
+
+/// Where the local nonce should go given the node's pending nonce
+fn reconciled_nonce(local: Option<&Uint256>, pending: &Uint256, unsent: usize) -> Uint256 {
+    match local {
+        // transactions we are still sending can't be in the mempool yet
+        Some(local) if *local > *pending && unsent > 0 => local.clone(),
+        _ => pending.clone(),
+    }
+}
+
+/// A shared handle to the next nonce for a single Ethereum address, clones
+/// of this struct all refer to the same underlying nonce
+#[derive(Clone)]
+pub struct NonceManager {
+    address: EthAddress,
+    state: Arc<Mutex<NonceState>>,
+}
+
+impl NonceManager {
+    pub fn new(address: EthAddress) -> Self {
+        NonceManager {
+            address,
+            state: Arc::new(Mutex::new(NonceState::default())),
+        }
+    }
+
+    /// Creates a nonce manager that will hand out `nonce` next without first
+    /// querying the chain
+    pub fn with_starting_nonce(address: EthAddress, nonce: Uint256) -> Self {
+        NonceManager {
+            address,
+            state: Arc::new(Mutex::new(NonceState {
+                next_nonce: Some(nonce),
+                last_reconciled: Some(Instant::now()),
+                unsent: 0,
+            })),
+        }
+    }
+
+    /// Returns the nonce to use for the next transaction from this address. The lock is held
+    /// while reconciling so that concurrent callers always receive distinct nonces. Every nonce
+    /// handed out must be followed by mark_sent once the node accepts the transaction, or by
+    /// reset if it does not
+    pub async fn next_nonce(&self, web3: &Web3) -> Result<Uint256, Web3Error> {
+        let mut state = self.state.lock().await;
+        let needs_reconcile = match state.last_reconciled {
+            Some(last) => Instant::now() - last > NONCE_RECONCILE_INTERVAL,
+            None => true,
+        };
+        if needs_reconcile || state.next_nonce.is_none() {
+            let pending = get_pending_nonce(web3, self.address).await?;
+            let next = reconciled_nonce(state.next_nonce.as_ref(), &pending, state.unsent);
+            if let Some(local) = state.next_nonce.as_ref() {
+                if *local > next {
+                    warn!(
+                        "Nonce {} for {} is ahead of the pending nonce {}, resetting",
+                        local, self.address, next
+                    );
+                    swarn!(&LOGGING.logger, "NONCE_GAP_RESET";
+                        "function" => "next_nonce()",
+                        "address" => format!("{}",self.address),
+                        "local_nonce" => format!("{}",local),
+                        "pending_nonce" => format!("{}",next),
+                    );
+                }
+            }
+            if needs_reconcile {
+                trace!("Reconciled nonce for {} to {}", self.address, next);
+            }
+            state.next_nonce = Some(next);
+            state.last_reconciled = Some(Instant::now());
+        }
+        let nonce = state.next_nonce.clone().unwrap();
+        state.next_nonce = Some(nonce.clone() + 1u8.into());
+        state.unsent += 1;
+        Ok(nonce)
+    }
+
+    /// Records that the node accepted a transaction using a nonce from next_nonce
+    pub async fn mark_sent(&self) {
+        let mut state = self.state.lock().await;
+        state.unsent = state.unsent.saturating_sub(1);
+    }
+
+    /// Discards the locally tracked nonce, to be called when a submission fails so
+    /// that a nonce which was never used on chain does not leave a permanent gap
+    pub async fn reset(&self) {
+        let mut state = self.state.lock().await;
+        state.next_nonce = None;
+        state.last_reconciled = None;
+        state.unsent = state.unsent.saturating_sub(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn test_concurrent_nonces_are_distinct() {
+        // the nonce was just reconciled so no Ethereum node is required
+        let web3 = Web3::new("http://localhost:8545", Duration::from_secs(1));
+        let manager = NonceManager::with_starting_nonce(EthAddress::default(), 5u8.into());
+        let valset_manager = manager.clone();
+        let batch_manager = manager.clone();
+
+        let (a, b) = futures::join!(
+            valset_manager.next_nonce(&web3),
+            batch_manager.next_nonce(&web3)
+        );
+        let (a, b) = (a.unwrap(), b.unwrap());
+        assert_ne!(a, b);
+        let mut nonces = vec![a, b];
+        nonces.sort();
+        let expected: Vec<Uint256> = vec![5u8.into(), 6u8.into()];
+        assert_eq!(nonces, expected);
+        assert_eq!(manager.next_nonce(&web3).await.unwrap(), Uint256::from(7u8));
+    }
+
+    #[test]
+    fn test_reconciled_nonce() {
+        let pending: Uint256 = 5u8.into();
+        // nothing tracked locally yet
+        assert_eq!(reconciled_nonce(None, &pending, 0), pending);
+        // another sender used the account, the pending nonce is the floor
+        assert_eq!(reconciled_nonce(Some(&3u8.into()), &pending, 0), pending);
+        // our transactions were dropped from the mempool, fill the gap
+        assert_eq!(reconciled_nonce(Some(&8u8.into()), &pending, 0), pending);
+        // a nonce we handed out is still being sent
+        let local: Uint256 = 6u8.into();
+        assert_eq!(reconciled_nonce(Some(&local), &pending, 1), local);
+    }
+}
@@ -0,0 +1,128 @@
+//! Price feeds value ERC20 tokens in ETH, letting relayers compare the fees paid by a batch against
+//! the gas it costs to submit even when those fees are not paid in a token pegged to ETH.
+
+use crate::token_metadata::TokenMetadataCache;
+use async_trait::async_trait;
+use clarity::{Address as EthAddress, Uint256};
+use peggy_utils::error::PeggyError;
+use std::collections::HashMap;
+use std::time::Duration;
+use web30::client::Web3;
+
+const PRICE_FEED_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[async_trait(?Send)]
+pub trait PriceFeed {
+    /// The value of one whole token, not one base unit, in ETH
+    async fn get_eth_price(&self, token: EthAddress) -> Result<f64, PeggyError>;
+}
+
+/// A price feed with fixed prices provided by the operator
+pub struct StaticPriceFeed {
+    pub prices: HashMap<EthAddress, f64>,
+}
+
+impl StaticPriceFeed {
+    pub fn new(prices: HashMap<EthAddress, f64>) -> Self {
+        StaticPriceFeed { prices }
+    }
+}
+
+#[async_trait(?Send)]
+impl PriceFeed for StaticPriceFeed {
+    async fn get_eth_price(&self, token: EthAddress) -> Result<f64, PeggyError> {
+        self.prices
+            .get(&token)
+            .copied()
+            .ok_or_else(|| PeggyError::PriceFeedError(format!("No price set for {}", token)))
+    }
+}
+
+/// A price feed for any api that serves the CoinGecko simple token price format, the url should
+/// be the token_price endpoint for the Ethereum platform
+pub struct CoinGeckoPriceFeed {
+    pub url: String,
+}
+
+impl CoinGeckoPriceFeed {
+    pub fn new(url: String) -> Self {
+        CoinGeckoPriceFeed { url }
+    }
+}
+
+#[async_trait(?Send)]
+impl PriceFeed for CoinGeckoPriceFeed {
+    async fn get_eth_price(&self, token: EthAddress) -> Result<f64, PeggyError> {
+        let token = token.to_string().to_lowercase();
+        let client = awc::Client::default();
+        let mut response = client
+            .get(&self.url)
+            .query(&[
+                ("contract_addresses", token.as_str()),
+                ("vs_currencies", "eth"),
+            ])
+            .map_err(|e| PeggyError::PriceFeedError(format!("{:?}", e)))?
+            .timeout(PRICE_FEED_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| PeggyError::PriceFeedError(format!("{:?}", e)))?;
+        // token address to currency to price
+        let response: HashMap<String, HashMap<String, f64>> = response
+            .json()
+            .await
+            .map_err(|e| PeggyError::PriceFeedError(format!("{:?}", e)))?;
+        trace!("Price feed response {:?}", response);
+        match response.get(&token).and_then(|prices| prices.get("eth")) {
+            Some(price) if price.is_finite() && *price >= 0f64 => Ok(*price),
+            Some(price) => Err(PeggyError::PriceFeedError(format!(
+                "Invalid price {} for {}",
+                price, token
+            ))),
+            None => Err(PeggyError::PriceFeedError(format!(
+                "No price for {}",
+                token
+            ))),
+        }
+    }
+}
+
+/// Converts an amount of a token in base units to wei, given the value of a whole token in ETH
+pub fn token_amount_to_wei(amount: &Uint256, decimals: u8, eth_price: f64) -> Uint256 {
+    let wei_per_token: Uint256 = ((eth_price * 1e18) as u128).into();
+    let ten: Uint256 = 10u8.into();
+    let mut unit: Uint256 = 1u8.into();
+    for _ in 0..decimals {
+        unit = unit * ten.clone();
+    }
+    amount.clone() * wei_per_token / unit
+}
+
+/// Values an amount of a token in wei. Without a price feed the token is assumed to be
+/// denominated like ETH and the amount is returned unchanged
+pub async fn token_value_in_wei(
+    price_feed: Option<&dyn PriceFeed>,
+    token: EthAddress,
+    amount: &Uint256,
+    token_metadata: &mut TokenMetadataCache,
+    web3: &Web3,
+) -> Result<Uint256, PeggyError> {
+    match price_feed {
+        None => Ok(amount.clone()),
+        Some(price_feed) => {
+            let eth_price = price_feed.get_eth_price(token).await?;
+            let decimals = token_metadata.query_decimals(web3, token).await?;
+            Ok(token_amount_to_wei(amount, decimals, eth_price))
+        }
+    }
+}
+
+#[test]
+fn test_token_amount_to_wei() {
+    use crate::one_eth;
+    // 1 ETH worth of an 18 decimal token priced at 1 ETH
+    assert_eq!(token_amount_to_wei(&one_eth(), 18, 1.0), one_eth());
+    // 4 of a 6 decimal token at 0.25 ETH each is 1 ETH
+    let six_decimals: Uint256 = 4_000_000u64.into();
+    assert_eq!(token_amount_to_wei(&six_decimals, 6, 0.25), one_eth());
+    assert_eq!(token_amount_to_wei(&six_decimals, 6, 0.0), 0u8.into());
+}
@@ -0,0 +1,130 @@
+//! A durable, machine readable record of every Ethereum transaction the relayer is about to
+//! send. Each record is written and synced before the transaction is broadcast, so if the process
+//! dies between signing and confirmation an operator can see exactly what was submitted with which
+//! nonce. This is a recovery artifact rather than a log, one JSON object per line.
+
+use clarity::{Address as EthAddress, Uint256};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+lazy_static! {
+    static ref RECOVERY_LOG: Mutex<Option<RecoveryLog>> = Mutex::new(None);
+}
+
+/// An outgoing transaction as it is about to be sent
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecoveryRecord {
+    pub tx_type: String,
+    pub contract: EthAddress,
+    pub from: EthAddress,
+    /// None when the nonce was left for the node to fill
+    pub eth_nonce: Option<Uint256>,
+    /// None when the transaction was sent at the node's gas price
+    pub gas_price: Option<Uint256>,
+    pub gas_limit: Uint256,
+    /// The valset nonce, batch nonce, or logic call invalidation nonce this transaction submits
+    pub cosmos_nonce: u64,
+    /// The hex encoded call data
+    pub payload: String,
+}
+
+#[derive(Serialize)]
+struct TimestampedRecord<'a> {
+    timestamp: i64,
+    #[serde(flatten)]
+    record: &'a RecoveryRecord,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecoveryLog {
+    path: PathBuf,
+    max_bytes: u64,
+}
+
+impl RecoveryLog {
+    pub fn new(path: impl Into<PathBuf>, max_bytes: u64) -> Self {
+        RecoveryLog {
+            path: path.into(),
+            max_bytes,
+        }
+    }
+
+    fn rotated_path(&self) -> PathBuf {
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(".1");
+        rotated.into()
+    }
+
+    /// Appends a record, rotating the file first if the record would take it past max_bytes. Only
+    /// one rotated file is kept
+    pub fn append(&self, record: &RecoveryRecord) -> io::Result<()> {
+        let mut line = serde_json::to_vec(&TimestampedRecord {
+            timestamp: chrono::Utc::now().timestamp(),
+            record,
+        })?;
+        line.push(b'\n');
+        if let Ok(metadata) = fs::metadata(&self.path) {
+            if metadata.len() > 0 && metadata.len() + line.len() as u64 > self.max_bytes {
+                fs::rename(&self.path, self.rotated_path())?;
+            }
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(&line)?;
+        // the whole point of this file is surviving a crash
+        file.sync_data()
+    }
+}
+
+/// Sets the recovery log used by record_outgoing_transaction for the rest of the process
+pub fn set_recovery_log(log: RecoveryLog) {
+    *RECOVERY_LOG.lock().unwrap() = Some(log);
+}
+
+/// Records a transaction that is about to be sent, if a recovery log is configured. A failure to
+/// write is logged but does not hold up the transaction
+pub fn record_outgoing_transaction(record: &RecoveryRecord) {
+    if let Some(log) = RECOVERY_LOG.lock().unwrap().as_ref() {
+        if let Err(e) = log.append(record) {
+            error!(
+                "Failed to write {} nonce {} to the recovery log {:?}",
+                record.tx_type, record.cosmos_nonce, e
+            );
+        }
+    }
+}
+
+#[test]
+fn test_recovery_log_rotation() {
+    let dir = std::env::temp_dir().join(format!("peggy-recovery-log-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("recovery.log");
+    let record = RecoveryRecord {
+        tx_type: "transaction_batch".to_string(),
+        contract: EthAddress::default(),
+        from: EthAddress::default(),
+        eth_nonce: Some(7u8.into()),
+        gas_price: None,
+        gas_limit: 500_000u64.into(),
+        cosmos_nonce: 3,
+        payload: "c0ffee".to_string(),
+    };
+    let log = RecoveryLog::new(&path, 600);
+    log.append(&record).unwrap();
+    log.append(&record).unwrap();
+    let contents = fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = contents.lines().collect();
+    assert_eq!(lines.len(), 2);
+    let parsed: RecoveryRecord = serde_json::from_str(lines[0]).unwrap();
+    assert_eq!(parsed, record);
+
+    // the third record does not fit, the first two move to the rotated file
+    log.append(&record).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+    assert_eq!(fs::read_to_string(log.rotated_path()).unwrap(), contents);
+    fs::remove_dir_all(&dir).unwrap();
+}
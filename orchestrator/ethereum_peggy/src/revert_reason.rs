@@ -0,0 +1,116 @@
+//! Decodes the revert data returned by a failed Peggy contract call into the reason it gives, so a
+//! failed submission logs "Peggy: insufficient power" rather than a wall of hex.
+
+use clarity::utils::hex_str_to_bytes;
+use json_logger::LOGGING;
+use slog::error as serror;
+use std::fmt::Display;
+
+/// selector of `Error(string)`, used by `require` and `revert` with a message
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+/// selector of `Panic(uint256)`, used by failed asserts and arithmetic errors since Solidity 0.8
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// Reads an ABI word as a usize, None if it doesn't fit
+fn abi_word_to_usize(word: &[u8]) -> Option<usize> {
+    if word.len() != 32 || word[..24].iter().any(|b| *b != 0) {
+        return None;
+    }
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&word[24..]);
+    let value = u64::from_be_bytes(bytes);
+    if value > usize::MAX as u64 {
+        None
+    } else {
+        Some(value as usize)
+    }
+}
+
+fn panic_description(code: usize) -> &'static str {
+    match code {
+        0x01 => "assertion failed",
+        0x11 => "arithmetic overflow or underflow",
+        0x12 => "division or modulo by zero",
+        0x21 => "invalid enum value",
+        0x22 => "invalid storage byte array",
+        0x31 => "pop on an empty array",
+        0x32 => "array index out of bounds",
+        0x41 => "out of memory",
+        0x51 => "call to an uninitialized function",
+        _ => "unknown panic code",
+    }
+}
+
+/// Decodes revert data in the standard `Error(string)` or `Panic(uint256)` encoding, None if the
+/// data is in any other format
+pub fn decode_revert_reason(data: &[u8]) -> Option<String> {
+    if data.len() < 4 {
+        return None;
+    }
+    let (selector, body) = data.split_at(4);
+    if selector == ERROR_SELECTOR {
+        let offset = abi_word_to_usize(body.get(0..32)?)?;
+        let len_end = offset.checked_add(32)?;
+        let len = abi_word_to_usize(body.get(offset..len_end)?)?;
+        let reason = body.get(len_end..len_end.checked_add(len)?)?;
+        Some(String::from_utf8_lossy(reason).into_owned())
+    } else if selector == PANIC_SELECTOR {
+        let code = abi_word_to_usize(body.get(0..32)?)?;
+        Some(format!("panic {:#04x} ({})", code, panic_description(code)))
+    } else {
+        None
+    }
+}
+
+/// Looks for encoded revert data in the text of an Ethereum node error, nodes put it in the error
+/// data or message in differing formats so every hex string in the text is tried
+pub fn revert_reason_from_error(error: &str) -> Option<String> {
+    error.match_indices("0x").find_map(|(start, _)| {
+        let hex: String = error[start + 2..]
+            .chars()
+            .take_while(|c| c.is_ascii_hexdigit())
+            .collect();
+        if hex.len() < 8 || hex.len() % 2 != 0 {
+            return None;
+        }
+        decode_revert_reason(&hex_str_to_bytes(&hex).ok()?)
+    })
+}
+
+/// Logs the revert reason of a failed submission if the error carries one, returns the reason
+pub fn log_revert_reason<E: Display>(function: &str, error: &E) -> Option<String> {
+    let reason = revert_reason_from_error(&error.to_string())?;
+    error!("{} reverted with reason: {}", function, reason);
+    serror!(&LOGGING.logger, "ETH_TX_REVERTED";
+        "function" => function.to_string(),
+        "reason" => reason.clone(),
+    );
+    Some(reason)
+}
+
+#[test]
+fn test_decode_revert_reason() {
+    // Error("Peggy: insufficient power")
+    let error = "execution reverted, data: \"0x08c379a0\
+        0000000000000000000000000000000000000000000000000000000000000020\
+        0000000000000000000000000000000000000000000000000000000000000019\
+        50656767793a20696e73756666696369656e7420706f77657200000000000000\"";
+    assert_eq!(
+        revert_reason_from_error(error),
+        Some("Peggy: insufficient power".to_string())
+    );
+
+    let panic = hex_str_to_bytes(
+        "4e487b710000000000000000000000000000000000000000000000000000000000000011",
+    )
+    .unwrap();
+    assert_eq!(
+        decode_revert_reason(&panic),
+        Some("panic 0x11 (arithmetic overflow or underflow)".to_string())
+    );
+
+    // truncated data and unknown selectors are not decoded
+    assert_eq!(decode_revert_reason(&panic[..20]), None);
+    assert_eq!(decode_revert_reason(&[0xde, 0xad, 0xbe, 0xef]), None);
+    assert_eq!(revert_reason_from_error("nonce too low 0x12"), None);
+}
@@ -1,17 +1,26 @@
-use crate::utils::{get_tx_batch_nonce, GasCost};
+use crate::audit::{EthTxAudit, EthTxType};
+use crate::nonce_manager::NonceManager;
+use crate::recovery_log::{record_outgoing_transaction, RecoveryRecord};
+use crate::revert_reason::log_revert_reason;
+use crate::utils::{get_transaction_status, get_tx_batch_nonce, GasCost, TransactionStatus};
+use clarity::utils::bytes_to_hex_str;
 use clarity::PrivateKey as EthPrivateKey;
 use clarity::{Address as EthAddress, Uint256};
+use json_logger::LOGGING;
 use peggy_utils::error::PeggyError;
 use peggy_utils::message_signatures::encode_tx_batch_confirm_hashed;
 use peggy_utils::types::*;
+use slog::info as sinfo;
+use slog::warn as swarn;
 use std::{cmp::min, time::Duration};
-use web30::{client::Web3, types::TransactionRequest};
-use json_logger::LOGGING;
-use slog::{info as sinfo};
-
+use web30::{
+    client::Web3,
+    types::{SendTxOption, TransactionRequest},
+};
 
 /// this function generates an appropriate Ethereum transaction
-/// to submit the provided transaction batch
+/// to submit the provided transaction batch, returns the gas our transaction
+/// used only if it moved the batch nonce on Ethereum
 #[allow(clippy::too_many_arguments)]
 pub async fn send_eth_transaction_batch(
     current_valset: Valset,
@@ -22,7 +31,10 @@ pub async fn send_eth_transaction_batch(
     peggy_contract_address: EthAddress,
     peggy_id: String,
     our_eth_key: EthPrivateKey,
-) -> Result<(), PeggyError> {
+    gas_limit: Uint256,
+    gas_price: Uint256,
+    nonce_manager: &NonceManager,
+) -> Result<Option<Uint256>, PeggyError> {
     let new_batch_nonce = batch.nonce;
     let eth_address = our_eth_key.to_public_key().unwrap();
     info!(
@@ -50,7 +62,7 @@ pub async fn send_eth_transaction_batch(
         sinfo!(&LOGGING.logger, "SOMEONE_ELSE_UPDATED_THE_BATCH";
             "function" => "send_eth_transaction_batch()",
         );
-        return Ok(());
+        return Ok(None);
     } else if current_block_height > batch.batch_timeout.into() {
         info!(
             "This batch is timed out. timeout block: {} current block: {}, exiting early",
@@ -59,11 +71,22 @@ pub async fn send_eth_transaction_batch(
         sinfo!(&LOGGING.logger, "THIS_BATCH_IS_TIMED_OUT";
             "function" => "send_eth_transaction_batch()",
         );
-        return Ok(());
+        return Ok(None);
     }
 
     let payload = encode_batch_payload(current_valset, &batch, confirms, peggy_id)?;
 
+    let nonce = nonce_manager.next_nonce(web3).await?;
+    record_outgoing_transaction(&RecoveryRecord {
+        tx_type: EthTxType::TransactionBatch.to_string(),
+        contract: peggy_contract_address,
+        from: eth_address,
+        eth_nonce: Some(nonce.clone()),
+        gas_price: Some(gas_price.clone()),
+        gas_limit: gas_limit.clone(),
+        cosmos_nonce: new_batch_nonce,
+        payload: bytes_to_hex_str(&payload),
+    });
     let tx = web3
         .send_transaction(
             peggy_contract_address,
@@ -71,16 +94,57 @@ pub async fn send_eth_transaction_batch(
             0u32.into(),
             eth_address,
             our_eth_key,
-            vec![],
+            vec![
+                SendTxOption::GasLimit(gas_limit.clone()),
+                SendTxOption::GasPrice(gas_price.clone()),
+                SendTxOption::Nonce(nonce.clone()),
+            ],
         )
-        .await?;
+        .await;
+    if let Err(e) = &tx {
+        nonce_manager.reset().await;
+        log_revert_reason("send_eth_transaction_batch()", e);
+    }
+    let tx = tx?;
+    nonce_manager.mark_sent().await;
+    EthTxAudit {
+        tx_hash: tx.clone(),
+        tx_type: EthTxType::TransactionBatch,
+        eth_nonce: Some(nonce),
+        gas_price: Some(gas_price),
+        gas_limit,
+        cosmos_nonce: new_batch_nonce,
+    }
+    .log();
     info!("Sent batch update with txid {:#066x}", tx);
     sinfo!(&LOGGING.logger, "SENT_BATCH_UPDATE";
         "function" => "send_eth_transaction_batch()",
     );
 
-    web3.wait_for_transaction(tx.clone(), timeout, None).await?;
+    let res = web3.wait_for_transaction(tx.clone(), timeout, None).await;
+    if let Err(e) = &res {
+        log_revert_reason("send_eth_transaction_batch()", e);
+    }
+    res?;
 
+    // the batch nonce also moves when another relayer's transaction for the batch lands first, ours
+    // then reverts, so only our own receipt tells whether we relayed it. The transaction is already
+    // mined, a failed receipt lookup must not turn into a failed submission
+    let status = match get_transaction_status(web3, &tx).await {
+        Ok(status) => status,
+        Err(e) => {
+            warn!(
+                "Could not get the receipt of batch update {:#066x} {:?}",
+                tx, e
+            );
+            swarn!(&LOGGING.logger, "BATCH_UPDATE_RECEIPT_UNAVAILABLE";
+                "function" => "send_eth_transaction_batch()",
+                "tx_hash" => format!("{:#066x}",tx),
+                "error" => format!("{:?}",e),
+            );
+            return Ok(None);
+        }
+    };
     let last_nonce = get_tx_batch_nonce(
         peggy_contract_address,
         batch.token_contract,
@@ -88,21 +152,42 @@ pub async fn send_eth_transaction_batch(
         &web3,
     )
     .await?;
+    let gas_used = match status {
+        Some(TransactionStatus {
+            succeeded: true,
+            gas_used,
+        }) => gas_used,
+        status => {
+            warn!(
+                "Batch update {:#066x} did not succeed, receipt status {:?}, the batch nonce is {}",
+                tx, status, last_nonce
+            );
+            swarn!(&LOGGING.logger, "BATCH_UPDATE_TX_FAILED";
+                "function" => "send_eth_transaction_batch()",
+                "tx_hash" => format!("{:#066x}",tx),
+                "receipt_status" => format!("{:?}",status),
+                "batch_nonce" => format!("{}",last_nonce),
+            );
+            return Ok(None);
+        }
+    };
     if last_nonce != new_batch_nonce {
         error!(
             "Current nonce is {} expected to update to nonce {}",
             last_nonce, new_batch_nonce
         );
+        Ok(None)
     } else {
         info!("Successfully updated Batch with new Nonce {:?}", last_nonce);
         sinfo!(&LOGGING.logger, "SUCCESSFULLY_UPDATED_BATCH";
             "function" => "send_eth_transaction_batch()",
         );
+        Ok(Some(gas_used))
     }
-    Ok(())
 }
 
-/// Returns the cost in Eth of sending this batch
+/// Returns the cost in Eth of sending this batch at the provided gas price
+#[allow(clippy::too_many_arguments)]
 pub async fn estimate_tx_batch_cost(
     current_valset: Valset,
     batch: TransactionBatch,
@@ -111,12 +196,12 @@ pub async fn estimate_tx_batch_cost(
     peggy_contract_address: EthAddress,
     peggy_id: String,
     our_eth_key: EthPrivateKey,
+    gas_price: Uint256,
 ) -> Result<GasCost, PeggyError> {
     let our_eth_address = our_eth_key.to_public_key().unwrap();
     let our_balance = web3.eth_get_balance(our_eth_address).await?;
     let our_nonce = web3.eth_get_transaction_count(our_eth_address).await?;
     let gas_limit = min((u64::MAX - 1).into(), our_balance.clone());
-    let gas_price = web3.eth_gas_price().await?;
     let zero: Uint256 = 0u8.into();
     let val = web3
         .eth_estimate_gas(TransactionRequest {
@@ -0,0 +1,97 @@
+//! Caches ERC20 token metadata queried from Ethereum, a token's decimals never change so each
+//! token only needs to be queried once for the lifetime of the process.
+
+use clarity::{Address as EthAddress, Uint256};
+use peggy_utils::error::PeggyError;
+use std::collections::HashMap;
+use web30::client::Web3;
+
+/// The decimals assumed for a token that does not implement decimals(), this is the
+/// ERC20 default and the value used by the vast majority of tokens
+pub const DEFAULT_DECIMALS: u8 = 18;
+
+#[derive(Debug, Default, Clone)]
+pub struct TokenMetadataCache {
+    decimals: HashMap<EthAddress, u8>,
+}
+
+impl TokenMetadataCache {
+    pub fn new() -> Self {
+        TokenMetadataCache::default()
+    }
+
+    /// Every token whose decimals have been queried, in no particular order
+    pub fn known_decimals(&self) -> Vec<(EthAddress, u8)> {
+        self.decimals.iter().map(|(k, v)| (*k, *v)).collect()
+    }
+
+    /// Records the decimals of a token without querying it, for restoring a cache
+    pub fn insert_decimals(&mut self, erc20: EthAddress, decimals: u8) {
+        self.decimals.insert(erc20, decimals);
+    }
+
+    /// Returns the decimals of the provided ERC20, querying the contract the first time the token
+    /// is seen. Failed queries are not cached so they are retried on the next call
+    pub async fn query_decimals(
+        &mut self,
+        web3: &Web3,
+        erc20: EthAddress,
+    ) -> Result<u8, PeggyError> {
+        if let Some(decimals) = self.decimals.get(&erc20) {
+            return Ok(*decimals);
+        }
+        // decimals() is a view function, the caller address is irrelevant
+        let res = web3.contract_call(erc20, "decimals()", &[], erc20).await?;
+        let decimals = match res.get(0..32) {
+            Some(val) => Uint256::from_bytes_be(val),
+            None => {
+                return Err(PeggyError::EthereumContractError(format!(
+                    "Bad response from ERC20 {} decimals",
+                    erc20
+                )))
+            }
+        };
+        if decimals > u8::MAX.into() {
+            return Err(PeggyError::EthereumContractError(format!(
+                "ERC20 {} returned invalid decimals {}",
+                erc20, decimals
+            )));
+        }
+        let decimals: u8 = decimals.to_string().parse().unwrap();
+        self.decimals.insert(erc20, decimals);
+        Ok(decimals)
+    }
+
+    /// Like query_decimals but falls back to DEFAULT_DECIMALS when the query fails, for uses
+    /// where a wrong guess is preferable to not proceeding at all
+    pub async fn get_decimals(&mut self, web3: &Web3, erc20: EthAddress) -> u8 {
+        match self.query_decimals(web3, erc20).await {
+            Ok(decimals) => decimals,
+            Err(e) => {
+                warn!("Failed to get decimals for ERC20 {} {}", erc20, e);
+                DEFAULT_DECIMALS
+            }
+        }
+    }
+}
+
+/// Returns true if `amount` in the token's base units is at least `threshold` whole tokens
+pub fn is_large_deposit(amount: &Uint256, decimals: u8, threshold: u64) -> bool {
+    let ten: Uint256 = 10u8.into();
+    let mut threshold: Uint256 = threshold.into();
+    for _ in 0..decimals {
+        threshold = threshold * ten.clone();
+    }
+    *amount >= threshold
+}
+
+#[test]
+fn test_is_large_deposit() {
+    // 1000 tokens with 18 decimals and with 6 decimals are the same deposit
+    let eighteen: Uint256 = "1000000000000000000000".parse().unwrap();
+    let six: Uint256 = 1_000_000_000u64.into();
+    assert!(is_large_deposit(&eighteen, 18, 1000));
+    assert!(is_large_deposit(&six, 6, 1000));
+    assert!(!is_large_deposit(&six, 6, 1001));
+    assert!(!is_large_deposit(&six, 18, 1000));
+}
@@ -3,10 +3,17 @@ use clarity::Uint256;
 use clarity::{abi::encode_tokens, Address as EthAddress};
 use deep_space::address::Address as CosmosAddress;
 use peggy_utils::error::PeggyError;
+use peggy_utils::event_signatures::VALSET_UPDATED_EVENT_SIGS;
+use peggy_utils::message_signatures::compute_valset_checkpoint;
+use peggy_utils::rpc_headers::with_eth_rpc_headers;
 use peggy_utils::types::*;
+use serde_json::{json, Value};
 use sha3::{Digest, Keccak256};
+use std::cmp::min;
+use std::time::Duration;
 use std::u128::MAX as U128MAX;
 use std::u64::MAX as U64MAX;
+use web30::types::Log;
 use web30::{client::Web3, jsonrpc::error::Web3Error};
 
 pub fn get_correct_sig_for_address(
@@ -106,6 +113,213 @@ fn test_downcast_to_u128() {
     }
 }
 
+/// Applies the configured multiplier to a gas estimate, producing the gas limit we will actually
+/// submit with. The result is capped at the block gas limit since a transaction requesting more
+/// than that can never be included in a block.
+pub fn apply_gas_limit_multiplier(
+    estimate: Uint256,
+    multiplier: f64,
+    block_gas_limit: Uint256,
+) -> Uint256 {
+    let limit = match downcast_to_u128(estimate.clone()) {
+        Some(estimate) => ((estimate as f64 * multiplier) as u128).into(),
+        // an estimate this large is going to be capped anyways
+        None => estimate,
+    };
+    min(limit, block_gas_limit)
+}
+
+#[test]
+fn test_apply_gas_limit_multiplier() {
+    let block_gas_limit: Uint256 = 12_500_000u64.into();
+    assert_eq!(
+        apply_gas_limit_multiplier(100_000u64.into(), 1.2, block_gas_limit.clone()),
+        120_000u64.into()
+    );
+    assert_eq!(
+        apply_gas_limit_multiplier(100_000u64.into(), 1.0, block_gas_limit.clone()),
+        100_000u64.into()
+    );
+    // the multiplied value must never exceed the block gas limit
+    assert_eq!(
+        apply_gas_limit_multiplier(12_000_000u64.into(), 1.2, block_gas_limit.clone()),
+        block_gas_limit
+    );
+}
+
+/// Gets the gas limit of the latest block, no transaction can use more than this
+pub async fn get_block_gas_limit(web3: &Web3) -> Result<Uint256, Web3Error> {
+    let block = web3.eth_get_latest_block().await?;
+    Ok(block.gas_limit)
+}
+
+const RAW_RPC_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Reads the result of a JSON-RPC response, the node's error message if it returned one
+fn rpc_result(mut response: Value) -> Result<Value, Web3Error> {
+    if let Some(error) = response.get("error") {
+        return Err(Web3Error::BadResponse(
+            error["message"]
+                .as_str()
+                .map(str::to_string)
+                .unwrap_or_else(|| error.to_string()),
+        ));
+    }
+    match response.get_mut("result") {
+        Some(result) => Ok(result.take()),
+        None => Err(Web3Error::BadResponse(format!(
+            "Missing result in {}",
+            response
+        ))),
+    }
+}
+
+#[test]
+fn test_rpc_result() {
+    let result = json!({"jsonrpc": "2.0", "id": 1, "result": "0x1"});
+    assert_eq!(rpc_result(result).unwrap(), json!("0x1"));
+    let pending = json!({"jsonrpc": "2.0", "id": 1, "result": null});
+    assert_eq!(rpc_result(pending).unwrap(), Value::Null);
+    let error = json!({"jsonrpc": "2.0", "id": 1, "error": {"message": "unknown"}});
+    assert!(rpc_result(error).is_err());
+    assert!(rpc_result(json!({"jsonrpc": "2.0", "id": 1})).is_err());
+}
+
+/// Calls a JSON-RPC method web30 doesn't expose against the node behind `web3`, with the
+/// operator's GRAVITY_ETH_RPC_HEADERS, and returns the result field
+pub async fn raw_rpc_call(web3: &Web3, method: &str, params: Value) -> Result<Value, Web3Error> {
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": method,
+        "params": params,
+    });
+    let mut response = with_eth_rpc_headers(awc::Client::default().post(web3.get_url()))
+        .timeout(RAW_RPC_TIMEOUT)
+        .send_json(&request)
+        .await
+        .map_err(|e| Web3Error::BadResponse(format!("{:?}", e)))?;
+    // receipts carry every log of the transaction and contract code can be large, both more
+    // than the default 64KiB body limit
+    let response: Value = response
+        .json()
+        .limit(1024 * 1024)
+        .await
+        .map_err(|e| Web3Error::BadResponse(format!("{:?}", e)))?;
+    rpc_result(response)
+}
+
+/// The parts of a transaction receipt the relayer reads
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionStatus {
+    pub succeeded: bool,
+    pub gas_used: Uint256,
+}
+
+/// Reads the status of a transaction receipt, None if there is no receipt
+fn receipt_status(receipt: &Value) -> Result<Option<TransactionStatus>, Web3Error> {
+    if receipt.is_null() {
+        return Ok(None);
+    }
+    let succeeded = match receipt["status"].as_str() {
+        Some("0x1") => true,
+        Some("0x0") => false,
+        status => {
+            return Err(Web3Error::BadResponse(format!(
+                "Invalid receipt status {:?}",
+                status
+            )))
+        }
+    };
+    let gas_used = receipt["gasUsed"]
+        .as_str()
+        .and_then(|gas| u64::from_str_radix(gas.trim_start_matches("0x"), 16).ok())
+        .ok_or_else(|| {
+            Web3Error::BadResponse(format!("Invalid receipt gasUsed {}", receipt["gasUsed"]))
+        })?;
+    Ok(Some(TransactionStatus {
+        succeeded,
+        gas_used: gas_used.into(),
+    }))
+}
+
+/// Whether the transaction `tx_hash` succeeded according to its receipt and the gas it used, None
+/// if the node has no receipt for it. web30 doesn't expose receipts so they are requested directly
+pub async fn get_transaction_status(
+    web3: &Web3,
+    tx_hash: &Uint256,
+) -> Result<Option<TransactionStatus>, Web3Error> {
+    let receipt = raw_rpc_call(
+        web3,
+        "eth_getTransactionReceipt",
+        json!([format!("{:#066x}", tx_hash)]),
+    )
+    .await?;
+    receipt_status(&receipt)
+}
+
+#[test]
+fn test_receipt_status() {
+    let receipt = |status: &str| json!({"status": status, "gasUsed": "0x1d4c0"});
+    let status = |succeeded| TransactionStatus {
+        succeeded,
+        gas_used: 120_000u64.into(),
+    };
+    assert_eq!(receipt_status(&receipt("0x1")).unwrap(), Some(status(true)));
+    assert_eq!(
+        receipt_status(&receipt("0x0")).unwrap(),
+        Some(status(false))
+    );
+    assert_eq!(receipt_status(&Value::Null).unwrap(), None);
+    assert!(receipt_status(&json!({})).is_err());
+    assert!(receipt_status(&json!({"status": "0x1"})).is_err());
+}
+
+/// The gas limit passed to eth_estimate_gas, the estimate can't exceed what our balance can pay
+/// for but passing an unrealistically large limit is rejected by some nodes, so it is also capped
+/// at `cap`, normally the block gas limit
+pub fn estimation_gas_limit(balance: Uint256, cap: Uint256) -> Uint256 {
+    min(min((u64::MAX - 1).into(), balance), cap)
+}
+
+#[test]
+fn test_estimation_gas_limit() {
+    let cap: Uint256 = 12_500_000u64.into();
+    // a well funded account is capped
+    let balance: Uint256 = 10_000_000_000_000_000_000u128.into();
+    assert_eq!(estimation_gas_limit(balance, cap.clone()), cap);
+    // a nearly empty account is limited by its balance
+    assert_eq!(estimation_gas_limit(1_000u64.into(), cap), 1_000u64.into());
+}
+
+/// Gets the gas limit used for estimation, the block gas limit unless a lower cap is configured
+pub async fn get_estimation_gas_limit(
+    web3: &Web3,
+    balance: Uint256,
+    cap: Option<Uint256>,
+) -> Result<Uint256, Web3Error> {
+    let cap = match cap {
+        Some(cap) => cap,
+        None => get_block_gas_limit(web3).await?,
+    };
+    Ok(estimation_gas_limit(balance, cap))
+}
+
+/// Computes the gas limit to submit a transaction with from the provided estimate, see
+/// apply_gas_limit_multiplier for details
+pub async fn get_gas_limit(
+    estimate: Uint256,
+    multiplier: f64,
+    web3: &Web3,
+) -> Result<Uint256, Web3Error> {
+    let block_gas_limit = get_block_gas_limit(web3).await?;
+    Ok(apply_gas_limit_multiplier(
+        estimate,
+        multiplier,
+        block_gas_limit,
+    ))
+}
+
 /// Gets the latest validator set nonce
 pub async fn get_valset_nonce(
     contract_address: EthAddress,
@@ -129,6 +343,84 @@ pub async fn get_valset_nonce(
     Ok(downcast_uint256(real_num).expect("Valset nonce overflow! Bridge Halt!"))
 }
 
+/// Fetches the logs of both valset update events, see VALSET_UPDATED_EVENT_SIGS, in the order
+/// they were emitted. A contract only ever emits one of the two, but which one depends on when
+/// it was deployed
+pub async fn get_valset_updated_logs(
+    web3: &Web3,
+    start_block: Uint256,
+    end_block: Option<Uint256>,
+    contract_address: EthAddress,
+) -> Result<Vec<Log>, Web3Error> {
+    let mut logs = Vec::new();
+    for sig in VALSET_UPDATED_EVENT_SIGS.iter() {
+        let found = web3
+            .check_for_events(
+                start_block.clone(),
+                end_block.clone(),
+                vec![contract_address],
+                vec![*sig],
+            )
+            .await?;
+        logs.extend(found);
+    }
+    sort_logs(&mut logs);
+    Ok(logs)
+}
+
+/// Puts logs gathered from several eth_getLogs calls back in the order they were emitted
+pub fn sort_logs(logs: &mut [Log]) {
+    logs.sort_by_key(|log| (log.block_number.clone(), log.log_index.clone()));
+}
+
+/// Gets the power threshold the Peggy contract requires signatures to exceed
+pub async fn get_power_threshold(
+    contract_address: EthAddress,
+    caller_address: EthAddress,
+    web3: &Web3,
+) -> Result<u64, Web3Error> {
+    let val = web3
+        .contract_call(
+            contract_address,
+            "state_powerThreshold()",
+            &[],
+            caller_address,
+        )
+        .await?;
+    // the threshold is a fraction of the total power, which is u32 max
+    let real_num = Uint256::from_bytes_be(&val);
+    Ok(downcast_uint256(real_num).expect("Power threshold overflow! Bridge Halt!"))
+}
+
+/// Gets the checkpoint of the valset currently stored in the Peggy contract
+pub async fn get_valset_checkpoint(
+    contract_address: EthAddress,
+    caller_address: EthAddress,
+    web3: &Web3,
+) -> Result<Vec<u8>, Web3Error> {
+    web3.contract_call(
+        contract_address,
+        "state_lastValsetCheckpoint()",
+        &[],
+        caller_address,
+    )
+    .await
+}
+
+/// Checks that the valset checkpoint stored in the Peggy contract is the one the provided
+/// valset produces, letting operators confirm off chain which valset the contract holds
+pub async fn verify_valset_checkpoint(
+    contract_address: EthAddress,
+    caller_address: EthAddress,
+    web3: &Web3,
+    peggy_id: String,
+    valset: Valset,
+) -> Result<bool, Web3Error> {
+    let on_chain = get_valset_checkpoint(contract_address, caller_address, web3).await?;
+    let expected = compute_valset_checkpoint(peggy_id, valset);
+    Ok(on_chain[..] == expected[..])
+}
+
 /// Gets the latest transaction batch nonce
 pub async fn get_tx_batch_nonce(
     peggy_contract_address: EthAddress,
@@ -238,3 +530,32 @@ impl GasCost {
         self.gas.clone() * self.gas_price.clone()
     }
 }
+
+/// Returns true if `balance` can pay for `cost` while still leaving `reserve` in the account
+pub fn has_sufficient_balance(balance: &Uint256, cost: &GasCost, reserve: &Uint256) -> bool {
+    *balance >= cost.get_total() + reserve.clone()
+}
+
+#[test]
+fn test_has_sufficient_balance() {
+    let cost = GasCost {
+        gas: 100_000u64.into(),
+        gas_price: 10u8.into(),
+    };
+    let reserve: Uint256 = 500_000u64.into();
+    assert!(has_sufficient_balance(
+        &1_500_000u64.into(),
+        &cost,
+        &reserve
+    ));
+    assert!(!has_sufficient_balance(
+        &1_499_999u64.into(),
+        &cost,
+        &reserve
+    ));
+    assert!(has_sufficient_balance(
+        &1_000_000u64.into(),
+        &cost,
+        &0u8.into()
+    ));
+}
@@ -1,16 +1,31 @@
-use crate::utils::{get_valset_nonce, GasCost};
+use crate::audit::{EthTxAudit, EthTxType};
+use crate::nonce_manager::NonceManager;
+use crate::recovery_log::{record_outgoing_transaction, RecoveryRecord};
+use crate::revert_reason::log_revert_reason;
+use crate::utils::{get_estimation_gas_limit, get_valset_nonce, GasCost};
+use clarity::utils::bytes_to_hex_str;
 use clarity::PrivateKey as EthPrivateKey;
 use clarity::{Address as EthAddress, Uint256};
+use json_logger::LOGGING;
+use peggy_utils::relay_pause::is_relaying_paused;
 use peggy_utils::types::*;
 use peggy_utils::{error::PeggyError, message_signatures::encode_valset_confirm_hashed};
-use std::{cmp::min, time::Duration};
-use web30::{client::Web3, types::TransactionRequest};
-use json_logger::LOGGING;
-use slog::{info as sinfo};
-use slog::{error as serror};
+use slog::error as serror;
+use slog::info as sinfo;
+use slog::warn as swarn;
+use std::time::Duration;
+use web30::{
+    client::Web3,
+    types::{SendTxOption, TransactionRequest},
+};
 
 /// this function generates an appropriate Ethereum transaction
-/// to submit the provided validator set and signatures.
+/// to submit the provided validator set and signatures. A submission that fails for any reason
+/// but a revert is retried with a fresh nonce up to `submit_retries` times.
+/// `our_eth_key` only sends the transaction and must hold the ETH for gas, the update is authorized
+/// by the `confirms` of the old validator set so any funded key may submit it. `nonce_manager` must
+/// track the address of `our_eth_key`. Returns the hash of our mined transaction, None if nothing
+/// was submitted because the update is already on Ethereum, was overtaken or relaying is paused
 #[allow(clippy::too_many_arguments)]
 pub async fn send_eth_valset_update(
     new_valset: Valset,
@@ -21,59 +36,155 @@ pub async fn send_eth_valset_update(
     peggy_contract_address: EthAddress,
     peggy_id: String,
     our_eth_key: EthPrivateKey,
-) -> Result<(), PeggyError> {
+    gas_limit: Uint256,
+    nonce_manager: &NonceManager,
+    submit_retries: u32,
+) -> Result<Option<Uint256>, PeggyError> {
     let old_nonce = old_valset.nonce;
     let new_nonce = new_valset.nonce;
-    assert!(new_nonce > old_nonce);
+    if new_nonce <= old_nonce {
+        return Err(PeggyError::InvalidBridgeStateError(format!(
+            "Can not update validator set from nonce {} to nonce {}, the new nonce must be greater than the old nonce",
+            old_nonce, new_nonce
+        )));
+    }
     let eth_address = our_eth_key.to_public_key().unwrap();
+    let diff = old_valset.diff(&new_valset);
     info!(
-        "Ordering signatures and submitting validator set {} -> {} update to Ethereum",
-        old_nonce.clone(), new_nonce.clone()
+        "Ordering signatures and submitting validator set {} -> {} update to Ethereum, {}",
+        old_nonce.clone(),
+        new_nonce.clone(),
+        diff
     );
     sinfo!(&LOGGING.logger, "ORDERING_SIGNATURES_AND_SUBMITTING_VALIDATOR";
         "function" => "send_eth_valset_update()",
         "old_nonce" => format!("{}",old_nonce),
         "new_nonce" => format!("{}",new_nonce),
+        "diff" => format!("{}",diff),
+        "added" => format!("{}",ValsetMember::display_vec(&diff.added)),
+        "removed" => format!("{}",ValsetMember::display_vec(&diff.removed)),
+        "power_changed" => format!("{:?}",diff.power_changed),
     );
 
     let before_nonce = get_valset_nonce(peggy_contract_address, eth_address, web3).await?;
-    if before_nonce != old_nonce {
-        info!(
-            "Someone else updated the valset to {}, exiting early",
-            before_nonce.clone()
-        );
-        sinfo!(&LOGGING.logger, "SOMEONE_ELSE_UPDATED_THE_VALSET";
+    match check_valset_nonce(before_nonce, old_nonce, new_nonce) {
+        ValsetNonceCheck::AlreadyCurrent => {
+            info!(
+                "Valset {} is already current on Ethereum, nothing to submit",
+                before_nonce
+            );
+            sinfo!(&LOGGING.logger, "VALSET_ALREADY_CURRENT";
+                "function" => "send_eth_valset_update()",
+                "before_nonce" => format!("{}",before_nonce),
+                "new_nonce" => format!("{}",new_nonce),
+            );
+            return Ok(None);
+        }
+        ValsetNonceCheck::UpdatedByOther => {
+            info!(
+                "Someone else updated the valset to {}, exiting early",
+                before_nonce.clone()
+            );
+            sinfo!(&LOGGING.logger, "SOMEONE_ELSE_UPDATED_THE_VALSET";
+                "function" => "send_eth_valset_update()",
+                "before_nonce" => format!("{}",before_nonce),
+            );
+            return Ok(None);
+        }
+        ValsetNonceCheck::Submit => {}
+    }
+
+    if is_relaying_paused() {
+        info!("Relaying is paused, holding valset update to {}", new_nonce);
+        sinfo!(&LOGGING.logger, "VALSET_HELD_WHILE_PAUSED";
             "function" => "send_eth_valset_update()",
-            "before_nonce" => format!("{}",before_nonce),
+            "new_nonce" => format!("{}",new_nonce),
         );
-        return Ok(());
+        return Ok(None);
     }
 
     let payload = encode_valset_payload(new_valset, old_valset, confirms, peggy_id)?;
 
-    let tx = web3
-        .send_transaction(
-            peggy_contract_address,
-            payload,
-            0u32.into(),
-            eth_address,
-            our_eth_key,
-            vec![],
-        )
-        .await?;
+    let mut attempt = 0;
+    let (tx, nonce) = loop {
+        let nonce = nonce_manager.next_nonce(web3).await?;
+        record_outgoing_transaction(&RecoveryRecord {
+            tx_type: EthTxType::ValsetUpdate.to_string(),
+            contract: peggy_contract_address,
+            from: eth_address,
+            eth_nonce: Some(nonce.clone()),
+            gas_price: None,
+            gas_limit: gas_limit.clone(),
+            cosmos_nonce: new_nonce,
+            payload: bytes_to_hex_str(&payload),
+        });
+        let tx = web3
+            .send_transaction(
+                peggy_contract_address,
+                payload.clone(),
+                0u32.into(),
+                eth_address,
+                our_eth_key,
+                vec![
+                    SendTxOption::GasLimit(gas_limit.clone()),
+                    SendTxOption::Nonce(nonce.clone()),
+                ],
+            )
+            .await;
+        match tx {
+            Ok(tx) => {
+                nonce_manager.mark_sent().await;
+                break (tx, nonce);
+            }
+            Err(e) => {
+                // the next attempt picks up a fresh nonce from the node
+                nonce_manager.reset().await;
+                // a revert fails the same way every time, so only other failures are retried
+                let reverted = log_revert_reason("send_eth_valset_update()", &e).is_some();
+                if reverted || attempt >= submit_retries {
+                    return Err(e.into());
+                }
+                attempt += 1;
+                warn!(
+                    "Submitting valset {} failed, retry {} of {} {}",
+                    new_nonce, attempt, submit_retries, e
+                );
+                swarn!(&LOGGING.logger, "VALSET_SUBMIT_RETRY";
+                    "function" => "send_eth_valset_update()",
+                    "new_nonce" => format!("{}",new_nonce),
+                    "attempt" => format!("{}",attempt),
+                    "reason" => format!("{}",e),
+                );
+            }
+        }
+    };
+    EthTxAudit {
+        tx_hash: tx.clone(),
+        tx_type: EthTxType::ValsetUpdate,
+        eth_nonce: Some(nonce),
+        gas_price: None,
+        gas_limit,
+        cosmos_nonce: new_nonce,
+    }
+    .log();
     info!("Sent valset update with txid {:#066x}", tx);
     sinfo!(&LOGGING.logger, "SENT_VALSET_UPDATE_WITH_TXI";
         "function" => "send_eth_valset_update()",
         "tx" => format!("{:#066x}",tx),
     );
 
-    web3.wait_for_transaction(tx, timeout, None).await?;
+    let res = web3.wait_for_transaction(tx.clone(), timeout, None).await;
+    if let Err(e) = &res {
+        log_revert_reason("send_eth_valset_update()", e);
+    }
+    res?;
 
     let last_nonce = get_valset_nonce(peggy_contract_address, eth_address, web3).await?;
     if last_nonce != new_nonce {
         error!(
             "Current nonce is {} expected to update to nonce {}",
-            last_nonce.clone(), new_nonce.clone()
+            last_nonce.clone(),
+            new_nonce.clone()
         );
         serror!(&LOGGING.logger, "CURRENT_NONCE_IS_FAILED";
             "function" => "send_eth_valset_update()",
@@ -90,7 +201,27 @@ pub async fn send_eth_valset_update(
             "last_nonce" => format!("{:?}",last_nonce),
         );
     }
-    Ok(())
+    Ok(Some(tx))
+}
+
+/// The possible outcomes of comparing the valset nonce on Ethereum to the update we want to submit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValsetNonceCheck {
+    /// the new valset, or a later one, is already on Ethereum
+    AlreadyCurrent,
+    /// the valset on Ethereum is no longer the one our update is signed against
+    UpdatedByOther,
+    Submit,
+}
+
+fn check_valset_nonce(before_nonce: u64, old_nonce: u64, new_nonce: u64) -> ValsetNonceCheck {
+    if before_nonce >= new_nonce {
+        ValsetNonceCheck::AlreadyCurrent
+    } else if before_nonce != old_nonce {
+        ValsetNonceCheck::UpdatedByOther
+    } else {
+        ValsetNonceCheck::Submit
+    }
 }
 
 /// Returns the cost in Eth of sending this valset update
@@ -102,11 +233,12 @@ pub async fn estimate_valset_cost(
     peggy_contract_address: EthAddress,
     peggy_id: String,
     our_eth_key: EthPrivateKey,
+    gas_limit_cap: Option<Uint256>,
 ) -> Result<GasCost, PeggyError> {
     let our_eth_address = our_eth_key.to_public_key().unwrap();
     let our_balance = web3.eth_get_balance(our_eth_address).await?;
     let our_nonce = web3.eth_get_transaction_count(our_eth_address).await?;
-    let gas_limit = min((u64::MAX - 1).into(), our_balance.clone());
+    let gas_limit = get_estimation_gas_limit(web3, our_balance, gas_limit_cap).await?;
     let gas_price = web3.eth_gas_price().await?;
     let zero: Uint256 = 0u8.into();
     let val = web3
@@ -130,6 +262,27 @@ pub async fn estimate_valset_cost(
     })
 }
 
+/// Checks that a valset with its empty addresses filtered out still has members and power
+fn check_filtered_valset(
+    nonce: u64,
+    addresses: &[EthAddress],
+    powers: &[u64],
+) -> Result<(), PeggyError> {
+    if addresses.is_empty() {
+        return Err(PeggyError::EmptyValset {
+            nonce,
+            cause: "has no members with an Ethereum address".to_string(),
+        });
+    }
+    if powers.iter().all(|power| *power == 0) {
+        return Err(PeggyError::EmptyValset {
+            nonce,
+            cause: "has no power".to_string(),
+        });
+    }
+    Ok(())
+}
+
 /// Encodes the payload bytes for the validator set update call, useful for
 /// estimating the cost of submitting a validator set
 fn encode_valset_payload(
@@ -142,6 +295,8 @@ fn encode_valset_payload(
     let (new_addresses, new_powers) = new_valset.filter_empty_addresses();
     let old_nonce = old_valset.nonce;
     let new_nonce = new_valset.nonce;
+    check_filtered_valset(old_nonce, &old_addresses, &old_powers)?;
+    check_filtered_valset(new_nonce, &new_addresses, &new_powers)?;
 
     // remember the signatures are over the new valset and therefore this is the value we must encode
     // the old valset exists only as a hash in the ethereum store
@@ -181,3 +336,85 @@ fn encode_valset_payload(
 
     Ok(payload)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn test_valset_update_nonce_ordering() {
+        let web3 = Web3::new("http://localhost:8545", Duration::from_secs(1));
+        let key: EthPrivateKey =
+            "0xb1bab011e03a9862664706fc3bbaa1b16651528e5f0e7fbfcbfdd8be302a13e7"
+                .parse()
+                .unwrap();
+        let valset = Valset {
+            nonce: 5,
+            members: Vec::new(),
+        };
+        // this fails before making any requests, so no Ethereum node is required
+        let res = send_eth_valset_update(
+            valset.clone(),
+            valset,
+            &[],
+            &web3,
+            Duration::from_secs(1),
+            EthAddress::default(),
+            "foo".to_string(),
+            key,
+            0u8.into(),
+            &NonceManager::new(EthAddress::default()),
+            0,
+        )
+        .await;
+        match res {
+            Err(PeggyError::InvalidBridgeStateError(_)) => {}
+            _ => panic!("Expected an invalid bridge state error, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_encode_empty_valset() {
+        let member = ValsetMember {
+            power: 1000,
+            eth_address: None,
+        };
+        let empty = Valset {
+            nonce: 3,
+            members: vec![member.clone(), member],
+        };
+        let current = Valset {
+            nonce: 2,
+            members: vec![ValsetMember {
+                power: 1000,
+                eth_address: Some(EthAddress::default()),
+            }],
+        };
+        match encode_valset_payload(empty.clone(), current.clone(), &[], "foo".to_string()) {
+            Err(PeggyError::EmptyValset { nonce: 3, .. }) => {}
+            res => panic!("Expected an empty valset error, got {:?}", res),
+        }
+        match encode_valset_payload(current, empty, &[], "foo".to_string()) {
+            Err(PeggyError::EmptyValset { nonce: 3, .. }) => {}
+            res => panic!("Expected an empty valset error, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_check_valset_nonce() {
+        // the new valset is exactly what's already on chain
+        assert_eq!(
+            check_valset_nonce(6, 5, 6),
+            ValsetNonceCheck::AlreadyCurrent
+        );
+        assert_eq!(
+            check_valset_nonce(7, 5, 6),
+            ValsetNonceCheck::AlreadyCurrent
+        );
+        assert_eq!(
+            check_valset_nonce(4, 5, 6),
+            ValsetNonceCheck::UpdatedByOther
+        );
+        assert_eq!(check_valset_nonce(5, 5, 6), ValsetNonceCheck::Submit);
+    }
+}
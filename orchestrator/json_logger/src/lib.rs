@@ -1,50 +1,947 @@
-use once_cell::sync::Lazy;
-use slog::{PushFnValue, *};
-use std::fs::OpenOptions;
-use std::sync::Mutex;
+//! Structured JSON logging for the Peggy binaries. Every record is written as a single line of
+//! JSON to a file in /peggy/data/json_log/.
+//!
+//! Optionally records can also be sent to the local syslog daemon by setting GRAVITY_LOG_SYSLOG=1.
+//! The syslog facility and tag are set with GRAVITY_LOG_SYSLOG_FACILITY (user, daemon, local0-local7,
+//! defaults to user) and GRAVITY_LOG_SYSLOG_TAG (defaults to the binary name). If the local syslog
+//! socket is unavailable we print a warning and fall back to logging to the file only, logging is
+//! never a reason to prevent the orchestrator from starting.
+//!
+//! Setting GRAVITY_LOG_SOCKET to the path of a Unix domain socket, such as one opened by a local
+//! vector or fluentbit agent, sends the newline delimited JSON records there instead of to the file.
+//! If the socket can't be connected at startup we fall back to the file. Should the agent restart
+//! later records are buffered briefly while we reconnect, the oldest are dropped if it stays down.
+//!
+//! Subsystems can log to their own file with `LOGGING.named_logger("oracle")`, which writes to
+//! app-oracle-{ts}-{pid}.log next to the main log file. Each name opens exactly one file that is
+//! kept open for the life of the process, so the number of file handles is bounded by the number
+//! of distinct names. Only ever pass fixed names, never something derived from runtime data such
+//! as a token or batch. If the file can't be opened the root logger is used instead. Named
+//! loggers follow the same output settings as the root logger, with GRAVITY_LOG_SOCKET set each
+//! opens its own connection to the socket instead of a file, and with syslog enabled their records
+//! go there too. Their records carry a `logger` key holding the name.
+//!
+//! Every record carries a `chain_id` key holding the Ethereum net version, so logs from
+//! orchestrators attached to different chains can be told apart once aggregated. It is empty
+//! until the binary calls `set_chain_id` at startup.
+//!
+//! The most recent records, 1000 by default or the number set with GRAVITY_LOG_RECENT_RECORDS,
+//! are also kept in memory so that `recent_records` can hand them to an operator during an
+//! incident without reading the log files, the metrics server serves them at /logs/recent.
+//! Setting it to 0 disables the buffer.
+//!
+//! Values that operators don't want in shared log aggregation can be redacted by listing their
+//! keys in GRAVITY_LOG_REDACT_KEYS, for example `sender,destination`. GRAVITY_LOG_REDACT_MODE
+//! selects `hash` (the default), which replaces the value with a hash so records about the same
+//! address can still be correlated, or `truncate`, which keeps only its first few characters.
+//! Hashes are an HMAC-SHA256 keyed with GRAVITY_LOG_REDACT_SALT, so a redacted address can't be
+//! recovered by hashing every known address. Every orchestrator whose records are correlated
+//! should share the salt, without one a random salt is used that only holds for the process.
+//! Redaction is best effort, it only applies to the structured keys of a record and never to its
+//! message or to values that were formatted into another key.
+//!
+//! Log files grow without bound unless GRAVITY_LOG_ROTATE_BYTES is set. Once a file passes that
+//! size it is gzip compressed to {file}.1.gz, older rotations shift up to .2.gz and so on, and a
+//! fresh file is started. At most GRAVITY_LOG_ROTATE_KEEP rotations are kept, 5 by default.
+//! Rotation only happens between records, a record is never split across two files. If a rotation
+//! fails we print a warning and keep appending to the current file.
+//!
+//! When this crate is embedded as a library the `no-file-log` feature disables all of the above,
+//! LOGGING then discards every record and no files are created or messages printed.
+
+#[cfg(not(feature = "no-file-log"))]
 use chrono;
+#[cfg(not(feature = "no-file-log"))]
+use flate2::{write::GzEncoder, Compression};
+#[cfg(not(feature = "no-file-log"))]
+use hmac::{Hmac, Mac, NewMac};
+use once_cell::sync::{Lazy, OnceCell};
+#[cfg(not(feature = "no-file-log"))]
+use sha2::Sha256;
+use slog::{PushFnValue, *};
+#[cfg(not(feature = "no-file-log"))]
+use slog_syslog::Facility;
+use std::collections::{HashMap, VecDeque};
+#[cfg(not(feature = "no-file-log"))]
+use std::env;
+#[cfg(not(feature = "no-file-log"))]
+use std::fmt;
+#[cfg(not(feature = "no-file-log"))]
+use std::fs::{self, File, OpenOptions};
+#[cfg(not(feature = "no-file-log"))]
+use std::io::{self, Read, Write};
+#[cfg(not(feature = "no-file-log"))]
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, Mutex};
+#[cfg(not(feature = "no-file-log"))]
+use std::time::{Duration, Instant};
 
 // refs: https://rust.graystorm.com/tag/crate-slog/
 // refs: https://github.com/slog-rs/slog/issues/123
 
+/// set to 1 to enable the syslog drain
+pub const SYSLOG_ENABLED_ENV: &str = "GRAVITY_LOG_SYSLOG";
+pub const SYSLOG_FACILITY_ENV: &str = "GRAVITY_LOG_SYSLOG_FACILITY";
+pub const SYSLOG_TAG_ENV: &str = "GRAVITY_LOG_SYSLOG_TAG";
+/// the local syslog socket on Linux systems
+const SYSLOG_SOCKET: &str = "/dev/log";
+/// the named logger used by the Ethereum event watcher
+pub const ORACLE_LOGGER: &str = "oracle";
+/// the named logger used by the batch relayer
+pub const RELAYER_LOGGER: &str = "relayer";
+/// a Unix domain socket to write records to instead of the log file
+pub const LOG_SOCKET_ENV: &str = "GRAVITY_LOG_SOCKET";
+/// the most log data held while the socket is disconnected
+#[cfg(not(feature = "no-file-log"))]
+const LOG_SOCKET_BUFFER_BYTES: usize = 1024 * 1024;
+/// how long to wait between attempts to reconnect to the socket
+#[cfg(not(feature = "no-file-log"))]
+const LOG_SOCKET_RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+/// the number of records kept in memory for recent_records, 0 disables the buffer
+pub const RECENT_RECORDS_ENV: &str = "GRAVITY_LOG_RECENT_RECORDS";
+pub const DEFAULT_RECENT_RECORDS: usize = 1000;
+/// comma separated keys whose values are redacted from every record
+pub const REDACT_KEYS_ENV: &str = "GRAVITY_LOG_REDACT_KEYS";
+/// hash or truncate, how redacted values are rewritten
+pub const REDACT_MODE_ENV: &str = "GRAVITY_LOG_REDACT_MODE";
+/// the key redacted values are hashed with, see REDACT_SALT
+pub const REDACT_SALT_ENV: &str = "GRAVITY_LOG_REDACT_SALT";
+/// log files are rotated once they grow past this many bytes, unset or 0 disables rotation
+pub const ROTATE_BYTES_ENV: &str = "GRAVITY_LOG_ROTATE_BYTES";
+/// the number of compressed rotations kept for each log file
+pub const ROTATE_KEEP_ENV: &str = "GRAVITY_LOG_ROTATE_KEEP";
+pub const DEFAULT_ROTATE_KEEP: usize = 5;
+/// the number of characters a truncated value keeps
+#[cfg(not(feature = "no-file-log"))]
+const REDACT_TRUNCATE_CHARS: usize = 6;
+
 #[derive(Debug)]
 pub struct Logging {
     pub logger: slog::Logger,
+    /// loggers created by named_logger, by name
+    named: Mutex<HashMap<String, slog::Logger>>,
+    /// the timestamp and pid of the root log file name, shared by the named log files
+    ts: i64,
+    pid: String,
+}
+
+impl Logging {
+    /// Returns the logger for a subsystem, writing to its own app-{name}-{ts}-{pid}.log file.
+    /// The file is opened on the first call for a name and reused afterwards
+    pub fn named_logger(&self, name: &str) -> slog::Logger {
+        let mut named = self.named.lock().unwrap();
+        if let Some(logger) = named.get(name) {
+            return logger.clone();
+        }
+        let logger = build_named_logger(&self.logger, name, self.ts, &self.pid);
+        named.insert(name.to_string(), logger.clone());
+        logger
+    }
+}
+
+pub static LOGGING: Lazy<Logging> = Lazy::new(build_logging);
+
+/// the Ethereum chain id added to every record, see set_chain_id
+static CHAIN_ID: OnceCell<String> = OnceCell::new();
+
+/// the records returned by recent_records, oldest first
+static RECENT_RECORDS: Lazy<Arc<Mutex<VecDeque<String>>>> = Lazy::new(Default::default);
+
+/// Returns the most recent records from every logger as JSON lines, oldest first
+pub fn recent_records() -> Vec<String> {
+    RECENT_RECORDS.lock().unwrap().iter().cloned().collect()
+}
+
+/// Sets the chain_id key of every record logged from now on, including those of loggers that
+/// already exist. The chain id can only be set once, returns false if it was already set
+pub fn set_chain_id(chain_id: u64) -> bool {
+    CHAIN_ID.set(chain_id.to_string()).is_ok()
+}
+
+/// With file logging disabled every record is discarded, the structured log call sites
+/// elsewhere stay the same and simply become no-ops
+#[cfg(feature = "no-file-log")]
+fn build_logging() -> Logging {
+    Logging {
+        logger: Logger::root(Discard, o!()),
+        named: Mutex::new(HashMap::new()),
+        ts: 0,
+        pid: String::new(),
+    }
+}
+
+#[cfg(feature = "no-file-log")]
+fn build_named_logger(root: &Logger, _name: &str, _ts: i64, _pid: &str) -> Logger {
+    root.clone()
 }
 
-pub static LOGGING: Lazy<Logging> = Lazy::new(|| {
-    let pid=std::process::id().to_string();
+#[cfg(not(feature = "no-file-log"))]
+fn build_logging() -> Logging {
+    let pid = std::process::id().to_string();
     let ts = chrono::Local::now().timestamp();
 
-    // let logfile = format!("./app-{}-{}.log", ts, pid);
-    let logfile = format!("/peggy/data/json_log/app-{}-{}.log", ts, pid);
-    let file = OpenOptions::new()
+    let output: Box<dyn Write + Send> = match socket_writer() {
+        Some(socket) => Box::new(socket),
+        None => {
+            // let logfile = format!("./app-{}-{}.log", ts, pid);
+            let logfile = format!("/peggy/data/json_log/app-{}-{}.log", ts, pid);
+            log_file(&logfile).unwrap()
+        }
+    };
+
+    let applogger = output_logger(output, &pid);
+    println!("json_logger initialized");
+    Logging {
+        logger: applogger,
+        named: Mutex::new(HashMap::new()),
+        ts,
+        pid,
+    }
+}
+
+/// Named loggers write to the log socket when one is configured and to their own file otherwise,
+/// exactly as the root logger does, and to syslog when it is enabled
+#[cfg(not(feature = "no-file-log"))]
+fn build_named_logger(root: &Logger, name: &str, ts: i64, pid: &str) -> Logger {
+    let output: Box<dyn Write + Send> = match socket_writer() {
+        Some(socket) => Box::new(socket),
+        None => {
+            let logfile = format!("/peggy/data/json_log/app-{}-{}-{}.log", name, ts, pid);
+            match log_file(&logfile) {
+                Ok(file) => file,
+                Err(e) => {
+                    println!(
+                        "json_logger WARNING could not open {}, using the main log {:?}",
+                        logfile, e
+                    );
+                    return root.new(o!("logger" => name.to_string()));
+                }
+            }
+        }
+    };
+    output_logger(output, pid).new(o!("logger" => name.to_string()))
+}
+
+/// Builds a logger writing to `output` and the recent records buffer, and to syslog when it is
+/// enabled
+#[cfg(not(feature = "no-file-log"))]
+fn output_logger(output: Box<dyn Write + Send>, pid: &str) -> Logger {
+    let drain = Duplicate::new(json_drain(output, pid), recent_records_drain(pid)).fuse();
+    let redaction = Redaction::from_env();
+    match syslog_drain() {
+        Some(syslog) => Logger::root(
+            Redact::new(Duplicate::new(drain, syslog).fuse(), redaction),
+            record_values(),
+        ),
+        None => Logger::root(Redact::new(drain, redaction), record_values()),
+    }
+}
+
+/// Opens a log file for appending, wrapped in a RotatingFile if GRAVITY_LOG_ROTATE_BYTES is set
+#[cfg(not(feature = "no-file-log"))]
+fn log_file(path: &str) -> io::Result<Box<dyn Write + Send>> {
+    let max_bytes = env::var(ROTATE_BYTES_ENV)
+        .ok()
+        .and_then(|val| val.parse().ok())
+        .unwrap_or(0);
+    if max_bytes == 0 {
+        return Ok(Box::new(open_append(path)?));
+    }
+    let keep = env::var(ROTATE_KEEP_ENV)
+        .ok()
+        .and_then(|val| val.parse().ok())
+        .unwrap_or(DEFAULT_ROTATE_KEEP);
+    Ok(Box::new(RotatingFile::open(path, max_bytes, keep)?))
+}
+
+#[cfg(not(feature = "no-file-log"))]
+fn open_append(path: &str) -> io::Result<File> {
+    OpenOptions::new()
         .create(true)
         .write(true)
         .append(true)
-        .open(logfile)
-        .unwrap();
+        .open(path)
+}
 
-    let drain = slog_json::Json::new(file)
+/// Appends to a log file, once it has grown past `max_bytes` it is compressed to {path}.1.gz and
+/// truncated, earlier rotations shift up by one and only `keep` of them are kept. The size is
+/// checked at the newline slog_json ends every record with, so a rotation always falls between
+/// two records however the records are split into writes
+#[cfg(not(feature = "no-file-log"))]
+struct RotatingFile {
+    path: String,
+    file: File,
+    written: u64,
+    max_bytes: u64,
+    keep: usize,
+}
+
+#[cfg(not(feature = "no-file-log"))]
+impl RotatingFile {
+    fn open(path: &str, max_bytes: u64, keep: usize) -> io::Result<Self> {
+        let file = open_append(path)?;
+        let written = file.metadata()?.len();
+        Ok(RotatingFile {
+            path: path.to_string(),
+            file,
+            written,
+            max_bytes,
+            keep,
+        })
+    }
+
+    fn rotated_path(&self, n: usize) -> String {
+        format!("{}.{}.gz", self.path, n)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.keep > 0 {
+            // compressed under a temporary name before anything is moved so a failure never
+            // leaves a partial rotation behind, the records stay in the current file until it
+            // succeeds and the older rotations stay where they are
+            let compressed = format!("{}.tmp", self.rotated_path(1));
+            if let Err(e) = self.compress_to(&compressed) {
+                let _ = fs::remove_file(&compressed);
+                return Err(e);
+            }
+            // the oldest rotation is overwritten by the shift
+            for n in (1..self.keep).rev() {
+                let from = self.rotated_path(n);
+                if fs::metadata(&from).is_ok() {
+                    fs::rename(&from, self.rotated_path(n + 1))?;
+                }
+            }
+            fs::rename(&compressed, self.rotated_path(1))?;
+        }
+        self.file.set_len(0)
+    }
+
+    fn compress_to(&self, compressed: &str) -> io::Result<()> {
+        let mut encoder = GzEncoder::new(File::create(compressed)?, Compression::default());
+        io::copy(&mut File::open(&self.path)?, &mut encoder)?;
+        encoder.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "no-file-log"))]
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // written a record at a time so the size can be checked at the end of each
+        let len = buf
+            .iter()
+            .position(|b| *b == b'\n')
+            .map_or(buf.len(), |newline| newline + 1);
+        let written = self.file.write(&buf[..len])?;
+        self.written += written as u64;
+        if buf[..written].ends_with(b"\n") && self.written >= self.max_bytes {
+            // tried again once another max_bytes has been written
+            self.written = 0;
+            if let Err(e) = self.rotate() {
+                println!("json_logger WARNING could not rotate {} {:?}", self.path, e);
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Encodes records as JSON lines, flushing `output` after every record. slog_json only flushes
+/// when asked to and SocketWriter sends records on flush
+#[cfg(not(feature = "no-file-log"))]
+fn json_drain(
+    output: Box<dyn Write + Send>,
+    pid: &str,
+) -> Fuse<Mutex<Fuse<slog_json::Json<Box<dyn Write + Send>>>>> {
+    let drain = slog_json::Json::new(output)
         .set_pretty(false)
+        .set_flush(true)
         .add_default_keys()
         .add_key_value(o!(
-                "pid" => pid
-                ))
+        "pid" => pid.to_string()
+        ))
         .build()
         .fuse();
+    Mutex::new(drain).fuse()
+}
+
+/// Encodes records exactly as they are written to the log file and keeps them in
+/// RECENT_RECORDS
+#[cfg(not(feature = "no-file-log"))]
+fn recent_records_drain(pid: &str) -> Fuse<Mutex<Fuse<slog_json::Json<Box<dyn Write + Send>>>>> {
+    let capacity = env::var(RECENT_RECORDS_ENV)
+        .ok()
+        .and_then(|val| val.parse().ok())
+        .unwrap_or(DEFAULT_RECENT_RECORDS);
+    let writer = RecordRing {
+        records: RECENT_RECORDS.clone(),
+        capacity,
+        line: Vec::new(),
+    };
+    json_drain(Box::new(writer), pid)
+}
+
+/// Collects each record written to it and keeps the newest `capacity` of them. slog_json ends
+/// every record with a newline, so records are cut there rather than on flush, which may come
+/// mid record or cover several
+#[cfg(not(feature = "no-file-log"))]
+struct RecordRing {
+    records: Arc<Mutex<VecDeque<String>>>,
+    capacity: usize,
+    line: Vec<u8>,
+}
+
+#[cfg(not(feature = "no-file-log"))]
+impl Write for RecordRing {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.capacity == 0 {
+            return Ok(buf.len());
+        }
+        self.line.extend_from_slice(buf);
+        while let Some(end) = self.line.iter().position(|b| *b == b'\n') {
+            let rest = self.line.split_off(end + 1);
+            let line = std::mem::replace(&mut self.line, rest);
+            let line = String::from_utf8_lossy(&line).trim_end().to_string();
+            let mut records = self.records.lock().unwrap();
+            if records.len() >= self.capacity {
+                records.pop_front();
+            }
+            records.push_back(line);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// How the values of redacted keys are rewritten
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedactMode {
+    Hash,
+    Truncate,
+}
+
+/// The key redacted values are hashed with, from GRAVITY_LOG_REDACT_SALT or random when it is
+/// unset. It is read once so that every named logger hashes a value the same way
+#[cfg(not(feature = "no-file-log"))]
+static REDACT_SALT: Lazy<Vec<u8>> = Lazy::new(|| match env::var(REDACT_SALT_ENV) {
+    Ok(salt) if !salt.is_empty() => salt.into_bytes(),
+    _ => {
+        let mut salt = vec![0u8; 32];
+        if let Err(e) = File::open("/dev/urandom").and_then(|mut f| f.read_exact(&mut salt)) {
+            println!("json_logger failed to generate a redaction salt {:?}", e);
+        }
+        salt
+    }
+});
+
+/// The keys to redact and how, read from GRAVITY_LOG_REDACT_KEYS and GRAVITY_LOG_REDACT_MODE
+#[cfg(not(feature = "no-file-log"))]
+#[derive(Debug, Clone)]
+struct Redaction {
+    keys: Vec<String>,
+    mode: RedactMode,
+    salt: Vec<u8>,
+}
+
+#[cfg(not(feature = "no-file-log"))]
+impl Redaction {
+    fn from_env() -> Self {
+        let keys = env::var(REDACT_KEYS_ENV)
+            .map(|keys| {
+                keys.split(',')
+                    .map(|key| key.trim().to_string())
+                    .filter(|key| !key.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mode = match env::var(REDACT_MODE_ENV) {
+            Ok(mode) if mode.to_lowercase() == "truncate" => RedactMode::Truncate,
+            Ok(mode) if mode.to_lowercase() != "hash" => {
+                println!("json_logger unknown redaction mode {}, using hash", mode);
+                RedactMode::Hash
+            }
+            _ => RedactMode::Hash,
+        };
+        Redaction {
+            keys,
+            mode,
+            salt: REDACT_SALT.clone(),
+        }
+    }
+
+    fn applies_to(&self, key: Key) -> bool {
+        self.keys.iter().any(|k| k == key)
+    }
+
+    fn redact(&self, value: &str) -> String {
+        match self.mode {
+            RedactMode::Hash => {
+                // HMAC accepts a key of any length
+                let mut mac = Hmac::<Sha256>::new_varkey(&self.salt).unwrap();
+                mac.update(value.as_bytes());
+                let digest = mac.finalize().into_bytes();
+                let hex: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+                format!("hash:{}", hex)
+            }
+            RedactMode::Truncate => {
+                let kept: String = value.chars().take(REDACT_TRUNCATE_CHARS).collect();
+                format!("{}...", kept)
+            }
+        }
+    }
+}
+
+/// A drain that rewrites the values of the redacted keys of a record before passing it on,
+/// the logger's own values such as chain_id are never redacted
+#[cfg(not(feature = "no-file-log"))]
+struct Redact<D> {
+    drain: D,
+    redaction: Redaction,
+}
+
+#[cfg(not(feature = "no-file-log"))]
+impl<D> Redact<D> {
+    fn new(drain: D, redaction: Redaction) -> Self {
+        Redact { drain, redaction }
+    }
+}
+
+#[cfg(not(feature = "no-file-log"))]
+impl<D: Drain> Drain for Redact<D> {
+    type Ok = D::Ok;
+    type Err = D::Err;
+
+    fn log(
+        &self,
+        record: &Record,
+        values: &OwnedKVList,
+    ) -> std::result::Result<Self::Ok, Self::Err> {
+        if self.redaction.keys.is_empty() {
+            return self.drain.log(record, values);
+        }
+        let location = RecordLocation {
+            file: record.file(),
+            line: record.line(),
+            column: record.column(),
+            function: record.function(),
+            module: record.module(),
+        };
+        let rstatic = RecordStatic {
+            location: &location,
+            tag: record.tag(),
+            level: record.level(),
+        };
+        let kv = RedactedKV {
+            kv: record.kv(),
+            redaction: &self.redaction,
+        };
+        self.drain.log(
+            &Record::new(&rstatic, record.msg(), BorrowedKV(&kv)),
+            values,
+        )
+    }
+}
+
+#[cfg(not(feature = "no-file-log"))]
+struct RedactedKV<'a> {
+    kv: BorrowedKV<'a>,
+    redaction: &'a Redaction,
+}
+
+#[cfg(not(feature = "no-file-log"))]
+impl<'a> KV for RedactedKV<'a> {
+    fn serialize(&self, record: &Record, serializer: &mut dyn Serializer) -> Result {
+        self.kv.serialize(
+            record,
+            &mut RedactingSerializer {
+                inner: serializer,
+                redaction: self.redaction,
+            },
+        )
+    }
+}
+
+/// Passes every value through to the wrapped serializer unchanged, except those of redacted keys
+/// which are emitted as redacted strings
+#[cfg(not(feature = "no-file-log"))]
+struct RedactingSerializer<'a> {
+    inner: &'a mut dyn Serializer,
+    redaction: &'a Redaction,
+}
 
+#[cfg(not(feature = "no-file-log"))]
+macro_rules! redact_emit {
+    ($name:ident, $type:ty) => {
+        fn $name(&mut self, key: Key, val: $type) -> Result {
+            if self.redaction.applies_to(key) {
+                self.inner
+                    .emit_str(key, &self.redaction.redact(&val.to_string()))
+            } else {
+                self.inner.$name(key, val)
+            }
+        }
+    };
+}
+
+#[cfg(not(feature = "no-file-log"))]
+impl<'a> Serializer for RedactingSerializer<'a> {
+    redact_emit!(emit_usize, usize);
+    redact_emit!(emit_isize, isize);
+    redact_emit!(emit_bool, bool);
+    redact_emit!(emit_char, char);
+    redact_emit!(emit_u8, u8);
+    redact_emit!(emit_i8, i8);
+    redact_emit!(emit_u16, u16);
+    redact_emit!(emit_i16, i16);
+    redact_emit!(emit_u32, u32);
+    redact_emit!(emit_i32, i32);
+    redact_emit!(emit_f32, f32);
+    redact_emit!(emit_u64, u64);
+    redact_emit!(emit_i64, i64);
+    redact_emit!(emit_f64, f64);
+    redact_emit!(emit_str, &str);
+    redact_emit!(emit_arguments, &fmt::Arguments);
+
+    fn emit_unit(&mut self, key: Key) -> Result {
+        self.inner.emit_unit(key)
+    }
+
+    fn emit_none(&mut self, key: Key) -> Result {
+        self.inner.emit_none(key)
+    }
+}
+
+/// The module, source location, and chain id added to every record
+#[cfg(not(feature = "no-file-log"))]
+fn record_values() -> OwnedKV<impl SendSyncRefUnwindSafeKV + 'static> {
+    let chain_id = PushFnValue(|_: &Record, ser: PushFnValueSerializer| {
+        ser.emit(CHAIN_ID.get().map(|id| id.as_str()).unwrap_or_default())
+    });
     let module = PushFnValue(|r: &Record, ser: PushFnValueSerializer| {
         ser.emit(format_args!("{}", r.module()))
     });
     let location = PushFnValue(|r: &Record, ser: PushFnValueSerializer| {
-        ser.emit(format_args!("https://github.com/nkmr-jp/gravity-bridge/blob/mylog/orchestrator/{}#L{}", r.file(), r.line()))
+        ser.emit(format_args!(
+            "https://github.com/nkmr-jp/gravity-bridge/blob/mylog/orchestrator/{}#L{}",
+            r.file(),
+            r.line()
+        ))
     });
+    o!("module" => module,"location" => location,"chain_id" => chain_id,)
+}
 
-    let applogger = Logger::root(
-        Mutex::new(drain).fuse(),
-        o!("module" => module,"location" => location,),
-    );
-    println!("json_logger initialized");
-    Logging { logger: applogger }
-});
+/// Connects to the log socket if one has been configured
+#[cfg(not(feature = "no-file-log"))]
+fn socket_writer() -> Option<SocketWriter> {
+    let path = env::var(LOG_SOCKET_ENV).ok()?;
+    match UnixStream::connect(&path) {
+        Ok(stream) => Some(SocketWriter {
+            path,
+            stream: Some(stream),
+            buffer: VecDeque::new(),
+            last_connect_attempt: Instant::now(),
+        }),
+        Err(e) => {
+            println!(
+                "json_logger WARNING could not connect to log socket {}, logging to file {:?}",
+                path, e
+            );
+            None
+        }
+    }
+}
+
+/// Writes records to a Unix domain socket, reconnecting if the other end goes away. Records are
+/// only sent on flush, which json_drain does after every record, so a reconnect never splits one
+#[cfg(not(feature = "no-file-log"))]
+struct SocketWriter {
+    path: String,
+    stream: Option<UnixStream>,
+    buffer: VecDeque<u8>,
+    last_connect_attempt: Instant,
+}
+
+#[cfg(not(feature = "no-file-log"))]
+impl Write for SocketWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend(buf);
+        // while disconnected keep only the newest records, dropping whole lines where possible
+        if self.buffer.len() > LOG_SOCKET_BUFFER_BYTES {
+            let excess = self.buffer.len() - LOG_SOCKET_BUFFER_BYTES;
+            let cut = self
+                .buffer
+                .iter()
+                .skip(excess)
+                .position(|b| *b == b'\n')
+                .map(|pos| excess + pos + 1)
+                .unwrap_or(excess);
+            self.buffer.drain(..cut);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.stream.is_none() {
+            if self.last_connect_attempt.elapsed() < LOG_SOCKET_RECONNECT_INTERVAL {
+                return Ok(());
+            }
+            self.last_connect_attempt = Instant::now();
+            self.stream = UnixStream::connect(&self.path).ok();
+        }
+        if let Some(stream) = self.stream.as_mut() {
+            let (front, back) = self.buffer.as_slices();
+            let res = stream.write_all(front).and_then(|_| stream.write_all(back));
+            match res {
+                Ok(()) => self.buffer.clear(),
+                // the records stay buffered and are resent once we reconnect
+                Err(_) => self.stream = None,
+            }
+        }
+        // logging failures are never returned to the caller
+        Ok(())
+    }
+}
+
+/// Builds the syslog drain if it has been enabled, syslog write errors are ignored
+/// so that a flaky syslog daemon can't take down the process with it.
+#[cfg(not(feature = "no-file-log"))]
+fn syslog_drain() -> Option<IgnoreResult<Mutex<slog_syslog::Streamer3164>>> {
+    match env::var(SYSLOG_ENABLED_ENV) {
+        Ok(val) if val == "1" => {}
+        _ => return None,
+    }
+    let facility = match env::var(SYSLOG_FACILITY_ENV) {
+        Ok(val) => parse_facility(&val).unwrap_or_else(|| {
+            println!("json_logger unknown syslog facility {}, using user", val);
+            Facility::LOG_USER
+        }),
+        Err(_) => Facility::LOG_USER,
+    };
+    let tag = env::var(SYSLOG_TAG_ENV).unwrap_or_else(|_| default_tag());
+
+    match slog_syslog::SyslogBuilder::new()
+        .facility(facility)
+        .process(tag)
+        .unix(SYSLOG_SOCKET)
+        .start()
+    {
+        Ok(streamer) => Some(Mutex::new(streamer).ignore_res()),
+        Err(e) => {
+            println!(
+                "json_logger WARNING could not connect to syslog at {}, logging to file only {:?}",
+                SYSLOG_SOCKET, e
+            );
+            None
+        }
+    }
+}
+
+#[cfg(not(feature = "no-file-log"))]
+fn parse_facility(input: &str) -> Option<Facility> {
+    match input.to_lowercase().as_str() {
+        "user" => Some(Facility::LOG_USER),
+        "daemon" => Some(Facility::LOG_DAEMON),
+        "local0" => Some(Facility::LOG_LOCAL0),
+        "local1" => Some(Facility::LOG_LOCAL1),
+        "local2" => Some(Facility::LOG_LOCAL2),
+        "local3" => Some(Facility::LOG_LOCAL3),
+        "local4" => Some(Facility::LOG_LOCAL4),
+        "local5" => Some(Facility::LOG_LOCAL5),
+        "local6" => Some(Facility::LOG_LOCAL6),
+        "local7" => Some(Facility::LOG_LOCAL7),
+        _ => None,
+    }
+}
+
+/// the name of the running binary, used as the syslog tag when none is configured
+#[cfg(not(feature = "no-file-log"))]
+fn default_tag() -> String {
+    env::current_exe()
+        .ok()
+        .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+        .unwrap_or_else(|| "peggy".to_string())
+}
+
+#[cfg(all(test, not(feature = "no-file-log")))]
+mod tests {
+    use super::*;
+
+    /// collects everything written to it so the emitted records can be inspected
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_chain_id_in_record() {
+        let buffer = SharedBuffer::default();
+        let logger = Logger::root(json_drain(Box::new(buffer.clone()), "1"), record_values());
+        assert!(set_chain_id(15));
+        assert!(!set_chain_id(16));
+        info!(logger, "TEST_RECORD"; "function" => "test_chain_id_in_record()");
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("\"chain_id\":\"15\""), "{}", output);
+    }
+
+    #[test]
+    fn test_redaction() {
+        let buffer = SharedBuffer::default();
+        let redaction = Redaction {
+            keys: vec!["sender".to_string(), "n".to_string()],
+            mode: RedactMode::Hash,
+            salt: b"salt".to_vec(),
+        };
+        let drain = Redact::new(json_drain(Box::new(buffer.clone()), "1"), redaction.clone());
+        let logger = Logger::root(drain, record_values());
+        let sender = "0xc783df8a850f42e7F7e57013759C285caa701eB6";
+        info!(logger, "TEST_RECORD";
+            "function" => "test_redaction()",
+            "sender" => sender,
+            "n" => 5,
+            "amount" => format!("{}", 100),
+        );
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(!output.contains(sender), "{}", output);
+        let redacted_sender = format!("\"sender\":\"{}\"", redaction.redact(sender));
+        assert!(output.contains(&redacted_sender), "{}", output);
+        let redacted_n = format!("\"n\":\"{}\"", redaction.redact("5"));
+        assert!(output.contains(&redacted_n), "{}", output);
+        assert!(output.contains("\"amount\":\"100\""), "{}", output);
+
+        // the hash depends on the salt, so it can't be recomputed without it
+        let resalted = Redaction {
+            salt: b"other salt".to_vec(),
+            ..redaction.clone()
+        };
+        assert_ne!(resalted.redact(sender), redaction.redact(sender));
+
+        let truncate = Redaction {
+            keys: Vec::new(),
+            mode: RedactMode::Truncate,
+            salt: Vec::new(),
+        };
+        assert_eq!(truncate.redact(sender), "0xc783...");
+    }
+
+    #[test]
+    fn test_rotating_file() {
+        use flate2::read::GzDecoder;
+        use std::io::Read;
+
+        let dir = env::temp_dir().join(format!("json_logger_rotate_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log").to_str().unwrap().to_string();
+        let file = RotatingFile::open(&path, 1, 2).unwrap();
+        let logger = Logger::root(json_drain(Box::new(file), "1"), record_values());
+        for n in 0..4 {
+            info!(logger, "TEST_RECORD"; "function" => "test_rotating_file()", "n" => n);
+        }
+
+        // every record passed the size on its own, so each was rotated out as it ended
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+        assert!(fs::metadata(format!("{}.3.gz", path)).is_err());
+        let mut newest = String::new();
+        GzDecoder::new(File::open(format!("{}.1.gz", path)).unwrap())
+            .read_to_string(&mut newest)
+            .unwrap();
+        assert_eq!(newest.lines().count(), 1, "{}", newest);
+        assert!(newest.contains("\"n\":3"), "{}", newest);
+        let mut oldest = String::new();
+        GzDecoder::new(File::open(format!("{}.2.gz", path)).unwrap())
+            .read_to_string(&mut oldest)
+            .unwrap();
+        assert!(oldest.contains("\"n\":2"), "{}", oldest);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotating_file_failures() {
+        let dir = env::temp_dir().join(format!("json_logger_rotate_fail_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log").to_str().unwrap().to_string();
+        let mut file = RotatingFile::open(&path, 10, 2).unwrap();
+        let rotated = |n: usize| {
+            let mut rotated = String::new();
+            flate2::read::GzDecoder::new(File::open(format!("{}.{}.gz", path, n)).unwrap())
+                .read_to_string(&mut rotated)
+                .unwrap();
+            rotated
+        };
+
+        // two records in one write are rotated at the newline between them
+        file.write_all(b"first record\nsecond\n").unwrap();
+        assert_eq!(rotated(1), "first record\n");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second\n");
+
+        // the compressed file can't be created, the records stay put and nothing is left behind
+        fs::create_dir(format!("{}.1.gz.tmp", path)).unwrap();
+        file.write_all(b"third record\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second\nthird record\n");
+        let mut names: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        // the older rotation was not shifted away
+        assert_eq!(names, vec!["app.log", "app.log.1.gz", "app.log.1.gz.tmp"]);
+        assert_eq!(rotated(1), "first record\n");
+
+        // once the compressed file can be created the rotations shift as usual
+        fs::remove_dir(format!("{}.1.gz.tmp", path)).unwrap();
+        file.write_all(b"fourth record\n").unwrap();
+        assert_eq!(rotated(1), "second\nthird record\nfourth record\n");
+        assert_eq!(rotated(2), "first record\n");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_record_ring() {
+        let records = Arc::new(Mutex::new(VecDeque::new()));
+        let ring = RecordRing {
+            records: records.clone(),
+            capacity: 2,
+            line: Vec::new(),
+        };
+        let logger = Logger::root(json_drain(Box::new(ring), "1"), record_values());
+        for n in 0..3 {
+            info!(logger, "TEST_RECORD"; "function" => "test_record_ring()", "n" => n);
+        }
+        let records = records.lock().unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(records[0].contains("\"n\":1"), "{}", records[0]);
+        assert!(records[1].contains("\"n\":2"), "{}", records[1]);
+        assert!(records[1].starts_with('{') && records[1].ends_with('}'));
+    }
+
+    #[test]
+    fn test_record_ring_cuts_at_newlines() {
+        let records = Arc::new(Mutex::new(VecDeque::new()));
+        let mut ring = RecordRing {
+            records: records.clone(),
+            capacity: 3,
+            line: Vec::new(),
+        };
+        // one write holding a record and a half, then the rest without a flush in between
+        ring.write_all(b"{\"n\":1}\n{\"n\":").unwrap();
+        assert_eq!(records.lock().unwrap().len(), 1);
+        ring.write_all(b"2}\n{\"n\":3}\n").unwrap();
+        let records = records.lock().unwrap();
+        assert_eq!(*records, vec!["{\"n\":1}", "{\"n\":2}", "{\"n\":3}"]);
+    }
+}
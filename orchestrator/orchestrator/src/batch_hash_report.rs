@@ -0,0 +1,89 @@
+//! A one shot diagnostic for a batch the relayer won't submit because of its signatures, run with
+//! --batch-hash-report. It prints the hash the relayer expects validators to have signed and checks
+//! every confirm against it, so a peggy_id mismatch or a validator signing the wrong payload is
+//! visible at a glance.
+
+use clarity::utils::bytes_to_hex_str;
+use clarity::Address as EthAddress;
+use cosmos_peggy::query::{
+    get_latest_transaction_batches, get_peggy_params, get_transaction_batch_signatures,
+};
+use ethereum_peggy::utils::get_peggy_id;
+use peggy_proto::peggy::query_client::QueryClient as PeggyQueryClient;
+use peggy_utils::error::PeggyError;
+use peggy_utils::message_signatures::{
+    check_signatures, encode_tx_batch_confirm_hashed, SignatureCheck,
+};
+use tonic::transport::Channel;
+use web30::client::Web3;
+
+/// Prints the expected hash and the result of checking each confirm of a pending batch, returns
+/// the checks so the caller can exit with the outcome
+pub async fn batch_hash_report(
+    grpc_client: &mut PeggyQueryClient<Channel>,
+    web3: &Web3,
+    peggy_contract_address: EthAddress,
+    our_ethereum_address: EthAddress,
+    token_contract: EthAddress,
+    nonce: u64,
+) -> Result<Vec<SignatureCheck>, PeggyError> {
+    let batch = get_latest_transaction_batches(grpc_client)
+        .await?
+        .into_iter()
+        .find(|b| b.token_contract == token_contract && b.nonce == nonce)
+        .ok_or_else(|| {
+            PeggyError::InvalidBridgeStateError(format!(
+                "Batch {}/{} is not pending on Cosmos",
+                token_contract, nonce
+            ))
+        })?;
+    let confirms = get_transaction_batch_signatures(grpc_client, nonce, token_contract).await?;
+
+    // the same conversion the relayer uses when it builds the signed message
+    let peggy_id = get_peggy_id(peggy_contract_address, our_ethereum_address, web3).await?;
+    let peggy_id = String::from_utf8(peggy_id).map_err(|e| {
+        PeggyError::InvalidBridgeStateError(format!("Invalid contract peggy_id {}", e))
+    })?;
+    let hash = encode_tx_batch_confirm_hashed(peggy_id.clone(), batch.clone());
+    println!("batch {}/{}", token_contract, nonce);
+    println!("peggy_id {:?}", peggy_id);
+    println!("expected hash 0x{}", bytes_to_hex_str(&hash));
+
+    let checks = check_signatures(&hash, &confirms);
+    for (confirm, check) in confirms.iter().zip(checks.iter()) {
+        let recovered = match check.recovered_signer {
+            Some(signer) => signer.to_string(),
+            None => "invalid signature".to_string(),
+        };
+        println!(
+            "{} orchestrator {} signer {} recovered {}",
+            if check.matches() {
+                "OK      "
+            } else {
+                "MISMATCH"
+            },
+            confirm.orchestrator,
+            check.claimed_signer,
+            recovered
+        );
+    }
+
+    let mismatches = checks.iter().filter(|c| !c.matches()).count();
+    println!(
+        "{}/{} signatures match",
+        checks.len() - mismatches,
+        checks.len()
+    );
+    if mismatches > 0 {
+        let cosmos_peggy_id = get_peggy_params(grpc_client).await?.peggy_id;
+        let cosmos_hash = encode_tx_batch_confirm_hashed(cosmos_peggy_id.clone(), batch);
+        let cosmos_checks = check_signatures(&cosmos_hash, &confirms);
+        if cosmos_checks.iter().filter(|c| c.matches()).count() > checks.len() - mismatches {
+            println!(
+                "Hint: more signatures match the Cosmos peggy_id {:?}, the contract and chain disagree on the peggy_id",
+                cosmos_peggy_id
+            );
+        }
+    }
+    Ok(checks)
+}
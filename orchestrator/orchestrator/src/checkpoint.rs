@@ -0,0 +1,82 @@
+//! The checkpoint file stores values the oracle has expensively discovered about the chain so
+//! that they do not need to be searched for again on every restart.
+
+use clarity::{Address as EthAddress, Uint256};
+use ethereum_peggy::deploy_block::find_contract_deploy_block;
+use json_logger::LOGGING;
+use slog::info as sinfo;
+use std::fs;
+use std::io;
+use web30::client::Web3;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    /// The Peggy contract these values were found for, a checkpoint for
+    /// any other contract is ignored
+    pub peggy_contract_address: String,
+    /// The block the Peggy contract was deployed in
+    pub deploy_block: Uint256,
+}
+
+pub fn load_checkpoint(path: &str) -> Option<Checkpoint> {
+    let contents = fs::read_to_string(path).ok()?;
+    match serde_json::from_str(&contents) {
+        Ok(checkpoint) => Some(checkpoint),
+        Err(e) => {
+            warn!("Ignoring invalid checkpoint file {} {:?}", path, e);
+            None
+        }
+    }
+}
+
+pub fn save_checkpoint(path: &str, checkpoint: &Checkpoint) -> Result<(), io::Error> {
+    let contents = serde_json::to_string_pretty(checkpoint)?;
+    fs::write(path, contents)
+}
+
+/// Gets the block the Peggy contract was deployed in, from the checkpoint file if we have
+/// found it before and otherwise by searching the chain. Returns None if the search fails,
+/// for example because the Ethereum node is not an archive node.
+pub async fn get_contract_deploy_block(
+    web3: &Web3,
+    peggy_contract_address: EthAddress,
+    checkpoint_file: Option<&str>,
+) -> Option<Uint256> {
+    let contract = peggy_contract_address.to_string();
+    if let Some(checkpoint) = checkpoint_file.and_then(load_checkpoint) {
+        if checkpoint.peggy_contract_address == contract {
+            return Some(checkpoint.deploy_block);
+        }
+    }
+
+    let deploy_block = match find_contract_deploy_block(web3, peggy_contract_address).await {
+        Ok(block) => block,
+        Err(e) => {
+            warn!(
+                "Could not find the deploy block of {}, is your node an archive node? {:?}",
+                contract, e
+            );
+            return None;
+        }
+    };
+    info!(
+        "Peggy contract {} was deployed at block {}",
+        contract, deploy_block
+    );
+    sinfo!(&LOGGING.logger, "FOUND_CONTRACT_DEPLOY_BLOCK";
+        "function" => "get_contract_deploy_block()",
+        "peggy_contract_address" => format!("{}",contract),
+        "deploy_block" => format!("{}",deploy_block),
+    );
+
+    if let Some(path) = checkpoint_file {
+        let checkpoint = Checkpoint {
+            peggy_contract_address: contract,
+            deploy_block: deploy_block.clone(),
+        };
+        if let Err(e) = save_checkpoint(path, &checkpoint) {
+            warn!("Failed to write checkpoint file {} {:?}", path, e);
+        }
+    }
+    Some(deploy_block)
+}
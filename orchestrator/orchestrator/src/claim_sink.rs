@@ -0,0 +1,698 @@
+//! A claim sink is where the Ethereum event watcher sends the claims it observes. Normally this
+//! is the Cosmos chain, but routing claims through a trait allows the watcher to be tested without
+//! a chain and leaves room for a read only observer mode that records events elsewhere.
+
+use async_trait::async_trait;
+use clarity::Uint256;
+use contact::client::Contact;
+use cosmos_peggy::{
+    fees::{claims_fee, static_claims_fee},
+    query::get_last_event_nonce,
+    send::{order_claim_msgs, send_ethereum_claims},
+    utils::{wait_for_tx_inclusion, TxInclusion},
+};
+use deep_space::{coin::Coin, private_key::PrivateKey as CosmosPrivateKey};
+use ethereum_peggy::utils::downcast_uint256;
+use json_logger::LOGGING;
+use peggy_proto::peggy::query_client::QueryClient as PeggyQueryClient;
+use peggy_utils::{
+    error::PeggyError,
+    types::{
+        ERC20DeployedEvent, LogicCallExecutedEvent, SendToCosmosEvent,
+        TransactionBatchExecutedEvent,
+    },
+};
+use serde_json::Value;
+use slog::error as serror;
+use slog::warn as swarn;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering};
+use std::time::{Duration, Instant};
+use tokio::time::delay_for;
+use tonic::transport::Channel;
+
+/// All of the claims observed in a single poll of the Ethereum chain
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct EthereumClaims {
+    pub deposits: Vec<SendToCosmosEvent>,
+    pub withdraws: Vec<TransactionBatchExecutedEvent>,
+    pub erc20_deploys: Vec<ERC20DeployedEvent>,
+    pub logic_calls: Vec<LogicCallExecutedEvent>,
+}
+
+impl EthereumClaims {
+    pub fn is_empty(&self) -> bool {
+        self.deposits.is_empty()
+            && self.withdraws.is_empty()
+            && self.erc20_deploys.is_empty()
+            && self.logic_calls.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.deposits.len()
+            + self.withdraws.len()
+            + self.erc20_deploys.len()
+            + self.logic_calls.len()
+    }
+
+    /// Adds the claims of `other` whose event nonce isn't already among these claims
+    pub fn merge(&mut self, mut other: EthereumClaims) {
+        let nonces: HashSet<Uint256> = self
+            .deposits
+            .iter()
+            .map(|e| e.event_nonce.clone())
+            .chain(self.withdraws.iter().map(|e| e.event_nonce.clone()))
+            .chain(self.erc20_deploys.iter().map(|e| e.event_nonce.clone()))
+            .chain(self.logic_calls.iter().map(|e| e.event_nonce.clone()))
+            .collect();
+        let is_new = |nonce: &Uint256| !nonces.contains(nonce);
+        other.deposits.retain(|e| is_new(&e.event_nonce));
+        other.withdraws.retain(|e| is_new(&e.event_nonce));
+        other.erc20_deploys.retain(|e| is_new(&e.event_nonce));
+        other.logic_calls.retain(|e| is_new(&e.event_nonce));
+        self.deposits.extend(other.deposits);
+        self.withdraws.extend(other.withdraws);
+        self.erc20_deploys.extend(other.erc20_deploys);
+        self.logic_calls.extend(other.logic_calls);
+    }
+
+    /// The lowest block any of the claims was observed in, None if there are none
+    pub fn first_block(&self) -> Option<Uint256> {
+        self.deposits
+            .iter()
+            .map(|e| e.block_height.clone())
+            .chain(self.withdraws.iter().map(|e| e.block_height.clone()))
+            .chain(self.erc20_deploys.iter().map(|e| e.block_height.clone()))
+            .chain(self.logic_calls.iter().map(|e| e.block_height.clone()))
+            .min()
+    }
+
+    /// The highest event nonce of any of the claims, zero if there are none
+    pub fn highest_event_nonce(&self) -> u64 {
+        self.deposits
+            .iter()
+            .map(|e| e.event_nonce.clone())
+            .chain(self.withdraws.iter().map(|e| e.event_nonce.clone()))
+            .chain(self.erc20_deploys.iter().map(|e| e.event_nonce.clone()))
+            .chain(self.logic_calls.iter().map(|e| e.event_nonce.clone()))
+            .map(|nonce| downcast_uint256(nonce).unwrap_or(0))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The type of each claim in the order the messages are placed in the claim transaction,
+    /// this must match the ordering of cosmos_peggy::send::order_claim_msgs
+    pub fn types_in_submission_order(&self) -> Vec<ClaimType> {
+        let mut types = Vec::new();
+        for e in self.deposits.iter() {
+            types.push((e.event_nonce.clone(), ClaimType::Deposit));
+        }
+        for e in self.withdraws.iter() {
+            types.push((e.event_nonce.clone(), ClaimType::Withdraw));
+        }
+        for e in self.erc20_deploys.iter() {
+            types.push((e.event_nonce.clone(), ClaimType::ERC20Deployed));
+        }
+        for e in self.logic_calls.iter() {
+            types.push((e.event_nonce.clone(), ClaimType::LogicCallExecuted));
+        }
+        types.sort_by(|a, b| a.0.cmp(&b.0));
+        types.into_iter().map(|(_, t)| t).collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClaimType {
+    Deposit,
+    Withdraw,
+    ERC20Deployed,
+    LogicCallExecuted,
+}
+
+impl fmt::Display for ClaimType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClaimType::Deposit => write!(f, "deposit"),
+            ClaimType::Withdraw => write!(f, "withdraw"),
+            ClaimType::ERC20Deployed => write!(f, "erc20_deployed"),
+            ClaimType::LogicCallExecuted => write!(f, "logic_call_executed"),
+        }
+    }
+}
+
+/// The number of claims of each type accepted by the sink
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ClaimCounts {
+    pub deposits: usize,
+    pub withdraws: usize,
+    pub erc20_deploys: usize,
+    pub logic_calls: usize,
+}
+
+impl ClaimCounts {
+    pub fn add(&mut self, claim_type: ClaimType) {
+        match claim_type {
+            ClaimType::Deposit => self.deposits += 1,
+            ClaimType::Withdraw => self.withdraws += 1,
+            ClaimType::ERC20Deployed => self.erc20_deploys += 1,
+            ClaimType::LogicCallExecuted => self.logic_calls += 1,
+        }
+    }
+}
+
+/// The outcome of submitting a set of claims
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ClaimSubmission {
+    /// The last event nonce recorded by the sink once the claims were processed
+    pub last_event_nonce: u64,
+    pub accepted: ClaimCounts,
+    /// The gas used and requested by the claims transaction, when the sink submits one
+    pub gas_used: Option<u64>,
+    pub gas_wanted: Option<u64>,
+    /// The sink held the claims back instead of submitting them, see WindowedClaimSink
+    pub held: bool,
+}
+
+/// A claim that was part of a successful transaction but has no successful message log
+#[derive(Debug, Clone, PartialEq)]
+pub struct DroppedClaim {
+    pub msg_index: usize,
+    pub claim_type: ClaimType,
+    pub reason: String,
+}
+
+/// Checks the per message logs of a claim transaction response against the claims we submitted,
+/// `expected` being the claim types in message order. A message is accepted if the response
+/// contains a log for its msg_index with an `events` list and no error in `log`.
+pub fn verify_claim_logs(
+    expected: &[ClaimType],
+    logs: Option<&Value>,
+) -> (ClaimCounts, Vec<DroppedClaim>) {
+    let entries = logs.and_then(|l| l.as_array()).cloned().unwrap_or_default();
+    let mut accepted = ClaimCounts::default();
+    let mut dropped = Vec::new();
+    for (msg_index, claim_type) in expected.iter().enumerate() {
+        let entry = entries.iter().find(|e| {
+            e["msg_index"].as_u64() == Some(msg_index as u64)
+                // single message responses may omit the index
+                || (entries.len() == 1 && e.get("msg_index").is_none())
+        });
+        let reason = match entry {
+            None => Some("no log for message".to_string()),
+            Some(e) if e["success"] == false => Some(e["log"].to_string()),
+            Some(e) if !e["events"].is_array() => Some(format!("no events {}", e["log"])),
+            Some(_) => None,
+        };
+        match reason {
+            Some(reason) => dropped.push(DroppedClaim {
+                msg_index,
+                claim_type: *claim_type,
+                reason,
+            }),
+            None => accepted.add(*claim_type),
+        }
+    }
+    (accepted, dropped)
+}
+
+/// Reads the gas used and gas wanted from a Cosmos tx response, the legacy REST endpoints return
+/// them as strings while newer ones use numbers so both are accepted
+pub fn tx_gas_usage(response: &Value) -> (Option<u64>, Option<u64>) {
+    let parse = |v: &Value| {
+        v.as_u64()
+            .or_else(|| v.as_str().and_then(|s| s.parse().ok()))
+    };
+    (parse(&response["gas_used"]), parse(&response["gas_wanted"]))
+}
+
+#[async_trait(?Send)]
+pub trait ClaimSink {
+    /// Submits the provided claims, returning the last event nonce recorded by the sink
+    /// once they have been processed and how many claims of each type were accepted
+    async fn submit(&mut self, claims: EthereumClaims) -> Result<ClaimSubmission, PeggyError>;
+}
+
+/// The default claim sink, submits claims to the Cosmos chain as this validator
+pub struct CosmosClaimSink {
+    pub contact: Contact,
+    pub grpc_client: PeggyQueryClient<Channel>,
+    pub private_key: CosmosPrivateKey,
+    pub fee: Coin,
+    /// How long to wait before re-checking the event nonce when it has not advanced right after
+    /// the claim tx, doubled on every retry, see OrchestratorConfig::claim_confirm_delay
+    pub confirm_delay: Duration,
+    /// The Cosmos legacy REST url, used for the inclusion check and gas price queries
+    pub legacy_rpc_url: Option<String>,
+    /// How long to poll for the claims tx to be included in a block, see
+    /// OrchestratorConfig::claim_inclusion_timeout
+    pub inclusion_timeout: Option<Duration>,
+    /// When set the fee follows the chain's gas price, see OrchestratorConfig::claim_gas_multiplier
+    pub gas_multiplier: Option<f64>,
+    /// The bech32 prefix deposit destinations are claimed with, see
+    /// OrchestratorConfig::cosmos_prefix
+    pub cosmos_prefix: String,
+    /// Catches our last event nonce going backwards before claims are built on it
+    pub nonce_guard: NonceRegressionGuard,
+}
+
+/// Remembers the highest last event nonce Cosmos has reported for us, so that the nonce going
+/// backwards is caught before any claims are submitted on top of it
+#[derive(Debug, Clone, Default)]
+pub struct NonceRegressionGuard {
+    /// See OrchestratorConfig::halt_on_nonce_regression
+    halt: bool,
+    highest_event_nonce: Option<u64>,
+    /// The regression that halted claim submission, as (highest event nonce, new event nonce)
+    regression: Option<(u64, u64)>,
+}
+
+impl NonceRegressionGuard {
+    pub fn new(halt: bool) -> Self {
+        NonceRegressionGuard {
+            halt,
+            ..Default::default()
+        }
+    }
+
+    /// Records a last event nonce read from Cosmos, failing if it is lower than one read before
+    /// or an earlier regression halted claim submission
+    pub fn check(&mut self, event_nonce: u64) -> Result<(), PeggyError> {
+        if let Some((last_event_nonce, new_event_nonce)) = self.regression {
+            return Err(PeggyError::NonceRegression {
+                last_event_nonce,
+                new_event_nonce,
+                halted: true,
+            });
+        }
+        let highest = match self.highest_event_nonce {
+            Some(highest) if event_nonce < highest => highest,
+            _ => {
+                self.highest_event_nonce = Some(event_nonce);
+                return Ok(());
+            }
+        };
+        let halted = self.halt;
+        if halted {
+            self.regression = Some((highest, event_nonce));
+        } else {
+            // the claims are retried from the new nonce on the next poll
+            self.highest_event_nonce = Some(event_nonce);
+        }
+        let e = PeggyError::NonceRegression {
+            last_event_nonce: highest,
+            new_event_nonce: event_nonce,
+            halted,
+        };
+        error!("{}", e);
+        serror!(&LOGGING.logger, "NONCE_REGRESSION";
+            "function" => "NonceRegressionGuard::check()",
+            "last_event_nonce" => format!("{}",highest),
+            "new_event_nonce" => format!("{}",event_nonce),
+            "halted" => format!("{}",halted),
+        );
+        Err(e)
+    }
+}
+
+/// How many times the event nonce is re-checked after the first check before concluding
+/// that the claims were not processed
+pub const CLAIM_CONFIRM_RETRIES: u32 = 3;
+
+#[async_trait(?Send)]
+impl ClaimSink for CosmosClaimSink {
+    async fn submit(&mut self, claims: EthereumClaims) -> Result<ClaimSubmission, PeggyError> {
+        let our_cosmos_address = self.private_key.to_public_key().unwrap().to_address();
+        let claim_types = claims.types_in_submission_order();
+        let last_event_nonce =
+            get_last_event_nonce(&mut self.grpc_client, our_cosmos_address).await?;
+        // claiming on top of a nonce that went backwards could claim events twice
+        self.nonce_guard.check(last_event_nonce)?;
+        let fee = match (&self.legacy_rpc_url, self.gas_multiplier) {
+            (Some(url), Some(multiplier)) => {
+                // the same messages send_ethereum_claims will sign, so the simulation matches
+                let msgs = order_claim_msgs(
+                    our_cosmos_address,
+                    claims.deposits.clone(),
+                    claims.withdraws.clone(),
+                    claims.erc20_deploys.clone(),
+                    claims.logic_calls.clone(),
+                    &self.cosmos_prefix,
+                );
+                claims_fee(
+                    &self.contact,
+                    our_cosmos_address,
+                    &msgs,
+                    self.fee.clone(),
+                    url,
+                    multiplier,
+                )
+                .await
+            }
+            _ => static_claims_fee(self.fee.clone()),
+        };
+        let res = send_ethereum_claims(
+            &self.contact,
+            self.private_key,
+            claims.deposits,
+            claims.withdraws,
+            claims.erc20_deploys,
+            claims.logic_calls,
+            fee,
+            &self.cosmos_prefix,
+        )
+        .await?;
+        trace!("Claims response {:?}", res);
+        let (gas_used, gas_wanted) = serde_json::to_value(&res)
+            .map(|v| tx_gas_usage(&v))
+            .unwrap_or((None, None));
+        if let (Some(url), Some(timeout)) = (&self.legacy_rpc_url, self.inclusion_timeout) {
+            match wait_for_tx_inclusion(url, &res.txhash, timeout).await {
+                TxInclusion::Included { code: 0, .. } => {}
+                TxInclusion::Included { code, raw_log, .. } => {
+                    return Err(PeggyError::ClaimTxRejected {
+                        txhash: res.txhash,
+                        code,
+                        raw_log,
+                    })
+                }
+                TxInclusion::Pending => {
+                    return Err(PeggyError::ClaimTxPending { txhash: res.txhash })
+                }
+            }
+        }
+        // since we can't actually trust that the above txresponse is correct we have to check here
+        // we may be able to trust the tx response post grpc. On slow chains the tx may not be
+        // reflected in the nonce yet, so check a few more times before giving up
+        let mut new_event_nonce =
+            get_last_event_nonce(&mut self.grpc_client, our_cosmos_address).await?;
+        let mut delay = self.confirm_delay;
+        for _ in 0..CLAIM_CONFIRM_RETRIES {
+            if new_event_nonce != last_event_nonce || delay == Duration::from_secs(0) {
+                break;
+            }
+            trace!(
+                "Event nonce not advanced yet, checking again in {:?}",
+                delay
+            );
+            delay_for(delay).await;
+            delay *= 2;
+            new_event_nonce =
+                get_last_event_nonce(&mut self.grpc_client, our_cosmos_address).await?;
+        }
+        if new_event_nonce == last_event_nonce {
+            return Err(PeggyError::NonceNotAdvanced {
+                last_event_nonce,
+                txhash: res.txhash,
+            });
+        }
+        self.nonce_guard.check(new_event_nonce)?;
+        // the nonce advancing only tells us some claim was accepted, check each message
+        let (accepted, dropped) = verify_claim_logs(&claim_types, res.logs.as_ref());
+        for claim in dropped {
+            warn!(
+                "Claim {} of type {} was dropped from tx {}: {}",
+                claim.msg_index, claim.claim_type, res.txhash, claim.reason
+            );
+            swarn!(&LOGGING.logger, "CLAIM_DROPPED";
+                "function" => "CosmosClaimSink::submit()",
+                "txhash" => format!("{}",res.txhash),
+                "msg_index" => format!("{}",claim.msg_index),
+                "claim_type" => format!("{}",claim.claim_type),
+                "reason" => format!("{}",claim.reason),
+            );
+        }
+        Ok(ClaimSubmission {
+            last_event_nonce: new_event_nonce,
+            accepted,
+            gas_used,
+            gas_wanted,
+            held: false,
+        })
+    }
+}
+
+/// The number of claims held back by every WindowedClaimSink
+static HELD_CLAIMS: AtomicUsize = AtomicUsize::new(0);
+
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Makes every WindowedClaimSink submit the claims it is given right away instead of waiting
+/// out its window, used to get held claims submitted before the process exits
+pub fn request_shutdown() {
+    SHUTDOWN_REQUESTED.store(true, AtomicOrdering::SeqCst);
+}
+
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN_REQUESTED.load(AtomicOrdering::SeqCst)
+}
+
+/// The number of claims currently held back by claim windows
+pub fn held_claims() -> usize {
+    HELD_CLAIMS.load(AtomicOrdering::SeqCst)
+}
+
+/// Holds claims back until `window` has passed since it first held any and then submits them to
+/// the inner sink, so that the claims of several polls go out in one transaction and pay one fee.
+/// The watcher sees ClaimSubmission::held and observes the same events again on its next poll, so
+/// nothing is lost if the process stops while claims are held. The held claims are also kept here
+/// so that flush can submit them on shutdown without waiting for another poll.
+/// A zero window submits every set of claims immediately
+pub struct WindowedClaimSink<S: ClaimSink> {
+    pub inner: S,
+    pub window: Duration,
+    /// Claims are submitted without waiting out the window once this many are pending
+    pub max_claims: usize,
+    window_start: Option<Instant>,
+    held: usize,
+    pending: Option<EthereumClaims>,
+}
+
+impl<S: ClaimSink> WindowedClaimSink<S> {
+    pub fn new(inner: S, window: Duration, max_claims: usize) -> Self {
+        WindowedClaimSink {
+            inner,
+            window,
+            max_claims,
+            window_start: None,
+            held: 0,
+            pending: None,
+        }
+    }
+
+    /// Submits the held claims to the inner sink right away, None if none are held
+    pub async fn flush(&mut self) -> Result<Option<ClaimSubmission>, PeggyError> {
+        let claims = match self.pending.take() {
+            Some(claims) => claims,
+            None => return Ok(None),
+        };
+        let res = self.inner.submit(claims).await?;
+        self.window_start = None;
+        self.set_held(0);
+        Ok(Some(res))
+    }
+
+    fn set_held(&mut self, held: usize) {
+        HELD_CLAIMS.fetch_sub(self.held, AtomicOrdering::SeqCst);
+        HELD_CLAIMS.fetch_add(held, AtomicOrdering::SeqCst);
+        self.held = held;
+    }
+}
+
+#[async_trait(?Send)]
+impl<S: ClaimSink> ClaimSink for WindowedClaimSink<S> {
+    async fn submit(&mut self, claims: EthereumClaims) -> Result<ClaimSubmission, PeggyError> {
+        let window_start = *self.window_start.get_or_insert_with(Instant::now);
+        // a poll observes the held claims again, but a chunked poll only passes some of them
+        let mut pending = self.pending.take().unwrap_or_default();
+        pending.merge(claims);
+        if window_start.elapsed() < self.window
+            && pending.len() < self.max_claims
+            && !shutdown_requested()
+        {
+            self.set_held(pending.len());
+            self.pending = Some(pending);
+            return Ok(ClaimSubmission {
+                held: true,
+                ..Default::default()
+            });
+        }
+        // a failed submission leaves the window closed, the claims are retried on the next poll
+        let res = self.inner.submit(pending).await?;
+        self.window_start = None;
+        self.set_held(0);
+        Ok(res)
+    }
+}
+
+/// A claim sink that records every submission in memory, intended for tests
+#[derive(Debug, Default, Clone)]
+pub struct InMemoryClaimSink {
+    pub submitted: Vec<EthereumClaims>,
+    pub last_event_nonce: u64,
+}
+
+#[async_trait(?Send)]
+impl ClaimSink for InMemoryClaimSink {
+    async fn submit(&mut self, claims: EthereumClaims) -> Result<ClaimSubmission, PeggyError> {
+        let mut accepted = ClaimCounts::default();
+        for claim_type in claims.types_in_submission_order() {
+            accepted.add(claim_type);
+        }
+        self.last_event_nonce = self.last_event_nonce.max(claims.highest_event_nonce());
+        self.submitted.push(claims);
+        Ok(ClaimSubmission {
+            last_event_nonce: self.last_event_nonce,
+            accepted,
+            ..Default::default()
+        })
+    }
+}
+
+/// Prints each set of claims to stdout as a line of JSON instead of submitting them, used by the
+/// events report to show what the oracle would claim without touching the Cosmos chain
+#[derive(Debug, Default)]
+pub struct StdoutClaimSink {
+    /// How many events have been printed
+    pub printed: usize,
+    pub last_event_nonce: u64,
+}
+
+#[async_trait(?Send)]
+impl ClaimSink for StdoutClaimSink {
+    async fn submit(&mut self, claims: EthereumClaims) -> Result<ClaimSubmission, PeggyError> {
+        let mut accepted = ClaimCounts::default();
+        for claim_type in claims.types_in_submission_order() {
+            accepted.add(claim_type);
+            self.printed += 1;
+        }
+        self.last_event_nonce = self.last_event_nonce.max(claims.highest_event_nonce());
+        println!("{}", serde_json::to_string(&claims).unwrap());
+        Ok(ClaimSubmission {
+            last_event_nonce: self.last_event_nonce,
+            accepted,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn test_in_memory_claim_sink() {
+        let mut sink = InMemoryClaimSink::default();
+        let claims = EthereumClaims {
+            deposits: vec![SendToCosmosEvent {
+                event_nonce: 2u8.into(),
+                ..Default::default()
+            }],
+            withdraws: vec![TransactionBatchExecutedEvent {
+                event_nonce: 3u8.into(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let res = sink.submit(claims.clone()).await.unwrap();
+        assert_eq!(res.last_event_nonce, 3);
+        assert_eq!(res.accepted.deposits, 1);
+        assert_eq!(res.accepted.withdraws, 1);
+        assert_eq!(sink.submitted, vec![claims]);
+    }
+
+    #[actix_rt::test]
+    async fn test_windowed_claim_sink() {
+        let claims = |nonce: u8| EthereumClaims {
+            deposits: vec![SendToCosmosEvent {
+                event_nonce: nonce.into(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let mut sink =
+            WindowedClaimSink::new(InMemoryClaimSink::default(), Duration::from_secs(60), 2);
+        assert!(sink.submit(claims(1)).await.unwrap().held);
+        assert!(sink.inner.submitted.is_empty());
+        // the watcher observes the held claim again alongside the new one
+        let mut pending = claims(1);
+        pending.deposits.extend(claims(2).deposits);
+        let res = sink.submit(pending.clone()).await.unwrap();
+        assert!(!res.held);
+        assert_eq!(res.last_event_nonce, 2);
+        assert_eq!(sink.inner.submitted, vec![pending]);
+
+        let mut sink =
+            WindowedClaimSink::new(InMemoryClaimSink::default(), Duration::from_secs(0), 2);
+        assert!(!sink.submit(claims(1)).await.unwrap().held);
+
+        // a flush submits what is held without waiting for the watcher to observe it again
+        let mut sink =
+            WindowedClaimSink::new(InMemoryClaimSink::default(), Duration::from_secs(60), 3);
+        assert!(sink.submit(claims(1)).await.unwrap().held);
+        assert!(sink.submit(claims(2)).await.unwrap().held);
+        let res = sink.flush().await.unwrap().unwrap();
+        assert_eq!(res.last_event_nonce, 2);
+        assert_eq!(sink.inner.submitted, vec![pending]);
+        assert!(sink.flush().await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_nonce_regression_guard() {
+        let mut guard = NonceRegressionGuard::new(true);
+        guard.check(5).unwrap();
+        guard.check(7).unwrap();
+        // caught when the nonce is read, before any claims are submitted on top of it
+        assert!(matches!(
+            guard.check(6),
+            Err(PeggyError::NonceRegression {
+                last_event_nonce: 7,
+                new_event_nonce: 6,
+                halted: true,
+            })
+        ));
+        // once halted nothing is submitted until a restart
+        assert!(guard.check(8).is_err());
+
+        let mut guard = NonceRegressionGuard::new(false);
+        guard.check(7).unwrap();
+        assert!(matches!(
+            guard.check(6),
+            Err(PeggyError::NonceRegression { halted: false, .. })
+        ));
+        // without halting the claims are retried from the new nonce
+        guard.check(6).unwrap();
+    }
+
+    #[test]
+    fn test_verify_claim_logs() {
+        let expected = vec![ClaimType::Withdraw, ClaimType::Deposit, ClaimType::Deposit];
+        let logs = serde_json::json!([
+            {"msg_index": 0, "log": "", "events": [{"type": "message", "attributes": []}]},
+            {"msg_index": 1, "log": "", "events": [{"type": "message", "attributes": []}]},
+        ]);
+        let (accepted, dropped) = verify_claim_logs(&expected, Some(&logs));
+        assert_eq!(accepted.withdraws, 1);
+        assert_eq!(accepted.deposits, 1);
+        assert_eq!(dropped.len(), 1);
+        assert_eq!(dropped[0].msg_index, 2);
+        assert_eq!(dropped[0].claim_type, ClaimType::Deposit);
+
+        let (accepted, dropped) = verify_claim_logs(&expected, None);
+        assert_eq!(accepted, ClaimCounts::default());
+        assert_eq!(dropped.len(), 3);
+    }
+
+    #[test]
+    fn test_tx_gas_usage() {
+        let legacy =
+            serde_json::json!({"txhash": "AB", "gas_used": "81234", "gas_wanted": "200000"});
+        assert_eq!(tx_gas_usage(&legacy), (Some(81234), Some(200000)));
+        let numeric = serde_json::json!({"gas_used": 81234, "gas_wanted": 200000});
+        assert_eq!(tx_gas_usage(&numeric), (Some(81234), Some(200000)));
+        assert_eq!(
+            tx_gas_usage(&serde_json::json!({"txhash": "AB"})),
+            (None, None)
+        );
+    }
+}
@@ -1,213 +1,1055 @@
 //! Ethereum Event watcher watches for events such as a deposit to the Peggy Ethereum contract or a validator set update
 //! or a transaction batch update. It then responds to these events by performing actions on the Cosmos chain if required
 
+use async_trait::async_trait;
 use clarity::{utils::bytes_to_hex_str, Address as EthAddress, Uint256};
-use contact::client::Contact;
-use cosmos_peggy::{query::get_last_event_nonce, send::send_ethereum_claims};
-use deep_space::{coin::Coin, private_key::PrivateKey as CosmosPrivateKey};
+use cosmos_peggy::query::get_last_event_nonce;
+use deep_space::private_key::PrivateKey as CosmosPrivateKey;
+use ethereum_peggy::token_metadata::{is_large_deposit, TokenMetadataCache};
+use ethereum_peggy::utils::{downcast_uint256, get_valset_updated_logs};
 use peggy_proto::peggy::query_client::QueryClient as PeggyQueryClient;
+use std::cmp::min;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use json_logger::{LOGGING, ORACLE_LOGGER};
 use peggy_utils::{
+    claim_review::{clear_reviews_through, request_review, ReviewState},
     error::PeggyError,
+    event_signatures::{
+        ERC20_DEPLOYED_EVENT_SIG, LOGIC_CALL_EVENT_SIG, SEND_TO_COSMOS_EVENT_SIG,
+        TRANSACTION_BATCH_EXECUTED_EVENT_SIG, VALSET_UPDATED_EVENT_SIGS,
+    },
+    metrics::{BRIDGED_VOLUME, DEPOSIT_CLAIM_LATENCY, PENDING_DEPOSITS},
+    relay_pause::{are_claims_held, detected_upgrade_block, hold_claims_for_upgrade},
+    rpc_endpoint::eth_rpc_endpoint,
     types::{
         ERC20DeployedEvent, LogicCallExecutedEvent, SendToCosmosEvent,
         TransactionBatchExecutedEvent, ValsetUpdatedEvent,
     },
 };
+use slog::{debug as sdebug, error as serror, info as sinfo, warn as swarn};
 use tonic::transport::Channel;
+use tracing::{field, info_span, Span};
 use web30::client::Web3;
-use web30::jsonrpc::error::Web3Error;
-use json_logger::LOGGING;
-use slog::{info as sinfo};
 
+use crate::claim_sink::{ClaimSink, EthereumClaims};
 use crate::get_with_retry::get_block_number_with_retry;
 use crate::get_with_retry::get_net_version_with_retry;
+use crate::rate_limit::RateLimiter;
+
+/// One oracle identity claiming events, several identities run by the same process can share a
+/// single fetch of the Ethereum logs through check_for_events_for_identities. The last event nonce
+/// and the previously submitted event nonces stay per identity since every validator's claims
+/// advance on their own
+pub struct OracleIdentity<'a, S: ClaimSink> {
+    pub private_key: CosmosPrivateKey,
+    pub claim_sink: &'a mut S,
+    pub previously_submitted: &'a mut HashSet<Uint256>,
+}
 
-pub async fn check_for_events(
+/// Fetches and claims the events from `starting_block` for a single identity, see
+/// check_for_events_for_identities
+#[allow(clippy::too_many_arguments)]
+pub async fn check_for_events<S: ClaimSink>(
     web3: &Web3,
-    contact: &Contact,
+    claim_sink: &mut S,
     grpc_client: &mut PeggyQueryClient<Channel>,
     peggy_contract_address: EthAddress,
     our_private_key: CosmosPrivateKey,
-    fee: Coin,
     starting_block: Uint256,
+    ending_block: Option<Uint256>,
+    rate_limiter: &mut RateLimiter,
+    previously_submitted: &mut HashSet<Uint256>,
+    max_events: Option<usize>,
+    alert_amount_threshold: Option<u64>,
+    review_amount_threshold: Option<u64>,
+    token_metadata: &mut TokenMetadataCache,
+    upgrade_event: Option<&str>,
+    observe_depth: Option<u64>,
+    max_block_range: Option<u64>,
 ) -> Result<Uint256, PeggyError> {
-    let our_cosmos_address = our_private_key.to_public_key().unwrap().to_address();
-    let latest_block = get_block_number_with_retry(web3).await;
-    let latest_block = latest_block - get_block_delay(web3).await;
+    let mut identities = [OracleIdentity {
+        private_key: our_private_key,
+        claim_sink,
+        previously_submitted,
+    }];
+    check_for_events_for_identities(
+        web3,
+        &mut identities,
+        grpc_client,
+        peggy_contract_address,
+        starting_block,
+        ending_block,
+        rate_limiter,
+        max_events,
+        alert_amount_threshold,
+        review_amount_threshold,
+        token_metadata,
+        upgrade_event,
+        observe_depth,
+        max_block_range,
+    )
+    .await
+}
+
+/// check_for_events for several oracle identities at once, the logs of every block range are
+/// fetched and parsed once and then claimed by each identity against its own last event nonce.
+/// Returns the last block every identity has fully processed, an identity that fails does not
+/// keep the others from claiming but the range is checked again on the next poll
+#[allow(clippy::too_many_arguments)]
+pub async fn check_for_events_for_identities<S: ClaimSink>(
+    web3: &Web3,
+    identities: &mut [OracleIdentity<'_, S>],
+    grpc_client: &mut PeggyQueryClient<Channel>,
+    peggy_contract_address: EthAddress,
+    starting_block: Uint256,
+    // the last block to check, when None the latest block old enough to be considered final
+    ending_block: Option<Uint256>,
+    rate_limiter: &mut RateLimiter,
+    max_events: Option<usize>,
+    alert_amount_threshold: Option<u64>,
+    // deposits of at least this many whole tokens are held until an operator approves them
+    review_amount_threshold: Option<u64>,
+    token_metadata: &mut TokenMetadataCache,
+    // the signature of an event the contract emits when it is upgraded, such as Upgraded(address)
+    upgrade_event: Option<&str>,
+    // deposits this many blocks deep are logged as pending, only used when shallower than the
+    // block delay and no ending block is given
+    observe_depth: Option<u64>,
+    // the range is fetched and claimed this many blocks at a time, each chunk's logs are dropped
+    // before the next is fetched so memory use doesn't grow with how far behind the oracle is
+    max_block_range: Option<u64>,
+) -> Result<Uint256, PeggyError> {
+    let span = info_span!("check_for_events");
+    // we don't advance past the upgrade so these blocks are checked again once it's acknowledged
+    if are_claims_held() {
+        return Err(PeggyError::ClaimsHeldForUpgrade {
+            block: detected_upgrade_block(),
+        });
+    }
+    let latest_block = match ending_block {
+        Some(ending_block) => ending_block,
+        None => {
+            rate_limiter.acquire().await;
+            let latest_block = get_block_number_with_retry(web3).await;
+            rate_limiter.acquire().await;
+            let block_delay = get_block_delay(web3).await;
+            if let Some(observe_depth) = observe_depth {
+                let observe_depth: Uint256 = observe_depth.into();
+                if observe_depth < block_delay {
+                    rate_limiter.acquire().await;
+                    observe_pending_deposits(
+                        web3,
+                        peggy_contract_address,
+                        latest_block.clone() - block_delay.clone() + 1u8.into(),
+                        latest_block.clone() - observe_depth,
+                        latest_block.clone(),
+                    )
+                    .await;
+                }
+            }
+            latest_block - block_delay
+        }
+    };
+
+    let mut claim_sinks = Vec::new();
+    let mut previously_submitted = Vec::new();
+    for identity in identities.iter_mut() {
+        claim_sinks.push((identity.private_key, &mut *identity.claim_sink));
+        previously_submitted.push(&mut *identity.previously_submitted);
+    }
+    let mut steps = OracleSteps {
+        web3,
+        claim_sinks,
+        grpc_client,
+        peggy_contract_address,
+        rate_limiter,
+        alert_amount_threshold,
+        review_amount_threshold,
+        token_metadata,
+        upgrade_event,
+    };
+    check_block_range(
+        &mut steps,
+        &mut previously_submitted,
+        starting_block,
+        latest_block,
+        max_block_range,
+        max_events,
+        &span,
+    )
+    .await
+}
+
+/// The two steps of checking a range of blocks, split out of check_for_events_for_identities so
+/// that check_block_range can be tested without an Ethereum node or a Cosmos chain
+#[async_trait(?Send)]
+trait BlockRangeSteps {
+    /// Fetches and parses the events from `start` through `end`
+    async fn fetch(
+        &mut self,
+        start: &Uint256,
+        end: &Uint256,
+        span: &Span,
+    ) -> Result<ObservedEvents, PeggyError>;
+
+    /// Claims `events` for the identity at `index`, see claim_block_range
+    async fn claim(
+        &mut self,
+        index: usize,
+        events: &ObservedEvents,
+        end: Uint256,
+        previously_submitted: &mut HashSet<Uint256>,
+        max_events: Option<usize>,
+        span: &Span,
+    ) -> Result<(Uint256, usize, u64), PeggyError>;
+}
+
+/// Fetches from the Ethereum node and claims through each identity's claim sink
+struct OracleSteps<'a, S: ClaimSink> {
+    web3: &'a Web3,
+    claim_sinks: Vec<(CosmosPrivateKey, &'a mut S)>,
+    grpc_client: &'a mut PeggyQueryClient<Channel>,
+    peggy_contract_address: EthAddress,
+    rate_limiter: &'a mut RateLimiter,
+    alert_amount_threshold: Option<u64>,
+    review_amount_threshold: Option<u64>,
+    token_metadata: &'a mut TokenMetadataCache,
+    upgrade_event: Option<&'a str>,
+}
+
+#[async_trait(?Send)]
+impl<'a, S: ClaimSink> BlockRangeSteps for OracleSteps<'a, S> {
+    async fn fetch(
+        &mut self,
+        start: &Uint256,
+        end: &Uint256,
+        span: &Span,
+    ) -> Result<ObservedEvents, PeggyError> {
+        fetch_block_range(
+            self.web3,
+            self.peggy_contract_address,
+            start,
+            end,
+            self.rate_limiter,
+            self.upgrade_event,
+            span,
+        )
+        .await
+    }
 
+    async fn claim(
+        &mut self,
+        index: usize,
+        events: &ObservedEvents,
+        end: Uint256,
+        previously_submitted: &mut HashSet<Uint256>,
+        max_events: Option<usize>,
+        span: &Span,
+    ) -> Result<(Uint256, usize, u64), PeggyError> {
+        let (private_key, claim_sink) = &mut self.claim_sinks[index];
+        claim_block_range(
+            self.web3,
+            *claim_sink,
+            self.grpc_client,
+            *private_key,
+            events,
+            end,
+            self.rate_limiter,
+            previously_submitted,
+            max_events,
+            self.alert_amount_threshold,
+            self.review_amount_threshold,
+            self.token_metadata,
+            span,
+        )
+        .await
+    }
+}
+
+/// Fetches and claims `starting_block` through `latest_block` a chunk of at most
+/// `max_block_range` blocks at a time, returning the last block every identity fully processed.
+/// `max_events` caps the events each identity claims over the whole range, not per chunk
+async fn check_block_range<R: BlockRangeSteps>(
+    steps: &mut R,
+    previously_submitted: &mut [&mut HashSet<Uint256>],
+    starting_block: Uint256,
+    latest_block: Uint256,
+    max_block_range: Option<u64>,
+    max_events: Option<usize>,
+    span: &Span,
+) -> Result<Uint256, PeggyError> {
+    // every chunk replaces each identity's previously_submitted, the next poll skips all of them
+    let mut submitted_this_poll = vec![HashSet::new(); previously_submitted.len()];
+    let mut remaining_events = vec![max_events; previously_submitted.len()];
+    // reviews are shared by every identity, only those all of them are past can be cleared
+    let mut lowest_event_nonce: Option<u64> = None;
+    let mut chunk_start = starting_block;
+    loop {
+        let chunk_end = match max_block_range {
+            Some(max_block_range) => block_chunk_end(&chunk_start, &latest_block, max_block_range),
+            None => latest_block.clone(),
+        };
+        let chunk_span = info_span!(
+            parent: span,
+            "check_block_range",
+            starting_block = %chunk_start,
+            ending_block = field::Empty,
+            event_nonces = field::Empty,
+            error = field::Empty,
+        );
+        let events = match steps.fetch(&chunk_start, &chunk_end, &chunk_span).await {
+            Ok(events) => events,
+            Err(e) => {
+                // what was claimed this poll must still be skipped when the range is checked again
+                for (previous, submitted) in
+                    previously_submitted.iter_mut().zip(submitted_this_poll)
+                {
+                    previous.extend(submitted);
+                }
+                return Err(e);
+            }
+        };
+        let mut checked = chunk_end.clone();
+        let mut first_error = None;
+        for (index, submitted) in submitted_this_poll.iter_mut().enumerate() {
+            match steps
+                .claim(
+                    index,
+                    &events,
+                    chunk_end.clone(),
+                    &mut *previously_submitted[index],
+                    remaining_events[index],
+                    &chunk_span,
+                )
+                .await
+            {
+                Ok((identity_checked, claimed, last_event_nonce)) => {
+                    checked = min(checked, identity_checked);
+                    lowest_event_nonce = Some(
+                        lowest_event_nonce.map_or(last_event_nonce, |n| min(n, last_event_nonce)),
+                    );
+                    if let Some(remaining) = remaining_events[index].as_mut() {
+                        *remaining = remaining.saturating_sub(claimed);
+                    }
+                    submitted.extend(previously_submitted[index].drain());
+                }
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        if let Some(e) = first_error {
+            for (previous, submitted) in previously_submitted.iter_mut().zip(submitted_this_poll) {
+                previous.extend(submitted);
+            }
+            return Err(e);
+        }
+        // the event cap or a held deposit ended this chunk early, the rest waits for the next poll
+        let cap_reached = remaining_events
+            .iter()
+            .any(|remaining| *remaining == Some(0));
+        if checked < chunk_end || chunk_end >= latest_block || cap_reached {
+            for (previous, submitted) in previously_submitted.iter_mut().zip(submitted_this_poll) {
+                **previous = submitted;
+            }
+            if let Some(event_nonce) = lowest_event_nonce {
+                clear_reviews_through(event_nonce);
+            }
+            return Ok(checked);
+        }
+        chunk_start = chunk_end + 1u8.into();
+    }
+}
+
+/// The last block of the chunk starting at `chunk_start`, never past `latest_block`
+pub fn block_chunk_end(
+    chunk_start: &Uint256,
+    latest_block: &Uint256,
+    max_block_range: u64,
+) -> Uint256 {
+    let chunk_end = chunk_start.clone() + max_block_range.saturating_sub(1).into();
+    min(chunk_end, latest_block.clone())
+}
+
+/// The events in one range of blocks, fetched and parsed once however many identities claim them
+struct ObservedEvents {
+    deposits: Vec<SendToCosmosEvent>,
+    withdraws: Vec<TransactionBatchExecutedEvent>,
+    erc20_deploys: Vec<ERC20DeployedEvent>,
+    logic_calls: Vec<LogicCallExecutedEvent>,
+    fetch_time: Duration,
+    parse_time: Duration,
+}
+
+/// Fetches and parses the events in one range of blocks
+async fn fetch_block_range(
+    web3: &Web3,
+    peggy_contract_address: EthAddress,
+    starting_block: &Uint256,
+    latest_block: &Uint256,
+    rate_limiter: &mut RateLimiter,
+    upgrade_event: Option<&str>,
+    span: &Span,
+) -> Result<ObservedEvents, PeggyError> {
+    let logger = LOGGING.named_logger(ORACLE_LOGGER);
+    let fetch_start = Instant::now();
+
+    rate_limiter.acquire().await;
+    let fetch_span = info_span!(parent: span, "fetch_deposits");
     let deposits = web3
         .check_for_events(
             starting_block.clone(),
             Some(latest_block.clone()),
             vec![peggy_contract_address],
-            vec!["SendToCosmosEvent(address,address,bytes32,uint256,uint256)"],
+            vec![SEND_TO_COSMOS_EVENT_SIG],
         )
         .await;
+    drop(fetch_span);
     trace!("Deposits {:?}", deposits);
 
+    rate_limiter.acquire().await;
+    let fetch_span = info_span!(parent: span, "fetch_batches");
     let batches = web3
         .check_for_events(
             starting_block.clone(),
             Some(latest_block.clone()),
             vec![peggy_contract_address],
-            vec!["TransactionBatchExecutedEvent(uint256,address,uint256)"],
+            vec![TRANSACTION_BATCH_EXECUTED_EVENT_SIG],
         )
         .await;
+    drop(fetch_span);
     trace!("Batches {:?}", batches);
 
-    let valsets = web3
-        .check_for_events(
-            starting_block.clone(),
-            Some(latest_block.clone()),
-            vec![peggy_contract_address],
-            vec!["ValsetUpdatedEvent(uint256,address[],uint256[])"],
-        )
-        .await;
+    // get_valset_updated_logs makes one request per event signature, each counts against the limit
+    for _ in VALSET_UPDATED_EVENT_SIGS.iter() {
+        rate_limiter.acquire().await;
+    }
+    let fetch_span = info_span!(parent: span, "fetch_valsets");
+    let valsets = get_valset_updated_logs(
+        web3,
+        starting_block.clone(),
+        Some(latest_block.clone()),
+        peggy_contract_address,
+    )
+    .await;
+    drop(fetch_span);
     trace!("Valsets {:?}", valsets);
 
+    rate_limiter.acquire().await;
+    let fetch_span = info_span!(parent: span, "fetch_erc20_deploys");
     let erc20_deployed = web3
         .check_for_events(
             starting_block.clone(),
             Some(latest_block.clone()),
             vec![peggy_contract_address],
-            vec!["ERC20DeployedEvent(string,address,string,string,uint8,uint256)"],
+            vec![ERC20_DEPLOYED_EVENT_SIG],
         )
         .await;
+    drop(fetch_span);
     trace!("ERC20 Deployments {:?}", erc20_deployed);
 
+    rate_limiter.acquire().await;
+    let fetch_span = info_span!(parent: span, "fetch_logic_calls");
     let logic_call_executed = web3
         .check_for_events(
             starting_block.clone(),
             Some(latest_block.clone()),
             vec![peggy_contract_address],
-            vec!["LogicCallEvent(bytes32,uint256,bytes,uint256)"],
+            vec![LOGIC_CALL_EVENT_SIG],
         )
         .await;
+    drop(fetch_span);
     trace!("Logic call executions {:?}", logic_call_executed);
 
-    if let (Ok(valsets), Ok(batches), Ok(deposits), Ok(deploys), Ok(logic_calls)) = (
+    // events observed after an upgrade may come from code that doesn't behave like the contract
+    // we were configured for, so no claims are made until an operator has looked at it
+    if let Some(upgrade_event) = upgrade_event {
+        rate_limiter.acquire().await;
+        let upgrades = web3
+            .check_for_events(
+                starting_block.clone(),
+                Some(latest_block.clone()),
+                vec![peggy_contract_address],
+                vec![upgrade_event],
+            )
+            .await?;
+        for upgrade in upgrades {
+            let block = upgrade.block_number.unwrap_or_else(|| latest_block.clone());
+            let block = downcast_uint256(block).unwrap_or(u64::MAX);
+            if hold_claims_for_upgrade(block) {
+                error!(
+                    "Peggy contract {} was upgraded at block {}, holding claims until acknowledged",
+                    peggy_contract_address, block
+                );
+                serror!(&logger, "CONTRACT_UPGRADED";
+                    "function" => "check_for_events()",
+                    "contract" => format!("{}",peggy_contract_address),
+                    "upgrade_event" => upgrade_event,
+                    "block" => format!("{}",block),
+                );
+            }
+        }
+        if are_claims_held() {
+            return Err(PeggyError::ClaimsHeldForUpgrade {
+                block: detected_upgrade_block(),
+            });
+        }
+    }
+    let fetch_time = fetch_start.elapsed();
+    span.record("ending_block", &field::display(&latest_block));
+    sdebug!(&logger, "EVENTS_FETCHED";
+        "function" => "check_for_events()",
+        "starting_block" => format!("{}",starting_block),
+        "ending_block" => format!("{}",latest_block),
+        "rpc_endpoint" => eth_rpc_endpoint(),
+    );
+
+    let (valsets, batches, deposits, deploys, logic_calls) = match (
         valsets,
         batches,
         deposits,
         erc20_deployed,
         logic_call_executed,
     ) {
-        let valsets = ValsetUpdatedEvent::from_logs(&valsets)?;
-        trace!("parsed valsets {:?}", valsets);
-        let withdraws = TransactionBatchExecutedEvent::from_logs(&batches)?;
-        trace!("parsed batches {:?}", batches);
-        let deposits = SendToCosmosEvent::from_logs(&deposits)?;
-        trace!("parsed deposits {:?}", deposits);
-        let erc20_deploys = ERC20DeployedEvent::from_logs(&deploys)?;
-        trace!("parsed erc20 deploys {:?}", erc20_deploys);
-        let logic_calls = LogicCallExecutedEvent::from_logs(&logic_calls)?;
-        trace!("logic call executions {:?}", logic_calls);
-
-        // note that starting block overlaps with our last checked block, because we have to deal with
-        // the possibility that the relayer was killed after relaying only one of multiple events in a single
-        // block, so we also need this routine so make sure we don't send in the first event in this hypothetical
-        // multi event block again. In theory we only send all events for every block and that will pass of fail
-        // atomicly but lets not take that risk.
-        let last_event_nonce = get_last_event_nonce(grpc_client, our_cosmos_address).await?;
-        let deposits = SendToCosmosEvent::filter_by_event_nonce(last_event_nonce, &deposits);
-        let withdraws =
-            TransactionBatchExecutedEvent::filter_by_event_nonce(last_event_nonce, &withdraws);
-        let erc20_deploys =
-            ERC20DeployedEvent::filter_by_event_nonce(last_event_nonce, &erc20_deploys);
-        let logic_calls =
-            LogicCallExecutedEvent::filter_by_event_nonce(last_event_nonce, &logic_calls);
-
-        if !deposits.is_empty() {
-            info!(
-                "Oracle observed deposit with sender {}, destination {}, amount {}, and event nonce {}",
-                deposits[0].sender, deposits[0].destination, deposits[0].amount, deposits[0].event_nonce
+        (Ok(valsets), Ok(batches), Ok(deposits), Ok(deploys), Ok(logic_calls)) => {
+            (valsets, batches, deposits, deploys, logic_calls)
+        }
+        _ => {
+            error!("Failed to get events");
+            span.record("error", &"Failed to get logs!");
+            return Err(PeggyError::RpcUnavailable {
+                endpoint: "Ethereum RPC".to_string(),
+                cause: "Failed to get logs!".to_string(),
+            });
+        }
+    };
+    let parse_start = Instant::now();
+    let valsets = ValsetUpdatedEvent::from_logs(&valsets)?;
+    trace!("parsed valsets {:?}", valsets);
+    let withdraws = TransactionBatchExecutedEvent::from_logs(&batches)?;
+    trace!("parsed batches {:?}", batches);
+    let deposits = SendToCosmosEvent::from_logs(&deposits)?;
+    trace!("parsed deposits {:?}", deposits);
+    let erc20_deploys = ERC20DeployedEvent::from_logs(&deploys)?;
+    trace!("parsed erc20 deploys {:?}", erc20_deploys);
+    let logic_calls = LogicCallExecutedEvent::from_logs(&logic_calls)?;
+    trace!("logic call executions {:?}", logic_calls);
+    let parse_time = parse_start.elapsed();
+    Ok(ObservedEvents {
+        deposits,
+        withdraws,
+        erc20_deploys,
+        logic_calls,
+        fetch_time,
+        parse_time,
+    })
+}
+
+/// Claims the events in one range of blocks for one identity, returns the last block fully
+/// processed, how many events were claimed and the last event nonce Cosmos has for the identity
+#[allow(clippy::too_many_arguments)]
+async fn claim_block_range<S: ClaimSink>(
+    web3: &Web3,
+    claim_sink: &mut S,
+    grpc_client: &mut PeggyQueryClient<Channel>,
+    our_private_key: CosmosPrivateKey,
+    events: &ObservedEvents,
+    latest_block: Uint256,
+    rate_limiter: &mut RateLimiter,
+    previously_submitted: &mut HashSet<Uint256>,
+    max_events: Option<usize>,
+    alert_amount_threshold: Option<u64>,
+    review_amount_threshold: Option<u64>,
+    token_metadata: &mut TokenMetadataCache,
+    span: &Span,
+) -> Result<(Uint256, usize, u64), PeggyError> {
+    let logger = LOGGING.named_logger(ORACLE_LOGGER);
+    let our_cosmos_address = our_private_key.to_public_key().unwrap().to_address();
+    let mut latest_block = latest_block;
+    let (fetch_time, parse_time) = (events.fetch_time, events.parse_time);
+    let submit_start = Instant::now();
+
+    // note that starting block overlaps with our last checked block (see next_starting_block), because we have to deal with
+    // the possibility that the relayer was killed after relaying only one of multiple events in a single
+    // block, so we also need this routine so make sure we don't send in the first event in this hypothetical
+    // multi event block again. In theory we only send all events for every block and that will pass of fail
+    // atomicly but lets not take that risk.
+    let last_event_nonce = get_last_event_nonce(grpc_client, our_cosmos_address)
+        .await
+        .map_err(|e| PeggyError::RpcUnavailable {
+            endpoint: "Cosmos gRPC".to_string(),
+            cause: e.to_string(),
+        })?;
+    let deposits = SendToCosmosEvent::filter_by_event_nonce(last_event_nonce, &events.deposits);
+    let withdraws =
+        TransactionBatchExecutedEvent::filter_by_event_nonce(last_event_nonce, &events.withdraws);
+    let erc20_deploys =
+        ERC20DeployedEvent::filter_by_event_nonce(last_event_nonce, &events.erc20_deploys);
+    let logic_calls =
+        LogicCallExecutedEvent::filter_by_event_nonce(last_event_nonce, &events.logic_calls);
+
+    // the nonce filter above depends on the Cosmos chain having already processed our last claims
+    // as a second guard we never resubmit an event nonce we submitted in the previous poll
+    let deposits = dedupe_events_across_polls(previously_submitted, &deposits, |e| &e.event_nonce);
+    let withdraws =
+        dedupe_events_across_polls(previously_submitted, &withdraws, |e| &e.event_nonce);
+    let erc20_deploys =
+        dedupe_events_across_polls(previously_submitted, &erc20_deploys, |e| &e.event_nonce);
+    let logic_calls =
+        dedupe_events_across_polls(previously_submitted, &logic_calls, |e| &e.event_nonce);
+
+    // if there are more events than we are allowed to process in one poll only the lowest nonces
+    // are claimed and we report only the blocks fully processed so the rest are picked up next poll
+    let (deposits, withdraws, erc20_deploys, logic_calls) = match max_events {
+        Some(max_events) => {
+            let events = deposits
+                .iter()
+                .map(|e| (e.event_nonce.clone(), e.block_height.clone()))
+                .chain(
+                    withdraws
+                        .iter()
+                        .map(|e| (e.event_nonce.clone(), e.block_height.clone())),
+                )
+                .chain(
+                    erc20_deploys
+                        .iter()
+                        .map(|e| (e.event_nonce.clone(), e.block_height.clone())),
+                )
+                .chain(
+                    logic_calls
+                        .iter()
+                        .map(|e| (e.event_nonce.clone(), e.block_height.clone())),
+                )
+                .collect();
+            match event_cap_cutoff(events, max_events) {
+                Some((max_nonce, last_full_block)) => {
+                    info!(
+                        "More than {} events observed, claiming up to event nonce {} and block {} this poll",
+                        max_events, max_nonce, last_full_block
+                    );
+                    sinfo!(&logger, "EVENT_CAP_REACHED";
+                        "function" => "check_for_events()",
+                        "max_events" => format!("{}",max_events),
+                        "max_nonce" => format!("{}",max_nonce),
+                        "last_full_block" => format!("{}",last_full_block),
+                    );
+                    latest_block = last_full_block;
+                    (
+                        deposits
+                            .into_iter()
+                            .filter(|e| e.event_nonce <= max_nonce)
+                            .collect(),
+                        withdraws
+                            .into_iter()
+                            .filter(|e| e.event_nonce <= max_nonce)
+                            .collect(),
+                        erc20_deploys
+                            .into_iter()
+                            .filter(|e| e.event_nonce <= max_nonce)
+                            .collect(),
+                        logic_calls
+                            .into_iter()
+                            .filter(|e| e.event_nonce <= max_nonce)
+                            .collect(),
+                    )
+                }
+                None => (deposits, withdraws, erc20_deploys, logic_calls),
+            }
+        }
+        None => (deposits, withdraws, erc20_deploys, logic_calls),
+    };
+
+    // large deposits wait for an operator to approve them, since claims must be made in event
+    // nonce order nothing observed after a held deposit can be claimed until it is approved
+    let mut held_deposit = None;
+    if let Some(threshold) = review_amount_threshold {
+        for deposit in deposits.iter() {
+            let decimals = token_metadata.get_decimals(web3, deposit.erc20).await;
+            if !is_large_deposit(&deposit.amount, decimals, threshold) {
+                continue;
+            }
+            let summary = format!(
+                "deposit of {} of ERC20 {} from {} to {} in block {}",
+                deposit.amount,
+                deposit.erc20,
+                deposit.sender,
+                deposit.destination,
+                deposit.block_height
             );
-            sinfo!(&LOGGING.logger, "ORACLE_OBSERVED_DEPOSIT";
+            let nonce = downcast_uint256(deposit.event_nonce.clone()).unwrap_or(u64::MAX);
+            let state = request_review(nonce, summary);
+            if state == ReviewState::Approved {
+                continue;
+            }
+            warn!(
+                "Deposit with event nonce {} is held for review, {:?}",
+                deposit.event_nonce, state
+            );
+            swarn!(&logger, "CLAIM_HELD_FOR_REVIEW";
                 "function" => "check_for_events()",
-                "last_nonce" => format!("{}",deposits[0].sender),
-                "destination" => format!("{}",deposits[0].destination),
-                "amount" => format!("{}",deposits[0].amount),
-                "event_nonce" => format!("{}",deposits[0].event_nonce),
+                "event_nonce" => format!("{}",deposit.event_nonce),
+                "review_state" => format!("{:?}",state),
+                "erc20" => format!("{}",deposit.erc20),
+                "amount" => format!("{}",deposit.amount),
+                "block" => format!("{}",deposit.block_height),
             );
+            held_deposit = Some((deposit.event_nonce.clone(), deposit.block_height.clone()));
+            break;
+        }
+    }
+    let (deposits, withdraws, erc20_deploys, logic_calls) = match held_deposit {
+        Some((held_nonce, held_block)) => {
+            // the held deposit's block is checked again, like a block cut off by the event cap
+            let last_full_block = if held_block > 0u8.into() {
+                held_block - 1u8.into()
+            } else {
+                held_block
+            };
+            if last_full_block < latest_block {
+                latest_block = last_full_block;
+            }
+            (
+                deposits
+                    .into_iter()
+                    .filter(|e| e.event_nonce < held_nonce)
+                    .collect(),
+                withdraws
+                    .into_iter()
+                    .filter(|e| e.event_nonce < held_nonce)
+                    .collect(),
+                erc20_deploys
+                    .into_iter()
+                    .filter(|e| e.event_nonce < held_nonce)
+                    .collect(),
+                logic_calls
+                    .into_iter()
+                    .filter(|e| e.event_nonce < held_nonce)
+                    .collect(),
+            )
+        }
+        None => (deposits, withdraws, erc20_deploys, logic_calls),
+    };
+
+    let submitted: HashSet<Uint256> = deposits
+        .iter()
+        .map(|e| e.event_nonce.clone())
+        .chain(withdraws.iter().map(|e| e.event_nonce.clone()))
+        .chain(erc20_deploys.iter().map(|e| e.event_nonce.clone()))
+        .chain(logic_calls.iter().map(|e| e.event_nonce.clone()))
+        .collect();
+    // the nonces tie the trace to the ORACLE_OBSERVED_* and CLAIMS_PROCESSED logs
+    let mut event_nonces: Vec<&Uint256> = submitted.iter().collect();
+    event_nonces.sort();
+    let event_nonces: Vec<String> = event_nonces.iter().map(|n| n.to_string()).collect();
+    let event_nonces = event_nonces.join(",");
+    span.record("event_nonces", &event_nonces.as_str());
+
+    if !deposits.is_empty() {
+        info!(
+            "Oracle observed deposit with sender {}, destination {}, amount {}, and event nonce {}",
+            deposits[0].sender,
+            deposits[0].destination,
+            deposits[0].amount,
+            deposits[0].event_nonce
+        );
+        sinfo!(&logger, "ORACLE_OBSERVED_DEPOSIT";
+            "function" => "check_for_events()",
+            "last_nonce" => format!("{}",deposits[0].sender),
+            "destination" => format!("{}",deposits[0].destination),
+            "amount" => format!("{}",deposits[0].amount),
+            "event_nonce" => format!("{}",deposits[0].event_nonce),
+        );
+    }
+    if let Some(threshold) = alert_amount_threshold {
+        for deposit in deposits.iter() {
+            let decimals = token_metadata.get_decimals(web3, deposit.erc20).await;
+            if is_large_deposit(&deposit.amount, decimals, threshold) {
+                warn!(
+                    "Oracle observed large deposit of {} of ERC20 {} from {} to {} with event nonce {}",
+                    deposit.amount, deposit.erc20, deposit.sender, deposit.destination, deposit.event_nonce
+                );
+                swarn!(&logger, "ORACLE_OBSERVED_LARGE_DEPOSIT";
+                    "function" => "check_for_events()",
+                    "sender" => format!("{}",deposit.sender),
+                    "destination" => format!("{}",deposit.destination),
+                    "erc20" => format!("{}",deposit.erc20),
+                    "amount" => format!("{}",deposit.amount),
+                    "decimals" => format!("{}",decimals),
+                    "threshold" => format!("{}",threshold),
+                    "event_nonce" => format!("{}",deposit.event_nonce),
+                );
+            }
+        }
+    }
+    if !withdraws.is_empty() {
+        info!(
+            "Oracle observed batch with nonce {}, contract {}, and event nonce {}",
+            withdraws[0].batch_nonce, withdraws[0].erc20, withdraws[0].event_nonce
+        );
+        sinfo!(&logger, "ORACLE_OBSERVED_BATCH";
+            "function" => "check_for_events()",
+            "batch_nonce" => format!("{}",withdraws[0].batch_nonce),
+            "erc20" => format!("{}",withdraws[0].erc20),
+            "event_nonce" => format!("{}",withdraws[0].event_nonce),
+        );
+    }
+    if !erc20_deploys.is_empty() {
+        info!(
+            "Oracle observed ERC20 deployment with denom {} erc20 name {} and symbol {} and event nonce {}",
+            erc20_deploys[0].cosmos_denom, erc20_deploys[0].name, erc20_deploys[0].symbol, erc20_deploys[0].event_nonce,
+        );
+        sinfo!(&logger, "ORACLE_OBSERVED_ERC20_DEPLOYMENT";
+            "function" => "check_for_events()",
+            "cosmos_denom" => format!("{}",erc20_deploys[0].cosmos_denom),
+            "name" => format!("{}",erc20_deploys[0].name),
+            "symbol" => format!("{}",erc20_deploys[0].symbol),
+            "event_nonce" => format!("{}",erc20_deploys[0].event_nonce),
+        );
+    }
+    if !logic_calls.is_empty() {
+        info!(
+            "Oracle observed logic call execution with ID {} Nonce {} and event nonce {}",
+            bytes_to_hex_str(&logic_calls[0].invalidation_id),
+            logic_calls[0].invalidation_nonce,
+            logic_calls[0].event_nonce
+        );
+        sinfo!(&logger, "ORACLE_OBSERVED_LOGIC_CALL_EXECUTION";
+            "function" => "check_for_events()",
+            "invalidation_id" => format!("{}",bytes_to_hex_str(&logic_calls[0].invalidation_id)),
+            "invalidation_nonce" => format!("{}",logic_calls[0].invalidation_nonce),
+            "event_nonce" => format!("{}",logic_calls[0].event_nonce),
+        );
+    }
+
+    let claims = EthereumClaims {
+        deposits,
+        withdraws,
+        erc20_deploys,
+        logic_calls,
+    };
+    let mut held = false;
+    if !claims.is_empty() {
+        let first_block = claims.first_block();
+        let claim_count = claims.len();
+        let deposit_volumes: Vec<(EthAddress, Uint256)> = claims
+            .deposits
+            .iter()
+            .map(|d| (d.erc20, d.amount.clone()))
+            .collect();
+        let deposit_blocks: Vec<Uint256> = claims
+            .deposits
+            .iter()
+            .map(|d| d.block_height.clone())
+            .collect();
+        let submit_span = info_span!(
+            parent: span,
+            "send_ethereum_claims",
+            event_nonces = %event_nonces,
+            error = field::Empty,
+        );
+        let res = claim_sink.submit(claims).await;
+        if let Err(e) = &res {
+            submit_span.record("error", &field::display(e));
         }
-        if !withdraws.is_empty() {
+        drop(submit_span);
+        let res = res?;
+        if res.held {
+            // held claims are observed again on the next poll and submitted with whatever follows
+            // them, so the range only counts as processed up to the block before the first of them
+            held = true;
+            if let Some(first_block) = first_block {
+                let last_full_block = if first_block > 0u8.into() {
+                    first_block - 1u8.into()
+                } else {
+                    first_block
+                };
+                if last_full_block < latest_block {
+                    latest_block = last_full_block;
+                }
+            }
             info!(
-                "Oracle observed batch with nonce {}, contract {}, and event nonce {}",
-                withdraws[0].batch_nonce, withdraws[0].erc20, withdraws[0].event_nonce
+                "Holding {} claims until the claim window closes",
+                claim_count
             );
-            sinfo!(&LOGGING.logger, "ORACLE_OBSERVED_BATCH";
+            sinfo!(&logger, "CLAIMS_HELD";
                 "function" => "check_for_events()",
-                "batch_nonce" => format!("{}",withdraws[0].batch_nonce),
-                "erc20" => format!("{}",withdraws[0].erc20),
-                "event_nonce" => format!("{}",withdraws[0].event_nonce),
+                "claims" => format!("{}",claim_count),
+                "event_nonces" => event_nonces.clone(),
             );
-        }
-        if !erc20_deploys.is_empty() {
-            info!(
-                "Oracle observed ERC20 deployment with denom {} erc20 name {} and symbol {} and event nonce {}",
-                erc20_deploys[0].cosmos_denom, erc20_deploys[0].name, erc20_deploys[0].symbol, erc20_deploys[0].event_nonce,
-            );
-            sinfo!(&LOGGING.logger, "ORACLE_OBSERVED_ERC20_DEPLOYMENT";
+        } else {
+            let submitted_at = SystemTime::now();
+            // only count deposits once they've been claimed so the block overlap can't double count
+            for (erc20, amount) in deposit_volumes {
+                BRIDGED_VOLUME.inc_by(&erc20.to_string(), amount);
+            }
+            observe_claim_latency(web3, rate_limiter, &deposit_blocks, submitted_at).await;
+            info!("Claims processed, new nonce {}", res.last_event_nonce);
+            sinfo!(&logger, "CLAIMS_PROCESSED";
                 "function" => "check_for_events()",
-                "cosmos_denom" => format!("{}",erc20_deploys[0].cosmos_denom),
-                "name" => format!("{}",erc20_deploys[0].name),
-                "symbol" => format!("{}",erc20_deploys[0].symbol),
-                "event_nonce" => format!("{}",erc20_deploys[0].event_nonce),
+                "new_event_nonce" => format!("{}",res.last_event_nonce),
+                "accepted_deposits" => format!("{}",res.accepted.deposits),
+                "accepted_withdraws" => format!("{}",res.accepted.withdraws),
+                "accepted_erc20_deploys" => format!("{}",res.accepted.erc20_deploys),
+                "accepted_logic_calls" => format!("{}",res.accepted.logic_calls),
+                "gas_used" => res.gas_used.map(|g| g.to_string()).unwrap_or_default(),
+                "gas_wanted" => res.gas_wanted.map(|g| g.to_string()).unwrap_or_default(),
             );
         }
-        if !logic_calls.is_empty() {
+    }
+    let claimed = submitted.len();
+    // held claims were not submitted, they must not be skipped when they are observed again
+    if !held {
+        *previously_submitted = submitted;
+    }
+    let submit_time = submit_start.elapsed();
+    debug!(
+        "Oracle poll took {}ms fetching events, {}ms parsing, {}ms submitting claims",
+        fetch_time.as_millis(),
+        parse_time.as_millis(),
+        submit_time.as_millis()
+    );
+    sinfo!(&logger, "POLL_TIMING";
+        "function" => "check_for_events()",
+        "fetch_ms" => format!("{}",fetch_time.as_millis()),
+        "parse_ms" => format!("{}",parse_time.as_millis()),
+        "submit_ms" => format!("{}",submit_time.as_millis()),
+    );
+    Ok((latest_block, claimed, last_event_nonce))
+}
+
+/// Logs the deposits between `from_block` and `to_block`, which are too recent to claim, so that
+/// operators see incoming deposits before they reach the block delay. A deposit is logged on every
+/// poll until it is deep enough to claim. Failures are only logged, claiming never waits on this
+async fn observe_pending_deposits(
+    web3: &Web3,
+    peggy_contract_address: EthAddress,
+    from_block: Uint256,
+    to_block: Uint256,
+    latest_block: Uint256,
+) {
+    let logger = LOGGING.named_logger(ORACLE_LOGGER);
+    let deposits = match web3
+        .check_for_events(
+            from_block,
+            Some(to_block),
+            vec![peggy_contract_address],
+            vec![SEND_TO_COSMOS_EVENT_SIG],
+        )
+        .await
+        .map_err(PeggyError::from)
+        .and_then(|logs| SendToCosmosEvent::from_logs(&logs))
+    {
+        Ok(deposits) => deposits,
+        Err(e) => {
+            warn!("Failed to observe pending deposits {}", e);
+            return;
+        }
+    };
+    PENDING_DEPOSITS.set((deposits.len() as u64).into());
+    for deposit in deposits {
+        let confirmations = latest_block.clone() - deposit.block_height.clone();
+        sinfo!(&logger, "ORACLE_PENDING_DEPOSIT";
+            "function" => "observe_pending_deposits()",
+            "sender" => format!("{}",deposit.sender),
+            "destination" => format!("{}",deposit.destination),
+            "erc20" => format!("{}",deposit.erc20),
+            "amount" => format!("{}",deposit.amount),
+            "event_nonce" => format!("{}",deposit.event_nonce),
+            "confirmations" => format!("{}",confirmations),
+        );
+    }
+}
+
+/// Records the time from each deposit's block to the submission of its claim in
+/// DEPOSIT_CLAIM_LATENCY, every block's timestamp is fetched once no matter how many deposits it
+/// holds. Failures are only logged, the metric just misses those deposits
+async fn observe_claim_latency(
+    web3: &Web3,
+    rate_limiter: &mut RateLimiter,
+    deposit_blocks: &[Uint256],
+    submitted_at: SystemTime,
+) {
+    let submitted_at = match submitted_at.duration_since(UNIX_EPOCH) {
+        Ok(since_epoch) => since_epoch.as_secs_f64(),
+        Err(_) => return,
+    };
+    let mut timestamps: HashMap<Uint256, Uint256> = HashMap::new();
+    for block in deposit_blocks {
+        let timestamp = match timestamps.get(block) {
+            Some(timestamp) => timestamp.clone(),
+            None => {
+                rate_limiter.acquire().await;
+                match web3.eth_get_block_by_number(block.clone()).await {
+                    Ok(eth_block) => {
+                        timestamps.insert(block.clone(), eth_block.timestamp.clone());
+                        eth_block.timestamp
+                    }
+                    Err(e) => {
+                        warn!("Failed to get the timestamp of block {} {:?}", block, e);
+                        continue;
+                    }
+                }
+            }
+        };
+        if let Some(timestamp) = downcast_uint256(timestamp) {
+            // clocks drift, a block can appear to be from slightly in the future
+            DEPOSIT_CLAIM_LATENCY.observe((submitted_at - timestamp as f64).max(0.0));
+        }
+    }
+}
+
+/// Removes any events whose event nonce was submitted as a claim in the previous poll. Since
+/// our starting block overlaps with the last block we checked the same events can be observed
+/// twice, this guard holds even if the last event nonce on Cosmos has not caught up yet
+pub fn dedupe_events_across_polls<T: Clone>(
+    previously_submitted: &HashSet<Uint256>,
+    events: &[T],
+    event_nonce: impl Fn(&T) -> &Uint256,
+) -> Vec<T> {
+    let mut ret = Vec::new();
+    for event in events {
+        let nonce = event_nonce(event);
+        if previously_submitted.contains(nonce) {
             info!(
-                "Oracle observed logic call execution with ID {} Nonce {} and event nonce {}",
-                bytes_to_hex_str(&logic_calls[0].invalidation_id),
-                logic_calls[0].invalidation_nonce,
-                logic_calls[0].event_nonce
+                "Event nonce {} was already submitted last poll, not submitting it again",
+                nonce
             );
-            sinfo!(&LOGGING.logger, "ORACLE_OBSERVED_LOGIC_CALL_EXECUTION";
-                "function" => "check_for_events()",
-                "invalidation_id" => format!("{}",bytes_to_hex_str(&logic_calls[0].invalidation_id)),
-                "invalidation_nonce" => format!("{}",logic_calls[0].invalidation_nonce),
-                "event_nonce" => format!("{}",logic_calls[0].event_nonce),
+            sinfo!(&LOGGING.named_logger(ORACLE_LOGGER), "DUPLICATE_EVENT_FILTERED";
+                "function" => "dedupe_events_across_polls()",
+                "event_nonce" => format!("{}",nonce),
             );
+        } else {
+            ret.push(event.clone());
         }
+    }
+    ret
+}
 
-        if !deposits.is_empty()
-            || !withdraws.is_empty()
-            || !erc20_deploys.is_empty()
-            || !logic_calls.is_empty()
-        {
-            let res = send_ethereum_claims(
-                contact,
-                our_private_key,
-                deposits,
-                withdraws,
-                erc20_deploys,
-                logic_calls,
-                fee,
-            )
-            .await?;
-            trace!("Claims response {:?}", res);
-            let new_event_nonce = get_last_event_nonce(grpc_client, our_cosmos_address).await?;
-            // since we can't actually trust that the above txresponse is correct we have to check here
-            // we may be able to trust the tx response post grpc
-            if new_event_nonce == last_event_nonce {
-                return Err(PeggyError::InvalidBridgeStateError(
-                    format!("Claims did not process, trying to update but still on {}, trying again in a moment, check txhash {} for errors", last_event_nonce, res.txhash),
-                ));
-            } else {
-                info!("Claims processed, new nonce {}", new_event_nonce);
-                sinfo!(&LOGGING.logger, "CLAIMS_PROCESSED";
-                    "function" => "check_for_events()",
-                    "new_event_nonce" => format!("{}",new_event_nonce),
-                );
-            }
-        }
-        Ok(latest_block)
-    } else {
-        error!("Failed to get events");
-        Err(PeggyError::EthereumRestError(Web3Error::BadResponse(
-            "Failed to get logs!".to_string(),
-        )))
+/// Given the (event nonce, block height) of every event observed in a poll, returns the highest
+/// event nonce to process and the last block whose events are all processed if there are more
+/// than `max_events` events, or None if they can all be processed. When the cap falls in the middle
+/// of a block that block is not reported as processed, the events from it that we did claim are
+/// filtered out by event nonce when it is checked again.
+pub fn event_cap_cutoff(
+    mut events: Vec<(Uint256, Uint256)>,
+    max_events: usize,
+) -> Option<(Uint256, Uint256)> {
+    // we must always make progress
+    let max_events = max_events.max(1);
+    if events.len() <= max_events {
+        return None;
     }
+    events.sort();
+    let max_nonce = events[max_events - 1].0.clone();
+    let first_excluded_block = events[max_events].1.clone();
+    let last_full_block = if first_excluded_block > 0u8.into() {
+        first_excluded_block - 1u8.into()
+    } else {
+        first_excluded_block
+    };
+    Some((max_nonce, last_full_block))
 }
 
 /// The number of blocks behind the 'latest block' on Ethereum our event checking should be.
@@ -244,3 +1086,397 @@ pub async fn get_block_delay(web3: &Web3) -> Uint256 {
         _ => 6u8.into(),
     }
 }
+
+/// The block the next poll should start from, given the last block checked by the previous poll.
+/// The previous poll ended at last_checked_block + 1 (exclusive) and the next one starts
+/// block_overlap blocks before that, so the default overlap of 1 re-scans the last checked block
+pub fn next_starting_block(last_checked_block: &Uint256, block_overlap: u64) -> Uint256 {
+    let previous_end: Uint256 = last_checked_block.clone() + 1u8.into();
+    let overlap: Uint256 = block_overlap.into();
+    if overlap > previous_end {
+        0u8.into()
+    } else {
+        previous_end - overlap
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::claim_sink::InMemoryClaimSink;
+    use peggy_utils::claim_review::held_claims;
+
+    fn deposit(event_nonce: u64, block_height: u64) -> SendToCosmosEvent {
+        SendToCosmosEvent {
+            event_nonce: event_nonce.into(),
+            block_height: block_height.into(),
+            ..Default::default()
+        }
+    }
+
+    fn events(pairs: &[(u64, u64)]) -> Vec<(Uint256, Uint256)> {
+        pairs
+            .iter()
+            .map(|(nonce, block)| ((*nonce).into(), (*block).into()))
+            .collect()
+    }
+
+    #[test]
+    fn test_event_cap_cutoff() {
+        let observed = events(&[(3, 11), (1, 10), (2, 10), (4, 12)]);
+        assert_eq!(event_cap_cutoff(observed.clone(), 4), None);
+        assert_eq!(event_cap_cutoff(observed.clone(), 10), None);
+        // the cap lands exactly on a block boundary
+        assert_eq!(
+            event_cap_cutoff(observed.clone(), 2),
+            Some((2u8.into(), 10u8.into()))
+        );
+        // the cap splits block 10, so only block 9 is fully processed
+        assert_eq!(
+            event_cap_cutoff(observed.clone(), 1),
+            Some((1u8.into(), 9u8.into()))
+        );
+        // a cap of zero still makes progress
+        assert_eq!(
+            event_cap_cutoff(observed, 0),
+            Some((1u8.into(), 9u8.into()))
+        );
+    }
+
+    #[test]
+    fn test_block_chunk_end() {
+        let latest_block: Uint256 = 2_500u16.into();
+        assert_eq!(
+            block_chunk_end(&0u8.into(), &latest_block, 1_000),
+            999u16.into()
+        );
+        assert_eq!(
+            block_chunk_end(&2_000u16.into(), &latest_block, 1_000),
+            latest_block
+        );
+        // a range of zero still makes progress one block at a time
+        assert_eq!(block_chunk_end(&7u8.into(), &latest_block, 0), 7u8.into());
+    }
+
+    /// Serves one deposit per block and claims like claim_block_range, skipping what was
+    /// submitted before and stopping at the event cap
+    struct MockSteps {
+        latest_block: u64,
+        fail_fetch_from: Option<u64>,
+        fetched: Vec<(u64, u64)>,
+    }
+
+    #[async_trait(?Send)]
+    impl BlockRangeSteps for MockSteps {
+        async fn fetch(
+            &mut self,
+            start: &Uint256,
+            end: &Uint256,
+            _span: &Span,
+        ) -> Result<ObservedEvents, PeggyError> {
+            let (start, end) = (
+                downcast_uint256(start.clone()).unwrap(),
+                downcast_uint256(end.clone()).unwrap(),
+            );
+            assert!(end <= self.latest_block);
+            self.fetched.push((start, end));
+            if self.fail_fetch_from == Some(start) {
+                return Err(PeggyError::RpcUnavailable {
+                    endpoint: "Ethereum RPC".to_string(),
+                    cause: "Failed to get logs!".to_string(),
+                });
+            }
+            Ok(ObservedEvents {
+                deposits: (start..=end).map(|block| deposit(block, block)).collect(),
+                withdraws: Vec::new(),
+                erc20_deploys: Vec::new(),
+                logic_calls: Vec::new(),
+                fetch_time: Duration::default(),
+                parse_time: Duration::default(),
+            })
+        }
+
+        async fn claim(
+            &mut self,
+            _index: usize,
+            events: &ObservedEvents,
+            end: Uint256,
+            previously_submitted: &mut HashSet<Uint256>,
+            max_events: Option<usize>,
+            _span: &Span,
+        ) -> Result<(Uint256, usize, u64), PeggyError> {
+            let mut deposits =
+                dedupe_events_across_polls(previously_submitted, &events.deposits, |e| {
+                    &e.event_nonce
+                });
+            let mut checked = end;
+            if let Some(max_events) = max_events {
+                let observed = deposits
+                    .iter()
+                    .map(|e| (e.event_nonce.clone(), e.block_height.clone()))
+                    .collect();
+                if let Some((max_nonce, last_full_block)) = event_cap_cutoff(observed, max_events) {
+                    deposits.retain(|e| e.event_nonce <= max_nonce);
+                    checked = last_full_block;
+                }
+            }
+            *previously_submitted = deposits.iter().map(|e| e.event_nonce.clone()).collect();
+            Ok((checked, deposits.len(), 0))
+        }
+    }
+
+    fn nonces(range: std::ops::RangeInclusive<u64>) -> HashSet<Uint256> {
+        range.map(|nonce| nonce.into()).collect()
+    }
+
+    async fn check_mock_range(
+        steps: &mut MockSteps,
+        previously_submitted: &mut HashSet<Uint256>,
+        max_events: Option<usize>,
+    ) -> Result<Uint256, PeggyError> {
+        let latest_block = steps.latest_block.into();
+        check_block_range(
+            steps,
+            &mut [previously_submitted],
+            1u8.into(),
+            latest_block,
+            Some(3),
+            max_events,
+            &Span::none(),
+        )
+        .await
+    }
+
+    #[actix_rt::test]
+    async fn test_check_block_range_chunks() {
+        let mut steps = MockSteps {
+            latest_block: 10,
+            fail_fetch_from: None,
+            fetched: Vec::new(),
+        };
+        let mut previously_submitted = HashSet::new();
+        let checked = check_mock_range(&mut steps, &mut previously_submitted, None).await;
+        assert_eq!(checked.unwrap(), 10u8.into());
+        assert_eq!(steps.fetched, vec![(1, 3), (4, 6), (7, 9), (10, 10)]);
+        // the next poll skips everything claimed in any chunk
+        assert_eq!(previously_submitted, nonces(1..=10));
+    }
+
+    #[actix_rt::test]
+    async fn test_check_block_range_caps_events_per_poll() {
+        let mut steps = MockSteps {
+            latest_block: 10,
+            fail_fetch_from: None,
+            fetched: Vec::new(),
+        };
+        let mut previously_submitted = HashSet::new();
+        let checked = check_mock_range(&mut steps, &mut previously_submitted, Some(4)).await;
+        // three events in the first chunk leave one for the second
+        assert_eq!(checked.unwrap(), 4u8.into());
+        assert_eq!(steps.fetched, vec![(1, 3), (4, 6)]);
+        assert_eq!(previously_submitted, nonces(1..=4));
+
+        // a cap used up exactly at the end of a chunk stops before the next one is fetched
+        steps.fetched.clear();
+        let mut previously_submitted = HashSet::new();
+        let checked = check_mock_range(&mut steps, &mut previously_submitted, Some(3)).await;
+        assert_eq!(checked.unwrap(), 3u8.into());
+        assert_eq!(steps.fetched, vec![(1, 3)]);
+    }
+
+    #[actix_rt::test]
+    async fn test_check_block_range_fetch_error_keeps_submitted() {
+        let mut steps = MockSteps {
+            latest_block: 10,
+            fail_fetch_from: Some(7),
+            fetched: Vec::new(),
+        };
+        let mut previously_submitted = HashSet::new();
+        let checked = check_mock_range(&mut steps, &mut previously_submitted, None).await;
+        assert!(checked.is_err());
+        // the chunks claimed before the failure are skipped when the range is checked again
+        assert_eq!(previously_submitted, nonces(1..=6));
+    }
+
+    /// Claims every identity's deposits through its own in memory sink, filtered by the last
+    /// event nonce Cosmos has processed for that identity
+    struct SinkSteps {
+        deposits: Vec<SendToCosmosEvent>,
+        last_event_nonces: Vec<u64>,
+        sinks: Vec<InMemoryClaimSink>,
+    }
+
+    #[async_trait(?Send)]
+    impl BlockRangeSteps for SinkSteps {
+        async fn fetch(
+            &mut self,
+            _start: &Uint256,
+            _end: &Uint256,
+            _span: &Span,
+        ) -> Result<ObservedEvents, PeggyError> {
+            Ok(ObservedEvents {
+                deposits: self.deposits.clone(),
+                withdraws: Vec::new(),
+                erc20_deploys: Vec::new(),
+                logic_calls: Vec::new(),
+                fetch_time: Duration::default(),
+                parse_time: Duration::default(),
+            })
+        }
+
+        async fn claim(
+            &mut self,
+            index: usize,
+            events: &ObservedEvents,
+            end: Uint256,
+            previously_submitted: &mut HashSet<Uint256>,
+            _max_events: Option<usize>,
+            _span: &Span,
+        ) -> Result<(Uint256, usize, u64), PeggyError> {
+            let deposits = SendToCosmosEvent::filter_by_event_nonce(
+                self.last_event_nonces[index],
+                &events.deposits,
+            );
+            let deposits =
+                dedupe_events_across_polls(previously_submitted, &deposits, |e| &e.event_nonce);
+            *previously_submitted = deposits.iter().map(|e| e.event_nonce.clone()).collect();
+            let claimed = deposits.len();
+            if claimed > 0 {
+                self.sinks[index]
+                    .submit(EthereumClaims {
+                        deposits,
+                        ..Default::default()
+                    })
+                    .await?;
+            }
+            Ok((end, claimed, self.last_event_nonces[index]))
+        }
+    }
+
+    fn submitted_nonces(sink: &InMemoryClaimSink) -> Vec<Vec<u64>> {
+        sink.submitted
+            .iter()
+            .map(|claims| {
+                claims
+                    .deposits
+                    .iter()
+                    .map(|e| downcast_uint256(e.event_nonce.clone()).unwrap())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[actix_rt::test]
+    async fn test_check_block_range_dedupes_per_identity() {
+        // the second identity's claims for the first two deposits have already been processed
+        let mut steps = SinkSteps {
+            deposits: vec![deposit(1, 1), deposit(2, 2), deposit(3, 3)],
+            last_event_nonces: vec![0, 2],
+            sinks: vec![InMemoryClaimSink::default(), InMemoryClaimSink::default()],
+        };
+        let (mut first, mut second) = (HashSet::new(), HashSet::new());
+        let checked = check_block_range(
+            &mut steps,
+            &mut [&mut first, &mut second],
+            1u8.into(),
+            3u8.into(),
+            None,
+            None,
+            &Span::none(),
+        )
+        .await;
+        assert_eq!(checked.unwrap(), 3u8.into());
+        assert_eq!(submitted_nonces(&steps.sinks[0]), vec![vec![1, 2, 3]]);
+        assert_eq!(submitted_nonces(&steps.sinks[1]), vec![vec![3]]);
+        assert_eq!(first, nonces(1..=3));
+        assert_eq!(second, nonces(3..=3));
+
+        // Cosmos hasn't caught up yet when the next poll re-scans the range and sees a new
+        // deposit, each identity only skips what it submitted itself
+        steps.deposits.push(deposit(4, 4));
+        let checked = check_block_range(
+            &mut steps,
+            &mut [&mut first, &mut second],
+            3u8.into(),
+            4u8.into(),
+            None,
+            None,
+            &Span::none(),
+        )
+        .await;
+        assert_eq!(checked.unwrap(), 4u8.into());
+        assert_eq!(
+            submitted_nonces(&steps.sinks[0]),
+            vec![vec![1, 2, 3], vec![4]]
+        );
+        assert_eq!(submitted_nonces(&steps.sinks[1]), vec![vec![3], vec![4]]);
+    }
+
+    #[actix_rt::test]
+    async fn test_check_block_range_keeps_reviews_a_lagging_identity_needs() {
+        // the first identity is past the held deposit, the second still has to claim it
+        request_review(101, "processed by both".to_string());
+        request_review(103, "needed by the second".to_string());
+        let mut steps = SinkSteps {
+            deposits: Vec::new(),
+            last_event_nonces: vec![104, 102],
+            sinks: vec![InMemoryClaimSink::default(), InMemoryClaimSink::default()],
+        };
+        let (mut first, mut second) = (HashSet::new(), HashSet::new());
+        let checked = check_block_range(
+            &mut steps,
+            &mut [&mut first, &mut second],
+            1u8.into(),
+            3u8.into(),
+            None,
+            None,
+            &Span::none(),
+        )
+        .await;
+        assert!(checked.is_ok());
+        let held: Vec<u64> = held_claims().iter().map(|(nonce, _, _)| *nonce).collect();
+        assert!(!held.contains(&101));
+        assert!(held.contains(&103));
+    }
+
+    #[test]
+    fn test_next_starting_block() {
+        let last_checked_block: Uint256 = 100u8.into();
+        let previous_end: Uint256 = last_checked_block.clone() + 1u8.into();
+        for overlap in 0..5u64 {
+            assert_eq!(
+                next_starting_block(&last_checked_block, overlap),
+                previous_end.clone() - overlap.into()
+            );
+        }
+        assert_eq!(
+            next_starting_block(&last_checked_block, 1),
+            last_checked_block
+        );
+        assert_eq!(next_starting_block(&3u8.into(), 10), 0u8.into());
+    }
+
+    /// Simulates the oracle polling twice over an overlapping block range where the
+    /// last event nonce on Cosmos has not yet caught up with the claims from the first poll
+    #[test]
+    fn test_dedupe_events_across_polls() {
+        let mut previously_submitted = HashSet::new();
+        let last_event_nonce = 0;
+
+        // first poll observes two deposits in block 100
+        let observed = vec![deposit(1, 100), deposit(2, 100)];
+        let filtered = SendToCosmosEvent::filter_by_event_nonce(last_event_nonce, &observed);
+        let first =
+            dedupe_events_across_polls(&previously_submitted, &filtered, |e| &e.event_nonce);
+        assert_eq!(first, observed);
+        previously_submitted = first.iter().map(|e| e.event_nonce.clone()).collect();
+
+        // second poll starts at block 100 again and also sees a new deposit in block 101
+        let observed = vec![deposit(1, 100), deposit(2, 100), deposit(3, 101)];
+        let filtered = SendToCosmosEvent::filter_by_event_nonce(last_event_nonce, &observed);
+        assert_eq!(filtered.len(), 3);
+        let second =
+            dedupe_events_across_polls(&previously_submitted, &filtered, |e| &e.event_nonce);
+        assert_eq!(second, vec![deposit(3, 101)]);
+    }
+}
@@ -0,0 +1,216 @@
+//! An alternative to polling for Ethereum events, for providers that support `eth_subscribe` we
+//! open a WebSocket log subscription filtered to the Peggy contract and only check for events
+//! when the node tells us a Peggy log has been emitted. Claiming itself is still performed by
+//! check_for_events so the block delay, nonce filtering and deduplication all still apply.
+//!
+//! The subscription runs in its own task and hands log block numbers to the oracle loop, which
+//! keeps running its heartbeat and state snapshot between notifications and polls on its own
+//! while the subscription is down.
+
+use crate::ethereum_event_watcher::get_block_delay;
+use awc::ws::{Frame, Message};
+use clarity::{Address as EthAddress, Uint256};
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::{SinkExt, StreamExt};
+use json_logger::LOGGING;
+use peggy_utils::error::PeggyError;
+use peggy_utils::event_signatures::{
+    event_topic, ERC20_DEPLOYED_EVENT_SIG, LEGACY_VALSET_UPDATED_EVENT_SIG, LOGIC_CALL_EVENT_SIG,
+    SEND_TO_COSMOS_EVENT_SIG, TRANSACTION_BATCH_EXECUTED_EVENT_SIG, VALSET_UPDATED_EVENT_SIG,
+};
+use serde_json::{json, Value};
+use slog::info as sinfo;
+use std::time::Duration;
+use tokio::time::timeout;
+use web30::client::Web3;
+
+/// How long the oracle polls after a subscription fails before subscribing again
+pub const RESUBSCRIBE_INTERVAL: Duration = Duration::from_secs(300);
+
+fn rpc_unavailable(ws_url: &str, cause: String) -> PeggyError {
+    PeggyError::RpcUnavailable {
+        endpoint: format!("Ethereum WebSocket {}", ws_url),
+        cause,
+    }
+}
+
+fn peggy_topics() -> Vec<String> {
+    vec![
+        SEND_TO_COSMOS_EVENT_SIG,
+        TRANSACTION_BATCH_EXECUTED_EVENT_SIG,
+        VALSET_UPDATED_EVENT_SIG,
+        LEGACY_VALSET_UPDATED_EVENT_SIG,
+        ERC20_DEPLOYED_EVENT_SIG,
+        LOGIC_CALL_EVENT_SIG,
+    ]
+    .into_iter()
+    .map(|sig| format!("0x{}", clarity::utils::bytes_to_hex_str(&event_topic(sig))))
+    .collect()
+}
+
+/// Returns the block number of a Peggy log delivered by an eth_subscription notification,
+/// None if the message is anything else or the log was removed by a reorg
+fn parse_log_notification(message: &Value) -> Option<Uint256> {
+    if message["method"] != "eth_subscription" {
+        return None;
+    }
+    let log = &message["params"]["result"];
+    if log["removed"].as_bool().unwrap_or(false) {
+        return None;
+    }
+    let block = log["blockNumber"].as_str()?;
+    let block = u64::from_str_radix(block.trim_start_matches("0x"), 16).ok()?;
+    Some(block.into())
+}
+
+/// A Peggy log subscription running in its own task, see subscribe_to_events
+pub struct EventSubscription {
+    ws_url: String,
+    receiver: UnboundedReceiver<Result<Uint256, PeggyError>>,
+}
+
+/// Opens a Peggy log subscription over `ws_url` in a background task, failing to connect is
+/// reported by the first call to next_log_block
+pub fn subscribe_to_events(ws_url: &str, peggy_contract_address: EthAddress) -> EventSubscription {
+    let (sender, receiver) = unbounded();
+    actix_rt::spawn(run_subscription(
+        ws_url.to_string(),
+        peggy_contract_address,
+        sender,
+    ));
+    EventSubscription {
+        ws_url: ws_url.to_string(),
+        receiver,
+    }
+}
+
+impl EventSubscription {
+    /// Waits up to `wait` for a Peggy log notification and returns its block, None if there
+    /// was none. An error means the subscription has ended and should be dropped
+    pub async fn next_log_block(&mut self, wait: Duration) -> Result<Option<Uint256>, PeggyError> {
+        match timeout(wait, self.receiver.next()).await {
+            Err(_) => Ok(None),
+            Ok(Some(Ok(log_block))) => Ok(Some(log_block)),
+            Ok(Some(Err(e))) => Err(e),
+            Ok(None) => Err(rpc_unavailable(
+                &self.ws_url,
+                "subscription closed".to_string(),
+            )),
+        }
+    }
+}
+
+/// Whether a log in `log_block` has passed the block delay and can be claimed, exactly as
+/// when polling
+pub async fn log_block_is_final(web3: &Web3, log_block: &Uint256) -> bool {
+    let block_delay = get_block_delay(web3).await;
+    match web3.eth_block_number().await {
+        Ok(latest) => latest >= log_block.clone() + block_delay,
+        Err(e) => {
+            warn!("Failed to get latest Ethereum block {:?}", e);
+            false
+        }
+    }
+}
+
+async fn run_subscription(
+    ws_url: String,
+    peggy_contract_address: EthAddress,
+    sender: UnboundedSender<Result<Uint256, PeggyError>>,
+) {
+    let e = forward_log_blocks(&ws_url, peggy_contract_address, &sender).await;
+    // the receiver may already be gone, in which case nobody is waiting on the error
+    let _ = sender.unbounded_send(Err(e));
+}
+
+/// Sends the block of every Peggy log notification to `sender`, only returning once the
+/// subscription fails
+async fn forward_log_blocks(
+    ws_url: &str,
+    peggy_contract_address: EthAddress,
+    sender: &UnboundedSender<Result<Uint256, PeggyError>>,
+) -> PeggyError {
+    let (_response, mut connection) = match awc::Client::new().ws(ws_url).connect().await {
+        Ok(v) => v,
+        Err(e) => return rpc_unavailable(ws_url, format!("{:?}", e)),
+    };
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "eth_subscribe",
+        "params": ["logs", {
+            "address": peggy_contract_address.to_string(),
+            "topics": [peggy_topics()],
+        }],
+    });
+    if let Err(e) = connection.send(Message::Text(request.to_string())).await {
+        return rpc_unavailable(ws_url, format!("{:?}", e));
+    }
+    info!("Subscribed to Peggy events over {}", ws_url);
+    sinfo!(&LOGGING.logger, "SUBSCRIBED_TO_PEGGY_EVENTS";
+        "function" => "forward_log_blocks()",
+        "ws_url" => format!("{}",ws_url),
+    );
+
+    loop {
+        let frame = match connection.next().await {
+            Some(Ok(frame)) => frame,
+            Some(Err(e)) => return rpc_unavailable(ws_url, format!("{:?}", e)),
+            None => return rpc_unavailable(ws_url, "subscription closed".to_string()),
+        };
+        match frame {
+            Frame::Text(text) => {
+                let message: Value = match serde_json::from_slice(&text) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        warn!("Invalid subscription message {:?}", e);
+                        continue;
+                    }
+                };
+                if message["id"] == 1 && message.get("error").is_some() {
+                    return rpc_unavailable(ws_url, message["error"].to_string());
+                }
+                if let Some(log_block) = parse_log_notification(&message) {
+                    trace!("Peggy log notification for block {}", log_block);
+                    if sender.unbounded_send(Ok(log_block)).is_err() {
+                        return rpc_unavailable(ws_url, "subscription dropped".to_string());
+                    }
+                }
+            }
+            Frame::Ping(data) => {
+                if let Err(e) = connection.send(Message::Pong(data)).await {
+                    return rpc_unavailable(ws_url, format!("{:?}", e));
+                }
+            }
+            Frame::Close(reason) => {
+                return rpc_unavailable(ws_url, format!("subscription closed {:?}", reason))
+            }
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn test_parse_log_notification() {
+    let notification = json!({
+        "jsonrpc": "2.0",
+        "method": "eth_subscription",
+        "params": {
+            "subscription": "0x9ce59a13059e417087c02d3236a0b1cc",
+            "result": {
+                "blockNumber": "0x1b4",
+                "removed": false,
+            },
+        },
+    });
+    assert_eq!(parse_log_notification(&notification), Some(436u16.into()));
+
+    let removed = json!({
+        "method": "eth_subscription",
+        "params": {"result": {"blockNumber": "0x1b4", "removed": true}},
+    });
+    assert_eq!(parse_log_notification(&removed), None);
+
+    let response = json!({"jsonrpc": "2.0", "id": 1, "result": "0x9ce5"});
+    assert_eq!(parse_log_notification(&response), None);
+}
@@ -0,0 +1,84 @@
+//! Decides when the oracle logs an ORACLE_HEARTBEAT. A bridge can go a long time without events,
+//! and without a heartbeat a quiet oracle's log looks the same as a dead one.
+
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct Heartbeat {
+    every_polls: Option<u64>,
+    every: Option<Duration>,
+    quiet_polls: u64,
+    last_beat: Instant,
+}
+
+impl Heartbeat {
+    /// A heartbeat due after `every_polls` polls without events or `every` time without events,
+    /// whichever comes first. With neither set it is never due
+    pub fn new(every_polls: Option<u64>, every: Option<Duration>) -> Self {
+        Heartbeat {
+            every_polls,
+            every,
+            quiet_polls: 0,
+            last_beat: Instant::now(),
+        }
+    }
+
+    /// Records a poll that processed events, the claims logged for it show the oracle is alive
+    pub fn active_poll(&mut self) {
+        self.quiet_polls = 0;
+        self.last_beat = Instant::now();
+    }
+
+    /// Records a poll that found no events, returns the number of quiet polls since the last
+    /// heartbeat if one is now due
+    pub fn quiet_poll(&mut self) -> Option<u64> {
+        self.quiet_poll_at(Instant::now())
+    }
+
+    fn quiet_poll_at(&mut self, now: Instant) -> Option<u64> {
+        self.quiet_polls += 1;
+        let polls_due = self.every_polls.map_or(false, |n| self.quiet_polls >= n);
+        let time_due = self
+            .every
+            .map_or(false, |every| now.duration_since(self.last_beat) >= every);
+        if polls_due || time_due {
+            let quiet_polls = self.quiet_polls;
+            self.quiet_polls = 0;
+            self.last_beat = now;
+            Some(quiet_polls)
+        } else {
+            None
+        }
+    }
+}
+
+#[test]
+fn test_heartbeat() {
+    let mut heartbeat = Heartbeat::new(Some(3), None);
+    assert_eq!(heartbeat.quiet_poll(), None);
+    assert_eq!(heartbeat.quiet_poll(), None);
+    assert_eq!(heartbeat.quiet_poll(), Some(3));
+    assert_eq!(heartbeat.quiet_poll(), None);
+    heartbeat.active_poll();
+    assert_eq!(heartbeat.quiet_poll(), None);
+    assert_eq!(heartbeat.quiet_poll(), None);
+    assert_eq!(heartbeat.quiet_poll(), Some(3));
+
+    let mut heartbeat = Heartbeat::new(None, Some(Duration::from_secs(60)));
+    let start = heartbeat.last_beat;
+    assert_eq!(
+        heartbeat.quiet_poll_at(start + Duration::from_secs(30)),
+        None
+    );
+    assert_eq!(
+        heartbeat.quiet_poll_at(start + Duration::from_secs(61)),
+        Some(2)
+    );
+    assert_eq!(
+        heartbeat.quiet_poll_at(start + Duration::from_secs(62)),
+        None
+    );
+
+    let mut never = Heartbeat::new(None, None);
+    assert_eq!(never.quiet_poll(), None);
+}
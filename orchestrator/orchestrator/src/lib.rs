@@ -1,7 +1,18 @@
 #[macro_use]
 extern crate log;
+#[macro_use]
+extern crate serde_derive;
 
+pub mod batch_hash_report;
+pub mod checkpoint;
+pub mod claim_sink;
 pub mod ethereum_event_watcher;
+pub mod event_subscription;
 pub mod get_with_retry;
+pub mod heartbeat;
 pub mod main_loop;
 pub mod oracle_resync;
+pub mod rate_limit;
+pub mod self_test;
+pub mod state_snapshot;
+pub mod trace_export;
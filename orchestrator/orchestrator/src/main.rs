@@ -16,51 +16,203 @@ extern crate lazy_static;
 #[macro_use]
 extern crate log;
 
+mod batch_hash_report;
+mod checkpoint;
+mod claim_sink;
 mod ethereum_event_watcher;
+mod event_subscription;
 mod get_with_retry;
+mod heartbeat;
 mod main_loop;
 mod oracle_resync;
+mod rate_limit;
+mod self_test;
+mod state_snapshot;
+mod trace_export;
 
+use crate::batch_hash_report::batch_hash_report;
+use crate::claim_sink::StdoutClaimSink;
+use crate::ethereum_event_watcher::check_for_events;
+use crate::get_with_retry::get_net_version_with_retry;
 use crate::main_loop::orchestrator_main_loop;
+use crate::rate_limit::RateLimiter;
+use crate::self_test::run_self_test;
 use clarity::Address as EthAddress;
 use clarity::PrivateKey as EthPrivateKey;
+use deep_space::address::Address as CosmosAddress;
 use deep_space::private_key::PrivateKey as CosmosPrivateKey;
 use docopt::Docopt;
 use env_logger::Env;
+use ethereum_peggy::bytecode::{parse_bytecode_hash, verify_contract_bytecode};
+use ethereum_peggy::token_metadata::TokenMetadataCache;
 use main_loop::{ETH_ORACLE_LOOP_SPEED, ETH_SIGNER_LOOP_SPEED};
 use peggy_utils::connection_prep::{
     check_delegate_addresses, check_for_eth, wait_for_cosmos_node_ready,
 };
-use peggy_utils::connection_prep::{check_for_fee_denom, create_rpc_connections};
+use peggy_utils::connection_prep::{
+    check_for_fee_denom, create_rpc_connections_with_grpc_config, GrpcChannelConfig,
+};
+use peggy_utils::keystore::load_keystore;
+use peggy_utils::rpc_headers::load_eth_rpc_headers;
+use peggy_utils::types::{
+    parse_logic_call_gas_stipend, parse_token_eth_price, validate_gas_multiplier,
+    validate_gas_price_smoothing, OrchestratorConfig, RelayerConfig,
+};
 use relayer::main_loop::LOOP_SPEED as RELAYER_LOOP_SPEED;
 use std::cmp::min;
+use std::collections::HashSet;
+use std::time::Duration;
+use web30::client::Web3;
 
 #[derive(Debug, Deserialize)]
 struct Args {
     flag_cosmos_phrase: String,
-    flag_ethereum_key: String,
+    flag_additional_cosmos_phrase: Vec<String>,
+    flag_ethereum_key: Option<String>,
+    flag_ethereum_keystore: Option<String>,
     flag_cosmos_legacy_rpc: String,
     flag_cosmos_grpc: String,
     flag_ethereum_rpc: String,
     flag_contract_address: String,
     flag_fees: String,
+    flag_gas_limit_multiplier: Option<f64>,
+    flag_gas_oracle_url: Option<String>,
+    flag_gas_price_tier: Option<String>,
+    flag_gas_price_smoothing: Option<f64>,
+    flag_eth_balance_reserve: Option<String>,
+    flag_batch_strategy: Option<String>,
+    flag_logic_call_gas_stipend: Vec<String>,
+    flag_eth_rpc_rate_limit: Option<f64>,
+    flag_checkpoint_file: Option<String>,
+    flag_max_events_per_poll: Option<usize>,
+    flag_ethereum_ws_rpc: Option<String>,
+    flag_block_overlap: Option<u64>,
+    flag_alert_amount_threshold: Option<u64>,
+    flag_claim_confirm_delay: Option<u64>,
+    flag_grpc_connect_timeout: Option<u64>,
+    flag_grpc_request_timeout: Option<u64>,
+    flag_control_socket: Option<String>,
+    flag_estimate_gas_cap: Option<String>,
+    flag_halt_on_valset_divergence: bool,
+    flag_max_batch_age: Option<u64>,
+    flag_price_feed_url: Option<String>,
+    flag_token_eth_price: Vec<String>,
+    flag_only_profitable_batches: bool,
+    flag_claim_inclusion_timeout: Option<u64>,
+    flag_claim_gas_multiplier: Option<f64>,
+    flag_events_report: bool,
+    flag_from_block: Option<u64>,
+    flag_to_block: Option<u64>,
+    flag_valset_power_drift: Option<f32>,
+    flag_contract_code_hash: Option<String>,
+    flag_recovery_log: Option<String>,
+    flag_recovery_log_max_bytes: Option<u64>,
+    flag_upgrade_event: Option<String>,
+    flag_review_amount_threshold: Option<u64>,
+    flag_observe_depth: Option<u64>,
+    flag_batch_max_gas_price: Option<String>,
+    flag_state_snapshot: Option<String>,
+    flag_heartbeat_polls: Option<u64>,
+    flag_heartbeat_secs: Option<u64>,
+    flag_valset_submit_retries: Option<u32>,
+    flag_allow_token: Vec<String>,
+    flag_deny_token: Vec<String>,
+    flag_batch_reconcile_interval: Option<u64>,
+    flag_otlp_endpoint: Option<String>,
+    flag_batch_hash_report: bool,
+    flag_batch_token: Option<String>,
+    flag_batch_nonce: Option<u64>,
+    flag_max_block_range: Option<u64>,
+    flag_cosmos_prefix: Option<String>,
+    flag_warn_on_nonce_regression: bool,
+    flag_valset_submission_key: Option<String>,
+    flag_relay_priority: Option<String>,
+    flag_claim_window: Option<u64>,
+    flag_claim_window_max_claims: Option<usize>,
+    flag_gas_estimate_failure: Option<String>,
+    flag_gas_estimate_retries: Option<u32>,
+    flag_batch_fallback_gas: Option<String>,
+    flag_metrics_listen: Option<String>,
+    cmd_self_test: bool,
 }
 
 lazy_static! {
     pub static ref USAGE: String = format!(
-    "Usage: {} --cosmos-phrase=<key> --ethereum-key=<key> --cosmos-legacy-rpc=<url> --cosmos-grpc=<url> --ethereum-rpc=<url> --fees=<denom> --contract-address=<addr>
+    "Usage: {} [self-test] --cosmos-phrase=<key> (--ethereum-key=<key> | --ethereum-keystore=<path>) --cosmos-legacy-rpc=<url> --cosmos-grpc=<url> --ethereum-rpc=<url> --fees=<denom> --contract-address=<addr> [--gas-limit-multiplier=<mult>] [--gas-oracle-url=<url>] [--gas-price-tier=<tier>] [--gas-price-smoothing=<alpha>] [--eth-balance-reserve=<wei>] [--batch-strategy=<strategy>] [--logic-call-gas-stipend=<stipend>...] [--eth-rpc-rate-limit=<rps>] [--checkpoint-file=<path>] [--max-events-per-poll=<n>] [--ethereum-ws-rpc=<url>] [--block-overlap=<n>] [--alert-amount-threshold=<tokens>] [--claim-confirm-delay=<ms>] [--grpc-connect-timeout=<secs>] [--grpc-request-timeout=<secs>] [--control-socket=<path>] [--estimate-gas-cap=<gas>] [--halt-on-valset-divergence] [--max-batch-age=<blocks>] [--price-feed-url=<url>] [--token-eth-price=<price>...] [--only-profitable-batches] [--claim-inclusion-timeout=<secs>] [--claim-gas-multiplier=<mult>] [--events-report --from-block=<block> --to-block=<block>] [--valset-power-drift=<fraction>] [--contract-code-hash=<hash>] [--recovery-log=<path>] [--recovery-log-max-bytes=<n>] [--upgrade-event=<signature>] [--review-amount-threshold=<tokens>] [--observe-depth=<blocks>] [--batch-max-gas-price=<wei>] [--state-snapshot=<path>] [--heartbeat-polls=<n>] [--heartbeat-secs=<secs>] [--valset-submit-retries=<n>] [--allow-token=<addr>...] [--deny-token=<addr>...] [--batch-reconcile-interval=<secs>] [--otlp-endpoint=<url>] [--batch-hash-report --batch-token=<addr> --batch-nonce=<nonce>] [--max-block-range=<blocks>] [--cosmos-prefix=<prefix>] [--warn-on-nonce-regression] [--valset-submission-key=<key>] [--relay-priority=<priority>] [--claim-window=<secs>] [--claim-window-max-claims=<n>] [--gas-estimate-failure=<policy>] [--gas-estimate-retries=<n>] [--batch-fallback-gas=<gas>] [--metrics-listen=<addr>] [--additional-cosmos-phrase=<key>...]
         Options:
             -h --help                    Show this screen.
             --cosmos-key=<ckey>          The Cosmos private key of the validator
             --ethereum-key=<ekey>        The Ethereum private key of the validator
+            --ethereum-keystore=<path>   A V3 keystore file holding the Ethereum key, the passphrase is read from GRAVITY_KEYSTORE_PASSPHRASE or prompted for
             --cosmos-legacy-rpc=<curl>   The Cosmos RPC url, usually the validator
             --cosmos-grpc=<gurl>         The Cosmos gRPC url, usually the validator
             --ethereum-rpc=<eurl>        The Ethereum RPC url, should be a self hosted node
             --fees=<denom>               The Cosmos Denom in which to pay Cosmos chain fees
             --contract-address=<addr>    The Ethereum contract address for Peggy, this is temporary
+            --gas-limit-multiplier=<mult> Multiplier applied to Ethereum gas estimates, defaults to 1.2
+            --gas-oracle-url=<url>       An ethgasstation compatible gas price oracle, defaults to the node gas price
+            --gas-price-tier=<tier>      The oracle gas price tier to pay, one of fast, standard, or slow
+            --gas-price-smoothing=<alpha> Weight of new samples in the gas price moving average, defaults to 0.2
+            --eth-balance-reserve=<wei>  ETH to always keep in the relayer account, defaults to 0.05 ETH
+            --batch-strategy=<strategy>  Which ready batch to relay, one of oldest, highest-fee, or highest-fee-density
+            --logic-call-gas-stipend=<stipend>  A fixed gas limit for calls to a logic contract, as <address>=<gas>
+            --eth-rpc-rate-limit=<rps>   Maximum Ethereum RPC requests per second made by the oracle, unlimited by default
+            --checkpoint-file=<path>     File used to remember the Peggy contract deploy block between restarts
+            --max-events-per-poll=<n>    Maximum number of Ethereum events the oracle claims at once, unlimited by default
+            --ethereum-ws-rpc=<url>      An Ethereum WebSocket RPC url, the oracle subscribes to events instead of polling when set
+            --block-overlap=<n>          Blocks re-scanned from the end of the previous oracle poll, defaults to 1
+            --alert-amount-threshold=<tokens> Deposits of at least this many whole tokens are logged as warnings for alerting
+            --claim-confirm-delay=<ms>   Delay before re-checking that submitted claims were processed, doubled per retry, defaults to 1000
+            --grpc-connect-timeout=<secs>  Seconds allowed to connect to Cosmos gRPC, defaults to 10
+            --grpc-request-timeout=<secs>  Seconds allowed for a single Cosmos gRPC request, defaults to 30
+            --control-socket=<path>      A Unix socket accepting pause, resume, and status commands for relaying
+            --estimate-gas-cap=<gas>     The largest gas limit used when estimating valset updates, defaults to the block gas limit
+            --halt-on-valset-divergence  Pause all relaying if Ethereum holds a valset nonce Cosmos does not know of
+            --max-batch-age=<blocks>     Do not relay batches created more than this many Ethereum blocks ago
+            --price-feed-url=<url>       A CoinGecko compatible token price api used to value batch fees in ETH
+            --token-eth-price=<price>    A fixed price in ETH of one whole fee token, as <address>=<price>
+            --only-profitable-batches    Skip batches whose fees are worth less than the gas to submit them
+            --claim-inclusion-timeout=<secs>  Seconds to wait for a claims tx to be included in a block, not checked by default
+            --claim-gas-multiplier=<mult>  Simulate claims txs and send them with this multiple of the simulated gas instead of the static fee
+            --events-report              Print the events the oracle would claim in a block range as JSON then exit, nothing is submitted
+            --from-block=<block>         The first block checked by the events report
+            --to-block=<block>           The last block checked by the events report
+            --valset-power-drift=<fraction>  Only relay valsets once this fraction of the power has moved or a validator joined or left
+            --contract-code-hash=<hash>  The keccak256 of the Peggy contract's runtime bytecode, checked at startup when set
+            --recovery-log=<path>        Record every Ethereum transaction to this file before it is sent, for disaster recovery
+            --recovery-log-max-bytes=<n>  The size the recovery log is rotated at, defaults to 10MiB
+            --upgrade-event=<signature>  Hold claims when the Peggy contract emits this event, such as 'Upgraded(address)', until acknowledged
+            --review-amount-threshold=<tokens>  Hold deposits of at least this many whole tokens until approved over the control socket, which is required
+            --observe-depth=<blocks>     Log deposits this many blocks deep as pending before they reach the block delay and are claimed
+            --batch-max-gas-price=<wei>  Defer batches while the gas price is above this, batches are submitted at any gas price by default
+            --state-snapshot=<path>      Save the oracle's and the relayer's progress to this file and resume from it on startup, for migrating hosts
+            --heartbeat-polls=<n>        Log an ORACLE_HEARTBEAT after this many polls in a row without events
+            --heartbeat-secs=<secs>      Log an ORACLE_HEARTBEAT after this many seconds without events
+            --valset-submit-retries=<n>  Times a failed valset update submission is retried with a fresh nonce, defaults to 2
+            --allow-token=<addr>         Only relay batches of this token, may be given several times
+            --deny-token=<addr>          Never relay batches of this token, may be given several times
+            --batch-reconcile-interval=<secs>  Compare executed batch nonces on Ethereum and Cosmos this often
+            --otlp-endpoint=<url>        Export traces of each oracle poll to this OTLP/gRPC collector endpoint
+            --batch-hash-report          Print the hash a pending batch's signatures should sign and check each one against it then exit
+            --batch-token=<addr>         The token of the batch checked by the batch hash report
+            --batch-nonce=<nonce>        The nonce of the batch checked by the batch hash report
+            --max-block-range=<blocks>   Fetch and claim event logs at most this many blocks at a time
+            --cosmos-prefix=<prefix>     The bech32 prefix deposit destinations are claimed with, defaults to cosmos
+            --warn-on-nonce-regression  Keep submitting claims when our event nonce on Cosmos goes backwards instead of halting
+            --valset-submission-key=<key>  Send valset updates from this Ethereum key, it pays their gas instead of the relayer key
+            --relay-priority=<priority>  valsets-first (default) or batches-first, which is relayed first each iteration
+            --claim-window=<secs>        Hold claims back for up to this long to submit them together, defaults to 0 (off)
+            --claim-window-max-claims=<n>  Submit held claims early once this many are waiting, defaults to 100
+            --gas-estimate-failure=<policy>  skip (default), retry or fixed-gas, what to do when a batch gas estimate fails
+            --gas-estimate-retries=<n>   Extra batch gas estimates made under the retry policy, defaults to 2
+            --batch-fallback-gas=<gas>   The gas limit batches are submitted with under the fixed-gas policy, which requires it, never above the estimate gas cap
+            --metrics-listen=<addr>      Serve Prometheus metrics at /metrics and recent log records at /logs/recent on this address, for example 127.0.0.1:9100
+            --additional-cosmos-phrase=<key>  The Cosmos key of another validator whose oracle shares this one's event fetches, may be given several times
         About:
             The Validator companion binary for Peggy. This must be run by all Peggy chain validators
             and is a mix of a relayer + oracle + ethereum signing infrastructure
+            The self-test command checks every RPC connection and the contract address then exits,
+            with a non zero status if any check failed
             Written By: {}
             Version {}",
             env!("CARGO_PKG_NAME"),
@@ -81,28 +233,207 @@ async fn main() {
         .unwrap_or_else(|e| e.exit());
     let cosmos_key = CosmosPrivateKey::from_phrase(&args.flag_cosmos_phrase, "")
         .expect("Invalid Private Cosmos Key!");
-    let ethereum_key: EthPrivateKey = args
-        .flag_ethereum_key
-        .parse()
-        .expect("Invalid Ethereum private key!");
+    let ethereum_key: EthPrivateKey = match (args.flag_ethereum_key, args.flag_ethereum_keystore) {
+        (Some(key), _) => key.parse().expect("Invalid Ethereum private key!"),
+        (None, Some(path)) => load_keystore(&path).unwrap_or_else(|e| panic!("{}", e)),
+        (None, None) => panic!("An Ethereum key or keystore is required!"),
+    };
     let contract_address: EthAddress = args
         .flag_contract_address
         .parse()
         .expect("Invalid contract address!");
 
     let fee_denom = args.flag_fees;
+    let mut relayer_config = RelayerConfig::default();
+    if let Some(multiplier) = args.flag_gas_limit_multiplier {
+        relayer_config.gas_limit_multiplier =
+            validate_gas_multiplier(multiplier).expect("Invalid gas limit multiplier!");
+    }
+    relayer_config.gas_oracle_url = args.flag_gas_oracle_url;
+    if let Some(tier) = args.flag_gas_price_tier {
+        relayer_config.gas_price_tier = tier.parse().expect("Invalid gas price tier!");
+    }
+    if let Some(smoothing) = args.flag_gas_price_smoothing {
+        relayer_config.gas_price_smoothing =
+            validate_gas_price_smoothing(smoothing).expect("Invalid gas price smoothing!");
+    }
+    if let Some(reserve) = args.flag_eth_balance_reserve {
+        relayer_config.eth_balance_reserve = reserve.parse().expect("Invalid ETH balance reserve!");
+    }
+    if let Some(strategy) = args.flag_batch_strategy {
+        relayer_config.batch_selection_strategy =
+            strategy.parse().expect("Invalid batch selection strategy!");
+    }
+    for stipend in args.flag_logic_call_gas_stipend {
+        let (logic_contract, gas) =
+            parse_logic_call_gas_stipend(&stipend).expect("Invalid logic call gas stipend!");
+        relayer_config
+            .logic_call_gas_stipends
+            .insert(logic_contract, gas);
+    }
+    relayer_config.control_socket = args.flag_control_socket;
+    if let Some(cap) = args.flag_estimate_gas_cap {
+        relayer_config.estimate_gas_limit_cap =
+            Some(cap.parse().expect("Invalid estimate gas cap!"));
+    }
+    relayer_config.halt_on_valset_divergence = args.flag_halt_on_valset_divergence;
+    relayer_config.max_batch_age_blocks = args.flag_max_batch_age;
+    relayer_config.price_feed_url = args.flag_price_feed_url;
+    for price in args.flag_token_eth_price {
+        let (token, price) = parse_token_eth_price(&price).expect("Invalid token price!");
+        relayer_config.token_eth_prices.insert(token, price);
+    }
+    relayer_config.only_relay_profitable_batches = args.flag_only_profitable_batches;
+    relayer_config.valset_power_drift_threshold = args.flag_valset_power_drift;
+    let contract_code_hash = args
+        .flag_contract_code_hash
+        .map(|hash| parse_bytecode_hash(&hash).expect("Invalid contract code hash!"));
+    relayer_config.recovery_log = args.flag_recovery_log;
+    if let Some(max) = args.flag_recovery_log_max_bytes {
+        relayer_config.recovery_log_max_bytes = max;
+    }
+    // the relayer keeps its own section of the same snapshot file
+    relayer_config.state_snapshot = args.flag_state_snapshot.clone();
+    if let Some(price) = args.flag_batch_max_gas_price {
+        relayer_config.batch_max_gas_price =
+            Some(price.parse().expect("Invalid batch max gas price!"));
+    }
+    if let Some(retries) = args.flag_valset_submit_retries {
+        relayer_config.valset_submit_retries = retries;
+    }
+    for token in args.flag_allow_token {
+        relayer_config
+            .token_allowlist
+            .insert(token.parse().expect("Invalid allowed token!"));
+    }
+    for token in args.flag_deny_token {
+        relayer_config
+            .token_denylist
+            .insert(token.parse().expect("Invalid denied token!"));
+    }
+    relayer_config.batch_reconcile_interval =
+        args.flag_batch_reconcile_interval.map(Duration::from_secs);
+    relayer_config.valset_submission_key = args
+        .flag_valset_submission_key
+        .map(|key| key.parse().expect("Invalid valset submission key!"));
+    if let Some(priority) = args.flag_relay_priority {
+        relayer_config.relay_priority = priority.parse().expect("Invalid relay priority!");
+    }
+    if let Some(policy) = args.flag_gas_estimate_failure {
+        relayer_config.gas_estimate_failure_policy = policy
+            .parse()
+            .expect("Invalid gas estimate failure policy!");
+    }
+    if let Some(retries) = args.flag_gas_estimate_retries {
+        relayer_config.gas_estimate_retries = retries;
+    }
+    relayer_config.batch_fallback_gas_limit = args
+        .flag_batch_fallback_gas
+        .map(|gas| gas.parse().expect("Invalid batch fallback gas!"));
+    relayer_config
+        .validate_gas_estimate_failure_policy()
+        .unwrap_or_else(|e| panic!("{}", e));
+    relayer_config.metrics_listen = args.flag_metrics_listen;
+
+    let mut orchestrator_config = OrchestratorConfig {
+        eth_rpc_requests_per_second: args.flag_eth_rpc_rate_limit,
+        checkpoint_file: args.flag_checkpoint_file,
+        max_events_per_poll: args.flag_max_events_per_poll,
+        eth_ws_url: args.flag_ethereum_ws_rpc,
+        alert_amount_threshold: args.flag_alert_amount_threshold,
+        review_amount_threshold: args.flag_review_amount_threshold,
+        cosmos_legacy_rpc_url: Some(args.flag_cosmos_legacy_rpc.clone()),
+        claim_inclusion_timeout: args.flag_claim_inclusion_timeout.map(Duration::from_secs),
+        claim_gas_multiplier: args.flag_claim_gas_multiplier.map(|multiplier| {
+            validate_gas_multiplier(multiplier).expect("Invalid claim gas multiplier!")
+        }),
+        upgrade_event: args.flag_upgrade_event,
+        observe_depth: args.flag_observe_depth,
+        state_snapshot: args.flag_state_snapshot,
+        heartbeat_polls: args.flag_heartbeat_polls,
+        heartbeat_interval: args.flag_heartbeat_secs.map(Duration::from_secs),
+        otlp_endpoint: args.flag_otlp_endpoint,
+        max_block_range: args.flag_max_block_range,
+        halt_on_nonce_regression: !args.flag_warn_on_nonce_regression,
+        ..Default::default()
+    };
+    if let Some(overlap) = args.flag_block_overlap {
+        orchestrator_config.block_overlap = overlap;
+    }
+    if let Some(delay) = args.flag_claim_confirm_delay {
+        orchestrator_config.claim_confirm_delay = Duration::from_millis(delay);
+    }
+    if let Some(window) = args.flag_claim_window {
+        orchestrator_config.claim_window = Duration::from_secs(window);
+    }
+    if let Some(max_claims) = args.flag_claim_window_max_claims {
+        orchestrator_config.claim_window_max_claims = max_claims;
+    }
+    // held deposits can only be approved over the control socket, without it they'd wait forever
+    if orchestrator_config.review_amount_threshold.is_some()
+        && relayer_config.control_socket.is_none()
+    {
+        panic!("A review amount threshold requires a control socket to approve held deposits!");
+    }
+    for phrase in args.flag_additional_cosmos_phrase {
+        orchestrator_config.additional_cosmos_keys.push(
+            CosmosPrivateKey::from_phrase(&phrase, "").expect("Invalid additional Cosmos key!"),
+        );
+    }
+    if let Some(prefix) = args.flag_cosmos_prefix {
+        // an invalid prefix would otherwise only fail once a deposit is claimed
+        CosmosAddress::default()
+            .to_bech32(&prefix)
+            .expect("Invalid Cosmos prefix!");
+        orchestrator_config.cosmos_prefix = prefix;
+    }
 
     let timeout = min(
         min(ETH_SIGNER_LOOP_SPEED, ETH_ORACLE_LOOP_SPEED),
         RELAYER_LOOP_SPEED,
     );
 
+    if let Err(e) = load_eth_rpc_headers() {
+        error!("{}", e);
+        std::process::exit(1);
+    }
+    let mut grpc_config = GrpcChannelConfig::default();
+    if let Some(secs) = args.flag_grpc_connect_timeout {
+        grpc_config.connect_timeout = Duration::from_secs(secs);
+    }
+    if let Some(secs) = args.flag_grpc_request_timeout {
+        grpc_config.request_timeout = Duration::from_secs(secs);
+    }
+
+    if args.cmd_self_test {
+        let web3 = Web3::new(args.flag_ethereum_rpc.trim_end_matches('/'), timeout);
+        let checks = run_self_test(
+            args.flag_cosmos_grpc.trim_end_matches('/').to_string(),
+            &grpc_config,
+            &web3,
+            contract_address,
+            ethereum_key
+                .to_public_key()
+                .expect("Invalid Ethereum Private Key!"),
+            cosmos_key
+                .to_public_key()
+                .expect("Invalid Cosmos Phrase!")
+                .to_address(),
+        )
+        .await;
+        if checks.iter().all(|c| c.passed) {
+            std::process::exit(0);
+        }
+        std::process::exit(1);
+    }
+
     // probe all rpc connections and see if they are valid
-    let connections = create_rpc_connections(
+    let connections = create_rpc_connections_with_grpc_config(
         Some(args.flag_cosmos_grpc),
         Some(args.flag_cosmos_legacy_rpc),
         Some(args.flag_ethereum_rpc),
         timeout,
+        grpc_config,
     )
     .await;
 
@@ -123,6 +454,77 @@ async fn main() {
     let contact = connections.contact.clone().unwrap();
     let web3 = connections.web3.clone().unwrap();
 
+    if let Some(expected) = contract_code_hash {
+        if let Err(e) = verify_contract_bytecode(&web3, contract_address, expected).await {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    if args.flag_events_report {
+        let from_block = args.flag_from_block.unwrap();
+        let to_block = args.flag_to_block.unwrap();
+        let mut claim_sink = StdoutClaimSink::default();
+        let res = check_for_events(
+            &web3,
+            &mut claim_sink,
+            &mut grpc,
+            contract_address,
+            cosmos_key,
+            from_block.into(),
+            Some(to_block.into()),
+            &mut RateLimiter::new(orchestrator_config.eth_rpc_requests_per_second),
+            &mut HashSet::new(),
+            None,
+            None,
+            None,
+            &mut TokenMetadataCache::new(),
+            None,
+            None,
+            orchestrator_config.max_block_range,
+        )
+        .await;
+        match res {
+            Ok(_) => {
+                info!(
+                    "Events report found {} unclaimed events in blocks {} to {}",
+                    claim_sink.printed, from_block, to_block
+                );
+                std::process::exit(0);
+            }
+            Err(e) => {
+                error!("Events report failed {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if args.flag_batch_hash_report {
+        let token = args
+            .flag_batch_token
+            .unwrap()
+            .parse()
+            .expect("Invalid batch token!");
+        let nonce = args.flag_batch_nonce.unwrap();
+        match batch_hash_report(
+            &mut grpc,
+            &web3,
+            contract_address,
+            public_eth_key,
+            token,
+            nonce,
+        )
+        .await
+        {
+            Ok(checks) if checks.iter().all(|c| c.matches()) => std::process::exit(0),
+            Ok(_) => std::process::exit(1),
+            Err(e) => {
+                error!("Batch hash report failed {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
     // check if the cosmos node is syncing, if so wait for it
     // we can't move any steps above this because they may fail on an incorrect
     // historic chain state while syncing occurs
@@ -134,6 +536,14 @@ async fn main() {
     // check if we actually have the promised balance of tokens to pay fees
     check_for_fee_denom(&fee_denom, public_cosmos_key, &contact).await;
     check_for_eth(public_eth_key, &web3).await;
+    // valset updates are sent from the submission key, it needs its own ETH for their gas
+    if let Some(key) = relayer_config.valset_submission_key {
+        check_for_eth(key.to_public_key().unwrap(), &web3).await;
+    }
+
+    // tag every structured log record with the chain we are attached to
+    let chain_id = get_net_version_with_retry(&web3).await;
+    json_logger::set_chain_id(chain_id);
 
     orchestrator_main_loop(
         cosmos_key,
@@ -143,6 +553,8 @@ async fn main() {
         connections.grpc.unwrap(),
         contract_address,
         fee_denom,
+        relayer_config,
+        orchestrator_config,
     )
     .await;
 }
@@ -2,7 +2,24 @@
 //! that can only be run by a validator. This single binary the 'Orchestrator' runs not only these two rules but also the untrusted role of a relayer, that does not need any permissions and has it's
 //! own crate and binary so that anyone may run it.
 
-use crate::{ethereum_event_watcher::check_for_events, oracle_resync::get_last_checked_block};
+use crate::checkpoint::get_contract_deploy_block;
+use crate::claim_sink::{
+    held_claims, request_shutdown, shutdown_requested, CosmosClaimSink, NonceRegressionGuard,
+    WindowedClaimSink,
+};
+use crate::event_subscription::{log_block_is_final, subscribe_to_events, RESUBSCRIBE_INTERVAL};
+use crate::heartbeat::Heartbeat;
+use crate::rate_limit::RateLimiter;
+use crate::state_snapshot::{load_state_snapshot, save_state_snapshot, StateSnapshot};
+use crate::trace_export::start_trace_export;
+use crate::{
+    ethereum_event_watcher::{
+        check_for_events_for_identities, next_starting_block, OracleIdentity,
+    },
+    oracle_resync::get_last_checked_block,
+};
+use actix_rt::signal::ctrl_c;
+use actix_rt::signal::unix::{signal, SignalKind};
 use clarity::{address::Address as EthAddress, Uint256};
 use clarity::{utils::bytes_to_hex_str, PrivateKey as EthPrivateKey};
 use contact::client::Contact;
@@ -14,17 +31,23 @@ use cosmos_peggy::{
     send::{send_batch_confirm, send_logic_call_confirm, send_valset_confirms},
 };
 use deep_space::{coin::Coin, private_key::PrivateKey as CosmosPrivateKey};
+use ethereum_peggy::token_metadata::TokenMetadataCache;
 use ethereum_peggy::utils::get_peggy_id;
-use futures::future::join3;
+use futures::future::{join3, select};
+use json_logger::LOGGING;
 use peggy_proto::peggy::query_client::QueryClient as PeggyQueryClient;
+use peggy_utils::types::{OrchestratorConfig, RelayerConfig};
 use relayer::main_loop::relayer_main_loop;
+use slog::info as sinfo;
+use slog::warn as swarn;
+use std::cmp::min;
+use std::collections::HashSet;
+use std::env;
 use std::time::Duration;
 use std::time::Instant;
 use tokio::time::delay_for;
 use tonic::transport::Channel;
 use web30::client::Web3;
-use json_logger::LOGGING;
-use slog::{info as sinfo};
 
 /// The execution speed governing all loops in this file
 /// which is to say all loops started by Orchestrator main
@@ -32,11 +55,19 @@ use slog::{info as sinfo};
 pub const ETH_SIGNER_LOOP_SPEED: Duration = Duration::from_secs(11);
 pub const ETH_ORACLE_LOOP_SPEED: Duration = Duration::from_secs(13);
 
+/// How long a shutdown waits for the claims held by the claim window to be submitted
+const SHUTDOWN_FLUSH_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// When set this environment variable overrides the block the oracle starts
+/// searching for events from, this is intended for testing against fresh devnets
+pub const START_BLOCK_OVERRIDE_ENV: &str = "GRAVITY_START_BLOCK";
+
 /// This loop combines the three major roles required to make
 /// up the 'Orchestrator', all three of these are async loops
 /// meaning they will occupy the same thread, but since they do
 /// very little actual cpu bound work and spend the vast majority
 /// of all execution time sleeping this shouldn't be an issue at all.
+#[allow(clippy::too_many_arguments)]
 pub async fn orchestrator_main_loop(
     cosmos_key: CosmosPrivateKey,
     ethereum_key: EthPrivateKey,
@@ -45,6 +76,8 @@ pub async fn orchestrator_main_loop(
     grpc_client: PeggyQueryClient<Channel>,
     peggy_contract_address: EthAddress,
     pay_fees_in: String,
+    relayer_config: RelayerConfig,
+    orchestrator_config: OrchestratorConfig,
 ) {
     let fee = Coin {
         denom: pay_fees_in.clone(),
@@ -58,6 +91,7 @@ pub async fn orchestrator_main_loop(
         grpc_client.clone(),
         peggy_contract_address,
         fee.clone(),
+        orchestrator_config,
     );
     let b = eth_signer_main_loop(
         cosmos_key,
@@ -73,6 +107,7 @@ pub async fn orchestrator_main_loop(
         web3,
         grpc_client.clone(),
         peggy_contract_address,
+        relayer_config,
     );
     join3(a, b, c).await;
 }
@@ -86,23 +121,139 @@ pub async fn eth_oracle_main_loop(
     grpc_client: PeggyQueryClient<Channel>,
     peggy_contract_address: EthAddress,
     fee: Coin,
+    config: OrchestratorConfig,
 ) {
     let our_cosmos_address = cosmos_key.to_public_key().unwrap().to_address();
+    if let Some(endpoint) = config.otlp_endpoint.clone() {
+        if let Err(e) = start_trace_export(endpoint) {
+            error!("Failed to start exporting traces {}", e);
+        }
+    }
+    let mut rate_limiter = RateLimiter::new(config.eth_rpc_requests_per_second);
     let long_timeout_web30 = Web3::new(&web3.get_url(), Duration::from_secs(120));
-    let mut last_checked_block: Uint256 = get_last_checked_block(
+    let snapshot = config.state_snapshot.as_deref().and_then(|path| {
+        load_state_snapshot(
+            path,
+            &peggy_contract_address.to_string(),
+            &our_cosmos_address.to_string(),
+        )
+    });
+    let deploy_block = match snapshot.as_ref().and_then(|s| s.deploy_block.clone()) {
+        Some(deploy_block) => Some(deploy_block),
+        None => {
+            get_contract_deploy_block(
+                &long_timeout_web30,
+                peggy_contract_address,
+                config.checkpoint_file.as_deref(),
+            )
+            .await
+        }
+    };
+    let mut last_checked_block = get_last_checked_block(
         grpc_client.clone(),
         our_cosmos_address,
         peggy_contract_address,
         &long_timeout_web30,
+        deploy_block.clone(),
     )
     .await;
+    // every identity resumes from the earliest block any of them still has to claim
+    for key in config.additional_cosmos_keys.iter() {
+        let block = get_last_checked_block(
+            grpc_client.clone(),
+            key.to_public_key().unwrap().to_address(),
+            peggy_contract_address,
+            &long_timeout_web30,
+            deploy_block.clone(),
+        )
+        .await;
+        last_checked_block = min(last_checked_block, block);
+    }
+    // Cosmos stays authoritative, the snapshot can only move the oracle back to rescan blocks
+    // the previous host may have checked without its claims landing
+    if let Some(block) = snapshot.as_ref().and_then(|s| s.last_checked_block.clone()) {
+        if block < last_checked_block {
+            info!(
+                "Resuming the oracle from block {} in the state snapshot rather than {}",
+                block, last_checked_block
+            );
+            sinfo!(&LOGGING.logger, "STATE_SNAPSHOT_RESTORED";
+                "function" => "eth_oracle_main_loop()",
+                "last_checked_block" => format!("{}",block),
+                "cosmos_last_checked_block" => format!("{}",last_checked_block),
+            );
+            last_checked_block = block;
+        }
+    }
+    if let Some(start_block) = get_start_block_override() {
+        info!(
+            "Overriding oracle starting block {} with {} from {}",
+            last_checked_block, start_block, START_BLOCK_OVERRIDE_ENV
+        );
+        sinfo!(&LOGGING.logger, "START_BLOCK_OVERRIDE";
+            "function" => "eth_oracle_main_loop()",
+            "computed_start_block" => format!("{}",last_checked_block),
+            "start_block" => format!("{}",start_block),
+        );
+        last_checked_block = start_block;
+    }
     info!("Oracle resync complete, Oracle now operational");
     sinfo!(&LOGGING.logger, "ORACLE_RESYNC_COMPLETE_ORACLE_NOW_OPERATIONAL";"function" => "eth_oracle_main_loop()");
+    // our own key and those of the co-located validators, each claims through its own sink
+    let cosmos_keys: Vec<CosmosPrivateKey> = Some(cosmos_key)
+        .into_iter()
+        .chain(config.additional_cosmos_keys.iter().cloned())
+        .collect();
+    let mut claim_sinks: Vec<_> = cosmos_keys
+        .iter()
+        .map(|private_key| {
+            let claim_sink = CosmosClaimSink {
+                contact: contact.clone(),
+                grpc_client: grpc_client.clone(),
+                private_key: *private_key,
+                fee: fee.clone(),
+                confirm_delay: config.claim_confirm_delay,
+                legacy_rpc_url: config.cosmos_legacy_rpc_url.clone(),
+                inclusion_timeout: config.claim_inclusion_timeout,
+                gas_multiplier: config.claim_gas_multiplier,
+                cosmos_prefix: config.cosmos_prefix.clone(),
+                nonce_guard: NonceRegressionGuard::new(config.halt_on_nonce_regression),
+            };
+            WindowedClaimSink::new(
+                claim_sink,
+                config.claim_window,
+                config.claim_window_max_claims,
+            )
+        })
+        .collect();
+    if config.claim_window > Duration::from_secs(0) {
+        actix_rt::spawn(request_shutdown_on_signal());
+    }
     let mut grpc_client = grpc_client;
+    let mut previously_submitted = vec![HashSet::new(); cosmos_keys.len()];
+    let mut token_metadata = TokenMetadataCache::new();
+    if let Some(snapshot) = &snapshot {
+        snapshot.restore_token_metadata(&mut token_metadata);
+    }
+    let mut heartbeat = Heartbeat::new(config.heartbeat_polls, config.heartbeat_interval);
+
+    // with a subscription events are only checked once a notified log passes the block delay,
+    // while it is down we poll every loop until it is time to subscribe again
+    let mut subscription = None;
+    let mut next_subscribe = Instant::now();
+    let mut pending_block = None;
 
     loop {
         let loop_start = Instant::now();
 
+        if let Some(ws_url) = config.eth_ws_url.as_deref() {
+            if subscription.is_none() && loop_start >= next_subscribe {
+                subscription = Some(subscribe_to_events(ws_url, peggy_contract_address));
+                // logs emitted while we were not subscribed are only picked up by a check
+                pending_block = Some(last_checked_block.clone());
+            }
+        }
+
         let latest_eth_block = web3.eth_block_number().await;
         let latest_cosmos_block = contact.get_latest_block_number().await;
         if let (Ok(latest_eth_block), Ok(latest_cosmos_block)) =
@@ -115,35 +266,193 @@ pub async fn eth_oracle_main_loop(
             );
         }
 
-        // Relays events from Ethereum -> Cosmos
-        match check_for_events(
-            &web3,
-            &contact,
-            &mut grpc_client,
-            peggy_contract_address,
-            cosmos_key,
-            fee.clone(),
-            last_checked_block.clone(),
-        )
-        .await
-        {
-            Ok(new_block) => last_checked_block = new_block,
-            Err(e) => error!(
-                "Failed to get events for block range, Check your Eth node and Cosmos gRPC {:?}",
-                e
-            ),
+        let check_due = match (&subscription, &pending_block) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(_), Some(log_block)) => log_block_is_final(&web3, log_block).await,
+        };
+        if !check_due {
+            if let Some(quiet_polls) = heartbeat.quiet_poll() {
+                log_heartbeat(quiet_polls, &last_checked_block);
+            }
+        } else {
+            let mut identities: Vec<_> = cosmos_keys
+                .iter()
+                .zip(claim_sinks.iter_mut())
+                .zip(previously_submitted.iter_mut())
+                .map(|((private_key, claim_sink), submitted)| OracleIdentity {
+                    private_key: *private_key,
+                    claim_sink,
+                    previously_submitted: submitted,
+                })
+                .collect();
+            // Relays events from Ethereum -> Cosmos
+            match check_for_events_for_identities(
+                &web3,
+                &mut identities,
+                &mut grpc_client,
+                peggy_contract_address,
+                next_starting_block(&last_checked_block, config.block_overlap),
+                None,
+                &mut rate_limiter,
+                config.max_events_per_poll,
+                config.alert_amount_threshold,
+                config.review_amount_threshold,
+                &mut token_metadata,
+                config.upgrade_event.as_deref(),
+                config.observe_depth,
+                config.max_block_range,
+            )
+            .await
+            {
+                Ok(new_block) => {
+                    last_checked_block = new_block;
+                    // the event cap or a held claim can stop the check short of a notified block
+                    pending_block = pending_block.filter(|block| *block > last_checked_block);
+                    // check_for_events records the event nonces it claimed each poll
+                    if previously_submitted.iter().any(|submitted| !submitted.is_empty()) {
+                        heartbeat.active_poll();
+                    } else if let Some(quiet_polls) = heartbeat.quiet_poll() {
+                        log_heartbeat(quiet_polls, &last_checked_block);
+                    }
+                    if let Some(path) = config.state_snapshot.as_deref() {
+                        let mut snapshot = StateSnapshot::new(
+                            peggy_contract_address.to_string(),
+                            our_cosmos_address.to_string(),
+                        );
+                        snapshot.deploy_block = deploy_block.clone();
+                        snapshot.last_checked_block = Some(last_checked_block.clone());
+                        snapshot.record_token_metadata(&token_metadata);
+                        if let Err(e) = save_state_snapshot(path, &snapshot) {
+                            warn!("Failed to write state snapshot {} {:?}", path, e);
+                        }
+                    }
+                }
+                // a pending log block stays pending and is checked again next loop
+                Err(e) => error!(
+                    "Failed to get events for block range, Check your Eth node and Cosmos gRPC {:?}",
+                    e
+                ),
+            }
         }
 
         // a bit of logic that tires to keep things running every LOOP_SPEED seconds exactly
         // this is not required for any specific reason. In fact we expect and plan for
         // the timing being off significantly
         let elapsed = Instant::now() - loop_start;
-        if elapsed < ETH_ORACLE_LOOP_SPEED {
-            delay_for(ETH_ORACLE_LOOP_SPEED - elapsed).await;
+        let remaining = ETH_ORACLE_LOOP_SPEED
+            .checked_sub(elapsed)
+            .unwrap_or_else(|| Duration::from_secs(0));
+        match subscription.as_mut() {
+            // waiting on the subscription takes the place of the loop delay
+            Some(events) => match events.next_log_block(remaining).await {
+                Ok(Some(log_block)) => {
+                    // keep the highest notified block, a check only clears what it got past
+                    if log_block > last_checked_block
+                        && pending_block
+                            .as_ref()
+                            .map_or(true, |block| log_block > *block)
+                    {
+                        pending_block = Some(log_block);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    warn!(
+                        "Ethereum event subscription failed, polling until resubscribing in {:?} {}",
+                        RESUBSCRIBE_INTERVAL, e
+                    );
+                    swarn!(&LOGGING.logger, "EVENT_SUBSCRIPTION_FAILED";
+                        "function" => "eth_oracle_main_loop()",
+                        "error" => format!("{}",e),
+                        "resubscribe_secs" => format!("{}",RESUBSCRIBE_INTERVAL.as_secs()),
+                    );
+                    subscription = None;
+                    next_subscribe = Instant::now() + RESUBSCRIBE_INTERVAL;
+                }
+            },
+            None => delay_for(remaining).await,
+        }
+
+        if shutdown_requested() {
+            submit_held_claims(&mut claim_sinks).await;
+            std::process::exit(0);
         }
     }
 }
 
+fn log_heartbeat(quiet_polls: u64, last_checked_block: &Uint256) {
+    info!(
+        "Oracle alive, no events in the last {} polls, checked up to block {}",
+        quiet_polls, last_checked_block
+    );
+    sinfo!(&LOGGING.logger, "ORACLE_HEARTBEAT";
+        "function" => "eth_oracle_main_loop()",
+        "last_checked_block" => format!("{}",last_checked_block),
+        "quiet_polls" => format!("{}",quiet_polls),
+    );
+}
+
+/// Requests a shutdown on ctrl-c or SIGTERM, the oracle loop then submits the claims held back by
+/// the claim window and exits. Held claims are observed again after a restart, submitting them
+/// only keeps them from being delayed by the resync. If the oracle loop hasn't exited after
+/// SHUTDOWN_FLUSH_TIMEOUT the process exits without them
+async fn request_shutdown_on_signal() {
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            warn!(
+                "Failed to listen for SIGTERM, held claims are not submitted on exit {:?}",
+                e
+            );
+            return;
+        }
+    };
+    select(Box::pin(ctrl_c()), Box::pin(terminate.recv())).await;
+    request_shutdown();
+    info!(
+        "Shutting down once the {} held claims are submitted",
+        held_claims()
+    );
+    delay_for(SHUTDOWN_FLUSH_TIMEOUT).await;
+    warn!(
+        "Exiting with {} claims held, they are claimed after the restart",
+        held_claims()
+    );
+    std::process::exit(0);
+}
+
+/// Submits the claims every claim window is holding, called by the oracle loop once a shutdown
+/// has been requested
+async fn submit_held_claims(claim_sinks: &mut [WindowedClaimSink<CosmosClaimSink>]) {
+    for claim_sink in claim_sinks.iter_mut() {
+        if let Err(e) = claim_sink.flush().await {
+            warn!(
+                "Failed to submit held claims on shutdown, they are claimed after the restart {}",
+                e
+            );
+        }
+    }
+}
+
+/// Reads the starting block override from the environment, an unparsable value is
+/// logged and ignored rather than halting the oracle
+fn get_start_block_override() -> Option<Uint256> {
+    match env::var(START_BLOCK_OVERRIDE_ENV) {
+        Ok(val) => match val.trim().parse() {
+            Ok(block) => Some(block),
+            Err(e) => {
+                warn!(
+                    "Invalid {} value {}, ignoring it {:?}",
+                    START_BLOCK_OVERRIDE_ENV, val, e
+                );
+                None
+            }
+        },
+        Err(_) => None,
+    }
+}
+
 /// The eth_signer simply signs off on any batches or validator sets provided by the validator
 /// since these are provided directly by a trusted Cosmsos node they can simply be assumed to be
 /// valid and signed off on.
@@ -1,15 +1,20 @@
 use clarity::{Address, Uint256};
 use deep_space::address::Address as CosmosAddress;
+use ethereum_peggy::utils::get_valset_updated_logs;
+use json_logger::LOGGING;
 use peggy_proto::peggy::query_client::QueryClient as PeggyQueryClient;
+use peggy_utils::event_signatures::{
+    ERC20_DEPLOYED_EVENT_SIG, LOGIC_CALL_EVENT_SIG, SEND_TO_COSMOS_EVENT_SIG,
+    TRANSACTION_BATCH_EXECUTED_EVENT_SIG,
+};
 use peggy_utils::types::{
     ERC20DeployedEvent, LogicCallExecutedEvent, SendToCosmosEvent, TransactionBatchExecutedEvent,
     ValsetUpdatedEvent,
 };
+use slog::info as sinfo;
 use tokio::time::delay_for;
 use tonic::transport::Channel;
 use web30::client::Web3;
-use json_logger::LOGGING;
-use slog::{info as sinfo};
 
 use crate::get_with_retry::get_block_number_with_retry;
 use crate::get_with_retry::get_last_event_nonce_with_retry;
@@ -17,11 +22,14 @@ use crate::get_with_retry::RETRY_TIME;
 
 /// This function retrieves the last event nonce this oracle has relayed to Cosmos
 /// it then uses the Ethereum indexes to determine what block the last entry
+/// If the block the contract was deployed in is known an oracle that has never relayed
+/// an event starts from there rather than searching back through the chain history
 pub async fn get_last_checked_block(
     grpc_client: PeggyQueryClient<Channel>,
     our_cosmos_address: CosmosAddress,
     peggy_contract_address: Address,
     web3: &Web3,
+    deploy_block: Option<Uint256>,
 ) -> Uint256 {
     let mut grpc_client = grpc_client;
     const BLOCKS_TO_SEARCH: u128 = 5_000u128;
@@ -36,6 +44,17 @@ pub async fn get_last_checked_block(
     // zero event nonce (it's pre-incremented in the solidity contract) we have to go
     // and look for event nonce one.
     if last_event_nonce == 0u8.into() {
+        if let Some(deploy_block) = deploy_block {
+            info!(
+                "Oracle has never relayed an event, starting from contract deploy block {}",
+                deploy_block
+            );
+            sinfo!(&LOGGING.logger, "ORACLE_STARTING_FROM_DEPLOY_BLOCK";
+                "function" => "get_last_checked_block()",
+                "deploy_block" => format!("{}",deploy_block),
+            );
+            return deploy_block;
+        }
         last_event_nonce = 1u8.into();
     }
 
@@ -61,7 +80,7 @@ pub async fn get_last_checked_block(
                 end_search.clone(),
                 Some(current_block.clone()),
                 vec![peggy_contract_address],
-                vec!["TransactionBatchExecutedEvent(uint256,address,uint256)"],
+                vec![TRANSACTION_BATCH_EXECUTED_EVENT_SIG],
             )
             .await;
         let send_to_cosmos_events = web3
@@ -69,7 +88,7 @@ pub async fn get_last_checked_block(
                 end_search.clone(),
                 Some(current_block.clone()),
                 vec![peggy_contract_address],
-                vec!["SendToCosmosEvent(address,address,bytes32,uint256,uint256)"],
+                vec![SEND_TO_COSMOS_EVENT_SIG],
             )
             .await;
         let erc20_deployed_events = web3
@@ -77,7 +96,7 @@ pub async fn get_last_checked_block(
                 end_search.clone(),
                 Some(current_block.clone()),
                 vec![peggy_contract_address],
-                vec!["ERC20DeployedEvent(string,address,string,string,uint8,uint256)"],
+                vec![ERC20_DEPLOYED_EVENT_SIG],
             )
             .await;
         let logic_call_executed_events = web3
@@ -85,7 +104,7 @@ pub async fn get_last_checked_block(
                 end_search.clone(),
                 Some(current_block.clone()),
                 vec![peggy_contract_address],
-                vec!["LogicCallEvent(bytes32,uint256,bytes,uint256)"],
+                vec![LOGIC_CALL_EVENT_SIG],
             )
             .await;
 
@@ -95,14 +114,13 @@ pub async fn get_last_checked_block(
         // in the contract constructor meaning once you find that event you can exit the search
         // with confidence that you have not missed any events without searching the entire blockchain
         // history
-        let valset_events = web3
-            .check_for_events(
-                end_search.clone(),
-                Some(current_block.clone()),
-                vec![peggy_contract_address],
-                vec!["ValsetUpdatedEvent(uint256,address[],uint256[])"],
-            )
-            .await;
+        let valset_events = get_valset_updated_logs(
+            web3,
+            end_search.clone(),
+            Some(current_block.clone()),
+            peggy_contract_address,
+        )
+        .await;
         if batch_events.is_err()
             || send_to_cosmos_events.is_err()
             || valset_events.is_err()
@@ -0,0 +1,58 @@
+//! A simple token bucket rate limiter used to keep the event watcher within the request quotas of
+//! metered Ethereum RPC providers. Rather than failing a call when the bucket is empty we wait until
+//! a token is available, smoothing bursts of requests out over time.
+
+use json_logger::LOGGING;
+use slog::debug as sdebug;
+use std::time::{Duration, Instant};
+use tokio::time::delay_for;
+
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    /// None indicates that no limit should be applied
+    requests_per_second: Option<f64>,
+    /// the maximum number of tokens the bucket can hold, this is the largest burst we allow
+    capacity: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: Option<f64>) -> Self {
+        // a bucket smaller than one token could never be drawn from
+        let capacity = requests_per_second.unwrap_or(1f64).max(1f64);
+        RateLimiter {
+            requests_per_second,
+            capacity,
+            tokens: capacity,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, rate: f64) {
+        let now = Instant::now();
+        let elapsed = (now - self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// Waits until a request may be made and consumes a token
+    pub async fn acquire(&mut self) {
+        let rate = match self.requests_per_second {
+            Some(rate) if rate > 0f64 => rate,
+            _ => return,
+        };
+        self.refill(rate);
+        if self.tokens < 1f64 {
+            let wait = Duration::from_secs_f64((1f64 - self.tokens) / rate);
+            debug!("Ethereum RPC rate limited, waiting {:?}", wait);
+            sdebug!(&LOGGING.logger, "RPC_RATE_LIMITED";
+                "function" => "acquire()",
+                "wait_ms" => format!("{}",wait.as_millis()),
+            );
+            delay_for(wait).await;
+            self.refill(rate);
+        }
+        self.tokens -= 1f64;
+    }
+}
@@ -0,0 +1,191 @@
+//! A one shot diagnostic of the orchestrator's connections, run with the self-test subcommand.
+//! Each check queries something the main loop depends on, so a misconfigured RPC url or contract
+//! address shows up as a failed check up front rather than as a stalled oracle or relayer later.
+//! It runs before the connection setup the main loop uses, which panics on an unreachable node,
+//! so that an unreachable node is reported as a failed check too.
+
+use crate::get_with_retry::{get_block_number_with_retry, get_net_version_with_retry};
+use clarity::Address as EthAddress;
+use cosmos_peggy::query::{get_last_event_nonce, get_peggy_params};
+use deep_space::address::Address as CosmosAddress;
+use ethereum_peggy::utils::{get_peggy_id, get_valset_nonce};
+use json_logger::LOGGING;
+use peggy_proto::peggy::query_client::QueryClient as PeggyQueryClient;
+use peggy_utils::connection_prep::{connect_grpc, GrpcChannelConfig};
+use peggy_utils::error::PeggyError;
+use slog::error as serror;
+use slog::info as sinfo;
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::timeout;
+use tonic::transport::Channel;
+use web30::client::Web3;
+
+/// How long a single check may take, the retrying checks would otherwise never fail
+const SELF_TEST_CHECK_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelfTestCheck {
+    pub name: &'static str,
+    pub passed: bool,
+    /// The value that was retrieved, or why the check failed
+    pub detail: String,
+}
+
+/// Returns true if the Peggy id stored in the contract matches the one the Cosmos chain signs
+/// with, the contract stores it as a bytes32 so trailing zero padding is ignored
+pub fn peggy_ids_match(contract_peggy_id: &[u8], cosmos_peggy_id: &str) -> bool {
+    let end = contract_peggy_id
+        .iter()
+        .rposition(|b| *b != 0)
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    &contract_peggy_id[..end] == cosmos_peggy_id.as_bytes()
+}
+
+/// Checks that the Peggy contract and the Cosmos chain agree on the Peggy id, if they don't
+/// every signature the validators produce will be rejected by the contract
+pub async fn verify_peggy_id(
+    grpc_client: &mut PeggyQueryClient<Channel>,
+    peggy_contract_address: EthAddress,
+    our_ethereum_address: EthAddress,
+    web3: &Web3,
+) -> Result<bool, PeggyError> {
+    let contract_peggy_id =
+        get_peggy_id(peggy_contract_address, our_ethereum_address, web3).await?;
+    let params = get_peggy_params(grpc_client).await?;
+    Ok(peggy_ids_match(&contract_peggy_id, &params.peggy_id))
+}
+
+async fn run_check<F, T, E>(name: &'static str, check: F) -> SelfTestCheck
+where
+    F: Future<Output = Result<T, E>>,
+    T: std::fmt::Debug,
+    E: std::fmt::Debug,
+{
+    let (passed, detail) = match timeout(SELF_TEST_CHECK_TIMEOUT, check).await {
+        Ok(Ok(value)) => (true, format!("{:?}", value)),
+        Ok(Err(e)) => (false, format!("{:?}", e)),
+        Err(_) => (
+            false,
+            format!("timed out after {:?}", SELF_TEST_CHECK_TIMEOUT),
+        ),
+    };
+    if passed {
+        info!("Self test {} passed: {}", name, detail);
+        sinfo!(&LOGGING.logger, "SELF_TEST_CHECK";
+            "function" => "run_self_test()",
+            "check" => name,
+            "passed" => format!("{}",passed),
+            "detail" => format!("{}",detail),
+        );
+    } else {
+        error!("Self test {} failed: {}", name, detail);
+        serror!(&LOGGING.logger, "SELF_TEST_CHECK";
+            "function" => "run_self_test()",
+            "check" => name,
+            "passed" => format!("{}",passed),
+            "detail" => format!("{}",detail),
+        );
+    }
+    SelfTestCheck {
+        name,
+        passed,
+        detail,
+    }
+}
+
+/// Runs every check, returning all of their results. The self test passes if every check does,
+/// the Cosmos checks are skipped when the gRPC connection can't be made
+pub async fn run_self_test(
+    grpc_url: String,
+    grpc_config: &GrpcChannelConfig,
+    web3: &Web3,
+    peggy_contract_address: EthAddress,
+    our_ethereum_address: EthAddress,
+    our_cosmos_address: CosmosAddress,
+) -> Vec<SelfTestCheck> {
+    let mut checks = Vec::new();
+    let mut grpc_client = None;
+    checks.push(
+        run_check("cosmos_grpc_connect", async {
+            grpc_client = Some(connect_grpc(grpc_url, grpc_config).await?);
+            Ok::<_, String>("connected")
+        })
+        .await,
+    );
+    checks.push(
+        run_check("ethereum_net_version", async {
+            Ok::<_, PeggyError>(get_net_version_with_retry(web3).await)
+        })
+        .await,
+    );
+    checks.push(
+        run_check("ethereum_block_number", async {
+            Ok::<_, PeggyError>(get_block_number_with_retry(web3).await)
+        })
+        .await,
+    );
+    checks.push(
+        run_check(
+            "ethereum_valset_nonce",
+            get_valset_nonce(peggy_contract_address, our_ethereum_address, web3),
+        )
+        .await,
+    );
+    if let Some(grpc_client) = grpc_client.as_mut() {
+        checks.push(
+            run_check(
+                "cosmos_last_event_nonce",
+                get_last_event_nonce(grpc_client, our_cosmos_address),
+            )
+            .await,
+        );
+        checks.push(
+            run_check("peggy_id", async {
+                match verify_peggy_id(
+                    grpc_client,
+                    peggy_contract_address,
+                    our_ethereum_address,
+                    web3,
+                )
+                .await
+                {
+                    Ok(true) => Ok("contract and Cosmos chain match".to_string()),
+                    Ok(false) => Err("contract and Cosmos chain Peggy ids differ".to_string()),
+                    Err(e) => Err(e.to_string()),
+                }
+            })
+            .await,
+        );
+    }
+
+    let passed = checks.iter().all(|c| c.passed);
+    let failed: Vec<&str> = checks
+        .iter()
+        .filter(|c| !c.passed)
+        .map(|c| c.name)
+        .collect();
+    info!(
+        "Self test {}, {} of {} checks passed",
+        if passed { "passed" } else { "failed" },
+        checks.len() - failed.len(),
+        checks.len()
+    );
+    sinfo!(&LOGGING.logger, "SELF_TEST_RESULT";
+        "function" => "run_self_test()",
+        "passed" => format!("{}",passed),
+        "failed_checks" => failed.join(","),
+    );
+    checks
+}
+
+#[test]
+fn test_peggy_ids_match() {
+    let mut contract_id = b"defaultpeggyid".to_vec();
+    contract_id.resize(32, 0);
+    assert!(peggy_ids_match(&contract_id, "defaultpeggyid"));
+    assert!(!peggy_ids_match(&contract_id, "otherpeggyid"));
+    assert!(!peggy_ids_match(&contract_id, "defaultpeggy"));
+    assert!(peggy_ids_match(&[0u8; 32], ""));
+}
@@ -0,0 +1,107 @@
+//! The oracle's section of the host migration snapshot, see peggy_utils::state_snapshot for the
+//! file itself. It lets a new machine skip the deploy block search and the decimals query for
+//! every token it has seen.
+//!
+//! Transferred as is, since the chains can't contradict them:
+//! - the Peggy contract deploy block, which never changes
+//! - ERC20 decimals, which never change
+//!
+//! The last Ethereum block the oracle checked is only a lower bound. The oracle still derives its
+//! starting block from the last event nonce Cosmos has for this validator and only starts from the
+//! snapshot's block when it is earlier, so a snapshot can cause a rescan but never skip events.
+
+use clarity::Uint256;
+use ethereum_peggy::token_metadata::TokenMetadataCache;
+use peggy_utils::state_snapshot::{load_snapshot_section, save_snapshot_section, ORACLE_SECTION};
+use std::collections::BTreeMap;
+use std::io;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StateSnapshot {
+    /// The Peggy contract the state was collected for, a snapshot for any other contract is ignored
+    pub peggy_contract_address: String,
+    /// The Cosmos address of the orchestrator, oracle progress is per validator so a snapshot
+    /// taken by another validator is ignored
+    pub cosmos_address: String,
+    pub deploy_block: Option<Uint256>,
+    pub last_checked_block: Option<Uint256>,
+    /// ERC20 decimals keyed by token address
+    pub token_decimals: BTreeMap<String, u8>,
+}
+
+impl StateSnapshot {
+    pub fn new(peggy_contract_address: String, cosmos_address: String) -> Self {
+        StateSnapshot {
+            peggy_contract_address,
+            cosmos_address,
+            deploy_block: None,
+            last_checked_block: None,
+            token_decimals: BTreeMap::new(),
+        }
+    }
+
+    /// Copies the decimals of every token in the cache into the snapshot
+    pub fn record_token_metadata(&mut self, token_metadata: &TokenMetadataCache) {
+        for (erc20, decimals) in token_metadata.known_decimals() {
+            self.token_decimals.insert(erc20.to_string(), decimals);
+        }
+    }
+
+    /// Fills a token metadata cache from the snapshot, unparsable addresses are skipped
+    pub fn restore_token_metadata(&self, token_metadata: &mut TokenMetadataCache) {
+        for (erc20, decimals) in self.token_decimals.iter() {
+            match erc20.parse() {
+                Ok(erc20) => token_metadata.insert_decimals(erc20, *decimals),
+                Err(e) => warn!("Ignoring invalid token {} in state snapshot {:?}", erc20, e),
+            }
+        }
+    }
+}
+
+/// Loads the oracle's section of the snapshot at `path` if it was taken for this contract and
+/// validator
+pub fn load_state_snapshot(
+    path: &str,
+    peggy_contract_address: &str,
+    cosmos_address: &str,
+) -> Option<StateSnapshot> {
+    let snapshot: StateSnapshot = load_snapshot_section(path, ORACLE_SECTION)?;
+    if snapshot.peggy_contract_address == peggy_contract_address
+        && snapshot.cosmos_address == cosmos_address
+    {
+        Some(snapshot)
+    } else {
+        warn!(
+            "Ignoring state snapshot {} taken for a different contract or validator",
+            path
+        );
+        None
+    }
+}
+
+/// Replaces the oracle's section of the snapshot at `path`
+pub fn save_state_snapshot(path: &str, snapshot: &StateSnapshot) -> Result<(), io::Error> {
+    save_snapshot_section(path, ORACLE_SECTION, snapshot)
+}
+
+#[test]
+fn test_state_snapshot_round_trip() {
+    let path = std::env::temp_dir().join(format!("state_snapshot_{}.json", std::process::id()));
+    let path = path.to_str().unwrap();
+    let contract = "0x8858eeB3DfffA017D4BCE9801D340D36Cf895CCf";
+    let mut snapshot = StateSnapshot::new(contract.to_string(), "cosmos1abc".to_string());
+    snapshot.deploy_block = Some(100u8.into());
+    snapshot.last_checked_block = Some(12_000_000u64.into());
+    let mut token_metadata = TokenMetadataCache::new();
+    token_metadata.insert_decimals(contract.parse().unwrap(), 6);
+    snapshot.record_token_metadata(&token_metadata);
+    save_state_snapshot(path, &snapshot).unwrap();
+
+    let loaded = load_state_snapshot(path, contract, "cosmos1abc").unwrap();
+    assert_eq!(loaded, snapshot);
+    let mut restored = TokenMetadataCache::new();
+    loaded.restore_token_metadata(&mut restored);
+    assert_eq!(restored.known_decimals(), token_metadata.known_decimals());
+    assert!(load_state_snapshot(path, contract, "cosmos1def").is_none());
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,88 @@
+//! Sends the oracle's tracing spans to an OpenTelemetry collector over OTLP. The spans are
+//! recorded with the tracing crate in ethereum_event_watcher and cost next to nothing until
+//! start_trace_export installs a subscriber for them. The OpenTelemetry SDK batches finished
+//! spans and exports them on its own timer, so a slow collector never delays the oracle.
+
+use opentelemetry::sdk::trace::{self, BatchSpanProcessor, TracerProvider};
+use opentelemetry::sdk::Resource;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::{ExporterConfig, TraceExporter};
+use tracing::Subscriber;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::Registry;
+
+const SERVICE_NAME: &str = "peggy-orchestrator";
+
+/// A subscriber that hands every span to `provider`
+fn otel_subscriber(provider: &TracerProvider) -> impl Subscriber + Send + Sync {
+    let tracer = provider.get_tracer("peggy", None);
+    Registry::default().with(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+/// Exports spans to the collector at `endpoint`, such as http://localhost:4317
+pub fn start_trace_export(endpoint: String) -> Result<(), String> {
+    let exporter = TraceExporter::new(ExporterConfig {
+        endpoint,
+        ..ExporterConfig::default()
+    })
+    .map_err(|e| e.to_string())?;
+    let processor =
+        BatchSpanProcessor::builder(exporter, tokio::spawn, tokio::time::interval).build();
+    let provider = TracerProvider::builder()
+        .with_span_processor(processor)
+        .with_config(
+            trace::config().with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                SERVICE_NAME,
+            )])),
+        )
+        .build();
+    let subscriber = otel_subscriber(&provider);
+    // tracers only hold a weak reference, the global provider keeps it alive
+    opentelemetry::global::set_tracer_provider(provider);
+    tracing::subscriber::set_global_default(subscriber).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use opentelemetry::sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+    use opentelemetry::sdk::trace::SimpleSpanProcessor;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, Clone, Default)]
+    struct CollectingExporter(Arc<Mutex<Vec<SpanData>>>);
+
+    #[async_trait]
+    impl SpanExporter for CollectingExporter {
+        async fn export(&mut self, batch: Vec<SpanData>) -> ExportResult {
+            self.0.lock().unwrap().extend(batch);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_spans_are_exported_with_their_parents() {
+        let exporter = CollectingExporter::default();
+        let provider = TracerProvider::builder()
+            .with_span_processor(SimpleSpanProcessor::new(Box::new(exporter.clone())))
+            .build();
+        // scoped to this thread, so other tests never see the subscriber
+        tracing::subscriber::with_default(otel_subscriber(&provider), || {
+            let root = tracing::info_span!("check_for_events");
+            let child = tracing::info_span!(parent: &root, "send_ethereum_claims");
+            drop(child);
+        });
+
+        let spans = exporter.0.lock().unwrap();
+        let names: Vec<&str> = spans.iter().map(|s| s.name.as_ref()).collect();
+        assert_eq!(names, vec!["send_ethereum_claims", "check_for_events"]);
+        assert_eq!(
+            spans[0].span_context.trace_id(),
+            spans[1].span_context.trace_id()
+        );
+        assert_eq!(spans[0].parent_span_id, spans[1].span_context.span_id());
+    }
+}
@@ -0,0 +1,51 @@
+//! Benchmarks ordering signatures against large validator sets, run with `cargo bench -p peggy_utils`.
+//! `order_sigs` indexes the validator set on every call while `indexed` reuses one index, as the
+//! relayer does when checking every pending batch against the current validator set.
+
+use clarity::{Address as EthAddress, PrivateKey as EthPrivateKey};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use peggy_utils::types::{Valset, ValsetConfirmResponse, ValsetMember, TOTAL_PEGGY_POWER};
+
+fn setup(size: usize) -> (Valset, Vec<ValsetConfirmResponse>, Vec<u8>) {
+    let hash = vec![7u8; 32];
+    let mut members = Vec::new();
+    let mut confirms = Vec::new();
+    for i in 0..size {
+        let mut secret = [1u8; 32];
+        secret[0] = (i / 256) as u8 + 1;
+        secret[1] = (i % 256) as u8;
+        let key = EthPrivateKey::from_slice(&secret).unwrap();
+        let eth_address: EthAddress = key.to_public_key().unwrap();
+        members.push(ValsetMember {
+            power: TOTAL_PEGGY_POWER / size as u64,
+            eth_address: Some(eth_address),
+        });
+        // 80% of the validators sign
+        if i % 5 != 0 {
+            confirms.push(ValsetConfirmResponse {
+                eth_address,
+                eth_signature: key.sign_hash(&hash),
+                ..Default::default()
+            });
+        }
+    }
+    (Valset { nonce: 1, members }, confirms, hash)
+}
+
+fn bench_order_sigs(c: &mut Criterion) {
+    let mut group = c.benchmark_group("order_sigs");
+    for size in [100usize, 175].iter() {
+        let (valset, confirms, hash) = setup(*size);
+        group.bench_with_input(BenchmarkId::new("order_sigs", size), size, |b, _| {
+            b.iter(|| valset.order_sigs(&hash, &confirms).unwrap())
+        });
+        let indexed = valset.index();
+        group.bench_with_input(BenchmarkId::new("indexed", size), size, |b, _| {
+            b.iter(|| indexed.order_sigs(&hash, &confirms).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_order_sigs);
+criterion_main!(benches);
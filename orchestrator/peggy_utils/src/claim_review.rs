@@ -0,0 +1,106 @@
+//! A process wide queue of deposits held for manual review before they are claimed. The oracle
+//! queues deposits over the review threshold and an operator approves or rejects them through the
+//! relayer control socket.
+//!
+//! Cosmos only accepts claims in event nonce order, so while a deposit is held no later event is
+//! claimed by this validator either. Every deposit over the threshold therefore delays all bridge
+//! traffic observed after it by however long the review takes, and a rejected deposit stays held
+//! until the operator approves it after all. The queue is not persisted, so restarting the process
+//! does not release anything, held deposits are observed again and wait for a fresh review.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReviewState {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+struct HeldClaim {
+    summary: String,
+    state: ReviewState,
+}
+
+lazy_static! {
+    // held claims by event nonce
+    static ref HELD_CLAIMS: Mutex<BTreeMap<u64, HeldClaim>> = Mutex::new(BTreeMap::new());
+}
+
+/// Queues the claim with this event nonce for review, returns its current state. `summary`
+/// describes the claim to the operator and is only recorded the first time the claim is queued
+pub fn request_review(event_nonce: u64, summary: String) -> ReviewState {
+    HELD_CLAIMS
+        .lock()
+        .unwrap()
+        .entry(event_nonce)
+        .or_insert(HeldClaim {
+            summary,
+            state: ReviewState::Pending,
+        })
+        .state
+}
+
+fn set_review_state(event_nonce: u64, state: ReviewState) -> bool {
+    match HELD_CLAIMS.lock().unwrap().get_mut(&event_nonce) {
+        Some(claim) => {
+            claim.state = state;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Approves a held claim so it is submitted on the next poll, returns false if no claim with this
+/// event nonce is held
+pub fn approve_claim(event_nonce: u64) -> bool {
+    set_review_state(event_nonce, ReviewState::Approved)
+}
+
+/// Rejects a held claim, it will not be submitted unless it is approved later. Returns false if no
+/// claim with this event nonce is held
+pub fn reject_claim(event_nonce: u64) -> bool {
+    set_review_state(event_nonce, ReviewState::Rejected)
+}
+
+/// Every claim that has not been approved, as (event nonce, state, summary) in nonce order
+pub fn held_claims() -> Vec<(u64, ReviewState, String)> {
+    HELD_CLAIMS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, claim)| claim.state != ReviewState::Approved)
+        .map(|(nonce, claim)| (*nonce, claim.state, claim.summary.clone()))
+        .collect()
+}
+
+/// Forgets every claim up to and including this event nonce, once the chain has accepted a claim
+/// it can never be submitted again so there is nothing left to review
+pub fn clear_reviews_through(event_nonce: u64) {
+    let mut held = HELD_CLAIMS.lock().unwrap();
+    *held = held.split_off(&event_nonce.saturating_add(1));
+}
+
+#[test]
+fn test_claim_review() {
+    assert_eq!(request_review(5, "five".to_string()), ReviewState::Pending);
+    assert_eq!(
+        request_review(5, "ignored".to_string()),
+        ReviewState::Pending
+    );
+    assert_eq!(request_review(7, "seven".to_string()), ReviewState::Pending);
+    assert!(!approve_claim(6));
+    assert!(approve_claim(5));
+    assert!(reject_claim(7));
+    assert_eq!(request_review(5, String::new()), ReviewState::Approved);
+    assert_eq!(
+        held_claims(),
+        vec![(7, ReviewState::Rejected, "seven".to_string())]
+    );
+    clear_reviews_through(5);
+    assert!(!approve_claim(5));
+    assert!(approve_claim(7));
+    clear_reviews_through(7);
+    assert!(held_claims().is_empty());
+}
@@ -2,6 +2,7 @@
 //! It's a common problem to have conflicts between ipv4 and ipv6 localhost and this module is first and foremost supposed to resolve that problem
 //! by trying more than one thing to handle potentially misconfigured inputs.
 
+use crate::rpc_endpoint::set_eth_rpc_endpoint;
 use clarity::Address as EthAddress;
 use contact::client::Contact;
 use deep_space::address::Address as CosmosAddress;
@@ -10,8 +11,8 @@ use peggy_proto::peggy::QueryDelegateKeysByEthAddress;
 use peggy_proto::peggy::QueryDelegateKeysByOrchestratorAddress;
 use std::process::exit;
 use std::time::Duration;
-use tokio::time::delay_for;
-use tonic::transport::Channel;
+use tokio::time::{delay_for, timeout as future_timeout};
+use tonic::transport::{Channel, Endpoint};
 use url::Url;
 use web30::client::Web3;
 
@@ -21,6 +22,54 @@ pub struct Connections {
     pub contact: Option<Contact>,
 }
 
+/// The default time allowed to establish a connection to the Cosmos gRPC server
+pub const DEFAULT_GRPC_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// The default time allowed for a single Cosmos gRPC request to complete
+pub const DEFAULT_GRPC_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// The default interval between TCP keep-alive probes on the gRPC connection
+pub const DEFAULT_GRPC_KEEP_ALIVE: Duration = Duration::from_secs(60);
+
+/// Connection level settings for the Cosmos gRPC channel, without these a blip
+/// in the gRPC endpoint can leave queries hanging indefinitely
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GrpcChannelConfig {
+    pub connect_timeout: Duration,
+    pub request_timeout: Duration,
+    pub keep_alive: Option<Duration>,
+}
+
+impl Default for GrpcChannelConfig {
+    fn default() -> Self {
+        GrpcChannelConfig {
+            connect_timeout: DEFAULT_GRPC_CONNECT_TIMEOUT,
+            request_timeout: DEFAULT_GRPC_REQUEST_TIMEOUT,
+            keep_alive: Some(DEFAULT_GRPC_KEEP_ALIVE),
+        }
+    }
+}
+
+/// Builds a Cosmos gRPC client with the provided timeouts and keep-alive. Once connected
+/// the underlying Channel transparently reconnects after transport errors, in the meantime
+/// requests fail with a timeout error rather than blocking forever.
+pub async fn connect_grpc(
+    grpc_url: String,
+    config: &GrpcChannelConfig,
+) -> Result<PeggyQueryClient<Channel>, String> {
+    let endpoint = Endpoint::from_shared(grpc_url.clone())
+        .map_err(|e| format!("Invalid gRPC url {} {:?}", grpc_url, e))?
+        .timeout(config.request_timeout)
+        .tcp_keepalive(config.keep_alive);
+    match future_timeout(config.connect_timeout, endpoint.connect()).await {
+        Ok(Ok(channel)) => Ok(PeggyQueryClient::new(channel)),
+        Ok(Err(e)) => Err(format!("Failed to connect to {} {:?}", grpc_url, e)),
+        Err(_) => Err(format!(
+            "Timed out after {}s connecting to {}",
+            config.connect_timeout.as_secs(),
+            grpc_url
+        )),
+    }
+}
+
 /// Returns the three major RPC connections required for Gravity
 /// operation in a error resilient manner. TODO find some way to generalize
 /// this so that it's less ugly
@@ -29,6 +78,24 @@ pub async fn create_rpc_connections(
     legacy_rpc_url: Option<String>,
     eth_rpc_url: Option<String>,
     timeout: Duration,
+) -> Connections {
+    create_rpc_connections_with_grpc_config(
+        grpc_url,
+        legacy_rpc_url,
+        eth_rpc_url,
+        timeout,
+        GrpcChannelConfig::default(),
+    )
+    .await
+}
+
+/// The same as create_rpc_connections but with custom settings for the gRPC channel
+pub async fn create_rpc_connections_with_grpc_config(
+    grpc_url: Option<String>,
+    legacy_rpc_url: Option<String>,
+    eth_rpc_url: Option<String>,
+    timeout: Duration,
+    grpc_config: GrpcChannelConfig,
 ) -> Connections {
     let mut web3 = None;
     let mut grpc = None;
@@ -39,7 +106,7 @@ pub async fn create_rpc_connections(
         check_scheme(&url, &grpc_url);
         let cosmos_grpc_url = grpc_url.trim_end_matches('/').to_string();
         // try the base url first.
-        let try_base = PeggyQueryClient::connect(cosmos_grpc_url).await;
+        let try_base = connect_grpc(cosmos_grpc_url, &grpc_config).await;
         match try_base {
             // it worked, lets go!
             Ok(val) => grpc = Some(val),
@@ -55,8 +122,8 @@ pub async fn create_rpc_connections(
                     let prefix = url.scheme();
                     let ipv6_url = format!("{}://::1:{}", prefix, port);
                     let ipv4_url = format!("{}://127.0.0.1:{}", prefix, port);
-                    let ipv6 = PeggyQueryClient::connect(ipv6_url.clone()).await;
-                    let ipv4 = PeggyQueryClient::connect(ipv4_url.clone()).await;
+                    let ipv6 = connect_grpc(ipv6_url.clone(), &grpc_config).await;
+                    let ipv4 = connect_grpc(ipv4_url.clone(), &grpc_config).await;
                     warn!("Trying fallback urls {} {}", ipv6_url, ipv4_url);
                     match (ipv4, ipv6) {
                         (Ok(v), Err(_)) => {
@@ -77,8 +144,8 @@ pub async fn create_rpc_connections(
                     // transparently upgrade to https if available, we can't transparently downgrade for obvious security reasons
                     let https_on_80_url = format!("https://{}:80", body);
                     let https_on_443_url = format!("https://{}:443", body);
-                    let https_on_80 = PeggyQueryClient::connect(https_on_80_url.clone()).await;
-                    let https_on_443 = PeggyQueryClient::connect(https_on_443_url.clone()).await;
+                    let https_on_80 = connect_grpc(https_on_80_url.clone(), &grpc_config).await;
+                    let https_on_443 = connect_grpc(https_on_443_url.clone(), &grpc_config).await;
                     warn!(
                         "Trying fallback urls {} {}",
                         https_on_443_url, https_on_80_url
@@ -182,7 +249,10 @@ pub async fn create_rpc_connections(
         let try_base = base_web30.eth_block_number().await;
         match try_base {
             // it worked, lets go!
-            Ok(_) => web3 = Some(base_web30),
+            Ok(_) => {
+                set_eth_rpc_endpoint(eth_url);
+                web3 = Some(base_web30)
+            }
             // did not work, now we check if it's localhost
             Err(e) => {
                 warn!(
@@ -203,10 +273,12 @@ pub async fn create_rpc_connections(
                     match (ipv4_test, ipv6_test) {
                         (Ok(_), Err(_)) => {
                             info!("Url fallback succeeded, your Ethereum rpc url {} has been corrected to {}", eth_rpc_url, ipv4_url);
+                            set_eth_rpc_endpoint(&ipv4_url);
                             web3 = Some(ipv4_web3)
                         }
                         (Err(_), Ok(_)) => {
                             info!("Url fallback succeeded, your Ethereum  rpc url {} has been corrected to {}", eth_rpc_url, ipv6_url);
+                            set_eth_rpc_endpoint(&ipv6_url);
                             web3 = Some(ipv6_web3)
                         },
                         (Ok(_), Ok(_)) => panic!("This should never happen? Why didn't things work the first time?"),
@@ -230,10 +302,12 @@ pub async fn create_rpc_connections(
                     match (https_on_80_test, https_on_443_test) {
                         (Ok(_), Err(_)) => {
                             info!("Https upgrade succeeded, your Ethereum rpc url {} has been corrected to {}", eth_rpc_url, https_on_80_url);
+                            set_eth_rpc_endpoint(&https_on_80_url);
                             web3 = Some(https_on_80_web3)
                         },
                         (Err(_), Ok(_)) => {
                             info!("Https upgrade succeeded, your Ethereum rpc url {} has been corrected to {}", eth_rpc_url, https_on_443_url);
+                            set_eth_rpc_endpoint(&https_on_443_url);
                             web3 = Some(https_on_443_web3)
                         },
                         (Ok(_), Ok(_)) => panic!("This should never happen? Why didn't things work the first time?"),
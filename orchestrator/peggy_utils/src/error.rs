@@ -1,6 +1,7 @@
 //! for things that don't belong in the cosmos or ethereum libraries but also don't belong
 //! in a function specific library
 
+use clarity::Address as EthAddress;
 use clarity::Error as ClarityError;
 use contact::jsonrpc::error::JsonRpcError;
 use deep_space::address::AddressError as CosmosAddressError;
@@ -26,6 +27,62 @@ pub enum PeggyError {
     CosmosgRPCError(Status),
     InsufficientVotingPowerToPass(String),
     ParseBigIntError(ParseBigIntError),
+    GasOracleError(String),
+    PriceFeedError(String),
+    /// Claims were submitted but our last event nonce on Cosmos did not move
+    NonceNotAdvanced {
+        last_event_nonce: u64,
+        txhash: String,
+    },
+    /// The signatures collected so far do not represent enough of the current valset's power
+    InsufficientSignerPower {
+        nonce: u64,
+        details: String,
+    },
+    /// Estimating the gas cost of an Ethereum transaction failed, usually because it would revert
+    GasEstimateFailed {
+        nonce: u64,
+        cause: String,
+    },
+    /// An RPC endpoint the bridge depends on could not be reached
+    RpcUnavailable {
+        endpoint: String,
+        cause: String,
+    },
+    /// An Ethereum keystore file could not be read or decrypted
+    KeystoreError(String),
+    /// The claims transaction was included in a block but failed
+    ClaimTxRejected {
+        txhash: String,
+        code: u32,
+        raw_log: String,
+    },
+    /// The claims transaction was not included in a block before the inclusion timeout
+    ClaimTxPending {
+        txhash: String,
+    },
+    /// A valset has no members with an Ethereum address, or no power, once empty addresses are
+    /// filtered out. The contract would reject it after the gas is spent
+    EmptyValset {
+        nonce: u64,
+        cause: String,
+    },
+    /// The runtime bytecode at the contract address does not hash to the expected value
+    ContractBytecodeMismatch {
+        contract: EthAddress,
+        expected: String,
+        actual: String,
+    },
+    /// Claim submission is held because the Peggy contract was upgraded at this block
+    ClaimsHeldForUpgrade {
+        block: u64,
+    },
+    /// Our last event nonce on Cosmos went backwards since it was last read
+    NonceRegression {
+        last_event_nonce: u64,
+        new_event_nonce: u64,
+        halted: bool,
+    },
 }
 
 impl fmt::Display for PeggyError {
@@ -53,6 +110,80 @@ impl fmt::Display for PeggyError {
                 write!(f, "{}", val)
             }
             PeggyError::ParseBigIntError(val) => write!(f, "Failed to parse big integer {}", val),
+            PeggyError::GasOracleError(val) => write!(f, "Gas oracle error {}", val),
+            PeggyError::PriceFeedError(val) => write!(f, "Price feed error {}", val),
+            PeggyError::NonceNotAdvanced {
+                last_event_nonce,
+                txhash,
+            } => write!(
+                f,
+                "Claims did not process, still on event nonce {}, check txhash {} for errors. Hint: make sure your orchestrator account has enough fee tokens and your Cosmos node is synced, on chains with slow blocks raise --claim-confirm-delay",
+                last_event_nonce, txhash
+            ),
+            PeggyError::InsufficientSignerPower { nonce, details } => write!(
+                f,
+                "Not enough signing power to submit nonce {} {}. Hint: this usually resolves once more validators sign, if it persists check that validators are running their orchestrators",
+                nonce, details
+            ),
+            PeggyError::GasEstimateFailed { nonce, cause } => write!(
+                f,
+                "Gas estimate for nonce {} failed {}. Hint: the transaction would likely revert, check that it has not already been submitted or timed out",
+                nonce, cause
+            ),
+            PeggyError::RpcUnavailable { endpoint, cause } => write!(
+                f,
+                "{} is unavailable {}. Hint: check that the node is running, synced, and reachable at the configured url",
+                endpoint, cause
+            ),
+            PeggyError::KeystoreError(val) => write!(
+                f,
+                "Keystore error {}. Hint: check the passphrase and that the file is a V3 keystore as written by geth",
+                val
+            ),
+            PeggyError::ClaimTxRejected {
+                txhash,
+                code,
+                raw_log,
+            } => write!(
+                f,
+                "Claims tx {} failed with code {} {}. Hint: make sure your orchestrator account has enough fee tokens and is the registered delegate of your validator",
+                txhash, code, raw_log
+            ),
+            PeggyError::ClaimTxPending { txhash } => write!(
+                f,
+                "Claims tx {} is not in a block yet. Hint: the Cosmos chain is likely congested, the claims are retried once it is included or dropped, raise --claim-inclusion-timeout to wait longer",
+                txhash
+            ),
+            PeggyError::EmptyValset { nonce, cause } => write!(
+                f,
+                "Valset {} {}, not submitting. Hint: validators must register an Ethereum address with set_orchestrator_address before their power counts",
+                nonce, cause
+            ),
+            PeggyError::ContractBytecodeMismatch {
+                contract,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Contract {} bytecode hash is {} but {} was expected. Hint: check --contract-address and --contract-code-hash are for the same deployment",
+                contract, actual, expected
+            ),
+            PeggyError::ClaimsHeldForUpgrade { block } => write!(
+                f,
+                "Claims are held, the Peggy contract was upgraded at block {}. Hint: verify the new implementation then send acknowledge-upgrade to the relayer --control-socket",
+                block
+            ),
+            PeggyError::NonceRegression {
+                last_event_nonce,
+                new_event_nonce,
+                halted,
+            } => write!(
+                f,
+                "Our last event nonce went back from {} to {}{}. Hint: the Cosmos node may be on a fork or have corrupted state, check it before restarting the orchestrator",
+                last_event_nonce,
+                new_event_nonce,
+                if *halted { ", claim submission is halted" } else { "" }
+            ),
         }
     }
 }
@@ -0,0 +1,67 @@
+//! The event signatures emitted by the Peggy contract. Logs are matched by the keccak hash
+//! of these strings, so if the contract changes an event and these are not updated the oracle
+//! simply stops seeing those events rather than producing an error.
+
+pub const SEND_TO_COSMOS_EVENT_SIG: &str =
+    "SendToCosmosEvent(address,address,bytes32,uint256,uint256)";
+pub const TRANSACTION_BATCH_EXECUTED_EVENT_SIG: &str =
+    "TransactionBatchExecutedEvent(uint256,address,uint256)";
+/// The valset update event with the relayer reward, (nonce, event nonce, reward amount, reward
+/// token, validators, powers)
+pub const VALSET_UPDATED_EVENT_SIG: &str =
+    "ValsetUpdatedEvent(uint256,uint256,uint256,address,address[],uint256[])";
+/// The valset update event of contracts deployed before relayer rewards
+pub const LEGACY_VALSET_UPDATED_EVENT_SIG: &str = "ValsetUpdatedEvent(uint256,address[],uint256[])";
+/// Both valset update events, valsets are searched for with each of these
+pub const VALSET_UPDATED_EVENT_SIGS: [&str; 2] =
+    [VALSET_UPDATED_EVENT_SIG, LEGACY_VALSET_UPDATED_EVENT_SIG];
+pub const ERC20_DEPLOYED_EVENT_SIG: &str =
+    "ERC20DeployedEvent(string,address,string,string,uint8,uint256)";
+pub const LOGIC_CALL_EVENT_SIG: &str = "LogicCallEvent(bytes32,uint256,bytes,uint256)";
+
+/// Computes the topic an event signature is logged under, the first topic of every log
+pub fn event_topic(signature: &str) -> Vec<u8> {
+    use sha3::{Digest, Keccak256};
+    Keccak256::digest(signature.as_bytes()).to_vec()
+}
+
+#[test]
+fn test_event_signature_topics() {
+    use clarity::utils::bytes_to_hex_str;
+
+    // topics of the events as emitted by the deployed Peggy.sol and its predecessor
+    let known_topics = [
+        (
+            SEND_TO_COSMOS_EVENT_SIG,
+            "d7767894d73c589daeca9643f445f03d7be61aad2950c117e7cbff4176fca7e4",
+        ),
+        (
+            TRANSACTION_BATCH_EXECUTED_EVENT_SIG,
+            "02c7e81975f8edb86e2a0c038b7b86a49c744236abf0f6177ff5afc6986ab708",
+        ),
+        (
+            VALSET_UPDATED_EVENT_SIG,
+            "76d08978c024a4bf8cbb30c67fd78fcaa1827cbc533e4e175f36d07e64ccf96a",
+        ),
+        (
+            LEGACY_VALSET_UPDATED_EVENT_SIG,
+            "c6d025c076bafcdd040f00632d5e280b3a5188963f110f8c70c4f810184b30f3",
+        ),
+        (
+            ERC20_DEPLOYED_EVENT_SIG,
+            "82fe3a4fa49c6382d0c085746698ddbbafe6c2bf61285b19410644b5b26287c7",
+        ),
+        (
+            LOGIC_CALL_EVENT_SIG,
+            "7c2bb24f8e1b3725cb613d7f11ef97d9745cc97a0e40f730621c052d684077a1",
+        ),
+    ];
+    for (sig, topic) in known_topics.iter() {
+        assert_eq!(
+            bytes_to_hex_str(&event_topic(sig)),
+            *topic,
+            "topic mismatch for {}",
+            sig
+        );
+    }
+}
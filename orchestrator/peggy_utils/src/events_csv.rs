@@ -0,0 +1,143 @@
+//! Exports observed bridge events as CSV so that deposits and withdrawals can be reconciled in a
+//! spreadsheet. Every event type shares the same columns, those that don't apply are left empty,
+//! so the output of several calls can be concatenated into one file.
+
+use crate::types::{SendToCosmosEvent, TransactionBatchExecutedEvent};
+use num256::Uint256;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+pub const EVENTS_CSV_HEADER: &str =
+    "type,block,event_nonce,sender,destination,token,amount,timestamp";
+
+/// An event that can be written as a row of the events CSV
+pub trait CsvEvent {
+    fn block_height(&self) -> &Uint256;
+    /// Every column but the timestamp, in header order
+    fn csv_fields(&self) -> [String; 7];
+}
+
+impl CsvEvent for SendToCosmosEvent {
+    fn block_height(&self) -> &Uint256 {
+        &self.block_height
+    }
+
+    fn csv_fields(&self) -> [String; 7] {
+        [
+            "deposit".to_string(),
+            self.block_height.to_string(),
+            self.event_nonce.to_string(),
+            self.sender.to_string(),
+            self.destination.to_string(),
+            self.erc20.to_string(),
+            self.amount.to_string(),
+        ]
+    }
+}
+
+impl CsvEvent for TransactionBatchExecutedEvent {
+    fn block_height(&self) -> &Uint256 {
+        &self.block_height
+    }
+
+    fn csv_fields(&self) -> [String; 7] {
+        [
+            "withdraw_batch".to_string(),
+            self.block_height.to_string(),
+            self.event_nonce.to_string(),
+            String::new(),
+            String::new(),
+            self.erc20.to_string(),
+            String::new(),
+        ]
+    }
+}
+
+/// Quotes a field if it contains a character that is special in CSV
+fn escape_csv_field(field: &str) -> String {
+    if field.contains(|c: char| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Writes the events as CSV rows without a header, for appending to an existing export.
+/// `block_timestamps` maps block heights to unix timestamps, events in a block that isn't in the
+/// map are written with an empty timestamp
+pub fn append_events_csv<T: CsvEvent, W: Write>(
+    events: &[T],
+    block_timestamps: &HashMap<Uint256, u64>,
+    writer: &mut W,
+) -> io::Result<()> {
+    for event in events {
+        let timestamp = block_timestamps
+            .get(event.block_height())
+            .map(|t| t.to_string())
+            .unwrap_or_default();
+        let row: Vec<String> = event
+            .csv_fields()
+            .iter()
+            .chain(Some(&timestamp))
+            .map(|field| escape_csv_field(field))
+            .collect();
+        writeln!(writer, "{}", row.join(","))?;
+    }
+    Ok(())
+}
+
+/// Writes the header followed by a row for each event
+pub fn write_events_csv<T: CsvEvent, W: Write>(
+    events: &[T],
+    block_timestamps: &HashMap<Uint256, u64>,
+    writer: &mut W,
+) -> io::Result<()> {
+    writeln!(writer, "{}", EVENTS_CSV_HEADER)?;
+    append_events_csv(events, block_timestamps, writer)
+}
+
+#[test]
+fn test_write_events_csv() {
+    use deep_space::address::Address as CosmosAddress;
+
+    let erc20 = "0x6b175474e89094c44da98b954eedeac495271d0f"
+        .parse()
+        .unwrap();
+    let deposit = SendToCosmosEvent {
+        erc20,
+        sender: "0x1a2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d"
+            .parse()
+            .unwrap(),
+        destination: CosmosAddress::from_bytes([1u8; 20]),
+        amount: 1_000_000u64.into(),
+        event_nonce: 4u8.into(),
+        block_height: 100u8.into(),
+    };
+    let withdraw = TransactionBatchExecutedEvent {
+        batch_nonce: 7u8.into(),
+        block_height: 101u8.into(),
+        erc20,
+        event_nonce: 5u8.into(),
+    };
+    let mut timestamps = HashMap::new();
+    timestamps.insert(100u8.into(), 1_600_000_000u64);
+
+    let mut out = Vec::new();
+    write_events_csv(&[deposit.clone()], &timestamps, &mut out).unwrap();
+    append_events_csv(&[withdraw], &timestamps, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], EVENTS_CSV_HEADER);
+    assert_eq!(
+        lines[1],
+        format!(
+            "deposit,100,4,{},{},{},1000000,1600000000",
+            deposit.sender, deposit.destination, erc20
+        )
+    );
+    assert_eq!(lines[2], format!("withdraw_batch,101,5,,,{},,", erc20));
+
+    assert_eq!(escape_csv_field("a,b"), "\"a,b\"");
+    assert_eq!(escape_csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+}
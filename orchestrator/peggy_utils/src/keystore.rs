@@ -0,0 +1,232 @@
+//! Loads Ethereum private keys from Web3 Secret Storage (V3 keystore) files, the encrypted UTC/JSON
+//! format used by geth and most Ethereum wallets, so that operators don't need to keep plaintext keys
+//! on disk. Both the scrypt and pbkdf2 key derivation functions are supported. Neither the decrypted
+//! key nor anything derived from it is ever logged or included in an error.
+
+use crate::error::PeggyError;
+use aes_ctr::cipher::generic_array::GenericArray;
+use aes_ctr::cipher::stream::{NewStreamCipher, SyncStreamCipher};
+use aes_ctr::Aes128Ctr;
+use clarity::utils::hex_str_to_bytes;
+use clarity::PrivateKey as EthPrivateKey;
+use hmac::Hmac;
+use sha2::Sha256;
+use sha3::{Digest, Keccak256};
+use std::env;
+use std::fs;
+
+/// The keystore passphrase, when unset the passphrase is prompted for on the terminal
+pub const KEYSTORE_PASSPHRASE_ENV: &str = "GRAVITY_KEYSTORE_PASSPHRASE";
+
+#[derive(Deserialize, Debug)]
+struct Keystore {
+    #[serde(alias = "Crypto")]
+    crypto: KeystoreCrypto,
+}
+
+#[derive(Deserialize, Debug)]
+struct KeystoreCrypto {
+    cipher: String,
+    cipherparams: CipherParams,
+    ciphertext: String,
+    #[serde(flatten)]
+    kdf: Kdf,
+    mac: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct CipherParams {
+    iv: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "kdf", content = "kdfparams", rename_all = "lowercase")]
+enum Kdf {
+    Scrypt {
+        dklen: usize,
+        n: u64,
+        r: u32,
+        p: u32,
+        salt: String,
+    },
+    Pbkdf2 {
+        dklen: usize,
+        c: u32,
+        prf: String,
+        salt: String,
+    },
+}
+
+fn keystore_error(message: &str) -> PeggyError {
+    PeggyError::KeystoreError(message.to_string())
+}
+
+fn decode_hex(input: &str, field: &str) -> Result<Vec<u8>, PeggyError> {
+    hex_str_to_bytes(input).map_err(|_| keystore_error(&format!("invalid hex in {}", field)))
+}
+
+fn derive_key(kdf: &Kdf, passphrase: &[u8]) -> Result<Vec<u8>, PeggyError> {
+    match kdf {
+        Kdf::Scrypt {
+            dklen,
+            n,
+            r,
+            p,
+            salt,
+        } => {
+            if !n.is_power_of_two() {
+                return Err(keystore_error("scrypt n must be a power of two"));
+            }
+            let params = scrypt::ScryptParams::new(n.trailing_zeros() as u8, *r, *p)
+                .map_err(|_| keystore_error("invalid scrypt parameters"))?;
+            let mut key = vec![0u8; *dklen];
+            scrypt::scrypt(passphrase, &decode_hex(salt, "salt")?, &params, &mut key)
+                .map_err(|_| keystore_error("invalid scrypt dklen"))?;
+            Ok(key)
+        }
+        Kdf::Pbkdf2 {
+            dklen,
+            c,
+            prf,
+            salt,
+        } => {
+            if prf != "hmac-sha256" {
+                return Err(keystore_error(&format!("unsupported pbkdf2 prf {}", prf)));
+            }
+            let mut key = vec![0u8; *dklen];
+            pbkdf2::pbkdf2::<Hmac<Sha256>>(passphrase, &decode_hex(salt, "salt")?, *c, &mut key);
+            Ok(key)
+        }
+    }
+}
+
+/// Decrypts the private key in a V3 keystore file's contents
+pub fn decrypt_keystore(keystore: &str, passphrase: &str) -> Result<EthPrivateKey, PeggyError> {
+    let keystore: Keystore = serde_json::from_str(keystore)
+        .map_err(|e| keystore_error(&format!("invalid keystore json {}", e)))?;
+    let crypto = keystore.crypto;
+    if crypto.cipher != "aes-128-ctr" {
+        return Err(keystore_error(&format!(
+            "unsupported cipher {}",
+            crypto.cipher
+        )));
+    }
+    let derived_key = derive_key(&crypto.kdf, passphrase.as_bytes())?;
+    if derived_key.len() < 32 {
+        return Err(keystore_error("dklen must be at least 32"));
+    }
+    let mut ciphertext = decode_hex(&crypto.ciphertext, "ciphertext")?;
+    let iv = decode_hex(&crypto.cipherparams.iv, "iv")?;
+    if iv.len() != 16 {
+        return Err(keystore_error("iv must be 16 bytes"));
+    }
+
+    let mut mac_input = derived_key[16..32].to_vec();
+    mac_input.extend_from_slice(&ciphertext);
+    if Keccak256::digest(&mac_input).as_slice() != decode_hex(&crypto.mac, "mac")?.as_slice() {
+        return Err(keystore_error(
+            "mac mismatch, the passphrase is probably wrong",
+        ));
+    }
+
+    let mut cipher = Aes128Ctr::new(
+        GenericArray::from_slice(&derived_key[0..16]),
+        GenericArray::from_slice(&iv),
+    );
+    cipher.apply_keystream(&mut ciphertext);
+    EthPrivateKey::from_slice(&ciphertext)
+        .map_err(|_| keystore_error("decrypted data is not a valid private key"))
+}
+
+/// Reads and decrypts a keystore file, taking the passphrase from GRAVITY_KEYSTORE_PASSPHRASE
+/// or prompting for it on the terminal if that is not set
+pub fn load_keystore(path: &str) -> Result<EthPrivateKey, PeggyError> {
+    let keystore = fs::read_to_string(path)
+        .map_err(|e| keystore_error(&format!("failed to read {} {}", path, e)))?;
+    let passphrase = match env::var(KEYSTORE_PASSPHRASE_ENV) {
+        Ok(passphrase) => passphrase,
+        Err(_) => {
+            rpassword::read_password_from_tty(Some(&format!("Passphrase for keystore {}: ", path)))
+                .map_err(|e| keystore_error(&format!("failed to read passphrase {}", e)))?
+        }
+    };
+    decrypt_keystore(&keystore, &passphrase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the pbkdf2 test vector from the Web3 Secret Storage definition
+    const PBKDF2_KEYSTORE: &str = r#"{
+        "crypto" : {
+            "cipher" : "aes-128-ctr",
+            "cipherparams" : {
+                "iv" : "6087dab2f9fdbbfaddc31a909735c1e6"
+            },
+            "ciphertext" : "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46",
+            "kdf" : "pbkdf2",
+            "kdfparams" : {
+                "c" : 262144,
+                "dklen" : 32,
+                "prf" : "hmac-sha256",
+                "salt" : "ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd"
+            },
+            "mac" : "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+        },
+        "id" : "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+        "version" : 3
+    }"#;
+
+    // the same key and passphrase encrypted with scrypt, using the Web3 Secret Storage salt and iv
+    // with a lower work factor so the test runs quickly, generated independently with Python's
+    // hashlib.scrypt and openssl
+    const SCRYPT_KEYSTORE: &str = r#"{
+        "crypto" : {
+            "cipher" : "aes-128-ctr",
+            "cipherparams" : {
+                "iv" : "83dbcc02d8ccb40e466191a123791e0e"
+            },
+            "ciphertext" : "91c23c03a8b5117a6e3d78dbb5dfc46f037aff9351dff59c2879586f51756502",
+            "kdf" : "scrypt",
+            "kdfparams" : {
+                "dklen" : 32,
+                "n" : 8192,
+                "r" : 8,
+                "p" : 1,
+                "salt" : "ab0c7876052600dd703518d6fc3fe8984592145b591fc8fb5c6d43190334ba19"
+            },
+            "mac" : "8f8639fe08fe1f43d6c13006d66ad2d993c6674ee2ba03f025596797a73614b9"
+        },
+        "id" : "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+        "version" : 3
+    }"#;
+
+    #[test]
+    fn test_decrypt_keystore() {
+        let expected: EthPrivateKey =
+            "0x7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d"
+                .parse()
+                .unwrap();
+        let key = decrypt_keystore(PBKDF2_KEYSTORE, "testpassword").unwrap();
+        assert_eq!(
+            key.to_public_key().unwrap(),
+            expected.to_public_key().unwrap()
+        );
+        assert!(decrypt_keystore(PBKDF2_KEYSTORE, "wrongpassword").is_err());
+    }
+
+    #[test]
+    fn test_decrypt_scrypt_keystore() {
+        let expected: EthPrivateKey =
+            "0x7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d"
+                .parse()
+                .unwrap();
+        let key = decrypt_keystore(SCRYPT_KEYSTORE, "testpassword").unwrap();
+        assert_eq!(
+            key.to_public_key().unwrap(),
+            expected.to_public_key().unwrap()
+        );
+        assert!(decrypt_keystore(SCRYPT_KEYSTORE, "wrongpassword").is_err());
+    }
+}
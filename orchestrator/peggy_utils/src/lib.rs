@@ -4,8 +4,19 @@
 extern crate serde_derive;
 #[macro_use]
 extern crate log;
+#[macro_use]
+extern crate lazy_static;
 
+pub mod claim_review;
 pub mod connection_prep;
 pub mod error;
+pub mod event_signatures;
+pub mod events_csv;
+pub mod keystore;
 pub mod message_signatures;
+pub mod metrics;
+pub mod relay_pause;
+pub mod rpc_endpoint;
+pub mod rpc_headers;
+pub mod state_snapshot;
 pub mod types;
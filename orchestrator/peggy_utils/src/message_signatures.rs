@@ -1,6 +1,8 @@
-use crate::types::{LogicCall, TransactionBatch, Valset};
+use crate::types::{Confirm, LogicCall, TransactionBatch, Valset};
 use clarity::abi::{encode_tokens, Token};
 use clarity::utils::get_ethereum_msg_hash;
+use clarity::Address as EthAddress;
+use sha3::{Digest, Keccak256};
 
 /// takes the required input data and produces the required signature to confirm a validator
 /// set update on the Peggy Ethereum contract. This value will then be signed before being
@@ -23,11 +25,19 @@ pub fn encode_valset_confirm_hashed(peggy_id: String, valset: Valset) -> Vec<u8>
     get_ethereum_msg_hash(&digest)
 }
 
+/// The checkpoint the Peggy contract stores in state_lastValsetCheckpoint once this valset
+/// has been submitted, unlike encode_valset_confirm_hashed this is not prefixed as an Ethereum
+/// signed message
+pub fn compute_valset_checkpoint(peggy_id: String, valset: Valset) -> [u8; 32] {
+    let mut checkpoint = [0u8; 32];
+    checkpoint.copy_from_slice(&Keccak256::digest(&encode_valset_confirm(peggy_id, valset)));
+    checkpoint
+}
+
 #[test]
 fn test_valset_signature() {
     use crate::types::ValsetMember;
     use clarity::utils::hex_str_to_bytes;
-    use sha3::{Digest, Keccak256};
 
     let correct_hash: Vec<u8> =
         hex_str_to_bytes("0x88165860d955aee7dc3e83d9d1156a5864b708841965585d206dbef6e9e1a499")
@@ -63,9 +73,13 @@ fn test_valset_signature() {
             },
         ],
     };
-    let checkpoint = encode_valset_confirm("foo".to_string(), valset);
+    let checkpoint = encode_valset_confirm("foo".to_string(), valset.clone());
     let checkpoint_hash = Keccak256::digest(&checkpoint);
     assert_eq!(correct_hash, checkpoint_hash.as_slice());
+    assert_eq!(
+        correct_hash,
+        compute_valset_checkpoint("foo".to_string(), valset).to_vec()
+    );
 
     // the same valset, except with an intentionally incorrect hash
     let valset = Valset {
@@ -126,6 +140,70 @@ pub fn encode_tx_batch_confirm_hashed(peggy_id: String, batch: TransactionBatch)
     get_ethereum_msg_hash(&digest)
 }
 
+/// The result of checking one confirm's signature against the hash we expect it to sign
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignatureCheck {
+    /// The Ethereum address the confirm says signed it
+    pub claimed_signer: EthAddress,
+    /// The address that actually signed our hash, None if the signature is malformed
+    pub recovered_signer: Option<EthAddress>,
+}
+
+impl SignatureCheck {
+    /// A mismatch means the validator signed some other message, such as one built with a
+    /// different peggy_id
+    pub fn matches(&self) -> bool {
+        self.recovered_signer == Some(self.claimed_signer)
+    }
+}
+
+/// Recovers the signer of every confirm as if it signed `signed_message`, for debugging
+/// signatures the relayer won't accept
+pub fn check_signatures<T: Confirm>(signed_message: &[u8], confirms: &[T]) -> Vec<SignatureCheck> {
+    confirms
+        .iter()
+        .map(|confirm| {
+            let signature = confirm.get_signature();
+            let recovered_signer = if signature.is_valid() {
+                signature.recover(signed_message).ok()
+            } else {
+                None
+            };
+            SignatureCheck {
+                claimed_signer: confirm.get_eth_address(),
+                recovered_signer,
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_check_signatures() {
+    use crate::types::ValsetConfirmResponse;
+    use clarity::PrivateKey as EthPrivateKey;
+
+    let key = EthPrivateKey::from_slice(&[1u8; 32]).unwrap();
+    let address = key.to_public_key().unwrap();
+    let hash = vec![7u8; 32];
+    let confirms = vec![
+        ValsetConfirmResponse {
+            eth_address: address,
+            eth_signature: key.sign_hash(&hash),
+            ..Default::default()
+        },
+        // signed with a different peggy_id
+        ValsetConfirmResponse {
+            eth_address: address,
+            eth_signature: key.sign_hash(&[8u8; 32]),
+            ..Default::default()
+        },
+    ];
+    let checks = check_signatures(&hash, &confirms);
+    assert!(checks[0].matches());
+    assert_eq!(checks[0].recovered_signer, Some(address));
+    assert!(!checks[1].matches());
+}
+
 #[test]
 fn test_batch_signature() {
     use crate::types::BatchTransaction;
@@ -133,7 +211,6 @@ fn test_batch_signature() {
     use clarity::utils::hex_str_to_bytes;
     use clarity::PrivateKey as EthPrivateKey;
     use rand::Rng;
-    use sha3::{Digest, Keccak256};
 
     let correct_hash: Vec<u8> =
         hex_str_to_bytes("0xa3a7ee0a363b8ad2514e7ee8f110d7449c0d88f3b0913c28c1751e6e0079a9b2")
@@ -275,7 +352,6 @@ fn test_logic_call_signature() {
     use crate::types::ERC20Token;
     use crate::types::LogicCall;
     use clarity::utils::hex_str_to_bytes;
-    use sha3::{Digest, Keccak256};
 
     let correct_hash: Vec<u8> =
         hex_str_to_bytes("0x1de95c9ace999f8ec70c6dc8d045942da2612950567c4861aca959c0650194da")
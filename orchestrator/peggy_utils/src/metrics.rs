@@ -0,0 +1,361 @@
+//! A minimal metrics registry for the orchestrator and relayer, rendered in the Prometheus text
+//! exposition format. Counter values are kept as Uint256 since token amounts in base units
+//! routinely exceed what fits in a u64 or can be represented exactly as a float, histograms only
+//! hold durations so they use floats.
+//!
+//! start_metrics_server serves render_metrics over HTTP at `/metrics` for a Prometheus scraper.
+
+use clarity::Uint256;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io::{self, BufRead, BufReader, Write as IoWrite};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// How long a scrape may take to send its request or read the response, requests are served one
+/// at a time so a client that stalls would otherwise block every later scrape
+const METRICS_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A monotonically increasing counter with a single label
+pub struct CounterVec {
+    name: &'static str,
+    help: &'static str,
+    label: &'static str,
+    values: Mutex<BTreeMap<String, Uint256>>,
+}
+
+impl CounterVec {
+    pub fn new(name: &'static str, help: &'static str, label: &'static str) -> Self {
+        CounterVec {
+            name,
+            help,
+            label,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn inc_by(&self, label_value: &str, amount: Uint256) {
+        let mut values = self.values.lock().unwrap();
+        let value = values
+            .entry(label_value.to_string())
+            .or_insert_with(|| 0u8.into());
+        *value = value.clone() + amount;
+    }
+
+    pub fn get(&self, label_value: &str) -> Uint256 {
+        let values = self.values.lock().unwrap();
+        values
+            .get(label_value)
+            .cloned()
+            .unwrap_or_else(|| 0u8.into())
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "# HELP {} {}", self.name, self.help).unwrap();
+        writeln!(out, "# TYPE {} counter", self.name).unwrap();
+        for (label_value, value) in self.values.lock().unwrap().iter() {
+            writeln!(
+                out,
+                "{}{{{}=\"{}\"}} {}",
+                self.name, self.label, label_value, value
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+/// A single value that can go up and down
+pub struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    value: Mutex<Option<Uint256>>,
+}
+
+impl Gauge {
+    pub fn new(name: &'static str, help: &'static str) -> Self {
+        Gauge {
+            name,
+            help,
+            value: Mutex::new(None),
+        }
+    }
+
+    pub fn set(&self, value: Uint256) {
+        *self.value.lock().unwrap() = Some(value);
+    }
+
+    pub fn get(&self) -> Option<Uint256> {
+        self.value.lock().unwrap().clone()
+    }
+
+    /// A gauge that has never been set renders only its help and type
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "# HELP {} {}", self.name, self.help).unwrap();
+        writeln!(out, "# TYPE {} gauge", self.name).unwrap();
+        if let Some(value) = self.get() {
+            writeln!(out, "{} {}", self.name, value).unwrap();
+        }
+        out
+    }
+}
+
+/// Observations counted into cumulative buckets, each bucket holds the observations less than or
+/// equal to its upper bound
+pub struct Histogram {
+    name: &'static str,
+    help: &'static str,
+    bounds: &'static [f64],
+    state: Mutex<HistogramState>,
+}
+
+struct HistogramState {
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    pub fn new(name: &'static str, help: &'static str, bounds: &'static [f64]) -> Self {
+        Histogram {
+            name,
+            help,
+            bounds,
+            state: Mutex::new(HistogramState {
+                buckets: vec![0; bounds.len()],
+                sum: 0.0,
+                count: 0,
+            }),
+        }
+    }
+
+    pub fn observe(&self, value: f64) {
+        let mut state = self.state.lock().unwrap();
+        for (bound, bucket) in self.bounds.iter().zip(state.buckets.iter_mut()) {
+            if value <= *bound {
+                *bucket += 1;
+            }
+        }
+        state.sum += value;
+        state.count += 1;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.state.lock().unwrap().count
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "# HELP {} {}", self.name, self.help).unwrap();
+        writeln!(out, "# TYPE {} histogram", self.name).unwrap();
+        let state = self.state.lock().unwrap();
+        for (bound, bucket) in self.bounds.iter().zip(state.buckets.iter()) {
+            writeln!(out, "{}_bucket{{le=\"{}\"}} {}", self.name, bound, bucket).unwrap();
+        }
+        writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", self.name, state.count).unwrap();
+        writeln!(out, "{}_sum {}", self.name, state.sum).unwrap();
+        writeln!(out, "{}_count {}", self.name, state.count).unwrap();
+        out
+    }
+}
+
+lazy_static! {
+    /// The total amount deposited to Cosmos per ERC20 contract, in the token's base units,
+    /// counted only once the deposit has been claimed by this oracle
+    pub static ref BRIDGED_VOLUME: CounterVec = CounterVec::new(
+        "gravity_bridged_volume_total",
+        "Total amount bridged from Ethereum to Cosmos in token base units",
+        "token"
+    );
+    /// Lookups of the relayer's batch signature cache, labeled hit or miss
+    pub static ref BATCH_SIGNATURE_CACHE_LOOKUPS: CounterVec = CounterVec::new(
+        "gravity_batch_signature_cache_lookups_total",
+        "Batch signature cache lookups by result",
+        "result"
+    );
+    /// Valsets and batches each validator signed, keyed by the validator's Ethereum address
+    pub static ref VALIDATOR_SIGNATURES_PRESENT: CounterVec = CounterVec::new(
+        "gravity_validator_signatures_present_total",
+        "Relayed valsets and batches signed by each validator",
+        "validator"
+    );
+    /// Valsets and batches each validator failed to sign, validators with no Ethereum key are labeled unset
+    pub static ref VALIDATOR_SIGNATURES_MISSING: CounterVec = CounterVec::new(
+        "gravity_validator_signatures_missing_total",
+        "Relayed valsets and batches each validator did not sign",
+        "validator"
+    );
+    /// Batches this relayer moved the nonce of on Ethereum, per ERC20 contract
+    pub static ref BATCHES_RELAYED: CounterVec = CounterVec::new(
+        "gravity_batches_relayed_total",
+        "Batches relayed to Ethereum by this relayer",
+        "token"
+    );
+    /// Gas used by the batches counted in BATCHES_RELAYED, per ERC20 contract
+    pub static ref BATCH_RELAY_GAS: CounterVec = CounterVec::new(
+        "gravity_batch_relay_gas_total",
+        "Gas spent relaying batches to Ethereum",
+        "token"
+    );
+    /// Fees earned from the batches counted in BATCHES_RELAYED, in the token's base units
+    pub static ref BATCH_RELAY_FEES: CounterVec = CounterVec::new(
+        "gravity_batch_relay_fees_total",
+        "Fees earned relaying batches in token base units",
+        "token"
+    );
+    /// The relayer's exponential moving average of the Ethereum gas price, in wei
+    pub static ref GAS_PRICE_EMA: Gauge = Gauge::new(
+        "gravity_gas_price_ema_wei",
+        "Exponential moving average of the Ethereum gas price in wei"
+    );
+    /// Deposits seen above the observe depth that are not yet deep enough to be claimed
+    pub static ref PENDING_DEPOSITS: Gauge = Gauge::new(
+        "gravity_pending_deposits",
+        "Observed deposits waiting for the block delay before they are claimed"
+    );
+    /// Seconds from the block containing a deposit to this oracle submitting its claim
+    pub static ref DEPOSIT_CLAIM_LATENCY: Histogram = Histogram::new(
+        "gravity_deposit_claim_latency_seconds",
+        "Seconds between a deposit's Ethereum block and the submission of its claim",
+        &[30.0, 60.0, 120.0, 300.0, 600.0, 1200.0, 1800.0, 3600.0, 7200.0]
+    );
+}
+
+/// Renders every metric in the Prometheus text format
+pub fn render_metrics() -> String {
+    let mut out = BRIDGED_VOLUME.render();
+    out += &BATCH_SIGNATURE_CACHE_LOOKUPS.render();
+    out += &VALIDATOR_SIGNATURES_PRESENT.render();
+    out += &VALIDATOR_SIGNATURES_MISSING.render();
+    out += &BATCHES_RELAYED.render();
+    out += &BATCH_RELAY_GAS.render();
+    out += &BATCH_RELAY_FEES.render();
+    out += &GAS_PRICE_EMA.render();
+    out += &PENDING_DEPOSITS.render();
+    out += &DEPOSIT_CLAIM_LATENCY.render();
+    out
+}
+
+/// Binds `addr`, for example 127.0.0.1:9100, and serves render_metrics at `/metrics` and the most
+/// recent log records as a JSON array at `/logs/recent` from a background thread
+pub fn start_metrics_server(addr: &str) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    info!(
+        "Serving metrics on http://{}/metrics",
+        listener.local_addr()?
+    );
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(e) = handle_metrics_request(stream) {
+                        warn!("Metrics request failed {:?}", e);
+                    }
+                }
+                Err(e) => warn!("Metrics accept failed {:?}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+fn handle_metrics_request(stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(METRICS_REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(METRICS_REQUEST_TIMEOUT))?;
+    let mut request_line = String::new();
+    let mut reader = BufReader::new(&stream);
+    reader.read_line(&mut request_line)?;
+    // the rest of the request head is ignored, but read it so the client sees a clean close
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+    let (status, content_type, body) = metrics_response(&request_line);
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    (&stream).write_all(response.as_bytes())
+}
+
+fn metrics_response(request_line: &str) -> (&'static str, &'static str, String) {
+    let mut parts = request_line.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            ("200 OK", "text/plain; version=0.0.4", render_metrics())
+        }
+        (Some("GET"), Some("/logs/recent")) => (
+            "200 OK",
+            "application/json",
+            format!("[{}]", json_logger::recent_records().join(",")),
+        ),
+        _ => ("404 Not Found", "text/plain", "not found\n".to_string()),
+    }
+}
+
+#[test]
+fn test_metrics_response() {
+    let (status, _, body) = metrics_response("GET /metrics HTTP/1.1\r\n");
+    assert_eq!(status, "200 OK");
+    assert!(body.contains("# TYPE"));
+    let (status, content_type, body) = metrics_response("GET /logs/recent HTTP/1.1\r\n");
+    assert_eq!(status, "200 OK");
+    assert_eq!(content_type, "application/json");
+    assert!(body.starts_with('['));
+    let (status, _, _) = metrics_response("GET / HTTP/1.1\r\n");
+    assert_eq!(status, "404 Not Found");
+}
+
+#[test]
+fn test_counter_vec() {
+    let counter = CounterVec::new("test_total", "A test counter", "token");
+    let big: Uint256 = u128::MAX.into();
+    counter.inc_by("0xabc", big.clone());
+    counter.inc_by("0xabc", 1u8.into());
+    counter.inc_by("0xdef", 5u8.into());
+    assert_eq!(counter.get("0xabc"), big + 1u8.into());
+    assert_eq!(counter.get("0x123"), 0u8.into());
+    assert_eq!(
+        counter.render(),
+        format!(
+            "# HELP test_total A test counter\n# TYPE test_total counter\ntest_total{{token=\"0xabc\"}} {}\ntest_total{{token=\"0xdef\"}} 5\n",
+            counter.get("0xabc")
+        )
+    );
+}
+
+#[test]
+fn test_gauge() {
+    let gauge = Gauge::new("test_wei", "A test gauge");
+    assert_eq!(
+        gauge.render(),
+        "# HELP test_wei A test gauge\n# TYPE test_wei gauge\n"
+    );
+    gauge.set(5u8.into());
+    gauge.set(3u8.into());
+    assert_eq!(gauge.get(), Some(3u8.into()));
+    assert_eq!(
+        gauge.render(),
+        "# HELP test_wei A test gauge\n# TYPE test_wei gauge\ntest_wei 3\n"
+    );
+}
+
+#[test]
+fn test_histogram() {
+    let histogram = Histogram::new("test_seconds", "A test histogram", &[1.0, 10.0]);
+    histogram.observe(0.5);
+    histogram.observe(5.0);
+    histogram.observe(60.0);
+    assert_eq!(histogram.count(), 3);
+    assert_eq!(
+        histogram.render(),
+        "# HELP test_seconds A test histogram\n# TYPE test_seconds histogram\ntest_seconds_bucket{le=\"1\"} 1\ntest_seconds_bucket{le=\"10\"} 2\ntest_seconds_bucket{le=\"+Inf\"} 3\ntest_seconds_sum 65.5\ntest_seconds_count 3\n"
+    );
+}
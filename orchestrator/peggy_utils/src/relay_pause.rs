@@ -0,0 +1,74 @@
+//! Process wide switches that hold Ethereum submissions or Cosmos claims while an operator
+//! intervenes, the rest of the orchestrator keeps running while either is held so no progress
+//! is lost.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+static RELAYING_PAUSED: AtomicBool = AtomicBool::new(false);
+
+/// Pauses relaying, returns true if relaying was previously running
+pub fn pause_relaying() -> bool {
+    !RELAYING_PAUSED.swap(true, Ordering::SeqCst)
+}
+
+/// Resumes relaying, returns true if relaying was previously paused
+pub fn resume_relaying() -> bool {
+    RELAYING_PAUSED.swap(false, Ordering::SeqCst)
+}
+
+pub fn is_relaying_paused() -> bool {
+    RELAYING_PAUSED.load(Ordering::SeqCst)
+}
+
+// claim submission is held separately from relaying, it is only held by the oracle when it observes
+// a Peggy contract upgrade and must be released by an operator acknowledging the upgrade
+static CLAIMS_HELD: AtomicBool = AtomicBool::new(false);
+static DETECTED_UPGRADE_BLOCK: AtomicU64 = AtomicU64::new(0);
+static ACKNOWLEDGED_UPGRADE_BLOCK: AtomicU64 = AtomicU64::new(0);
+
+/// Holds claim submission for a contract upgrade observed at `block`, returns false if the
+/// upgrade at this block has already been acknowledged and claims were not held
+pub fn hold_claims_for_upgrade(block: u64) -> bool {
+    if block <= ACKNOWLEDGED_UPGRADE_BLOCK.load(Ordering::SeqCst) {
+        return false;
+    }
+    DETECTED_UPGRADE_BLOCK.fetch_max(block, Ordering::SeqCst);
+    CLAIMS_HELD.store(true, Ordering::SeqCst);
+    true
+}
+
+/// Acknowledges every upgrade detected so far and releases claim submission, returns true if
+/// claims were previously held
+pub fn acknowledge_upgrade() -> bool {
+    ACKNOWLEDGED_UPGRADE_BLOCK.fetch_max(
+        DETECTED_UPGRADE_BLOCK.load(Ordering::SeqCst),
+        Ordering::SeqCst,
+    );
+    CLAIMS_HELD.swap(false, Ordering::SeqCst)
+}
+
+pub fn are_claims_held() -> bool {
+    CLAIMS_HELD.load(Ordering::SeqCst)
+}
+
+/// The block of the newest contract upgrade observed, zero if none has been
+pub fn detected_upgrade_block() -> u64 {
+    DETECTED_UPGRADE_BLOCK.load(Ordering::SeqCst)
+}
+
+#[test]
+fn test_hold_claims_for_upgrade() {
+    assert!(!are_claims_held());
+    assert!(hold_claims_for_upgrade(100));
+    assert!(are_claims_held());
+    assert!(acknowledge_upgrade());
+    assert!(!are_claims_held());
+    // the overlapping block range sees the acknowledged upgrade again
+    assert!(!hold_claims_for_upgrade(100));
+    assert!(!are_claims_held());
+    assert!(hold_claims_for_upgrade(101));
+    assert!(are_claims_held());
+    assert_eq!(detected_upgrade_block(), 101);
+    assert!(acknowledge_upgrade());
+    assert!(!acknowledge_upgrade());
+}
@@ -0,0 +1,85 @@
+//! Custom HTTP headers for the Ethereum RPC endpoint, for providers that take their API key as a
+//! header rather than in the url. GRAVITY_ETH_RPC_HEADERS holds `Name: value` pairs separated by
+//! `;`, for example `x-api-key: abc123;x-team: ops`. The values are secrets, they are never logged
+//! or included in errors.
+//!
+//! web30's Web3 builds its HTTP client internally with no way to attach headers, the requests we
+//! make to the endpoint directly go through with_eth_rpc_headers instead.
+
+use awc::ClientRequest;
+use std::env;
+use std::sync::RwLock;
+
+/// `Name: value` pairs separated by `;` added to every Ethereum RPC request
+pub const ETH_RPC_HEADERS_ENV: &str = "GRAVITY_ETH_RPC_HEADERS";
+
+lazy_static! {
+    static ref ETH_RPC_HEADERS: RwLock<Vec<(String, String)>> = RwLock::new(Vec::new());
+}
+
+/// Reads and validates GRAVITY_ETH_RPC_HEADERS, to be called once at startup before any
+/// connection is made so that a malformed value is reported as a config error
+pub fn load_eth_rpc_headers() -> Result<(), String> {
+    let headers = match env::var(ETH_RPC_HEADERS_ENV) {
+        Ok(val) => {
+            parse_headers(&val).map_err(|e| format!("Invalid {} {}", ETH_RPC_HEADERS_ENV, e))?
+        }
+        Err(_) => Vec::new(),
+    };
+    *ETH_RPC_HEADERS.write().unwrap() = headers;
+    Ok(())
+}
+
+/// Adds the headers configured with GRAVITY_ETH_RPC_HEADERS to a request for the Ethereum RPC
+pub fn with_eth_rpc_headers(mut request: ClientRequest) -> ClientRequest {
+    for (name, value) in ETH_RPC_HEADERS.read().unwrap().iter() {
+        request = request.header(name.as_str(), value.as_str());
+    }
+    request
+}
+
+/// Parses `Name: value` pairs separated by `;`. Errors only name the position of the bad entry
+/// since a malformed entry may well be the secret itself
+pub fn parse_headers(input: &str) -> Result<Vec<(String, String)>, String> {
+    let mut headers = Vec::new();
+    let entries = input.split(';').map(str::trim).filter(|e| !e.is_empty());
+    for (index, entry) in entries.enumerate() {
+        let mut parts = entry.splitn(2, ':');
+        let name = parts.next().unwrap_or_default().trim();
+        let value = match parts.next() {
+            Some(value) => value.trim(),
+            None => return Err(format!("header {} has no `:` separator", index + 1)),
+        };
+        let valid_name = name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+        if name.is_empty() || !valid_name {
+            return Err(format!("header {} has an invalid name", index + 1));
+        }
+        if value.bytes().any(|b| b.is_ascii_control()) {
+            return Err(format!(
+                "header {} has control characters in its value",
+                index + 1
+            ));
+        }
+        headers.push((name.to_string(), value.to_string()));
+    }
+    Ok(headers)
+}
+
+#[test]
+fn test_parse_headers() {
+    let headers = parse_headers("x-api-key: abc:123; x-team:ops;").unwrap();
+    assert_eq!(
+        headers,
+        vec![
+            ("x-api-key".to_string(), "abc:123".to_string()),
+            ("x-team".to_string(), "ops".to_string())
+        ]
+    );
+    assert_eq!(parse_headers("").unwrap(), Vec::new());
+    // a missing separator must not echo the entry, it may be the key itself
+    let err = parse_headers("x-team: ops;secretvalue").unwrap_err();
+    assert!(!err.contains("secretvalue"), "{}", err);
+    assert!(parse_headers("bad name: value").is_err());
+}
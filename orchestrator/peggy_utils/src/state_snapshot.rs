@@ -0,0 +1,129 @@
+//! A single portable file holding the state worth carrying over when an orchestrator or relayer
+//! is moved to new hardware. The oracle and the relayer each keep their own section of the file,
+//! see orchestrator::state_snapshot and relayer::state_snapshot for what each section holds.
+//!
+//! ```json
+//! {"version": 2, "oracle": {...}, "relayer": {...}}
+//! ```
+//!
+//! Everything else is re-derived on startup. Batch signatures are re-queried from Cosmos, which is
+//! the source of truth and only cached for a minute, and the Ethereum nonce always comes from the
+//! node since a stale nonce from another machine would replace or block transactions.
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+use std::fs;
+use std::io;
+use std::sync::Mutex;
+
+/// Bumped whenever the file format changes, older versions are migrated when loaded
+pub const STATE_SNAPSHOT_VERSION: u64 = 2;
+/// The section holding the oracle's state
+pub const ORACLE_SECTION: &str = "oracle";
+/// The section holding the relayer's state
+pub const RELAYER_SECTION: &str = "relayer";
+
+lazy_static! {
+    // the orchestrator runs the oracle and the relayer in one process and every save rewrites
+    // the whole file, so saves take turns to not drop the other's section
+    static ref SNAPSHOT_WRITE: Mutex<()> = Mutex::new(());
+}
+
+/// Brings a snapshot of any earlier version up to the current format and returns its sections.
+/// Version 1 files held only the oracle's state, the relayer's was kept in a separate file
+fn migrate_snapshot(snapshot: Value) -> Result<Map<String, Value>, String> {
+    let version = snapshot["version"]
+        .as_u64()
+        .ok_or_else(|| "missing version".to_string())?;
+    match (version, snapshot) {
+        (1, oracle) => {
+            let mut sections = Map::new();
+            sections.insert(ORACLE_SECTION.to_string(), oracle);
+            Ok(sections)
+        }
+        (STATE_SNAPSHOT_VERSION, Value::Object(mut sections)) => {
+            sections.remove("version");
+            Ok(sections)
+        }
+        (STATE_SNAPSHOT_VERSION, _) => Err("not a JSON object".to_string()),
+        (v, _) => Err(format!(
+            "version {} is newer than the supported version {}",
+            v, STATE_SNAPSHOT_VERSION
+        )),
+    }
+}
+
+fn read_sections(path: &str) -> Option<Result<Map<String, Value>, String>> {
+    let contents = fs::read_to_string(path).ok()?;
+    Some(
+        serde_json::from_str(&contents)
+            .map_err(|e| e.to_string())
+            .and_then(migrate_snapshot),
+    )
+}
+
+/// Loads one section of the snapshot at `path`, None if there is no snapshot, it has no such
+/// section or it can't be read
+pub fn load_snapshot_section<T: DeserializeOwned>(path: &str, section: &str) -> Option<T> {
+    let sections = match read_sections(path)? {
+        Ok(sections) => sections,
+        Err(e) => {
+            warn!("Ignoring invalid state snapshot {} {}", path, e);
+            return None;
+        }
+    };
+    match serde_json::from_value(sections.get(section)?.clone()) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            warn!(
+                "Ignoring invalid {} section of state snapshot {} {}",
+                section, path, e
+            );
+            None
+        }
+    }
+}
+
+/// Replaces one section of the snapshot at `path`, keeping the others. Writes to a temporary
+/// file first so a crash mid write never leaves a truncated snapshot behind
+pub fn save_snapshot_section<T: Serialize>(
+    path: &str,
+    section: &str,
+    value: &T,
+) -> Result<(), io::Error> {
+    let _write = SNAPSHOT_WRITE.lock().unwrap();
+    // an unreadable snapshot is replaced rather than blocking every later save
+    let mut sections = read_sections(path).and_then(Result::ok).unwrap_or_default();
+    sections.insert(section.to_string(), serde_json::to_value(value)?);
+    sections.insert("version".to_string(), STATE_SNAPSHOT_VERSION.into());
+    let contents = serde_json::to_string_pretty(&Value::Object(sections))?;
+    let tmp_path = format!("{}.tmp", path);
+    fs::write(&tmp_path, contents)?;
+    fs::rename(tmp_path, path)
+}
+
+#[test]
+fn test_snapshot_sections() {
+    let path = std::env::temp_dir().join(format!("state_snapshot_{}.json", std::process::id()));
+    let path = path.to_str().unwrap();
+    assert_eq!(load_snapshot_section::<u64>(path, ORACLE_SECTION), None);
+    save_snapshot_section(path, ORACLE_SECTION, &12_000_000u64).unwrap();
+    save_snapshot_section(path, RELAYER_SECTION, &"relayer".to_string()).unwrap();
+    // saving one section keeps the other
+    assert_eq!(
+        load_snapshot_section(path, ORACLE_SECTION),
+        Some(12_000_000u64)
+    );
+    assert_eq!(
+        load_snapshot_section(path, RELAYER_SECTION),
+        Some("relayer".to_string())
+    );
+    fs::remove_file(path).unwrap();
+
+    let v1 = serde_json::json!({"version": 1, "deploy_block": 100});
+    let sections = migrate_snapshot(v1.clone()).unwrap();
+    assert_eq!(sections.get(ORACLE_SECTION), Some(&v1));
+    assert!(sections.get(RELAYER_SECTION).is_none());
+    let future = serde_json::json!({"version": STATE_SNAPSHOT_VERSION + 1});
+    assert!(migrate_snapshot(future).is_err());
+}
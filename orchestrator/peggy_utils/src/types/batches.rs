@@ -43,6 +43,22 @@ pub struct TransactionBatch {
 }
 
 impl TransactionBatch {
+    /// The contract only accepts a batch in blocks strictly below its timeout, so once the
+    /// current block reaches the timeout no submission can succeed
+    pub fn is_timed_out(&self, current_eth_block: Uint256) -> bool {
+        current_eth_block >= self.batch_timeout.into()
+    }
+
+    /// How many more blocks a submission of this batch can be included in, zero once timed out
+    pub fn blocks_until_timeout(&self, current_eth_block: Uint256) -> u64 {
+        if self.is_timed_out(current_eth_block.clone()) {
+            return 0;
+        }
+        let remaining = Uint256::from(self.batch_timeout) - current_eth_block;
+        // below the timeout, which is itself a u64
+        remaining.to_string().parse().unwrap()
+    }
+
     /// extracts the amounts, destinations and fees as submitted to the Ethereum contract
     /// and used for signatures
     pub fn get_checkpoint_values(&self) -> (Token, Token, Token) {
@@ -124,3 +140,20 @@ impl Confirm for BatchConfirmResponse {
         self.eth_signature.clone()
     }
 }
+
+#[test]
+fn test_batch_timeout() {
+    let batch = TransactionBatch {
+        batch_timeout: 1000,
+        ..Default::default()
+    };
+    assert!(!batch.is_timed_out(998u64.into()));
+    assert_eq!(batch.blocks_until_timeout(998u64.into()), 2);
+    assert!(!batch.is_timed_out(999u64.into()));
+    assert_eq!(batch.blocks_until_timeout(999u64.into()), 1);
+    // the boundary, a transaction in block 1000 would revert
+    assert!(batch.is_timed_out(1000u64.into()));
+    assert_eq!(batch.blocks_until_timeout(1000u64.into()), 0);
+    assert!(batch.is_timed_out(1001u64.into()));
+    assert_eq!(batch.blocks_until_timeout(1001u64.into()), 0);
+}
@@ -0,0 +1,577 @@
+//! Runtime configuration for the relayer and orchestrator, everything in here has a sane default
+//! so that operators only need to specify the values they actually want to change.
+
+use clarity::{Address as EthAddress, PrivateKey as EthPrivateKey, Uint256};
+use deep_space::private_key::PrivateKey as CosmosPrivateKey;
+use std::cmp::min;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The default multiplier applied to gas estimates before a transaction is submitted
+pub const DEFAULT_GAS_LIMIT_MULTIPLIER: f64 = 1.2;
+/// The default amount of ETH, in wei, the relayer will always leave in its account (0.05 ETH)
+pub const DEFAULT_ETH_BALANCE_RESERVE: u128 = 50_000_000_000_000_000;
+/// The default number of blocks the oracle re-scans from the end of its previous poll
+pub const DEFAULT_BLOCK_OVERLAP: u64 = 1;
+/// The default delay before re-checking whether submitted claims were processed, short enough
+/// that a slow re-check costs little on fast chains
+pub const DEFAULT_CLAIM_CONFIRM_DELAY: Duration = Duration::from_secs(1);
+/// The default weight of each new gas price sample in the relayer's moving average
+pub const DEFAULT_GAS_PRICE_SMOOTHING: f64 = 0.2;
+/// The recovery log is rotated at this size, the previous file is kept with a .1 suffix so at
+/// most twice this is used on disk
+pub const DEFAULT_RECOVERY_LOG_MAX_BYTES: u64 = 10 * 1024 * 1024;
+/// The default number of times a failed valset update submission is retried in place
+pub const DEFAULT_VALSET_SUBMIT_RETRIES: u32 = 2;
+/// The default number of extra batch gas estimates made under GasEstimateFailurePolicy::Retry
+pub const DEFAULT_GAS_ESTIMATE_RETRIES: u32 = 2;
+
+/// The number of pending claims that are submitted without waiting out the claim window
+pub const DEFAULT_CLAIM_WINDOW_MAX_CLAIMS: usize = 100;
+
+/// The bech32 prefix of the Cosmos chain deposits are claimed on
+pub const DEFAULT_COSMOS_PREFIX: &str = "cosmos";
+
+/// The speed tiers offered by gas price oracles, faster tiers pay a higher gas price
+/// in exchange for a shorter expected wait before the transaction is included
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GasPriceTier {
+    Fast,
+    Standard,
+    Slow,
+}
+
+impl Default for GasPriceTier {
+    fn default() -> Self {
+        GasPriceTier::Standard
+    }
+}
+
+impl FromStr for GasPriceTier {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "fast" => Ok(GasPriceTier::Fast),
+            "standard" => Ok(GasPriceTier::Standard),
+            "slow" => Ok(GasPriceTier::Slow),
+            _ => Err(format!("{} is not a valid gas price tier", s)),
+        }
+    }
+}
+
+/// How the relayer chooses which batch to submit when more than one is ready
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchSelectionStrategy {
+    /// the batch with the lowest nonce, first in first out
+    Oldest,
+    /// the batch paying the largest total fee, valued in wei with the price feed
+    HighestFee,
+    /// the batch paying the largest fee per transaction valued in wei, since each transaction
+    /// in a batch costs roughly the same gas this approximates fee per gas
+    HighestFeeDensity,
+}
+
+impl Default for BatchSelectionStrategy {
+    fn default() -> Self {
+        BatchSelectionStrategy::Oldest
+    }
+}
+
+impl FromStr for BatchSelectionStrategy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "oldest" => Ok(BatchSelectionStrategy::Oldest),
+            "highest-fee" => Ok(BatchSelectionStrategy::HighestFee),
+            "highest-fee-density" => Ok(BatchSelectionStrategy::HighestFeeDensity),
+            _ => Err(format!("{} is not a valid batch selection strategy", s)),
+        }
+    }
+}
+
+/// Whether valset updates or batches are relayed first in each relayer loop iteration
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelayPriority {
+    /// valset updates first, batches are signed by the validator set in the bridge so a
+    /// pending valset update changes which signatures a batch needs, relaying the valset
+    /// first means batches are only ever checked and submitted against the newest set
+    ValsetsFirst,
+    /// batches first, their fees reach relayers sooner but a batch submitted just before a
+    /// valset update is racing it, if the update lands first the batch reverts and its gas
+    /// is lost
+    BatchesFirst,
+}
+
+impl Default for RelayPriority {
+    fn default() -> Self {
+        RelayPriority::ValsetsFirst
+    }
+}
+
+impl FromStr for RelayPriority {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "valsets-first" => Ok(RelayPriority::ValsetsFirst),
+            "batches-first" => Ok(RelayPriority::BatchesFirst),
+            _ => Err(format!("{} is not a valid relay priority", s)),
+        }
+    }
+}
+
+/// What the relayer does when the gas estimate for a batch fails
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GasEstimateFailurePolicy {
+    /// skip relaying until the next loop iteration, a failed estimate usually means the batch
+    /// would revert because it was already submitted or timed out
+    Skip,
+    /// estimate again up to gas_estimate_retries times before skipping, for flaky Ethereum nodes
+    Retry,
+    /// submit anyway with batch_fallback_gas_limit as the gas limit, gas is lost if the batch
+    /// really does revert
+    FixedGas,
+}
+
+impl Default for GasEstimateFailurePolicy {
+    fn default() -> Self {
+        GasEstimateFailurePolicy::Skip
+    }
+}
+
+impl FromStr for GasEstimateFailurePolicy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "skip" => Ok(GasEstimateFailurePolicy::Skip),
+            "retry" => Ok(GasEstimateFailurePolicy::Retry),
+            "fixed-gas" => Ok(GasEstimateFailurePolicy::FixedGas),
+            _ => Err(format!("{} is not a valid gas estimate failure policy", s)),
+        }
+    }
+}
+
+/// Configuration for the relaying of valsets, batches and logic calls to Ethereum
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RelayerConfig {
+    /// Gas estimates often undershoot for complex contract calls, this value is
+    /// multiplied with the estimate to produce the gas limit that is actually submitted
+    pub gas_limit_multiplier: f64,
+    /// An EthGasStation compatible gas price oracle, when None the gas price reported
+    /// by the Ethereum node is used
+    pub gas_oracle_url: Option<String>,
+    /// The gas price tier to request from the gas oracle
+    pub gas_price_tier: GasPriceTier,
+    /// The weight, between 0 and 1, of each new sample in the moving average of the node's gas
+    /// price. When no gas oracle is set batches are submitted at this average unless close to timing out
+    pub gas_price_smoothing: f64,
+    /// The amount of ETH, in wei, that must remain in the relayer account after paying for
+    /// a submission, submissions that would dip below this are skipped until the account is topped up
+    pub eth_balance_reserve: Uint256,
+    /// Which batch to submit when more than one is ready to be relayed
+    pub batch_selection_strategy: BatchSelectionStrategy,
+    /// Fixed gas limits for logic calls to specific logic contracts, used instead of the multiplied
+    /// estimate since eth_estimate_gas can under estimate calls with dynamic inner execution
+    pub logic_call_gas_stipends: HashMap<EthAddress, Uint256>,
+    /// A Unix socket path accepting pause and resume commands, when None no socket is opened
+    pub control_socket: Option<String>,
+    /// The address to serve Prometheus metrics on at /metrics, when None they are not served
+    pub metrics_listen: Option<String>,
+    /// The largest gas limit passed to eth_estimate_gas when estimating a valset update,
+    /// when None the block gas limit is used
+    pub estimate_gas_limit_cap: Option<Uint256>,
+    /// When the valset nonce on Ethereum is ahead of every valset Cosmos knows of, pause all
+    /// relaying until an operator resumes it rather than only skipping valset relaying
+    pub halt_on_valset_divergence: bool,
+    /// Batches created more than this many Ethereum blocks ago are not relayed. A batch's creation
+    /// block is estimated from its timeout minus the chain's target batch timeout, so this only has an
+    /// effect when it is smaller than that window, batches past their timeout are never relayed anyway
+    pub max_batch_age_blocks: Option<u64>,
+    /// A CoinGecko compatible token price api used to value batch fees in ETH, takes precedence
+    /// over token_eth_prices
+    pub price_feed_url: Option<String>,
+    /// Fixed prices in ETH of one whole token, used to value batch fees when no price feed url is set.
+    /// With neither configured batch fees are assumed to be denominated like ETH
+    pub token_eth_prices: HashMap<EthAddress, f64>,
+    /// Skip batches whose fees are worth less than the gas needed to submit them
+    pub only_relay_profitable_batches: bool,
+    /// Only relay a valset update once the power that moved since the valset in the bridge
+    /// exceeds this fraction of the total power, or a validator joined or left the set. None
+    /// relays every new valset
+    pub valset_power_drift_threshold: Option<f32>,
+    /// A file every outgoing Ethereum transaction is recorded to before it is sent, see
+    /// ethereum_peggy::recovery_log
+    pub recovery_log: Option<String>,
+    /// The size the recovery log is rotated at
+    pub recovery_log_max_bytes: u64,
+    /// A snapshot file the relayer's gas price average is saved to every loop and restored from
+    /// on startup, see relayer::state_snapshot and peggy_utils::state_snapshot
+    pub state_snapshot: Option<String>,
+    /// Batches are not submitted while the gas price, in wei, is above this, they are retried
+    /// once it drops. None submits at any gas price
+    pub batch_max_gas_price: Option<Uint256>,
+    /// How many times a valset update whose submission fails is resent with a fresh nonce before
+    /// the error is returned, submissions that revert are never retried
+    pub valset_submit_retries: u32,
+    /// When not empty only batches of these tokens are relayed
+    pub token_allowlist: HashSet<EthAddress>,
+    /// Batches of these tokens are never relayed. Skipping a batch only means this relayer won't
+    /// spend gas on it, any other relayer may still submit it
+    pub token_denylist: HashSet<EthAddress>,
+    /// How often the batch nonces executed on Ethereum are compared against the batches Cosmos
+    /// still has pending, see relayer::batch_reconciliation. None disables the check
+    pub batch_reconcile_interval: Option<Duration>,
+    /// Submits valset updates from this key rather than the relayer's own Ethereum key, it is only
+    /// the sender and so the account that must hold ETH for gas, the update is still authorized by
+    /// the validator set's confirms. Never written out with the rest of the config
+    #[serde(skip)]
+    pub valset_submission_key: Option<EthPrivateKey>,
+    /// Whether valset updates or batches are relayed first each loop iteration
+    pub relay_priority: RelayPriority,
+    /// What to do when the gas estimate for a batch fails
+    pub gas_estimate_failure_policy: GasEstimateFailurePolicy,
+    /// How many more times a failed batch gas estimate is made under the Retry policy
+    pub gas_estimate_retries: u32,
+    /// The gas limit batches are submitted with under GasEstimateFailurePolicy::FixedGas, which
+    /// requires it. Capped at estimate_gas_limit_cap, see batch_fallback_gas
+    pub batch_fallback_gas_limit: Option<Uint256>,
+}
+
+impl RelayerConfig {
+    /// Whether batches of this token may be relayed under the token allowlist and denylist
+    pub fn relays_token(&self, token: &EthAddress) -> bool {
+        (self.token_allowlist.is_empty() || self.token_allowlist.contains(token))
+            && !self.token_denylist.contains(token)
+    }
+
+    /// The gas limit a batch whose gas estimate failed is submitted with, None unless the FixedGas
+    /// policy has a fallback gas limit. It never exceeds estimate_gas_limit_cap
+    pub fn batch_fallback_gas(&self) -> Option<Uint256> {
+        if self.gas_estimate_failure_policy != GasEstimateFailurePolicy::FixedGas {
+            return None;
+        }
+        let gas_limit = self.batch_fallback_gas_limit.clone()?;
+        match &self.estimate_gas_limit_cap {
+            Some(cap) => Some(min(gas_limit, cap.clone())),
+            None => Some(gas_limit),
+        }
+    }
+
+    /// Checks the FixedGas policy has a fallback gas limit, without one every batch whose estimate
+    /// fails would be skipped as if the policy were Skip
+    pub fn validate_gas_estimate_failure_policy(&self) -> Result<(), String> {
+        match (
+            self.gas_estimate_failure_policy,
+            &self.batch_fallback_gas_limit,
+        ) {
+            (GasEstimateFailurePolicy::FixedGas, None) => {
+                Err("The fixed-gas policy requires a batch fallback gas limit".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[test]
+fn test_gas_estimate_failure_policy() {
+    assert_eq!("skip".parse(), Ok(GasEstimateFailurePolicy::Skip));
+    assert_eq!("Retry".parse(), Ok(GasEstimateFailurePolicy::Retry));
+    assert_eq!("fixed-gas".parse(), Ok(GasEstimateFailurePolicy::FixedGas));
+    assert!("fixed".parse::<GasEstimateFailurePolicy>().is_err());
+
+    let mut config = RelayerConfig {
+        batch_fallback_gas_limit: Some(500_000u32.into()),
+        ..Default::default()
+    };
+    assert!(config.validate_gas_estimate_failure_policy().is_ok());
+    // only the fixed-gas policy submits with the fallback
+    assert_eq!(config.batch_fallback_gas(), None);
+    config.gas_estimate_failure_policy = GasEstimateFailurePolicy::FixedGas;
+    assert_eq!(config.batch_fallback_gas(), Some(500_000u32.into()));
+    config.estimate_gas_limit_cap = Some(300_000u32.into());
+    assert_eq!(config.batch_fallback_gas(), Some(300_000u32.into()));
+    config.batch_fallback_gas_limit = None;
+    assert!(config.validate_gas_estimate_failure_policy().is_err());
+}
+
+impl Default for RelayerConfig {
+    fn default() -> Self {
+        RelayerConfig {
+            gas_limit_multiplier: DEFAULT_GAS_LIMIT_MULTIPLIER,
+            gas_oracle_url: None,
+            gas_price_tier: GasPriceTier::default(),
+            gas_price_smoothing: DEFAULT_GAS_PRICE_SMOOTHING,
+            eth_balance_reserve: DEFAULT_ETH_BALANCE_RESERVE.into(),
+            batch_selection_strategy: BatchSelectionStrategy::default(),
+            logic_call_gas_stipends: HashMap::new(),
+            control_socket: None,
+            metrics_listen: None,
+            estimate_gas_limit_cap: None,
+            halt_on_valset_divergence: false,
+            max_batch_age_blocks: None,
+            price_feed_url: None,
+            token_eth_prices: HashMap::new(),
+            only_relay_profitable_batches: false,
+            valset_power_drift_threshold: None,
+            recovery_log: None,
+            recovery_log_max_bytes: DEFAULT_RECOVERY_LOG_MAX_BYTES,
+            state_snapshot: None,
+            batch_max_gas_price: None,
+            valset_submit_retries: DEFAULT_VALSET_SUBMIT_RETRIES,
+            token_allowlist: HashSet::new(),
+            token_denylist: HashSet::new(),
+            batch_reconcile_interval: None,
+            valset_submission_key: None,
+            relay_priority: RelayPriority::default(),
+            gas_estimate_failure_policy: GasEstimateFailurePolicy::default(),
+            gas_estimate_retries: DEFAULT_GAS_ESTIMATE_RETRIES,
+            batch_fallback_gas_limit: None,
+        }
+    }
+}
+
+/// Parses a logic call gas stipend in the form <logic contract address>=<gas>
+pub fn parse_logic_call_gas_stipend(input: &str) -> Result<(EthAddress, Uint256), String> {
+    let mut parts = input.splitn(2, '=');
+    let address = parts.next().unwrap_or_default();
+    let gas = parts
+        .next()
+        .ok_or_else(|| format!("{} is not in the form <address>=<gas>", input))?;
+    let address: EthAddress = address
+        .trim()
+        .parse()
+        .map_err(|e| format!("Invalid logic contract address {} {:?}", address, e))?;
+    let gas: Uint256 = gas
+        .trim()
+        .parse()
+        .map_err(|e| format!("Invalid gas stipend {} {:?}", gas, e))?;
+    Ok((address, gas))
+}
+
+#[test]
+fn test_parse_logic_call_gas_stipend() {
+    let (address, gas) =
+        parse_logic_call_gas_stipend("0xD7600ae27C99988A6CD360234062b540F88ECA43=500000").unwrap();
+    assert_eq!(
+        address,
+        "0xD7600ae27C99988A6CD360234062b540F88ECA43"
+            .parse()
+            .unwrap()
+    );
+    assert_eq!(gas, 500_000u32.into());
+    assert!(parse_logic_call_gas_stipend("0xD7600ae27C99988A6CD360234062b540F88ECA43").is_err());
+    assert!(parse_logic_call_gas_stipend("bad=500000").is_err());
+}
+
+/// Checks a gas price smoothing weight is in (0, 1], anything else would make the moving average
+/// ignore new samples or overshoot them
+pub fn validate_gas_price_smoothing(smoothing: f64) -> Result<f64, String> {
+    if smoothing > 0f64 && smoothing <= 1f64 {
+        Ok(smoothing)
+    } else {
+        Err(format!(
+            "Gas price smoothing must be in (0, 1], got {}",
+            smoothing
+        ))
+    }
+}
+
+#[test]
+fn test_validate_gas_price_smoothing() {
+    assert_eq!(validate_gas_price_smoothing(0.2), Ok(0.2));
+    assert_eq!(validate_gas_price_smoothing(1.0), Ok(1.0));
+    assert!(validate_gas_price_smoothing(0.0).is_err());
+    assert!(validate_gas_price_smoothing(1.5).is_err());
+    assert!(validate_gas_price_smoothing(f64::NAN).is_err());
+}
+
+/// Checks a gas multiplier is at least 1, a smaller one would set the gas limit or fee below the
+/// estimate and every transaction would run out of gas or be rejected
+pub fn validate_gas_multiplier(multiplier: f64) -> Result<f64, String> {
+    if multiplier >= 1f64 && multiplier.is_finite() {
+        Ok(multiplier)
+    } else {
+        Err(format!(
+            "Gas multiplier must be a number of at least 1, got {}",
+            multiplier
+        ))
+    }
+}
+
+#[test]
+fn test_validate_gas_multiplier() {
+    assert_eq!(validate_gas_multiplier(1.0), Ok(1.0));
+    assert_eq!(validate_gas_multiplier(1.2), Ok(1.2));
+    assert!(validate_gas_multiplier(0.8).is_err());
+    assert!(validate_gas_multiplier(0.0).is_err());
+    assert!(validate_gas_multiplier(-1.0).is_err());
+    assert!(validate_gas_multiplier(f64::NAN).is_err());
+    assert!(validate_gas_multiplier(f64::INFINITY).is_err());
+}
+
+/// Parses a token price in the form <address>=<price in ETH>
+pub fn parse_token_eth_price(input: &str) -> Result<(EthAddress, f64), String> {
+    let mut parts = input.splitn(2, '=');
+    let address = parts.next().unwrap_or_default();
+    let price = parts
+        .next()
+        .ok_or_else(|| format!("{} is not in the form <address>=<price>", input))?;
+    let address: EthAddress = address
+        .trim()
+        .parse()
+        .map_err(|e| format!("Invalid token address {} {:?}", address, e))?;
+    let price: f64 = price
+        .trim()
+        .parse()
+        .map_err(|e| format!("Invalid token price {} {:?}", price, e))?;
+    if !price.is_finite() || price < 0f64 {
+        return Err(format!("Invalid token price {}", price));
+    }
+    Ok((address, price))
+}
+
+#[test]
+fn test_parse_token_eth_price() {
+    let (address, price) =
+        parse_token_eth_price("0xD7600ae27C99988A6CD360234062b540F88ECA43=0.0005").unwrap();
+    assert_eq!(
+        address,
+        "0xD7600ae27C99988A6CD360234062b540F88ECA43"
+            .parse()
+            .unwrap()
+    );
+    assert!((price - 0.0005).abs() < f64::EPSILON);
+    assert!(parse_token_eth_price("0xD7600ae27C99988A6CD360234062b540F88ECA43=-1").is_err());
+    assert!(parse_token_eth_price("bad=1").is_err());
+}
+
+#[test]
+fn test_relays_token() {
+    let dai: EthAddress = "0x6b175474e89094c44da98b954eedeac495271d0f"
+        .parse()
+        .unwrap();
+    let usdc: EthAddress = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"
+        .parse()
+        .unwrap();
+    let mut config = RelayerConfig::default();
+    assert!(config.relays_token(&dai));
+    config.token_denylist.insert(usdc);
+    assert!(config.relays_token(&dai));
+    assert!(!config.relays_token(&usdc));
+    config.token_allowlist.insert(usdc);
+    assert!(!config.relays_token(&dai));
+    // the denylist wins over the allowlist
+    assert!(!config.relays_token(&usdc));
+}
+
+/// Configuration for the Ethereum oracle and signer components of the orchestrator
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OrchestratorConfig {
+    /// The maximum number of Ethereum RPC requests per second the event watcher
+    /// may make, None means no limit is applied
+    pub eth_rpc_requests_per_second: Option<f64>,
+    /// A file used to store the Peggy contract deploy block once it has been found, when
+    /// None the deploy block is searched for again on every start
+    pub checkpoint_file: Option<String>,
+    /// The maximum number of events claimed in a single oracle poll, any further events
+    /// are claimed in following polls. None means no limit is applied
+    pub max_events_per_poll: Option<usize>,
+    /// An Ethereum WebSocket RPC url, when set the oracle subscribes to Peggy events
+    /// instead of polling for them, falling back to polling if the subscription fails
+    pub eth_ws_url: Option<String>,
+    /// How many blocks each poll re-scans from the end of the previous poll. Events from a
+    /// block that was only partially claimed before a restart are picked up by the overlap
+    pub block_overlap: u64,
+    /// Deposits of at least this many whole tokens, scaled by each token's decimals, are
+    /// additionally logged as ORACLE_OBSERVED_LARGE_DEPOSIT warnings. None disables the alert
+    pub alert_amount_threshold: Option<u64>,
+    /// Deposits of at least this many whole tokens are held until an operator approves them over
+    /// the relayer control socket. Every later event waits behind a held deposit, so this trades
+    /// the latency of a manual review for a human check on large transfers. None disables review
+    pub review_amount_threshold: Option<u64>,
+    /// When the event nonce has not advanced right after submitting claims it is checked again
+    /// after this delay, doubling each time, before the claims are considered failed. Zero disables
+    /// the re-checks
+    pub claim_confirm_delay: Duration,
+    /// The Cosmos legacy REST url, used to look up claim transactions by hash
+    pub cosmos_legacy_rpc_url: Option<String>,
+    /// When set, after submitting claims the transaction is looked up by hash for up to this
+    /// long to tell a claim stuck in the mempool apart from a rejected one, before the event
+    /// nonce is checked. None only checks the event nonce
+    pub claim_inclusion_timeout: Option<Duration>,
+    /// When set the claims tx is simulated and its gas, scaled by this multiplier, is the gas
+    /// limit, paid for at the node's minimum gas price or with the static fee amount on chains that
+    /// don't expose one. None, or a node that can't simulate the tx, pays the static fee
+    pub claim_gas_multiplier: Option<f64>,
+    /// The signature of the event the Peggy contract emits when it is upgraded, for example
+    /// Upgraded(address). When set and the event is observed claims are held until an operator
+    /// acknowledges the upgrade
+    pub upgrade_event: Option<String>,
+    /// Deposits this many blocks deep are logged as ORACLE_PENDING_DEPOSIT and counted in the
+    /// pending deposits metric before they are claimed. Claims still wait for the full block delay,
+    /// so this only has an effect when it is less than the block delay
+    pub observe_depth: Option<u64>,
+    /// A snapshot file the oracle's progress and token metadata are saved to after every poll and
+    /// restored from on startup, see orchestrator::state_snapshot and peggy_utils::state_snapshot
+    pub state_snapshot: Option<String>,
+    /// Log an ORACLE_HEARTBEAT after this many polls in a row find no events
+    pub heartbeat_polls: Option<u64>,
+    /// Log an ORACLE_HEARTBEAT when this long has passed without events, whichever of the two
+    /// heartbeat settings is reached first applies. With neither set no heartbeat is logged
+    pub heartbeat_interval: Option<Duration>,
+    /// An OpenTelemetry collector's OTLP/gRPC endpoint, such as http://localhost:4317. When set
+    /// each oracle poll is traced from fetching events through claim submission
+    pub otlp_endpoint: Option<String>,
+    /// The most blocks the oracle fetches logs for at once, a longer range is fetched and claimed
+    /// in chunks of this size so that catching up on a long range can't exhaust memory. None
+    /// fetches the whole range at once
+    pub max_block_range: Option<u64>,
+    /// The bech32 prefix deposit destinations are encoded with in claims, for chains that don't
+    /// use the default cosmos prefix
+    pub cosmos_prefix: String,
+    /// Stop submitting claims until the orchestrator is restarted when our last event nonce on
+    /// Cosmos goes backwards, which means the node is on a fork or its state is corrupted.
+    /// Otherwise the regression is logged and the claims are retried
+    pub halt_on_nonce_regression: bool,
+    /// Claims are held back for this long after the first of them is observed and then submitted
+    /// in a single transaction, trading claim latency for fewer Cosmos transaction fees. Zero
+    /// submits the claims of every poll immediately
+    pub claim_window: Duration,
+    /// Claims are submitted without waiting out the claim window once this many are pending
+    pub claim_window_max_claims: usize,
+    /// The orchestrator keys of further validators whose oracles run in this process, every
+    /// identity claims from the same fetch of the Ethereum logs. Never written out with the rest
+    /// of the config
+    #[serde(skip)]
+    pub additional_cosmos_keys: Vec<CosmosPrivateKey>,
+}
+
+impl Default for OrchestratorConfig {
+    fn default() -> Self {
+        OrchestratorConfig {
+            eth_rpc_requests_per_second: None,
+            checkpoint_file: None,
+            max_events_per_poll: None,
+            eth_ws_url: None,
+            block_overlap: DEFAULT_BLOCK_OVERLAP,
+            alert_amount_threshold: None,
+            review_amount_threshold: None,
+            claim_confirm_delay: DEFAULT_CLAIM_CONFIRM_DELAY,
+            cosmos_legacy_rpc_url: None,
+            claim_inclusion_timeout: None,
+            claim_gas_multiplier: None,
+            upgrade_event: None,
+            observe_depth: None,
+            state_snapshot: None,
+            heartbeat_polls: None,
+            heartbeat_interval: None,
+            otlp_endpoint: None,
+            max_block_range: None,
+            cosmos_prefix: DEFAULT_COSMOS_PREFIX.to_string(),
+            halt_on_nonce_regression: true,
+            claim_window: Duration::from_secs(0),
+            claim_window_max_claims: DEFAULT_CLAIM_WINDOW_MAX_CLAIMS,
+            additional_cosmos_keys: Vec::new(),
+        }
+    }
+}
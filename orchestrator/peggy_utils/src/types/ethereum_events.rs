@@ -1,7 +1,9 @@
-use std::unimplemented;
-
 use super::ValsetMember;
 use crate::error::PeggyError;
+use crate::event_signatures::{
+    event_topic, ERC20_DEPLOYED_EVENT_SIG, LEGACY_VALSET_UPDATED_EVENT_SIG, LOGIC_CALL_EVENT_SIG,
+    SEND_TO_COSMOS_EVENT_SIG, TRANSACTION_BATCH_EXECUTED_EVENT_SIG, VALSET_UPDATED_EVENT_SIG,
+};
 use clarity::Address as EthAddress;
 use deep_space::address::Address as CosmosAddress;
 use num256::Uint256;
@@ -13,11 +15,18 @@ use web30::types::Log;
 pub struct ValsetUpdatedEvent {
     pub nonce: u64,
     pub members: Vec<ValsetMember>,
+    /// The reward paid to the relayer of this update, zero for events from contracts emitting the
+    /// legacy event without rewards
+    pub reward_amount: Uint256,
+    /// The token the reward is paid in, None for legacy events
+    pub reward_token: Option<EthAddress>,
 }
 
 impl ValsetUpdatedEvent {
     /// This function is not an abi compatible bytes parser, but it's actually
-    /// not hard at all to extract data like this by hand.
+    /// not hard at all to extract data like this by hand. Logs with the
+    /// VALSET_UPDATED_EVENT_SIG topic are parsed with their reward fields, any
+    /// other log is parsed as the LEGACY_VALSET_UPDATED_EVENT_SIG event
     pub fn from_log(input: &Log) -> Result<ValsetUpdatedEvent, PeggyError> {
         // we have one indexed event so we should fine two indexes, one the event itself
         // and one the indexed nonce
@@ -26,6 +35,24 @@ impl ValsetUpdatedEvent {
                 "Too few topics".to_string(),
             ));
         }
+        let with_rewards = input.topics[0] == event_topic(VALSET_UPDATED_EVENT_SIG);
+        // the words before the arrays, the legacy event has only the two array offsets while
+        // the current one starts with the event nonce, reward amount and reward token
+        let head_words = if with_rewards { 5 } else { 2 };
+        if input.data.len() < (head_words + 1) * 32 {
+            return Err(PeggyError::InvalidEventLogError(
+                "Too little data, probably incorrect parsing".to_string(),
+            ));
+        }
+        let (reward_amount, reward_token) = if with_rewards {
+            let reward_token = EthAddress::from_slice(&input.data[76..96])?;
+            (
+                Uint256::from_bytes_be(&input.data[32..64]),
+                Some(reward_token),
+            )
+        } else {
+            (0u8.into(), None)
+        };
         let nonce_data = &input.topics[1];
         let nonce = Uint256::from_bytes_be(nonce_data);
         if nonce > u64::MAX.into() {
@@ -34,9 +61,9 @@ impl ValsetUpdatedEvent {
             ));
         }
         let nonce: u64 = nonce.to_string().parse().unwrap();
-        // first two indexes contain event info we don't care about, third index is
+        // the head contains event info we don't care about, the next index is
         // the length of the eth addresses array
-        let index_start = 2 * 32;
+        let index_start = head_words * 32;
         let index_end = index_start + 32;
         let eth_addresses_offset = index_start + 32;
         let len_eth_addresses = Uint256::from_bytes_be(&input.data[index_start..index_end]);
@@ -46,7 +73,7 @@ impl ValsetUpdatedEvent {
             ));
         }
         let len_eth_addresses: usize = len_eth_addresses.to_string().parse().unwrap();
-        let index_start = (3 + len_eth_addresses) * 32;
+        let index_start = (head_words + 1 + len_eth_addresses) * 32;
         let index_end = index_start + 32;
         let powers_offset = index_start + 32;
         let len_powers = Uint256::from_bytes_be(&input.data[index_start..index_end]);
@@ -99,6 +126,8 @@ impl ValsetUpdatedEvent {
         Ok(ValsetUpdatedEvent {
             nonce,
             members: validators,
+            reward_amount,
+            reward_token,
         })
     }
     pub fn from_logs(input: &[Log]) -> Result<Vec<ValsetUpdatedEvent>, PeggyError> {
@@ -193,7 +222,9 @@ pub struct SendToCosmosEvent {
     pub erc20: EthAddress,
     /// The Ethereum Sender
     pub sender: EthAddress,
-    /// The Cosmos destination
+    /// The Cosmos destination, the contract only stores the 20 address bytes so the bech32
+    /// prefix the depositor used is not part of the event and can't be filtered on. Any
+    /// prefix shown when this address is displayed is applied locally, see destination_bech32
     pub destination: CosmosAddress,
     /// The amount of the erc20 token that is being sent
     pub amount: Uint256,
@@ -204,6 +235,10 @@ pub struct SendToCosmosEvent {
 }
 
 impl SendToCosmosEvent {
+    /// The destination encoded with the chain's bech32 prefix, which is how it is claimed
+    pub fn destination_bech32(&self, prefix: &str) -> Result<String, PeggyError> {
+        Ok(self.destination.to_bech32(prefix)?)
+    }
     pub fn from_log(input: &Log) -> Result<SendToCosmosEvent, PeggyError> {
         let topics = (
             input.topics.get(1),
@@ -440,7 +475,68 @@ pub struct LogicCallExecutedEvent {
 
 impl LogicCallExecutedEvent {
     pub fn from_log(input: &Log) -> Result<LogicCallExecutedEvent, PeggyError> {
-        unimplemented!()
+        // none of the fields are indexed, the data holds the invalidation id, invalidation
+        // nonce, the offset of the return data, the event nonce, then the return data itself
+        if input.data.len() < 5 * 32 {
+            return Err(PeggyError::InvalidEventLogError(
+                "Too little data, probably incorrect parsing".to_string(),
+            ));
+        }
+        let invalidation_id = input.data[0..32].to_vec();
+        let invalidation_nonce = Uint256::from_bytes_be(&input.data[32..64]);
+        let return_data_offset = Uint256::from_bytes_be(&input.data[64..96]);
+        let event_nonce = Uint256::from_bytes_be(&input.data[96..128]);
+        if invalidation_nonce > u64::MAX.into() || event_nonce > u64::MAX.into() {
+            return Err(PeggyError::InvalidEventLogError(
+                "Nonce overflow, probably incorrect parsing".to_string(),
+            ));
+        }
+        // it's not probable that we have 4+ gigabytes of event data
+        if return_data_offset > u32::MAX.into() {
+            return Err(PeggyError::InvalidEventLogError(
+                "Return data offset overflow, probably incorrect parsing".to_string(),
+            ));
+        }
+        let index_start: usize = return_data_offset.to_string().parse().unwrap();
+        let index_end = index_start + 32;
+        let return_data_len = match input.data.get(index_start..index_end) {
+            Some(len) => Uint256::from_bytes_be(len),
+            None => {
+                return Err(PeggyError::InvalidEventLogError(
+                    "Return data out of bounds, probably incorrect parsing".to_string(),
+                ))
+            }
+        };
+        if return_data_len > u32::MAX.into() {
+            return Err(PeggyError::InvalidEventLogError(
+                "Return data length overflow, probably incorrect parsing".to_string(),
+            ));
+        }
+        let return_data_len: usize = return_data_len.to_string().parse().unwrap();
+        let return_data = match input.data.get(index_end..index_end + return_data_len) {
+            Some(return_data) => return_data.to_vec(),
+            None => {
+                return Err(PeggyError::InvalidEventLogError(
+                    "Return data out of bounds, probably incorrect parsing".to_string(),
+                ))
+            }
+        };
+        let block_height = if let Some(bn) = input.block_number.clone() {
+            bn
+        } else {
+            return Err(PeggyError::InvalidEventLogError(
+                "Log does not have block number, we only search logs already in blocks?"
+                    .to_string(),
+            ));
+        };
+
+        Ok(LogicCallExecutedEvent {
+            invalidation_id,
+            invalidation_nonce,
+            return_data,
+            event_nonce,
+            block_height,
+        })
     }
     pub fn from_logs(input: &[Log]) -> Result<Vec<LogicCallExecutedEvent>, PeggyError> {
         let mut res = Vec::new();
@@ -461,3 +557,39 @@ impl LogicCallExecutedEvent {
         ret
     }
 }
+
+/// Any of the events emitted by the Peggy contract
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub enum PeggyEvent {
+    ValsetUpdated(ValsetUpdatedEvent),
+    TransactionBatchExecuted(TransactionBatchExecutedEvent),
+    SendToCosmos(SendToCosmosEvent),
+    ERC20Deployed(ERC20DeployedEvent),
+    LogicCallExecuted(LogicCallExecutedEvent),
+}
+
+/// Parses a raw Ethereum log into the Peggy event it represents by matching on the
+/// event topic. Returns None if the log is not one of the Peggy events, this allows
+/// indexers and explorers to reuse our parsing without running the oracle.
+pub fn parse_peggy_log(log: &Log) -> Result<Option<PeggyEvent>, PeggyError> {
+    let topic: &[u8] = match log.topics.get(0) {
+        Some(topic) => topic,
+        None => return Ok(None),
+    };
+    let event = if topic == event_topic(VALSET_UPDATED_EVENT_SIG).as_slice()
+        || topic == event_topic(LEGACY_VALSET_UPDATED_EVENT_SIG).as_slice()
+    {
+        PeggyEvent::ValsetUpdated(ValsetUpdatedEvent::from_log(log)?)
+    } else if topic == event_topic(TRANSACTION_BATCH_EXECUTED_EVENT_SIG).as_slice() {
+        PeggyEvent::TransactionBatchExecuted(TransactionBatchExecutedEvent::from_log(log)?)
+    } else if topic == event_topic(SEND_TO_COSMOS_EVENT_SIG).as_slice() {
+        PeggyEvent::SendToCosmos(SendToCosmosEvent::from_log(log)?)
+    } else if topic == event_topic(ERC20_DEPLOYED_EVENT_SIG).as_slice() {
+        PeggyEvent::ERC20Deployed(ERC20DeployedEvent::from_log(log)?)
+    } else if topic == event_topic(LOGIC_CALL_EVENT_SIG).as_slice() {
+        PeggyEvent::LogicCallExecuted(LogicCallExecutedEvent::from_log(log)?)
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(event))
+}
@@ -0,0 +1,325 @@
+//! Regression tests running Peggy contract logs through the same parsing and filtering the
+//! oracle applies in check_for_events. The fixtures in peggy_utils/fixtures/events are hand built
+//! logs in the exact JSON format returned by eth_getLogs, so any change to an event struct or
+//! parser that alters the parsed output shows up here. Logs captured from a real chain go in
+//! peggy_utils/fixtures/events/recorded together with their expected events, see the README there.
+
+use super::*;
+use crate::event_signatures::{
+    event_topic, ERC20_DEPLOYED_EVENT_SIG, LEGACY_VALSET_UPDATED_EVENT_SIG, LOGIC_CALL_EVENT_SIG,
+    SEND_TO_COSMOS_EVENT_SIG, TRANSACTION_BATCH_EXECUTED_EVENT_SIG,
+};
+use clarity::utils::hex_str_to_bytes;
+use deep_space::address::Address as CosmosAddress;
+use std::fs;
+use std::path::Path;
+use web30::types::Log;
+
+const DAI: &str = "0x6b175474e89094c44da98b954eedeac495271d0f";
+const USDC: &str = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+const GRAVITON: &str = "0x9a4f2c1b8e7d6c5b4a3f2e1d0c9b8a7f6e5d4c3b";
+const SENDER_A: &str = "0x1a2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d";
+const SENDER_B: &str = "0x9f8e7d6c5b4a39281706f5e4d3c2b1a098f7e6d5";
+const DESTINATION_A: &str = "3e5f6c0e6d5c0a4b2f1e9d8c7b6a5f4e3d2c1b0a";
+const DESTINATION_B: &str = "0011223344556677889900aabbccddeeff001122";
+const VALIDATORS: [&str; 3] = [
+    "0x2a3f7ee1be5c6f1b0a5b7c3d4e9f8a1b2c3d4e5f",
+    "0x5b6c7d8e9f0a1b2c3d4e5f6a7b8c9d0e1f2a3b4c",
+    "0x7c8d9e0f1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d",
+];
+
+fn logs(json: &str) -> Vec<Log> {
+    serde_json::from_str(json).unwrap()
+}
+
+fn eth(address: &str) -> EthAddress {
+    address.parse().unwrap()
+}
+
+fn cosmos(address: &str) -> CosmosAddress {
+    let mut bytes = [0u8; 20];
+    bytes.copy_from_slice(&hex_str_to_bytes(address).unwrap());
+    CosmosAddress::from_bytes(bytes)
+}
+
+fn deposit(
+    erc20: &str,
+    sender: &str,
+    destination: &str,
+    amount: Uint256,
+    event_nonce: u64,
+    block_height: u64,
+) -> SendToCosmosEvent {
+    SendToCosmosEvent {
+        erc20: eth(erc20),
+        sender: eth(sender),
+        destination: cosmos(destination),
+        amount,
+        event_nonce: event_nonce.into(),
+        block_height: block_height.into(),
+    }
+}
+
+fn withdraw(
+    batch_nonce: u64,
+    erc20: &str,
+    event_nonce: u64,
+    block_height: u64,
+) -> TransactionBatchExecutedEvent {
+    TransactionBatchExecutedEvent {
+        batch_nonce: batch_nonce.into(),
+        block_height: block_height.into(),
+        erc20: eth(erc20),
+        event_nonce: event_nonce.into(),
+    }
+}
+
+/// A valset as emitted in the legacy event without rewards
+fn valset(nonce: u64) -> ValsetUpdatedEvent {
+    ValsetUpdatedEvent {
+        nonce,
+        members: VALIDATORS
+            .iter()
+            .map(|address| ValsetMember {
+                power: 1_431_655_765,
+                eth_address: Some(eth(address)),
+            })
+            .collect(),
+        reward_amount: 0u8.into(),
+        reward_token: None,
+    }
+}
+
+fn erc20_deploy(event_nonce: u64, block_height: u64) -> ERC20DeployedEvent {
+    ERC20DeployedEvent {
+        cosmos_denom: "ugraviton".to_string(),
+        erc20_address: eth(GRAVITON),
+        name: "Graviton".to_string(),
+        symbol: "GRAV".to_string(),
+        decimals: 6,
+        event_nonce: event_nonce.into(),
+        block_height: block_height.into(),
+    }
+}
+
+#[test]
+fn test_send_to_cosmos_fixture() {
+    let logs = logs(include_str!("../../fixtures/events/send_to_cosmos.json"));
+    let events = SendToCosmosEvent::from_logs(&logs).unwrap();
+    let dai_amount: Uint256 = "1500000000000000000000".parse().unwrap();
+    assert_eq!(
+        events,
+        vec![
+            deposit(DAI, SENDER_A, DESTINATION_A, dai_amount, 4, 12_000_000),
+            deposit(
+                USDC,
+                SENDER_B,
+                DESTINATION_B,
+                250_000_000u64.into(),
+                5,
+                12_000_000
+            ),
+        ]
+    );
+    let filtered = SendToCosmosEvent::filter_by_event_nonce(4, &events);
+    assert_eq!(filtered, vec![events[1].clone()]);
+}
+
+#[test]
+fn test_send_to_cosmos_custom_prefix() {
+    let logs = logs(include_str!("../../fixtures/events/send_to_cosmos.json"));
+    let event = SendToCosmosEvent::from_logs(&logs).unwrap().remove(0);
+    let destination = event.destination_bech32("osmo").unwrap();
+    assert!(destination.starts_with("osmo1"));
+    let default = event.destination_bech32(DEFAULT_COSMOS_PREFIX).unwrap();
+    assert!(default.starts_with("cosmos1"));
+    // the prefix is only applied when encoding, both decode to the bytes in the log
+    assert_eq!(
+        destination.parse::<CosmosAddress>().unwrap(),
+        event.destination
+    );
+    assert_eq!(default.parse::<CosmosAddress>().unwrap(), event.destination);
+}
+
+#[test]
+fn test_transaction_batch_executed_fixture() {
+    let logs = logs(include_str!(
+        "../../fixtures/events/transaction_batch_executed.json"
+    ));
+    let events = TransactionBatchExecutedEvent::from_logs(&logs).unwrap();
+    assert_eq!(events, vec![withdraw(7, DAI, 6, 12_000_001)]);
+    assert!(TransactionBatchExecutedEvent::filter_by_event_nonce(6, &events).is_empty());
+}
+
+#[test]
+fn test_valset_updated_fixture() {
+    let logs = logs(include_str!("../../fixtures/events/valset_updated.json"));
+    let events = ValsetUpdatedEvent::from_logs(&logs).unwrap();
+    assert_eq!(
+        events,
+        vec![ValsetUpdatedEvent {
+            reward_amount: 1_000_000u32.into(),
+            reward_token: Some(eth(DAI)),
+            ..valset(5)
+        }]
+    );
+}
+
+#[test]
+fn test_legacy_valset_updated_fixture() {
+    let logs = logs(include_str!(
+        "../../fixtures/events/valset_updated_legacy.json"
+    ));
+    let events = ValsetUpdatedEvent::from_logs(&logs).unwrap();
+    assert_eq!(events, vec![valset(3)]);
+}
+
+#[test]
+fn test_erc20_deployed_fixture() {
+    let logs = logs(include_str!("../../fixtures/events/erc20_deployed.json"));
+    let events = ERC20DeployedEvent::from_logs(&logs).unwrap();
+    assert_eq!(events, vec![erc20_deploy(8, 12_000_003)]);
+}
+
+#[test]
+fn test_logic_call_executed_fixture() {
+    let logs = logs(include_str!(
+        "../../fixtures/events/logic_call_executed.json"
+    ));
+    let events = LogicCallExecutedEvent::from_logs(&logs).unwrap();
+    let mut invalidation_id = b"invalidation-id-01".to_vec();
+    invalidation_id.resize(32, 0);
+    let mut return_data = vec![0u8; 32];
+    return_data[31] = 42;
+    assert_eq!(
+        events,
+        vec![LogicCallExecutedEvent {
+            invalidation_id,
+            invalidation_nonce: 2u8.into(),
+            return_data,
+            event_nonce: 9u8.into(),
+            block_height: 12_000_004u64.into(),
+        }]
+    );
+}
+
+/// A single block with every claimable event type interleaved, as the oracle sees it when the
+/// logs are fetched per event signature
+#[test]
+fn test_mixed_block_fixture() {
+    let logs = logs(include_str!("../../fixtures/events/mixed_block.json"));
+    let with_topic = |sig: &str| -> Vec<Log> {
+        let topic = event_topic(sig);
+        logs.iter()
+            .filter(|log| log.topics.get(0).map(|t| t.as_slice()) == Some(topic.as_slice()))
+            .cloned()
+            .collect()
+    };
+    let block = 12_000_010;
+
+    let deposits = SendToCosmosEvent::from_logs(&with_topic(SEND_TO_COSMOS_EVENT_SIG)).unwrap();
+    let withdraws =
+        TransactionBatchExecutedEvent::from_logs(&with_topic(TRANSACTION_BATCH_EXECUTED_EVENT_SIG))
+            .unwrap();
+    let valsets =
+        ValsetUpdatedEvent::from_logs(&with_topic(LEGACY_VALSET_UPDATED_EVENT_SIG)).unwrap();
+    let deploys = ERC20DeployedEvent::from_logs(&with_topic(ERC20_DEPLOYED_EVENT_SIG)).unwrap();
+    assert!(with_topic(LOGIC_CALL_EVENT_SIG).is_empty());
+
+    let one_dai: Uint256 = "1000000000000000000".parse().unwrap();
+    assert_eq!(
+        deposits,
+        vec![
+            deposit(DAI, SENDER_A, DESTINATION_A, one_dai, 10, block),
+            deposit(
+                USDC,
+                SENDER_B,
+                DESTINATION_B,
+                5_000_000u64.into(),
+                11,
+                block
+            ),
+            deposit(DAI, SENDER_B, DESTINATION_A, 42u8.into(), 14, block),
+        ]
+    );
+    assert_eq!(
+        withdraws,
+        vec![withdraw(8, USDC, 12, block), withdraw(9, DAI, 15, block)]
+    );
+    assert_eq!(valsets, vec![valset(4)]);
+    assert_eq!(deploys, vec![erc20_deploy(13, block)]);
+
+    // the oracle has already claimed up to event nonce 11
+    let deposits = SendToCosmosEvent::filter_by_event_nonce(11, &deposits);
+    let withdraws = TransactionBatchExecutedEvent::filter_by_event_nonce(11, &withdraws);
+    let deploys = ERC20DeployedEvent::filter_by_event_nonce(11, &deploys);
+    assert_eq!(
+        deposits,
+        vec![deposit(
+            DAI,
+            SENDER_B,
+            DESTINATION_A,
+            42u8.into(),
+            14,
+            block
+        )]
+    );
+    assert_eq!(
+        withdraws,
+        vec![withdraw(8, USDC, 12, block), withdraw(9, DAI, 15, block)]
+    );
+    assert_eq!(deploys, vec![erc20_deploy(13, block)]);
+
+    // what remains must be a contiguous run of event nonces or Cosmos will reject the claims
+    let mut nonces: Vec<Uint256> = deposits
+        .iter()
+        .map(|e| e.event_nonce.clone())
+        .chain(withdraws.iter().map(|e| e.event_nonce.clone()))
+        .chain(deploys.iter().map(|e| e.event_nonce.clone()))
+        .collect();
+    nonces.sort();
+    let expected: Vec<Uint256> = (12u64..=15).map(|n| n.into()).collect();
+    assert_eq!(nonces, expected);
+}
+
+/// A file in fixtures/events/recorded, see the README there
+#[derive(Deserialize)]
+struct RecordedFixture {
+    source: String,
+    logs: Vec<Log>,
+    expected: Vec<PeggyEvent>,
+}
+
+#[test]
+fn test_recorded_fixtures() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/events/recorded");
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map(|e| e == "json").unwrap_or(false))
+        .collect();
+    paths.sort();
+    // an empty directory would pass without checking anything against a real chain
+    assert!(
+        !paths.is_empty(),
+        "no recorded fixtures in {}, capture one as described in the README there",
+        dir.display()
+    );
+    for path in paths {
+        let fixture: RecordedFixture =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        let events: Vec<PeggyEvent> = fixture
+            .logs
+            .iter()
+            .filter_map(|log| parse_peggy_log(log).unwrap())
+            .collect();
+        assert_eq!(
+            events,
+            fixture.expected,
+            "{} ({}) parsed to {}",
+            path.display(),
+            fixture.source,
+            serde_json::to_string_pretty(&events).unwrap()
+        );
+    }
+}
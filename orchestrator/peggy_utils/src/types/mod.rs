@@ -2,13 +2,17 @@ use clarity::Address as EthAddress;
 use contact::types::parse_val;
 use num256::Uint256;
 mod batches;
+mod config;
 mod ethereum_events;
+#[cfg(test)]
+mod event_fixtures;
 mod logic_call;
 mod signatures;
 mod valsets;
 use crate::error::PeggyError;
 
 pub use batches::*;
+pub use config::*;
 pub use ethereum_events::*;
 pub use logic_call::*;
 pub use signatures::*;
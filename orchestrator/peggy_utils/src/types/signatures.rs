@@ -15,6 +15,20 @@ pub struct PeggySignature {
     pub s: Uint256,
 }
 
+impl PeggySignature {
+    /// A zeroed signature, which the contract interprets as no signature
+    /// provided for this validator
+    pub fn empty(power: u64, eth_address: EthAddress) -> Self {
+        PeggySignature {
+            power,
+            eth_address,
+            v: 0u8.into(),
+            r: 0u8.into(),
+            s: 0u8.into(),
+        }
+    }
+}
+
 impl Ord for PeggySignature {
     // Alex wrote the Go sorting implementation for validator
     // sets as Greatest to Least, now this isn't the convention
@@ -4,7 +4,11 @@ use clarity::Address as EthAddress;
 use clarity::Signature as EthSignature;
 use contact::jsonrpc::error::JsonRpcError;
 use deep_space::address::Address as CosmosAddress;
+use json_logger::LOGGING;
+use slog::warn as swarn;
 use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::Mutex;
 use std::{
     cmp::Ordering,
     collections::{HashMap, HashSet},
@@ -16,6 +20,57 @@ use std::{
 /// stored in a u64 to prevent overflow during computation.
 pub const TOTAL_PEGGY_POWER: u64 = u32::MAX as u64;
 
+/// The power threshold the Peggy contract is deployed with, 66% of TOTAL_PEGGY_POWER. The
+/// contract requires signatures representing strictly more power than its threshold
+pub const DEFAULT_POWER_THRESHOLD: u64 = 2_834_678_415;
+
+static POWER_THRESHOLD: AtomicU64 = AtomicU64::new(DEFAULT_POWER_THRESHOLD);
+
+/// Sets the power threshold signatures are checked against, this should be the value read from
+/// the Peggy contract at startup
+pub fn set_power_threshold(threshold: u64) {
+    POWER_THRESHOLD.store(threshold, AtomicOrdering::SeqCst);
+}
+
+pub fn power_threshold() -> u64 {
+    POWER_THRESHOLD.load(AtomicOrdering::SeqCst)
+}
+
+lazy_static! {
+    /// The confirm signers and valset nonces a dropped confirm has already been warned about,
+    /// the same confirms are fetched again every relaying loop
+    static ref DROPPED_CONFIRMS_LOGGED: Mutex<HashSet<(EthAddress, u64)>> =
+        Mutex::new(HashSet::new());
+}
+
+/// Warns about a confirm dropped by filter_valid_confirms the first time it is seen for a
+/// valset, after that it is only logged at trace level
+fn log_dropped_confirm(address: EthAddress, valset_nonce: u64, reason: &str) {
+    let first_seen = DROPPED_CONFIRMS_LOGGED
+        .lock()
+        .unwrap()
+        .insert((address, valset_nonce));
+    if !first_seen {
+        trace!(
+            "Dropping confirm from {}, it {} valset {}",
+            address,
+            reason,
+            valset_nonce
+        );
+        return;
+    }
+    warn!(
+        "Dropping confirm from {}, it {} valset {}",
+        address, reason, valset_nonce
+    );
+    swarn!(&LOGGING.logger, "DROPPED_INVALID_CONFIRM";
+        "function" => "filter_valid_confirms()",
+        "eth_address" => format!("{}",address),
+        "valset_nonce" => format!("{}",valset_nonce),
+        "reason" => format!("{}",reason),
+    );
+}
+
 /// takes in an amount of power in the peggy bridge, returns a percentage of total
 fn peggy_power_to_percent(input: u64) -> f32 {
     (input as f32 / TOTAL_PEGGY_POWER as f32) * 100f32
@@ -119,6 +174,191 @@ impl Valset {
         ))
     }
 
+    /// Builds the member lookup used to order signatures, when ordering signatures for
+    /// several messages against the same validator set build this once and reuse it
+    pub fn index(&self) -> IndexedValset {
+        IndexedValset::new(self)
+    }
+
+    pub fn order_sigs<T: Confirm + Clone + Debug>(
+        &self,
+        signed_message: &[u8],
+        signatures: &[T],
+    ) -> Result<Vec<PeggySignature>, PeggyError> {
+        self.index().order_sigs(signed_message, signatures)
+    }
+
+    /// Drops the confirms whose Ethereum address does not belong to a member of this valset with
+    /// power, such a confirm can never count towards the power threshold. Each dropped confirm
+    /// is warned about once per valset, see log_dropped_confirm
+    pub fn filter_valid_confirms<T: Confirm + Clone>(&self, confirms: &[T]) -> Vec<T> {
+        let members: HashMap<EthAddress, u64> = self
+            .members
+            .iter()
+            .filter_map(|member| member.eth_address.map(|address| (address, member.power)))
+            .collect();
+        confirms
+            .iter()
+            .filter(|confirm| {
+                let address = confirm.get_eth_address();
+                match members.get(&address) {
+                    Some(power) if *power > 0 => true,
+                    Some(_) => {
+                        log_dropped_confirm(address, self.nonce, "has no power in");
+                        false
+                    }
+                    None => {
+                        log_dropped_confirm(address, self.nonce, "is not a member of");
+                        false
+                    }
+                }
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// A utility function to provide a HashMap of members for easy lookups
+    pub fn to_hashmap(&self) -> HashMap<EthAddress, u64> {
+        let mut res = HashMap::new();
+        for item in self.members.iter() {
+            if let Some(address) = item.eth_address {
+                res.insert(address, item.power);
+            } else {
+                error!("Validator in active set without Eth Address! This must be corrected immediately!")
+            }
+        }
+        res
+    }
+
+    /// A utility function to provide a HashSet of members for union operations
+    pub fn to_hashset(&self) -> HashSet<EthAddress> {
+        let mut res = HashSet::new();
+        for item in self.members.iter() {
+            if let Some(address) = item.eth_address {
+                res.insert(address);
+            } else {
+                error!("Validator in active set without Eth Address! This must be corrected immediately!")
+            }
+        }
+        res
+    }
+
+    /// This function takes the current valset and compares it to a provided one
+    /// returning a percentage difference in their power allocation. This is a very
+    /// important function as it's used to decide when the validator sets are updated
+    /// on the Ethereum chain and when new validator sets are requested on the Cosmos
+    /// side. In theory an error here, if unnoticed for long enough, could allow funds
+    /// to be stolen from the bridge without the validators in question still having stake
+    /// to lose.
+    /// Returned value must be less than or equal to two
+    pub fn power_diff(&self, other: &Valset) -> f32 {
+        let mut total_power_diff = 0u64;
+        let a = self.to_hashmap();
+        let b = other.to_hashmap();
+        let a_map = self.to_hashset();
+        let b_map = other.to_hashset();
+        // items in A and B, we go through these and compute the absolute value of the
+        // difference in power and sum it.
+        let intersection = a_map.intersection(&b_map);
+        // items in A but not in B or vice versa, since we're just trying to compute the difference
+        // we can simply sum all of these up.
+        let symmetric_difference = a_map.symmetric_difference(&b_map);
+        for item in symmetric_difference {
+            let mut power = None;
+            if let Some(val) = a.get(item) {
+                power = Some(val);
+            } else if let Some(val) = b.get(item) {
+                power = Some(val);
+            }
+            // impossible for this to panic without a failure in the logic
+            // of the symmetric difference function
+            let power = power.unwrap();
+            total_power_diff += power;
+        }
+        for item in intersection {
+            // can't panic since there must be an entry for both.
+            let power_a = a[item];
+            let power_b = b[item];
+            if power_a > power_b {
+                total_power_diff += power_a - power_b;
+            } else {
+                total_power_diff += power_b - power_a;
+            }
+        }
+
+        (total_power_diff as f32) / (u32::MAX as f32)
+    }
+
+    /// Compares this validator set against a provided one and returns the members
+    /// that where added, removed, or had their power changed going from self to other.
+    /// This is used to explain to the operator why a validator set update is occurring
+    pub fn diff(&self, other: &Valset) -> ValsetDiff {
+        let a = self.to_hashmap();
+        let b = other.to_hashmap();
+        let mut added = Vec::new();
+        let mut removed = Vec::new();
+        let mut power_changed = Vec::new();
+        // iterate over the members rather than the hashmaps so that the output
+        // ordering is deterministic and matches the valset ordering
+        for member in other.members.iter() {
+            if let Some(address) = member.eth_address {
+                match a.get(&address) {
+                    Some(old_power) => {
+                        if *old_power != member.power {
+                            power_changed.push(ValsetPowerChange {
+                                eth_address: address,
+                                old_power: *old_power,
+                                new_power: member.power,
+                            })
+                        }
+                    }
+                    None => added.push(member.clone()),
+                }
+            }
+        }
+        for member in self.members.iter() {
+            if let Some(address) = member.eth_address {
+                if !b.contains_key(&address) {
+                    removed.push(member.clone())
+                }
+            }
+        }
+        ValsetDiff {
+            added,
+            removed,
+            power_changed,
+        }
+    }
+}
+
+/// A validator set with the position of each member precomputed, so that ordering
+/// signatures is a lookup per signature rather than a scan of the whole set
+#[derive(Debug, Clone)]
+pub struct IndexedValset<'a> {
+    valset: &'a Valset,
+    /// the positions of every member with an Ethereum address set, an address
+    /// would only have more than one position in a malformed validator set
+    member_positions: HashMap<EthAddress, Vec<usize>>,
+}
+
+impl<'a> IndexedValset<'a> {
+    pub fn new(valset: &'a Valset) -> Self {
+        let mut member_positions: HashMap<EthAddress, Vec<usize>> =
+            HashMap::with_capacity(valset.members.len());
+        for (position, member) in valset.members.iter().enumerate() {
+            if let Some(eth_address) = member.eth_address {
+                member_positions
+                    .entry(eth_address)
+                    .or_default()
+                    .push(position);
+            }
+        }
+        IndexedValset {
+            valset,
+            member_positions,
+        }
+    }
+
     /// combines the provided signatures with the valset ensuring that ordering and signature data is correct
     /// Note the 'correct' ordering is the *same* ordering as the validator set members in 'self'. In some cases
     /// this will be sorted, in others it will be improperly sorted but must be maintained so that the signatures
@@ -135,8 +375,9 @@ impl Valset {
             ));
         }
 
-        let mut out = Vec::new();
-        let signatures_hashmap: HashMap<EthAddress, T> = get_hashmap(signatures);
+        // every member starts out as a nonvoter, or unset if they have no key, and is
+        // then filled in from the signatures that match a member
+        let mut out = Vec::with_capacity(self.valset.members.len());
         let mut power_of_good_sigs = 0;
         let mut power_of_unset_keys = 0;
         let mut number_of_unset_key_validators = 0;
@@ -144,57 +385,55 @@ impl Valset {
         let mut number_of_nonvoters = 0;
         let mut power_of_invalid_signers = 0;
         let mut number_of_invalid_signers = 0;
-        for member in self.members.iter() {
-            if let Some(eth_address) = member.eth_address {
-                if let Some(sig) = signatures_hashmap.get(&eth_address) {
-                    assert_eq!(sig.get_eth_address(), eth_address);
-                    assert!(sig.get_signature().is_valid());
-                    let recover_key = sig.get_signature().recover(signed_message).unwrap();
-                    if recover_key == sig.get_eth_address() {
-                        out.push(PeggySignature {
-                            power: member.power,
-                            eth_address: sig.get_eth_address(),
-                            v: sig.get_signature().v.clone(),
-                            r: sig.get_signature().r.clone(),
-                            s: sig.get_signature().s.clone(),
-                        });
-                        power_of_good_sigs += member.power;
-                    } else {
-                        out.push(PeggySignature {
-                            power: member.power,
-                            eth_address,
-                            v: 0u8.into(),
-                            r: 0u8.into(),
-                            s: 0u8.into(),
-                        });
-                        power_of_invalid_signers += member.power;
-                        number_of_invalid_signers += 1;
-                    }
-                } else {
-                    out.push(PeggySignature {
-                        power: member.power,
-                        eth_address,
-                        v: 0u8.into(),
-                        r: 0u8.into(),
-                        s: 0u8.into(),
-                    });
+        for member in self.valset.members.iter() {
+            match member.eth_address {
+                Some(eth_address) => {
+                    out.push(PeggySignature::empty(member.power, eth_address));
                     power_of_nonvoters += member.power;
                     number_of_nonvoters += 1;
                 }
-            } else {
-                out.push(PeggySignature {
-                    power: member.power,
-                    eth_address: EthAddress::default(),
-                    v: 0u8.into(),
-                    r: 0u8.into(),
-                    s: 0u8.into(),
-                });
-                power_of_unset_keys += member.power;
-                number_of_unset_key_validators += 1;
+                None => {
+                    out.push(PeggySignature::empty(member.power, EthAddress::default()));
+                    power_of_unset_keys += member.power;
+                    number_of_unset_key_validators += 1;
+                }
             }
         }
 
-        let num_validators = self.members.len();
+        // when an address has signed more than once the last signature is used
+        let mut latest: HashMap<EthAddress, &T> = HashMap::with_capacity(signatures.len());
+        for sig in signatures.iter() {
+            latest.insert(sig.get_eth_address(), sig);
+        }
+        for (eth_address, sig) in latest {
+            let positions = match self.member_positions.get(&eth_address) {
+                Some(positions) => positions,
+                None => continue,
+            };
+            let signature = sig.get_signature();
+            assert!(signature.is_valid());
+            let recover_key = signature.recover(signed_message).unwrap();
+            for position in positions.iter() {
+                let power = out[*position].power;
+                power_of_nonvoters -= power;
+                number_of_nonvoters -= 1;
+                if recover_key == eth_address {
+                    out[*position] = PeggySignature {
+                        power,
+                        eth_address,
+                        v: signature.v.clone(),
+                        r: signature.r.clone(),
+                        s: signature.s.clone(),
+                    };
+                    power_of_good_sigs += power;
+                } else {
+                    power_of_invalid_signers += power;
+                    number_of_invalid_signers += 1;
+                }
+            }
+        }
+
+        let num_validators = self.valset.members.len();
         Ok(SignatureStatus {
             ordered_signatures: out,
             power_of_good_sigs,
@@ -217,10 +456,11 @@ impl Valset {
         // now that we have collected the signatures we can determine if the measure has the votes to pass
         // and error early if it does not, otherwise the user will pay fees for a transaction that will
         // just throw
-        if peggy_power_to_percent(status.power_of_good_sigs) < 66f32 {
+        let threshold = power_threshold();
+        if status.power_of_good_sigs <= threshold {
             let message = format!(
                 "
-                has {}/{} or {:.2}% power voting! Can not execute on Ethereum!
+                has {}/{} or {:.2}% power voting, more than {} or {:.2}% is required! Can not execute on Ethereum!
                 {}/{} validators have unset Ethereum keys representing {}/{} or {:.2}% of the power required
                 {}/{} validators have Ethereum keys set but have not voted representing {}/{} or {:.2}% of the power required
                 {}/{} validators have Invalid signatures {}/{} or {:.2}% of the power required
@@ -228,6 +468,8 @@ impl Valset {
                 status.power_of_good_sigs,
                 TOTAL_PEGGY_POWER,
                 peggy_power_to_percent(status.power_of_good_sigs),
+                threshold,
+                peggy_power_to_percent(threshold),
                 status.number_of_unset_key_validators,
                 status.num_validators,
                 status.power_of_unset_keys,
@@ -249,77 +491,44 @@ impl Valset {
             Ok(status.ordered_signatures)
         }
     }
+}
 
-    /// A utility function to provide a HashMap of members for easy lookups
-    pub fn to_hashmap(&self) -> HashMap<EthAddress, u64> {
-        let mut res = HashMap::new();
-        for item in self.members.iter() {
-            if let Some(address) = item.eth_address {
-                res.insert(address, item.power);
-            } else {
-                error!("Validator in active set without Eth Address! This must be corrected immediately!")
-            }
-        }
-        res
-    }
+/// A single validator whose power differs between two validator sets
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct ValsetPowerChange {
+    pub eth_address: EthAddress,
+    pub old_power: u64,
+    pub new_power: u64,
+}
 
-    /// A utility function to provide a HashSet of members for union operations
-    pub fn to_hashset(&self) -> HashSet<EthAddress> {
-        let mut res = HashSet::new();
-        for item in self.members.iter() {
-            if let Some(address) = item.eth_address {
-                res.insert(address);
-            } else {
-                error!("Validator in active set without Eth Address! This must be corrected immediately!")
-            }
-        }
-        res
+/// The difference between two validator sets, as produced by Valset::diff
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct ValsetDiff {
+    pub added: Vec<ValsetMember>,
+    pub removed: Vec<ValsetMember>,
+    pub power_changed: Vec<ValsetPowerChange>,
+}
+
+impl ValsetDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.power_changed.is_empty()
     }
 
-    /// This function takes the current valset and compares it to a provided one
-    /// returning a percentage difference in their power allocation. This is a very
-    /// important function as it's used to decide when the validator sets are updated
-    /// on the Ethereum chain and when new validator sets are requested on the Cosmos
-    /// side. In theory an error here, if unnoticed for long enough, could allow funds
-    /// to be stolen from the bridge without the validators in question still having stake
-    /// to lose.
-    /// Returned value must be less than or equal to two
-    pub fn power_diff(&self, other: &Valset) -> f32 {
-        let mut total_power_diff = 0u64;
-        let a = self.to_hashmap();
-        let b = other.to_hashmap();
-        let a_map = self.to_hashset();
-        let b_map = other.to_hashset();
-        // items in A and B, we go through these and compute the absolute value of the
-        // difference in power and sum it.
-        let intersection = a_map.intersection(&b_map);
-        // items in A but not in B or vice versa, since we're just trying to compute the difference
-        // we can simply sum all of these up.
-        let symmetric_difference = a_map.symmetric_difference(&b_map);
-        for item in symmetric_difference {
-            let mut power = None;
-            if let Some(val) = a.get(item) {
-                power = Some(val);
-            } else if let Some(val) = b.get(item) {
-                power = Some(val);
-            }
-            // impossible for this to panic without a failure in the logic
-            // of the symmetric difference function
-            let power = power.unwrap();
-            total_power_diff += power;
-        }
-        for item in intersection {
-            // can't panic since there must be an entry for both.
-            let power_a = a[item];
-            let power_b = b[item];
-            if power_a > power_b {
-                total_power_diff += power_a - power_b;
-            } else {
-                total_power_diff += power_b - power_a;
-            }
-        }
+    /// True if any validator joined or left the set, as opposed to only power changes
+    pub fn membership_changed(&self) -> bool {
+        !self.added.is_empty() || !self.removed.is_empty()
+    }
+}
 
-        (total_power_diff as f32) / (u32::MAX as f32)
+impl fmt::Display for ValsetDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} removed, {} power changed",
+            self.added.len(),
+            self.removed.len(),
+            self.power_changed.len()
+        )
     }
 }
 
@@ -417,3 +626,167 @@ impl From<&peggy_proto::peggy::BridgeValidator> for ValsetMember {
         }
     }
 }
+
+#[test]
+fn test_valset_diff() {
+    let a: EthAddress = "0xc783df8a850f42e7F7e57013759C285caa701eB6"
+        .parse()
+        .unwrap();
+    let b: EthAddress = "0xeAD9C93b79Ae7C1591b1FB5323BD777E86e150d4"
+        .parse()
+        .unwrap();
+    let c: EthAddress = "0xE5904695748fe4A84b40b3fc79De2277660BD1D3"
+        .parse()
+        .unwrap();
+    let old = Valset {
+        nonce: 1,
+        members: vec![
+            ValsetMember {
+                power: 3333,
+                eth_address: Some(a),
+            },
+            ValsetMember {
+                power: 3333,
+                eth_address: Some(b),
+            },
+        ],
+    };
+    let new = Valset {
+        nonce: 2,
+        members: vec![
+            ValsetMember {
+                power: 5000,
+                eth_address: Some(a),
+            },
+            ValsetMember {
+                power: 1666,
+                eth_address: Some(c),
+            },
+        ],
+    };
+    let diff = old.diff(&new);
+    assert_eq!(
+        diff.added,
+        vec![ValsetMember {
+            power: 1666,
+            eth_address: Some(c),
+        }]
+    );
+    assert_eq!(
+        diff.removed,
+        vec![ValsetMember {
+            power: 3333,
+            eth_address: Some(b),
+        }]
+    );
+    assert_eq!(
+        diff.power_changed,
+        vec![ValsetPowerChange {
+            eth_address: a,
+            old_power: 3333,
+            new_power: 5000,
+        }]
+    );
+    assert!(old.diff(&old).is_empty());
+    assert!(diff.membership_changed());
+}
+
+#[test]
+fn test_order_sigs() {
+    use clarity::PrivateKey as EthPrivateKey;
+    let keys: Vec<EthPrivateKey> = (1u8..=4)
+        .map(|i| EthPrivateKey::from_slice(&[i; 32]).unwrap())
+        .collect();
+    let addresses: Vec<EthAddress> = keys.iter().map(|k| k.to_public_key().unwrap()).collect();
+    let hash = vec![7u8; 32];
+    let power = TOTAL_PEGGY_POWER / 10;
+    let valset = Valset {
+        nonce: 1,
+        members: vec![
+            ValsetMember {
+                power: power * 7,
+                eth_address: Some(addresses[0]),
+            },
+            ValsetMember {
+                power,
+                eth_address: Some(addresses[1]),
+            },
+            ValsetMember {
+                power,
+                eth_address: None,
+            },
+            ValsetMember {
+                power,
+                eth_address: Some(addresses[2]),
+            },
+        ],
+    };
+    let confirm = |address: EthAddress, key: &EthPrivateKey| ValsetConfirmResponse {
+        eth_address: address,
+        eth_signature: key.sign_hash(&hash),
+        ..Default::default()
+    };
+    let confirms = vec![
+        // signed by the wrong key, counted as invalid
+        confirm(addresses[2], &keys[3]),
+        confirm(addresses[0], &keys[0]),
+        // not a member, ignored
+        confirm(addresses[3], &keys[3]),
+    ];
+    let good = confirms[1].eth_signature.clone();
+    let ordered = valset.order_sigs(&hash, &confirms).unwrap();
+    assert_eq!(
+        ordered,
+        vec![
+            PeggySignature {
+                power: power * 7,
+                eth_address: addresses[0],
+                v: good.v,
+                r: good.r,
+                s: good.s,
+            },
+            PeggySignature::empty(power, addresses[1]),
+            PeggySignature::empty(power, EthAddress::default()),
+            PeggySignature::empty(power, addresses[2]),
+        ]
+    );
+    // the same signatures without the valid one do not have enough power
+    assert!(valset.order_sigs(&hash, &confirms[..1]).is_err());
+}
+
+#[test]
+fn test_filter_valid_confirms() {
+    let member: EthAddress = "0xc783df8a850f42e7F7e57013759C285caa701eB6"
+        .parse()
+        .unwrap();
+    let powerless: EthAddress = "0xeAD9C93b79Ae7C1591b1FB5323BD777E86e150d4"
+        .parse()
+        .unwrap();
+    let bogus: EthAddress = "0xE5904695748fe4A84b40b3fc79De2277660BD1D3"
+        .parse()
+        .unwrap();
+    let valset = Valset {
+        nonce: 1,
+        members: vec![
+            ValsetMember {
+                power: TOTAL_PEGGY_POWER,
+                eth_address: Some(member),
+            },
+            ValsetMember {
+                power: 0,
+                eth_address: Some(powerless),
+            },
+        ],
+    };
+    let confirm = |address: EthAddress| ValsetConfirmResponse {
+        eth_address: address,
+        ..Default::default()
+    };
+    let confirms = vec![confirm(bogus), confirm(member), confirm(powerless)];
+    let valid: Vec<EthAddress> = valset
+        .filter_valid_confirms(&confirms)
+        .iter()
+        .map(|c| c.eth_address)
+        .collect();
+    assert_eq!(valid, vec![member]);
+}
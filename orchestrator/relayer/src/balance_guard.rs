@@ -0,0 +1,57 @@
+//! Once the relayer account can't pay for gas every submission fails with a confusing revert or
+//! insufficient funds error on every loop. This module checks the balance before submitting so
+//! that the operator gets a single clear warning instead.
+
+use clarity::address::Address as EthAddress;
+use clarity::Uint256;
+use ethereum_peggy::utils::{has_sufficient_balance, GasCost};
+use json_logger::LOGGING;
+use slog::error as serror;
+use slog::warn as swarn;
+use web30::client::Web3;
+
+/// Returns true if our account can pay for the whole `gas_limit` we submit with at `gas_price`
+/// and still keep `reserve`, otherwise logs a LOW_ETH_BALANCE warning and returns false. The
+/// node rejects a transaction the sender can't cover up to its gas limit, so the estimate alone
+/// is not enough. `function` names the calling submission path
+pub async fn check_eth_balance(
+    web3: &Web3,
+    our_ethereum_address: EthAddress,
+    gas_limit: &Uint256,
+    gas_price: &Uint256,
+    reserve: &Uint256,
+    function: &'static str,
+) -> bool {
+    let cost = GasCost {
+        gas: gas_limit.clone(),
+        gas_price: gas_price.clone(),
+    };
+    let balance = web3.eth_get_balance(our_ethereum_address).await;
+    if balance.is_err() {
+        error!("Failed to get our ETH balance with {:?}", balance);
+        serror!(&LOGGING.logger, "FAILED_TO_GET_ETH_BALANCE";
+            "function" => function,
+            "error" => format!("{:?}",balance),
+        );
+        return false;
+    }
+    let balance = balance.unwrap();
+    if has_sufficient_balance(&balance, &cost, reserve) {
+        return true;
+    }
+    warn!(
+        "Low ETH balance! {} has {} wei but needs {} wei for gas plus a {} wei reserve, skipping submission until topped up",
+        our_ethereum_address,
+        balance,
+        cost.get_total(),
+        reserve
+    );
+    swarn!(&LOGGING.logger, "LOW_ETH_BALANCE";
+        "function" => function,
+        "address" => format!("{}",our_ethereum_address),
+        "balance" => format!("{}",balance),
+        "cost" => format!("{}",cost.get_total()),
+        "reserve" => format!("{}",reserve),
+    );
+    false
+}
@@ -0,0 +1,184 @@
+//! Read only monitoring that compares the batch nonce executed on Ethereum for each token against
+//! the batches Cosmos still considers pending, to catch the two chains drifting apart.
+//!
+//! Cosmos has no query for the last executed batch nonce, but once it observes a batch executed it
+//! removes that batch and every earlier batch for the token. Any pending batch at or below the
+//! nonce executed on Ethereum is therefore an execution Cosmos has not yet processed. That is
+//! normal for the few minutes it takes the oracle to claim the execution, so a discrepancy is only
+//! warned about once it is still present on the following run.
+
+use clarity::Address as EthAddress;
+use cosmos_peggy::query::get_latest_transaction_batches;
+use ethereum_peggy::utils::get_tx_batch_nonce;
+use json_logger::{LOGGING, RELAYER_LOGGER};
+use peggy_proto::peggy::query_client::QueryClient as PeggyQueryClient;
+use peggy_utils::error::PeggyError;
+use peggy_utils::types::TransactionBatch;
+use slog::info as sinfo;
+use slog::warn as swarn;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use tonic::transport::Channel;
+use web30::client::Web3;
+
+/// A token whose executed batch nonce on Ethereum has not been processed by Cosmos
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchDiscrepancy {
+    pub token_contract: EthAddress,
+    pub ethereum_nonce: u64,
+    /// The batches Cosmos still has pending at or below the Ethereum nonce
+    pub stale_cosmos_nonces: Vec<u64>,
+}
+
+/// Compares the pending batches on Cosmos against the last batch nonce executed on Ethereum for
+/// each of their tokens, tokens missing from `ethereum_nonces` are skipped
+pub fn find_batch_discrepancies(
+    pending_batches: &[TransactionBatch],
+    ethereum_nonces: &HashMap<EthAddress, u64>,
+) -> Vec<BatchDiscrepancy> {
+    let mut discrepancies = Vec::new();
+    for (token_contract, ethereum_nonce) in ethereum_nonces.iter() {
+        let mut stale_cosmos_nonces: Vec<u64> = pending_batches
+            .iter()
+            .filter(|b| b.token_contract == *token_contract && b.nonce <= *ethereum_nonce)
+            .map(|b| b.nonce)
+            .collect();
+        if !stale_cosmos_nonces.is_empty() {
+            stale_cosmos_nonces.sort_unstable();
+            discrepancies.push(BatchDiscrepancy {
+                token_contract: *token_contract,
+                ethereum_nonce: *ethereum_nonce,
+                stale_cosmos_nonces,
+            });
+        }
+    }
+    discrepancies
+}
+
+/// Runs the reconciliation at most once per interval and remembers the discrepancies it found
+/// last time, so that only persistent ones are warned about
+pub struct BatchReconciler {
+    interval: Duration,
+    last_run: Option<Instant>,
+    previous: HashSet<(EthAddress, u64)>,
+}
+
+impl BatchReconciler {
+    pub fn new(interval: Duration) -> Self {
+        BatchReconciler {
+            interval,
+            last_run: None,
+            previous: HashSet::new(),
+        }
+    }
+
+    pub fn is_due(&self) -> bool {
+        self.last_run
+            .map_or(true, |last| last.elapsed() >= self.interval)
+    }
+
+    /// Queries both chains and logs the result. Makes one Cosmos query plus one Ethereum call per
+    /// token with pending batches, it is up to the caller to only run it when there is time to
+    /// spare in the relay loop
+    pub async fn reconcile(
+        &mut self,
+        web3: &Web3,
+        grpc_client: &mut PeggyQueryClient<Channel>,
+        peggy_contract_address: EthAddress,
+        our_ethereum_address: EthAddress,
+    ) -> Result<Vec<BatchDiscrepancy>, PeggyError> {
+        self.last_run = Some(Instant::now());
+        let logger = LOGGING.named_logger(RELAYER_LOGGER);
+        let pending_batches = get_latest_transaction_batches(grpc_client).await?;
+        let tokens: HashSet<EthAddress> =
+            pending_batches.iter().map(|b| b.token_contract).collect();
+        let mut ethereum_nonces = HashMap::new();
+        for token in tokens {
+            let nonce =
+                get_tx_batch_nonce(peggy_contract_address, token, our_ethereum_address, web3)
+                    .await?;
+            ethereum_nonces.insert(token, nonce);
+        }
+        let discrepancies = find_batch_discrepancies(&pending_batches, &ethereum_nonces);
+
+        let current: HashSet<(EthAddress, u64)> = discrepancies
+            .iter()
+            .map(|d| (d.token_contract, d.ethereum_nonce))
+            .collect();
+        let persistent: Vec<&BatchDiscrepancy> = discrepancies
+            .iter()
+            .filter(|d| {
+                self.previous
+                    .contains(&(d.token_contract, d.ethereum_nonce))
+            })
+            .collect();
+        self.previous = current;
+
+        let summary: Vec<String> = discrepancies
+            .iter()
+            .map(|d| {
+                format!(
+                    "{} executed {} pending {:?}",
+                    d.token_contract, d.ethereum_nonce, d.stale_cosmos_nonces
+                )
+            })
+            .collect();
+        if persistent.is_empty() {
+            sinfo!(&logger, "BATCH_STATE_RECONCILED";
+                "function" => "BatchReconciler::reconcile()",
+                "tokens_checked" => format!("{}",ethereum_nonces.len()),
+                "discrepancies" => summary.join(", "),
+            );
+        } else {
+            warn!(
+                "Cosmos has not processed executed batches for {} tokens {}",
+                persistent.len(),
+                summary.join(", ")
+            );
+            swarn!(&logger, "BATCH_STATE_RECONCILED";
+                "function" => "BatchReconciler::reconcile()",
+                "tokens_checked" => format!("{}",ethereum_nonces.len()),
+                "discrepancies" => summary.join(", "),
+                "persistent" => format!("{}",persistent.len()),
+            );
+        }
+        Ok(discrepancies)
+    }
+}
+
+#[test]
+fn test_find_batch_discrepancies() {
+    use peggy_utils::types::ERC20Token;
+
+    let dai: EthAddress = "0x6b175474e89094c44da98b954eedeac495271d0f"
+        .parse()
+        .unwrap();
+    let usdc: EthAddress = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"
+        .parse()
+        .unwrap();
+    let batch = |token_contract: EthAddress, nonce: u64| TransactionBatch {
+        nonce,
+        batch_timeout: 1000,
+        transactions: Vec::new(),
+        total_fee: ERC20Token {
+            amount: 0u8.into(),
+            token_contract_address: token_contract,
+        },
+        token_contract,
+    };
+    let pending = vec![batch(dai, 4), batch(dai, 3), batch(dai, 6), batch(usdc, 2)];
+    let mut ethereum_nonces = HashMap::new();
+    ethereum_nonces.insert(dai, 4);
+    ethereum_nonces.insert(usdc, 1);
+
+    assert_eq!(
+        find_batch_discrepancies(&pending, &ethereum_nonces),
+        vec![BatchDiscrepancy {
+            token_contract: dai,
+            ethereum_nonce: 4,
+            stale_cosmos_nonces: vec![3, 4],
+        }]
+    );
+    ethereum_nonces.insert(dai, 2);
+    assert!(find_batch_discrepancies(&pending, &ethereum_nonces).is_empty());
+}
@@ -1,21 +1,50 @@
+use crate::balance_guard::check_eth_balance;
+use crate::find_latest_valset::find_latest_valset;
+use crate::participation::{ParticipationTracker, SignedItem};
+use crate::signature_cache::BatchSignatureCache;
 use clarity::address::Address as EthAddress;
 use clarity::PrivateKey as EthPrivateKey;
+use clarity::Uint256;
+use cosmos_peggy::query::get_batch_timeout_blocks;
 use cosmos_peggy::query::get_latest_transaction_batches;
 use cosmos_peggy::query::get_transaction_batch_signatures;
-use ethereum_peggy::utils::{downcast_to_u128, get_tx_batch_nonce};
-use ethereum_peggy::{one_eth, submit_batch::send_eth_transaction_batch};
+use ethereum_peggy::gas_oracle::{resolve_gas_price, GasOracle};
+use ethereum_peggy::gas_price_tracker::GasPriceTracker;
+use ethereum_peggy::nonce_manager::NonceManager;
+use ethereum_peggy::one_eth;
+use ethereum_peggy::price_feed::{token_value_in_wei, PriceFeed};
+use ethereum_peggy::submit_batch::{estimate_tx_batch_cost, send_eth_transaction_batch};
+use ethereum_peggy::token_metadata::TokenMetadataCache;
+use ethereum_peggy::utils::{
+    downcast_to_u128, downcast_uint256, get_gas_limit, get_peggy_id, get_tx_batch_nonce,
+    get_valset_nonce, GasCost,
+};
+use json_logger::{LOGGING, RELAYER_LOGGER};
 use peggy_proto::peggy::query_client::QueryClient as PeggyQueryClient;
+use peggy_utils::error::PeggyError;
 use peggy_utils::message_signatures::encode_tx_batch_confirm_hashed;
-use peggy_utils::types::Valset;
+use peggy_utils::metrics::{BATCHES_RELAYED, BATCH_RELAY_FEES, BATCH_RELAY_GAS};
+use peggy_utils::relay_pause::is_relaying_paused;
 use peggy_utils::types::{BatchConfirmResponse, TransactionBatch};
+use peggy_utils::types::{BatchSelectionStrategy, GasEstimateFailurePolicy, RelayerConfig, Valset};
+use slog::error as serror;
+use slog::info as sinfo;
+use slog::warn as swarn;
 use std::time::Duration;
+use tokio::time::delay_for;
 use tonic::transport::Channel;
 use web30::client::Web3;
-use json_logger::LOGGING;
-use slog::{info as sinfo};
-use slog::{warn as swarn};
-use slog::{error as serror};
 
+/// The average Ethereum block time, used to convert a gas oracles expected wait
+/// into a number of blocks
+const ETH_AVERAGE_BLOCK_TIME: Duration = Duration::from_secs(13);
+/// Batches this many blocks or fewer from timing out are submitted at the spot gas
+/// price rather than the moving average, so that they are included in time
+const URGENT_BATCH_TIMEOUT_BLOCKS: u64 = 100;
+/// The wait between batch gas estimates under GasEstimateFailurePolicy::Retry
+const GAS_ESTIMATE_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+#[allow(clippy::too_many_arguments)]
 pub async fn relay_batches(
     // the validator set currently in the contract on Ethereum
     current_valset: Valset,
@@ -25,37 +54,148 @@ pub async fn relay_batches(
     peggy_contract_address: EthAddress,
     peggy_id: String,
     timeout: Duration,
+    config: &RelayerConfig,
+    gas_oracle: Option<&dyn GasOracle>,
+    gas_price_tracker: &GasPriceTracker,
+    price_feed: Option<&dyn PriceFeed>,
+    token_metadata: &mut TokenMetadataCache,
+    signature_cache: &mut BatchSignatureCache,
+    participation: &mut ParticipationTracker,
+    nonce_manager: &NonceManager,
 ) {
+    let logger = LOGGING.named_logger(RELAYER_LOGGER);
     let our_ethereum_address = ethereum_key.to_public_key().unwrap();
 
     let latest_batches = get_latest_transaction_batches(grpc_client).await;
     trace!("Latest batches {:?}", latest_batches);
-    if latest_batches.is_err() {
+    if let Err(e) = latest_batches {
+        let e = PeggyError::RpcUnavailable {
+            endpoint: "Cosmos gRPC".to_string(),
+            cause: e.to_string(),
+        };
+        warn!("Could not get the latest batches {}", e);
+        swarn!(&logger, "COULD_NOT_GET_LATEST_BATCHES";
+            "function" => "relay_batches()",
+            "error" => format!("{}",e),
+        );
         return;
     }
     let latest_batches = latest_batches.unwrap();
-    let mut oldest_signed_batch: Option<TransactionBatch> = None;
-    let mut oldest_signatures: Option<Vec<BatchConfirmResponse>> = None;
+    signature_cache.retain_batches(&latest_batches);
+    participation.retain(|item| match item {
+        SignedItem::Batch {
+            token_contract,
+            nonce,
+        } => latest_batches
+            .iter()
+            .any(|b| b.token_contract == *token_contract && b.nonce == *nonce),
+        SignedItem::Valset { .. } => true,
+    });
+    // the current block height and timeout window needed to estimate batch ages, only
+    // queried when a max age is configured
+    let age_limit = match config.max_batch_age_blocks {
+        Some(max_age) => {
+            let current_block_height = web3.eth_block_number().await;
+            let current_block_height = current_block_height.ok().and_then(downcast_uint256);
+            let timeout_window = get_batch_timeout_blocks(grpc_client).await;
+            match (current_block_height, timeout_window) {
+                (Some(height), Ok(window)) => Some((max_age, height, window)),
+                (_, window) => {
+                    error!(
+                        "Could not estimate batch ages, not relaying batches {:?}",
+                        window
+                    );
+                    return;
+                }
+            }
+        }
+        None => None,
+    };
+    let mut submittable_batches: Vec<TransactionBatch> = Vec::new();
+    let mut submittable_signatures: Vec<Vec<BatchConfirmResponse>> = Vec::new();
+    let indexed_valset = current_valset.index();
     for batch in latest_batches {
-        let sigs =
-            get_transaction_batch_signatures(grpc_client, batch.nonce, batch.token_contract).await;
+        // checked first so that no signatures are fetched for batches we won't relay
+        if !config.relays_token(&batch.token_contract) {
+            trace!(
+                "Batch {}/{} is for a filtered token, not relaying",
+                batch.token_contract,
+                batch.nonce
+            );
+            sinfo!(&logger, "TOKEN_FILTERED";
+                "function" => "relay_batches()",
+                "token_contract" => format!("{}",batch.token_contract),
+                "nonce" => format!("{}",batch.nonce),
+            );
+            continue;
+        }
+        if let Some((max_age, current_block_height, timeout_window)) = age_limit {
+            let age = estimate_batch_age(batch.batch_timeout, timeout_window, current_block_height);
+            if age > max_age {
+                trace!(
+                    "Batch {}/{} is about {} blocks old, not relaying",
+                    batch.token_contract,
+                    batch.nonce,
+                    age
+                );
+                sinfo!(&logger, "BATCH_TOO_OLD";
+                    "function" => "relay_batches()",
+                    "token_contract" => format!("{}",batch.token_contract),
+                    "nonce" => format!("{}",batch.nonce),
+                    "age_blocks" => format!("{}",age),
+                    "max_age_blocks" => format!("{}",max_age),
+                );
+                continue;
+            }
+        }
+        let sigs = match signature_cache.get(batch.token_contract, batch.nonce) {
+            Some(sigs) => Ok(sigs),
+            None => {
+                let sigs = get_transaction_batch_signatures(
+                    grpc_client,
+                    batch.nonce,
+                    batch.token_contract,
+                )
+                .await;
+                if let Ok(sigs) = &sigs {
+                    signature_cache.insert(batch.token_contract, batch.nonce, sigs.clone());
+                }
+                sigs
+            }
+        };
         trace!("Got sigs {:?}", sigs);
         if let Ok(sigs) = sigs {
             // this checks that the signatures for the batch are actually possible to submit to the chain
             let hash = encode_tx_batch_confirm_hashed(peggy_id.clone(), batch.clone());
-            if current_valset.order_sigs(&hash, &sigs).is_ok() {
-                oldest_signed_batch = Some(batch);
-                oldest_signatures = Some(sigs);
-            } else {
+            let valid_sigs = current_valset.filter_valid_confirms(&sigs);
+            if let Err(e) = indexed_valset.order_sigs(&hash, &valid_sigs) {
+                // more signatures are expected, so refetch them next time
+                signature_cache.invalidate(batch.token_contract, batch.nonce);
+                let e = PeggyError::InsufficientSignerPower {
+                    nonce: batch.nonce,
+                    details: e.to_string(),
+                };
                 warn!(
-                    "Batch {}/{} can not be submitted yet, waiting for more signatures",
-                    batch.token_contract, batch.nonce
+                    "Batch {}/{} can not be submitted yet, waiting for more signatures {}",
+                    batch.token_contract, batch.nonce, e
                 );
-                swarn!(&LOGGING.logger, "BATCH_CAN_NOT_BE_SUBMITTED_YET";
+                swarn!(&logger, "BATCH_CAN_NOT_BE_SUBMITTED_YET";
                     "function" => "relay_batches()",
                     "token_contract" => format!("{}",batch.token_contract),
                     "nonce" => format!("{}",batch.nonce),
+                    "error" => format!("{}",e),
+                );
+            } else {
+                participation.record(
+                    SignedItem::Batch {
+                        token_contract: batch.token_contract,
+                        nonce: batch.nonce,
+                    },
+                    &current_valset,
+                    &valid_sigs,
                 );
+                submittable_batches.push(batch);
+                submittable_signatures.push(valid_sigs);
             }
         } else {
             error!(
@@ -64,12 +204,23 @@ pub async fn relay_batches(
             );
         }
     }
-    if oldest_signed_batch.is_none() {
+    let fee_values = if config.batch_selection_strategy == BatchSelectionStrategy::Oldest {
+        Vec::new()
+    } else {
+        batch_fee_values(&submittable_batches, price_feed, token_metadata, web3).await
+    };
+    let selected = select_batch(
+        &submittable_batches,
+        &fee_values,
+        config.batch_selection_strategy,
+    );
+    if selected.is_none() {
         trace!("Could not find batch with signatures! exiting");
         return;
     }
-    let oldest_signed_batch = oldest_signed_batch.unwrap();
-    let oldest_signatures = oldest_signatures.unwrap();
+    let selected = selected.unwrap();
+    let oldest_signed_batch = submittable_batches.swap_remove(selected);
+    let oldest_signatures = submittable_signatures.swap_remove(selected);
     let erc20_contract = oldest_signed_batch.token_contract;
 
     let latest_ethereum_batch = get_tx_batch_nonce(
@@ -79,48 +230,208 @@ pub async fn relay_batches(
         web3,
     )
     .await;
-    if latest_ethereum_batch.is_err() {
-        error!(
-            "Failed to get latest Ethereum batch with {:?}",
-            latest_ethereum_batch
+    if let Err(e) = latest_ethereum_batch {
+        let e = PeggyError::RpcUnavailable {
+            endpoint: "Ethereum RPC".to_string(),
+            cause: format!("failed to get latest Ethereum batch {}", e),
+        };
+        error!("{}", e);
+        serror!(&logger, "COULD_NOT_GET_LATEST_ETHEREUM_BATCH";
+            "function" => "relay_batches()",
+            "token_contract" => format!("{}",erc20_contract),
+            "error" => format!("{}",e),
         );
         return;
     }
     let latest_ethereum_batch = latest_ethereum_batch.unwrap();
     let latest_cosmos_batch_nonce = oldest_signed_batch.clone().nonce;
     if latest_cosmos_batch_nonce > latest_ethereum_batch {
-        let cost = ethereum_peggy::submit_batch::estimate_tx_batch_cost(
-            current_valset.clone(),
-            oldest_signed_batch.clone(),
-            &oldest_signatures,
+        // if we can't tell how close the batch is to timing out treat it as urgent
+        let urgent = match web3.eth_block_number().await {
+            Ok(current_block_height) => {
+                oldest_signed_batch.blocks_until_timeout(current_block_height)
+                    <= URGENT_BATCH_TIMEOUT_BLOCKS
+            }
+            Err(_) => true,
+        };
+        let gas_price = resolve_gas_price(
+            gas_oracle,
+            config.gas_price_tier,
             web3,
-            peggy_contract_address,
-            peggy_id.clone(),
-            ethereum_key,
+            gas_price_tracker.baseline(urgent),
         )
         .await;
-        if cost.is_err() {
-            error!("Batch cost estimate failed with {:?}", cost);
+        if gas_price.is_err() {
+            error!("Failed to get gas price with {:?}", gas_price);
             return;
         }
-        let cost = cost.unwrap();
+        let gas_price = gas_price.unwrap();
+        // batches can wait out a gas price spike, they are checked again next poll
+        if let Some(max_gas_price) = &config.batch_max_gas_price {
+            if gas_price.gas_price > *max_gas_price {
+                info!(
+                    "Gas price {} is above the batch ceiling {}, deferring batch {}/{}",
+                    gas_price.gas_price,
+                    max_gas_price,
+                    oldest_signed_batch.token_contract,
+                    oldest_signed_batch.nonce
+                );
+                sinfo!(&logger, "BATCH_DEFERRED_GAS";
+                    "function" => "relay_batches()",
+                    "token_contract" => format!("{}",oldest_signed_batch.token_contract),
+                    "nonce" => format!("{}",oldest_signed_batch.nonce),
+                    "gas_price" => format!("{}",gas_price.gas_price),
+                    "max_gas_price" => format!("{}",max_gas_price),
+                );
+                return;
+            }
+        }
+        // if the oracle expects us to wait longer than the batch has left before it times out
+        // there's no point in paying to submit it, the transaction would just revert
+        if let Some(expected_wait) = gas_price.expected_wait {
+            let current_block_height = web3.eth_block_number().await;
+            if let Ok(current_block_height) = current_block_height {
+                let wait_blocks = expected_wait.as_secs() / ETH_AVERAGE_BLOCK_TIME.as_secs();
+                if oldest_signed_batch.blocks_until_timeout(current_block_height) <= wait_blocks {
+                    warn!(
+                        "Batch {}/{} is expected to time out before a {:?} tier transaction is included, not submitting",
+                        oldest_signed_batch.token_contract, oldest_signed_batch.nonce, config.gas_price_tier
+                    );
+                    swarn!(&logger, "BATCH_EXPECTED_TO_TIME_OUT";
+                        "function" => "relay_batches()",
+                        "token_contract" => format!("{}",oldest_signed_batch.token_contract),
+                        "nonce" => format!("{}",oldest_signed_batch.nonce),
+                        "expected_wait" => format!("{:?}",expected_wait),
+                    );
+                    return;
+                }
+            }
+        }
+
+        let (cost, gas_limit) = match estimate_batch_submission(
+            &current_valset,
+            &oldest_signed_batch,
+            &oldest_signatures,
+            web3,
+            peggy_contract_address,
+            &peggy_id,
+            ethereum_key,
+            gas_price.gas_price.clone(),
+            config,
+        )
+        .await
+        {
+            Some(estimate) => estimate,
+            None => return,
+        };
         info!(
-                "We have detected latest batch {} but latest on Ethereum is {} This batch is estimated to cost {} Gas / {:.4} ETH to submit",
+                "We have detected latest batch {} but latest on Ethereum is {} This batch is estimated to cost {} Gas / {:.4} ETH to submit, submitting with gas limit {}",
                 latest_cosmos_batch_nonce,
                 latest_ethereum_batch,
                 cost.gas_price.clone(),
                 downcast_to_u128(cost.get_total()).unwrap() as f32
-                    / downcast_to_u128(one_eth()).unwrap() as f32
+                    / downcast_to_u128(one_eth()).unwrap() as f32,
+                gas_limit
             );
-        sinfo!(&LOGGING.logger, "WE_HAVE_DETECTED_LATEST_BATCH";
+        sinfo!(&logger, "WE_HAVE_DETECTED_LATEST_BATCH";
             "function" => "relay_batches()",
             "latest_cosmos_batch_nonce" => format!("{}",latest_cosmos_batch_nonce),
             "latest_ethereum_batch" => format!("{}",latest_ethereum_batch),
             "cost_gas_price" => format!("{}",cost.gas_price.clone()),
             "per_eth" => format!("{:.4}",downcast_to_u128(cost.get_total()).unwrap() as f32
                 / downcast_to_u128(one_eth()).unwrap() as f32),
+            "gas_estimate" => format!("{}",cost.gas),
+            "gas_limit" => format!("{}",gas_limit),
+            "expected_wait" => format!("{:?}",gas_price.expected_wait),
         );
 
+        // every fee in a batch is paid in the batch's token
+        let fee_value = token_value_in_wei(
+            price_feed,
+            erc20_contract,
+            &oldest_signed_batch.total_fee.amount,
+            token_metadata,
+            web3,
+        )
+        .await;
+        match fee_value {
+            Ok(fee_value) => {
+                let profitable = fee_value >= cost.get_total();
+                sinfo!(&logger, "BATCH_PROFITABILITY";
+                    "function" => "relay_batches()",
+                    "token_contract" => format!("{}",erc20_contract),
+                    "nonce" => format!("{}",latest_cosmos_batch_nonce),
+                    "fee_value_wei" => format!("{}",fee_value),
+                    "cost_wei" => format!("{}",cost.get_total()),
+                    "profitable" => format!("{}",profitable),
+                );
+                if !profitable && config.only_relay_profitable_batches {
+                    info!(
+                        "Batch {}/{} fees are worth {} wei but cost {} wei to submit, not submitting",
+                        erc20_contract, latest_cosmos_batch_nonce, fee_value, cost.get_total()
+                    );
+                    return;
+                }
+            }
+            Err(e) => {
+                warn!(
+                    "Could not value the fees of batch {}/{} {}",
+                    erc20_contract, latest_cosmos_batch_nonce, e
+                );
+                if config.only_relay_profitable_batches {
+                    return;
+                }
+            }
+        }
+
+        if !check_eth_balance(
+            web3,
+            our_ethereum_address,
+            &gas_limit,
+            &cost.gas_price,
+            &config.eth_balance_reserve,
+            "relay_batches()",
+        )
+        .await
+        {
+            return;
+        }
+
+        if is_relaying_paused() {
+            info!(
+                "Relaying is paused, holding batch {}/{}",
+                erc20_contract, latest_cosmos_batch_nonce
+            );
+            sinfo!(&logger, "BATCH_HELD_WHILE_PAUSED";
+                "function" => "relay_batches()",
+                "token_contract" => format!("{}",erc20_contract),
+                "nonce" => format!("{}",latest_cosmos_batch_nonce),
+            );
+            return;
+        }
+
+        // the valset may have been updated since the signatures were checked above, the
+        // submission would revert if they no longer have enough power in the new one
+        let current_valset = match recheck_valset(
+            current_valset,
+            &oldest_signed_batch,
+            &oldest_signatures,
+            &peggy_id,
+            web3,
+            grpc_client,
+            peggy_contract_address,
+            our_ethereum_address,
+        )
+        .await
+        {
+            Some(valset) => valset,
+            None => {
+                signature_cache.invalidate(erc20_contract, latest_cosmos_batch_nonce);
+                return;
+            }
+        };
+
+        let total_fee = oldest_signed_batch.total_fee.amount.clone();
         let res = send_eth_transaction_batch(
             current_valset,
             oldest_signed_batch,
@@ -130,14 +441,424 @@ pub async fn relay_batches(
             peggy_contract_address,
             peggy_id,
             ethereum_key,
+            gas_limit,
+            cost.gas_price,
+            nonce_manager,
         )
         .await;
-        if res.is_err() {
-            info!("Batch submission failed with {:?}", res);
-            sinfo!(&LOGGING.logger, "BATCH_SUBMISSION_FAILED";
-                "function" => "relay_batches()",
-                "res" => format!("{:?}",res),
+        match res {
+            Ok(Some(gas_used)) => record_batch_relayed(erc20_contract, gas_used, total_fee),
+            Ok(None) => {}
+            Err(e) => {
+                info!("Batch submission failed with {:?}", e);
+                sinfo!(&logger, "BATCH_SUBMISSION_FAILED";
+                    "function" => "relay_batches()",
+                    "res" => format!("{:?}",e),
+                );
+            }
+        }
+    }
+}
+
+/// Adds a batch we relayed to the per token counters and logs the running totals for its token,
+/// the gas is what our transaction's receipt says it used
+fn record_batch_relayed(token_contract: EthAddress, gas: Uint256, fees: Uint256) {
+    let logger = LOGGING.named_logger(RELAYER_LOGGER);
+    let token = token_contract.to_string();
+    BATCHES_RELAYED.inc_by(&token, 1u8.into());
+    BATCH_RELAY_GAS.inc_by(&token, gas);
+    BATCH_RELAY_FEES.inc_by(&token, fees);
+    sinfo!(&logger, "BATCH_RELAY_STATS";
+        "function" => "record_batch_relayed()",
+        "token_contract" => token.clone(),
+        "batches_relayed" => format!("{}",BATCHES_RELAYED.get(&token)),
+        "total_gas" => format!("{}",BATCH_RELAY_GAS.get(&token)),
+        "total_fees" => format!("{}",BATCH_RELAY_FEES.get(&token)),
+    );
+}
+
+/// Estimates the gas of submitting `batch` and the gas limit to submit it with, applying the
+/// configured GasEstimateFailurePolicy when the estimate fails. None if the batch should be skipped
+#[allow(clippy::too_many_arguments)]
+async fn estimate_batch_submission(
+    current_valset: &Valset,
+    batch: &TransactionBatch,
+    signatures: &[BatchConfirmResponse],
+    web3: &Web3,
+    peggy_contract_address: EthAddress,
+    peggy_id: &str,
+    ethereum_key: EthPrivateKey,
+    gas_price: Uint256,
+    config: &RelayerConfig,
+) -> Option<(GasCost, Uint256)> {
+    let logger = LOGGING.named_logger(RELAYER_LOGGER);
+    let policy = config.gas_estimate_failure_policy;
+    let attempts = match policy {
+        GasEstimateFailurePolicy::Retry => config.gas_estimate_retries + 1,
+        _ => 1,
+    };
+    let mut cause = String::new();
+    for attempt in 1..=attempts {
+        if attempt > 1 {
+            delay_for(GAS_ESTIMATE_RETRY_DELAY).await;
+        }
+        match estimate_tx_batch_cost(
+            current_valset.clone(),
+            batch.clone(),
+            signatures,
+            web3,
+            peggy_contract_address,
+            peggy_id.to_string(),
+            ethereum_key,
+            gas_price.clone(),
+        )
+        .await
+        {
+            Ok(cost) => {
+                let gas_limit =
+                    get_gas_limit(cost.gas.clone(), config.gas_limit_multiplier, web3).await;
+                if gas_limit.is_err() {
+                    error!("Failed to get block gas limit with {:?}", gas_limit);
+                    return None;
+                }
+                return Some((cost, gas_limit.unwrap()));
+            }
+            Err(e) => {
+                warn!(
+                    "Batch cost estimate attempt {} of {} failed, {}",
+                    attempt, attempts, e
+                );
+                cause = e.to_string();
+            }
+        }
+    }
+
+    let e = PeggyError::GasEstimateFailed {
+        nonce: batch.nonce,
+        cause,
+    };
+    match config.batch_fallback_gas() {
+        Some(gas_limit) => {
+            warn!(
+                "Batch cost estimate failed, submitting with the fallback gas limit {}, {}",
+                gas_limit, e
+            );
+            swarn!(&logger, "BATCH_GAS_ESTIMATE_FALLBACK";
+                "function" => "estimate_batch_submission()",
+                "token_contract" => format!("{}",batch.token_contract),
+                "nonce" => format!("{}",batch.nonce),
+                "policy" => format!("{:?}",policy),
+                "fallback" => "fixed-gas",
+                "gas_limit" => format!("{}",gas_limit),
+            );
+            let cost = GasCost {
+                gas: gas_limit.clone(),
+                gas_price,
+            };
+            Some((cost, gas_limit))
+        }
+        None => {
+            error!(
+                "Batch cost estimate failed, skipping until next iteration, {}",
+                e
+            );
+            serror!(&logger, "BATCH_GAS_ESTIMATE_FALLBACK";
+                "function" => "estimate_batch_submission()",
+                "token_contract" => format!("{}",batch.token_contract),
+                "nonce" => format!("{}",batch.nonce),
+                "policy" => format!("{:?}",policy),
+                "fallback" => "skip",
+                "attempts" => format!("{}",attempts),
+            );
+            None
+        }
+    }
+}
+
+/// Checks the valset nonce on Ethereum against the valset the batch signatures were checked
+/// with. If it has changed the new valset is fetched and the signatures checked again, returns
+/// the valset to submit with or None if the signatures no longer have enough power. If the nonce
+/// can't be queried the batch is submitted with the valset it was checked against
+#[allow(clippy::too_many_arguments)]
+async fn recheck_valset(
+    current_valset: Valset,
+    batch: &TransactionBatch,
+    sigs: &[BatchConfirmResponse],
+    peggy_id: &str,
+    web3: &Web3,
+    grpc_client: &mut PeggyQueryClient<Channel>,
+    peggy_contract_address: EthAddress,
+    our_ethereum_address: EthAddress,
+) -> Option<Valset> {
+    let logger = LOGGING.named_logger(RELAYER_LOGGER);
+    let nonce = match get_valset_nonce(peggy_contract_address, our_ethereum_address, web3).await {
+        Ok(nonce) => nonce,
+        Err(e) => {
+            warn!(
+                "Could not recheck the valset nonce before submitting {:?}",
+                e
             );
+            return Some(current_valset);
         }
+    };
+    if nonce == current_valset.nonce {
+        return Some(current_valset);
+    }
+    warn!(
+        "Valset changed from {} to {} while relaying batch {}/{}, checking signatures again",
+        current_valset.nonce, nonce, batch.token_contract, batch.nonce
+    );
+    let new_valset = find_latest_valset(
+        grpc_client,
+        our_ethereum_address,
+        peggy_contract_address,
+        web3,
+    )
+    .await;
+    let new_valset = match new_valset {
+        Ok(valset) => valset,
+        Err(e) => {
+            error!("Could not get the new valset {}, not submitting", e);
+            return None;
+        }
+    };
+    let hash = encode_tx_batch_confirm_hashed(peggy_id.to_string(), batch.clone());
+    let still_valid = new_valset.index().order_sigs(&hash, sigs).is_ok();
+    swarn!(&logger, "VALSET_CHURN_DETECTED";
+        "function" => "recheck_valset()",
+        "token_contract" => format!("{}",batch.token_contract),
+        "nonce" => format!("{}",batch.nonce),
+        "old_valset_nonce" => format!("{}",current_valset.nonce),
+        "new_valset_nonce" => format!("{}",new_valset.nonce),
+        "signatures_valid" => format!("{}",still_valid),
+    );
+    if still_valid {
+        Some(new_valset)
+    } else {
+        warn!(
+            "Batch {}/{} signatures do not have enough power in valset {}, waiting for more",
+            batch.token_contract, batch.nonce, new_valset.nonce
+        );
+        None
+    }
+}
+
+/// Estimates how many blocks ago a batch was created, batches store only their timeout height
+/// which Cosmos sets to roughly the Ethereum height at creation plus the timeout window
+pub fn estimate_batch_age(
+    batch_timeout: u64,
+    timeout_window: u64,
+    current_block_height: u64,
+) -> u64 {
+    let created = batch_timeout.saturating_sub(timeout_window);
+    current_block_height.saturating_sub(created)
+}
+
+/// Values the total fee of each batch in wei so that fees paid in different tokens can be
+/// compared. A batch whose fees can't be valued is treated as paying nothing
+async fn batch_fee_values(
+    batches: &[TransactionBatch],
+    price_feed: Option<&dyn PriceFeed>,
+    token_metadata: &mut TokenMetadataCache,
+    web3: &Web3,
+) -> Vec<Uint256> {
+    let mut values = Vec::with_capacity(batches.len());
+    for batch in batches {
+        let value = token_value_in_wei(
+            price_feed,
+            batch.token_contract,
+            &batch.total_fee.amount,
+            token_metadata,
+            web3,
+        )
+        .await;
+        values.push(value.unwrap_or_else(|e| {
+            warn!(
+                "Could not value the fees of batch {}/{} for selection {}",
+                batch.token_contract, batch.nonce, e
+            );
+            0u8.into()
+        }));
+    }
+    values
+}
+
+/// Picks which of the provided submittable batches to relay according to the strategy,
+/// returning its index. `fee_values` holds each batch's total fee in wei from
+/// batch_fee_values, it is only used by the fee strategies
+pub fn select_batch(
+    batches: &[TransactionBatch],
+    fee_values: &[Uint256],
+    strategy: BatchSelectionStrategy,
+) -> Option<usize> {
+    let fee_value =
+        |i: usize| -> Uint256 { fee_values.get(i).cloned().unwrap_or_else(|| 0u8.into()) };
+    let fee_density = |i: usize, batch: &TransactionBatch| -> Uint256 {
+        if batch.transactions.is_empty() {
+            0u8.into()
+        } else {
+            fee_value(i) / (batch.transactions.len() as u64).into()
+        }
+    };
+    let indexed = batches.iter().enumerate();
+    match strategy {
+        BatchSelectionStrategy::Oldest => indexed.min_by_key(|(_, b)| b.nonce).map(|(i, _)| i),
+        BatchSelectionStrategy::HighestFee => {
+            indexed.max_by_key(|(i, _)| fee_value(*i)).map(|(i, _)| i)
+        }
+        BatchSelectionStrategy::HighestFeeDensity => indexed
+            .max_by_key(|(i, b)| fee_density(*i, b))
+            .map(|(i, _)| i),
+    }
+}
+
+/// The state of a single transaction batch as seen from both chains, see get_pending_batch_status
+#[derive(Debug, Clone)]
+pub struct BatchStatus {
+    pub batch: TransactionBatch,
+    /// The latest batch nonce for this token on Ethereum, if it is greater than or
+    /// equal to this batches nonce the batch has been executed (or skipped)
+    pub latest_ethereum_nonce: u64,
+    pub executed: bool,
+    /// true if this batch has enough signatures to be submitted to the current valset
+    pub has_enough_signatures: bool,
+    pub timed_out: bool,
+}
+
+/// Gathers the status of every batch currently available on the Cosmos chain, this performs the same
+/// queries as relay_batches but only reports on them and never submits anything to Ethereum.
+pub async fn get_pending_batch_status(
+    grpc_client: &mut PeggyQueryClient<Channel>,
+    web3: &Web3,
+    peggy_contract_address: EthAddress,
+    our_ethereum_address: EthAddress,
+) -> Result<Vec<BatchStatus>, PeggyError> {
+    let current_valset = find_latest_valset(
+        grpc_client,
+        our_ethereum_address,
+        peggy_contract_address,
+        web3,
+    )
+    .await?;
+    let peggy_id = get_peggy_id(peggy_contract_address, our_ethereum_address, web3).await?;
+    let peggy_id = String::from_utf8(peggy_id)
+        .map_err(|e| PeggyError::InvalidBridgeStateError(format!("Invalid PeggyID {}", e)))?;
+    let current_block_height = web3.eth_block_number().await?;
+
+    let latest_batches = get_latest_transaction_batches(grpc_client).await?;
+    let mut out = Vec::new();
+    let indexed_valset = current_valset.index();
+    for batch in latest_batches {
+        let sigs = get_transaction_batch_signatures(grpc_client, batch.nonce, batch.token_contract)
+            .await?;
+        let hash = encode_tx_batch_confirm_hashed(peggy_id.clone(), batch.clone());
+        let has_enough_signatures = indexed_valset.order_sigs(&hash, &sigs).is_ok();
+        let latest_ethereum_nonce = get_tx_batch_nonce(
+            peggy_contract_address,
+            batch.token_contract,
+            our_ethereum_address,
+            web3,
+        )
+        .await?;
+        out.push(BatchStatus {
+            executed: latest_ethereum_nonce >= batch.nonce,
+            timed_out: batch.is_timed_out(current_block_height.clone()),
+            latest_ethereum_nonce,
+            has_enough_signatures,
+            batch,
+        });
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use peggy_utils::types::{BatchTransaction, ERC20Token};
+
+    fn batch(nonce: u64, fees: &[u64]) -> TransactionBatch {
+        let transactions: Vec<BatchTransaction> = fees
+            .iter()
+            .map(|fee| BatchTransaction {
+                erc20_fee: ERC20Token {
+                    amount: (*fee).into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .collect();
+        TransactionBatch {
+            nonce,
+            total_fee: ERC20Token {
+                amount: fees.iter().sum::<u64>().into(),
+                ..Default::default()
+            },
+            transactions,
+            ..Default::default()
+        }
+    }
+
+    fn batches() -> Vec<TransactionBatch> {
+        vec![
+            // total fee 60, density 20
+            batch(7, &[20, 20, 20]),
+            // total fee 10, density 10
+            batch(3, &[10]),
+            // total fee 50, density 25
+            batch(5, &[25, 25]),
+        ]
+    }
+
+    /// the fees of batches() valued one to one in wei
+    fn fee_values() -> Vec<Uint256> {
+        batches()
+            .iter()
+            .map(|b| b.total_fee.amount.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_select_batch_oldest() {
+        assert_eq!(
+            select_batch(&batches(), &[], BatchSelectionStrategy::Oldest),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn test_select_batch_highest_fee() {
+        let strategy = BatchSelectionStrategy::HighestFee;
+        assert_eq!(select_batch(&batches(), &fee_values(), strategy), Some(0));
+        // the second batch's token is worth far more than the others
+        let values: Vec<Uint256> = vec![60u8.into(), 1000u16.into(), 50u8.into()];
+        assert_eq!(select_batch(&batches(), &values, strategy), Some(1));
+    }
+
+    #[test]
+    fn test_select_batch_highest_fee_density() {
+        assert_eq!(
+            select_batch(
+                &batches(),
+                &fee_values(),
+                BatchSelectionStrategy::HighestFeeDensity
+            ),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn test_estimate_batch_age() {
+        assert_eq!(estimate_batch_age(1_100, 1_000, 150), 50);
+        // created in the future according to our node, which may be behind
+        assert_eq!(estimate_batch_age(1_100, 1_000, 50), 0);
+        assert_eq!(estimate_batch_age(500, 1_000, 150), 150);
+    }
+
+    #[test]
+    fn test_select_batch_empty() {
+        assert_eq!(
+            select_batch(&[], &[], BatchSelectionStrategy::HighestFee),
+            None
+        );
     }
 }
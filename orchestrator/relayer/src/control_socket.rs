@@ -0,0 +1,149 @@
+//! A Unix socket accepting operator commands at runtime. Each connection sends a single line
+//! command and receives a single line reply, for example `echo pause | nc -U <path>`
+//!
+//! pause  - hold all valset, batch and logic call submissions to Ethereum
+//! resume - continue submitting
+//! status - reply with paused or running
+//! acknowledge-upgrade - release claims held after the oracle observed a Peggy contract upgrade
+//! held - list the deposits held for review by event nonce
+//! approve <nonce> - claim the held deposit with this event nonce on the next oracle poll
+//! reject <nonce> - keep the held deposit with this event nonce from being claimed
+
+use json_logger::LOGGING;
+use peggy_utils::claim_review::{approve_claim, held_claims, reject_claim};
+use peggy_utils::relay_pause::{
+    acknowledge_upgrade, is_relaying_paused, pause_relaying, resume_relaying,
+};
+use slog::info as sinfo;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::thread;
+
+/// Binds the control socket at `path` and serves it from a background thread
+pub fn start_control_socket(path: &str) -> io::Result<()> {
+    // a socket file left behind by a previous run would prevent binding
+    if Path::new(path).exists() {
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    info!("Relayer control socket listening on {}", path);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(e) = handle_connection(stream) {
+                        warn!("Control socket connection failed {:?}", e);
+                    }
+                }
+                Err(e) => warn!("Control socket accept failed {:?}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+fn handle_connection(stream: UnixStream) -> io::Result<()> {
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let reply = handle_command(line.trim());
+    (&stream).write_all(format!("{}\n", reply).as_bytes())
+}
+
+fn handle_command(command: &str) -> String {
+    let mut words = command.split_whitespace();
+    let reply = match (words.next(), words.next()) {
+        (Some("pause"), None) => {
+            if pause_relaying() {
+                info!("Relaying paused by operator");
+                sinfo!(&LOGGING.logger, "RELAYING_PAUSED";
+                    "function" => "handle_command()",
+                );
+            }
+            "paused"
+        }
+        (Some("resume"), None) => {
+            if resume_relaying() {
+                info!("Relaying resumed by operator");
+                sinfo!(&LOGGING.logger, "RELAYING_RESUMED";
+                    "function" => "handle_command()",
+                );
+            }
+            "running"
+        }
+        (Some("status"), None) => {
+            if is_relaying_paused() {
+                "paused"
+            } else {
+                "running"
+            }
+        }
+        (Some("acknowledge-upgrade"), None) => {
+            if acknowledge_upgrade() {
+                info!("Contract upgrade acknowledged by operator, claims resumed");
+                sinfo!(&LOGGING.logger, "CONTRACT_UPGRADE_ACKNOWLEDGED";
+                    "function" => "handle_command()",
+                );
+            }
+            "claims running"
+        }
+        (Some("held"), None) => return held_claims_reply(),
+        (Some(decision @ "approve"), Some(nonce)) | (Some(decision @ "reject"), Some(nonce)) => {
+            return review_claim(decision, nonce)
+        }
+        _ => "unknown command, expected pause, resume, status, acknowledge-upgrade, held, approve, or reject",
+    };
+    reply.to_string()
+}
+
+fn held_claims_reply() -> String {
+    let held = held_claims();
+    if held.is_empty() {
+        return "no claims held".to_string();
+    }
+    let held: Vec<String> = held
+        .iter()
+        .map(|(nonce, state, summary)| format!("{} {:?} {}", nonce, state, summary))
+        .collect();
+    held.join("; ")
+}
+
+fn review_claim(decision: &str, nonce: &str) -> String {
+    let nonce: u64 = match nonce.parse() {
+        Ok(nonce) => nonce,
+        Err(_) => return format!("invalid event nonce {}", nonce),
+    };
+    let found = if decision == "approve" {
+        approve_claim(nonce)
+    } else {
+        reject_claim(nonce)
+    };
+    if !found {
+        return format!("no claim with event nonce {} is held", nonce);
+    }
+    info!(
+        "Held claim with event nonce {} {} by operator",
+        nonce, decision
+    );
+    sinfo!(&LOGGING.logger, "HELD_CLAIM_REVIEWED";
+        "function" => "handle_command()",
+        "event_nonce" => format!("{}",nonce),
+        "decision" => decision,
+    );
+    format!("{} {}", decision, nonce)
+}
+
+#[test]
+fn test_handle_command() {
+    assert_eq!(handle_command("status"), "running");
+    assert_eq!(handle_command("pause"), "paused");
+    assert_eq!(handle_command("status"), "paused");
+    assert_eq!(handle_command("resume"), "running");
+    assert_eq!(handle_command("status"), "running");
+    assert_eq!(handle_command("acknowledge-upgrade"), "claims running");
+    assert_eq!(handle_command("held"), "no claims held");
+    assert!(handle_command("approve 3").starts_with("no claim"));
+    assert!(handle_command("reject three").starts_with("invalid"));
+    assert!(handle_command("stop").starts_with("unknown"));
+}
@@ -1,12 +1,15 @@
 use clarity::Address as EthAddress;
 use clarity::{Address, Uint256};
-use ethereum_peggy::utils::get_valset_nonce;
+use ethereum_peggy::utils::{get_valset_nonce, get_valset_updated_logs};
 use peggy_proto::peggy::query_client::QueryClient as PeggyQueryClient;
 use peggy_utils::types::ValsetUpdatedEvent;
 use peggy_utils::{error::PeggyError, types::Valset};
 use tonic::transport::Channel;
 use web30::client::Web3;
 
+/// How many blocks of event history are requested from the Ethereum node at once
+const BLOCKS_TO_SEARCH: u128 = 5_000u128;
+
 /// This function finds the latest valset on the Peggy contract by looking back through the event
 /// history and finding the most recent ValsetUpdatedEvent. Most of the time this will be very fast
 /// as the latest update will be in recent blockchain history and the search moves from the present
@@ -18,7 +21,6 @@ pub async fn find_latest_valset(
     peggy_contract_address: Address,
     web3: &Web3,
 ) -> Result<Valset, PeggyError> {
-    const BLOCKS_TO_SEARCH: u128 = 5_000u128;
     let latest_block = web3.eth_block_number().await?;
     let mut current_block: Uint256 = latest_block.clone();
     let latest_ethereum_valset =
@@ -36,14 +38,13 @@ pub async fn find_latest_valset(
         } else {
             current_block.clone() - BLOCKS_TO_SEARCH.into()
         };
-        let mut all_valset_events = web3
-            .check_for_events(
-                end_search.clone(),
-                Some(current_block.clone()),
-                vec![peggy_contract_address],
-                vec!["ValsetUpdatedEvent(uint256,address[],uint256[])"],
-            )
-            .await?;
+        let mut all_valset_events = get_valset_updated_logs(
+            web3,
+            end_search.clone(),
+            Some(current_block.clone()),
+            peggy_contract_address,
+        )
+        .await?;
         // by default the lowest found valset goes first, we want the highest.
         all_valset_events.reverse();
 
@@ -70,6 +71,62 @@ pub async fn find_latest_valset(
     panic!("Could not find the last validator set for contract {}, probably not a valid Peggy contract!", peggy_contract_address)
 }
 
+/// Rebuilds the valset with the provided nonce from the ValsetUpdatedEvent that installed it in the
+/// Peggy contract, for recovering when Cosmos no longer has a record of a valset that is still in use
+/// on Ethereum. Like find_latest_valset this searches backwards from the present, it stops once it
+/// reaches events older than the requested nonce or the start of the chain
+pub async fn reconstruct_valset_from_events(
+    web3: &Web3,
+    peggy_contract_address: Address,
+    nonce: u64,
+) -> Result<Valset, PeggyError> {
+    let mut current_block: Uint256 = web3.eth_block_number().await?;
+
+    while current_block.clone() > 0u8.into() {
+        let end_search = if current_block.clone() < BLOCKS_TO_SEARCH.into() {
+            0u8.into()
+        } else {
+            current_block.clone() - BLOCKS_TO_SEARCH.into()
+        };
+        let logs = get_valset_updated_logs(
+            web3,
+            end_search.clone(),
+            Some(current_block.clone()),
+            peggy_contract_address,
+        )
+        .await?;
+        let events = ValsetUpdatedEvent::from_logs(&logs)?;
+        if let Some(valset) = find_valset_event(&events, nonce) {
+            info!(
+                "Reconstructed valset {} from Ethereum events before block {}",
+                nonce, current_block
+            );
+            return Ok(valset);
+        }
+        // valset nonces only ever increase, so older blocks can't contain this nonce
+        if events.iter().any(|event| event.nonce < nonce) {
+            break;
+        }
+        current_block = end_search;
+    }
+
+    Err(PeggyError::InvalidBridgeStateError(format!(
+        "No ValsetUpdatedEvent for valset {} on contract {}",
+        nonce, peggy_contract_address
+    )))
+}
+
+/// Returns the valset installed by the event with the provided nonce, if there is one
+fn find_valset_event(events: &[ValsetUpdatedEvent], nonce: u64) -> Option<Valset> {
+    events
+        .iter()
+        .find(|event| event.nonce == nonce)
+        .map(|event| Valset {
+            nonce: event.nonce,
+            members: event.members.clone(),
+        })
+}
+
 /// This function exists to provide a warning if Cosmos and Ethereum have different validator sets
 /// for a given nonce. In the mundane version of this warning the validator sets disagree on sorting order
 /// which can happen if some relayer uses an unstable sort, or in a case of a mild griefing attack.
@@ -111,3 +168,37 @@ fn check_if_valsets_differ(cosmos_valset: Option<Valset>, ethereum_valset: &Vals
         }
     }
 }
+
+#[test]
+fn test_find_valset_event() {
+    use peggy_utils::types::ValsetMember;
+
+    let member = ValsetMember {
+        power: 100,
+        eth_address: Some(
+            "0x0000000000000000000000000000000000000001"
+                .parse()
+                .unwrap(),
+        ),
+    };
+    let events = vec![
+        ValsetUpdatedEvent {
+            nonce: 3,
+            members: vec![],
+            ..Default::default()
+        },
+        ValsetUpdatedEvent {
+            nonce: 4,
+            members: vec![member.clone()],
+            ..Default::default()
+        },
+    ];
+    assert_eq!(
+        find_valset_event(&events, 4),
+        Some(Valset {
+            nonce: 4,
+            members: vec![member],
+        })
+    );
+    assert_eq!(find_valset_event(&events, 5), None);
+}
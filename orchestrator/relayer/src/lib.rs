@@ -1,8 +1,16 @@
+pub mod balance_guard;
+pub mod batch_reconciliation;
 pub mod batch_relaying;
+pub mod control_socket;
 pub mod find_latest_valset;
 pub mod logic_call_relaying;
 pub mod main_loop;
+pub mod participation;
+pub mod signature_cache;
+pub mod state_snapshot;
 pub mod valset_relaying;
+pub mod valset_submission;
+pub mod workload_cost;
 
 #[macro_use]
 extern crate log;
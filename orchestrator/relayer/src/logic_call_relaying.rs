@@ -1,18 +1,34 @@
 use clarity::PrivateKey as EthPrivateKey;
 use clarity::{address::Address as EthAddress, utils::bytes_to_hex_str};
 use cosmos_peggy::query::{get_latest_logic_calls, get_logic_call_signatures};
-use ethereum_peggy::one_eth;
 use ethereum_peggy::{
     logic_call::send_eth_logic_call,
-    utils::{downcast_to_u128, get_logic_call_nonce},
+    nonce_manager::NonceManager,
+    one_eth,
+    utils::{downcast_to_u128, get_gas_limit, get_logic_call_nonce},
 };
+use json_logger::{LOGGING, RELAYER_LOGGER};
 use peggy_proto::peggy::query_client::QueryClient as PeggyQueryClient;
-use peggy_utils::types::{LogicCallConfirmResponse, Valset};
+use peggy_utils::relay_pause::is_relaying_paused;
+use peggy_utils::types::{LogicCallConfirmResponse, RelayerConfig, Valset};
 use peggy_utils::{message_signatures::encode_logic_call_confirm_hashed, types::LogicCall};
+use slog::info as sinfo;
+use std::collections::HashMap;
 use std::time::Duration;
 use tonic::transport::Channel;
 use web30::client::Web3;
 
+/// A logic call is superseded once the contract has executed a call with the same invalidation id
+/// and an equal or greater invalidation nonce, the same values a LogicCallExecutedEvent reports.
+/// The contract rejects superseded calls so submitting one only wastes gas
+pub fn is_logic_call_superseded(
+    call_invalidation_nonce: u64,
+    ethereum_invalidation_nonce: u64,
+) -> bool {
+    call_invalidation_nonce <= ethereum_invalidation_nonce
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn relay_logic_calls(
     // the validator set currently in the contract on Ethereum
     current_valset: Valset,
@@ -22,6 +38,8 @@ pub async fn relay_logic_calls(
     peggy_contract_address: EthAddress,
     peggy_id: String,
     timeout: Duration,
+    config: &RelayerConfig,
+    nonce_manager: &NonceManager,
 ) {
     let our_ethereum_address = ethereum_key.to_public_key().unwrap();
 
@@ -33,7 +51,52 @@ pub async fn relay_logic_calls(
     let latest_calls = latest_calls.unwrap();
     let mut oldest_signed_call: Option<LogicCall> = None;
     let mut oldest_signatures: Option<Vec<LogicCallConfirmResponse>> = None;
+    // the latest invalidation nonce executed on Ethereum for each invalidation id
+    let mut ethereum_nonces: HashMap<Vec<u8>, u64> = HashMap::new();
+    let logger = LOGGING.named_logger(RELAYER_LOGGER);
+    let indexed_valset = current_valset.index();
     for call in latest_calls {
+        let ethereum_nonce = match ethereum_nonces.get(&call.invalidation_id) {
+            Some(nonce) => *nonce,
+            None => {
+                let nonce = get_logic_call_nonce(
+                    peggy_contract_address,
+                    call.invalidation_id.clone(),
+                    our_ethereum_address,
+                    web3,
+                )
+                .await;
+                match nonce {
+                    Ok(nonce) => {
+                        ethereum_nonces.insert(call.invalidation_id.clone(), nonce);
+                        nonce
+                    }
+                    Err(e) => {
+                        error!(
+                            "Failed to get latest Ethereum LogicCall nonce for {} with {:?}",
+                            bytes_to_hex_str(&call.invalidation_id),
+                            e
+                        );
+                        continue;
+                    }
+                }
+            }
+        };
+        if is_logic_call_superseded(call.invalidation_nonce, ethereum_nonce) {
+            info!(
+                "LogicCall {}/{} has been invalidated by nonce {} on Ethereum, skipping",
+                bytes_to_hex_str(&call.invalidation_id),
+                call.invalidation_nonce,
+                ethereum_nonce
+            );
+            sinfo!(&logger, "LOGIC_CALL_INVALIDATED";
+                "function" => "relay_logic_calls()",
+                "invalidation_id" => bytes_to_hex_str(&call.invalidation_id),
+                "invalidation_nonce" => format!("{}",call.invalidation_nonce),
+                "ethereum_invalidation_nonce" => format!("{}",ethereum_nonce),
+            );
+            continue;
+        }
         let sigs = get_logic_call_signatures(
             grpc_client,
             call.invalidation_id.clone(),
@@ -44,7 +107,7 @@ pub async fn relay_logic_calls(
         if let Ok(sigs) = sigs {
             let hash = encode_logic_call_confirm_hashed(peggy_id.clone(), call.clone());
             // this checks that the signatures for the batch are actually possible to submit to the chain
-            if current_valset.order_sigs(&hash, &sigs).is_ok() {
+            if indexed_valset.order_sigs(&hash, &sigs).is_ok() {
                 oldest_signed_call = Some(call);
                 oldest_signatures = Some(sigs);
             } else {
@@ -70,60 +133,97 @@ pub async fn relay_logic_calls(
     let oldest_signed_call = oldest_signed_call.unwrap();
     let oldest_signatures = oldest_signatures.unwrap();
 
-    let latest_ethereum_call = get_logic_call_nonce(
-        peggy_contract_address,
-        oldest_signed_call.invalidation_id.clone(),
-        our_ethereum_address,
+    // superseded calls were skipped above, so this call is newer than the one on Ethereum and
+    // the Ethereum nonce is always cached here
+    let latest_ethereum_call = ethereum_nonces[&oldest_signed_call.invalidation_id];
+    let latest_cosmos_call_nonce = oldest_signed_call.clone().invalidation_nonce;
+    let cost = ethereum_peggy::logic_call::estimate_logic_call_cost(
+        current_valset.clone(),
+        oldest_signed_call.clone(),
+        &oldest_signatures,
         web3,
+        peggy_contract_address,
+        peggy_id.clone(),
+        ethereum_key,
     )
     .await;
-    if latest_ethereum_call.is_err() {
-        error!(
-            "Failed to get latest Ethereum LogicCall with {:?}",
-            latest_ethereum_call
-        );
+    if cost.is_err() {
+        error!("LogicCall cost estimate failed with {:?}", cost);
         return;
     }
-    let latest_ethereum_call = latest_ethereum_call.unwrap();
-    let latest_cosmos_call_nonce = oldest_signed_call.clone().invalidation_nonce;
-    if latest_cosmos_call_nonce > latest_ethereum_call {
-        let cost = ethereum_peggy::logic_call::estimate_logic_call_cost(
-            current_valset.clone(),
-            oldest_signed_call.clone(),
-            &oldest_signatures,
-            web3,
-            peggy_contract_address,
-            peggy_id.clone(),
-            ethereum_key,
-        )
-        .await;
-        if cost.is_err() {
-            error!("LogicCall cost estimate failed with {:?}", cost);
-            return;
+    let cost = cost.unwrap();
+    info!(
+            "We have detected latest LogicCall {} but latest on Ethereum is {} This LogicCall is estimated to cost {} Gas / {:.4} ETH to submit",
+            latest_cosmos_call_nonce,
+            latest_ethereum_call,
+            cost.gas_price.clone(),
+            downcast_to_u128(cost.get_total()).unwrap() as f32
+                / downcast_to_u128(one_eth()).unwrap() as f32
+        );
+
+    // operators may pin a stipend for logic contracts whose inner call is under estimated
+    let logic_contract = oldest_signed_call.logic_contract_address;
+    let gas_limit = match config.logic_call_gas_stipends.get(&logic_contract) {
+        Some(stipend) => {
+            info!(
+                "Submitting LogicCall to {} with configured gas stipend {}",
+                logic_contract, stipend
+            );
+            stipend.clone()
         }
-        let cost = cost.unwrap();
-        info!(
-                "We have detected latest LogicCall {} but latest on Ethereum is {} This LogicCall is estimated to cost {} Gas / {:.4} ETH to submit",
-                latest_cosmos_call_nonce,
-                latest_ethereum_call,
-                cost.gas_price.clone(),
-                downcast_to_u128(cost.get_total()).unwrap() as f32
-                    / downcast_to_u128(one_eth()).unwrap() as f32
+        None => {
+            let gas_limit =
+                get_gas_limit(cost.gas.clone(), config.gas_limit_multiplier, web3).await;
+            if gas_limit.is_err() {
+                error!("Failed to get block gas limit with {:?}", gas_limit);
+                return;
+            }
+            let gas_limit = gas_limit.unwrap();
+            info!(
+                "Submitting LogicCall to {} with estimated gas stipend {}",
+                logic_contract, gas_limit
             );
-
-        let res = send_eth_logic_call(
-            current_valset,
-            oldest_signed_call,
-            &oldest_signatures,
-            web3,
-            timeout,
-            peggy_contract_address,
-            peggy_id.clone(),
-            ethereum_key,
-        )
-        .await;
-        if res.is_err() {
-            info!("LogicCall submission failed with {:?}", res);
+            gas_limit
         }
+    };
+
+    if is_relaying_paused() {
+        info!(
+            "Relaying is paused, holding LogicCall {}/{}",
+            bytes_to_hex_str(&oldest_signed_call.invalidation_id),
+            latest_cosmos_call_nonce
+        );
+        sinfo!(&logger, "LOGIC_CALL_HELD_WHILE_PAUSED";
+            "function" => "relay_logic_calls()",
+            "invalidation_id" => bytes_to_hex_str(&oldest_signed_call.invalidation_id),
+            "invalidation_nonce" => format!("{}",latest_cosmos_call_nonce),
+        );
+        return;
     }
+
+    let res = send_eth_logic_call(
+        current_valset,
+        oldest_signed_call,
+        &oldest_signatures,
+        web3,
+        timeout,
+        peggy_contract_address,
+        peggy_id.clone(),
+        ethereum_key,
+        gas_limit,
+        nonce_manager,
+    )
+    .await;
+    if res.is_err() {
+        info!("LogicCall submission failed with {:?}", res);
+    }
+}
+
+#[test]
+fn test_is_logic_call_superseded() {
+    assert!(!is_logic_call_superseded(3, 2));
+    assert!(is_logic_call_superseded(3, 3));
+    assert!(is_logic_call_superseded(3, 7));
+    // an invalidation id that has never been used on Ethereum has nonce zero
+    assert!(!is_logic_call_superseded(1, 0));
 }
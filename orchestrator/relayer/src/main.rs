@@ -1,18 +1,35 @@
 use crate::main_loop::relayer_main_loop;
 use crate::main_loop::LOOP_SPEED;
+use crate::valset_submission::{submit_prepared_valset, PreparedValsetUpdate};
 use clarity::Address as EthAddress;
 use clarity::PrivateKey as EthPrivateKey;
 use docopt::Docopt;
 use env_logger::Env;
+use ethereum_peggy::bytecode::{parse_bytecode_hash, verify_contract_bytecode};
 use peggy_utils::connection_prep::{
-    check_for_eth, create_rpc_connections, wait_for_cosmos_node_ready,
+    check_for_eth, create_rpc_connections_with_grpc_config, wait_for_cosmos_node_ready,
+    GrpcChannelConfig,
 };
+use peggy_utils::keystore::load_keystore;
+use peggy_utils::rpc_headers::load_eth_rpc_headers;
+use peggy_utils::types::{
+    parse_logic_call_gas_stipend, parse_token_eth_price, validate_gas_multiplier,
+    validate_gas_price_smoothing, RelayerConfig,
+};
+use std::time::Duration;
 
+pub mod balance_guard;
+pub mod batch_reconciliation;
 pub mod batch_relaying;
+pub mod control_socket;
 pub mod find_latest_valset;
 pub mod logic_call_relaying;
 pub mod main_loop;
+pub mod participation;
+pub mod signature_cache;
 pub mod valset_relaying;
+pub mod valset_submission;
+pub mod workload_cost;
 
 #[macro_use]
 extern crate serde_derive;
@@ -23,23 +40,91 @@ extern crate log;
 
 #[derive(Debug, Deserialize)]
 struct Args {
-    flag_ethereum_key: String,
+    flag_ethereum_key: Option<String>,
+    flag_ethereum_keystore: Option<String>,
     flag_cosmos_legacy_rpc: String,
     flag_cosmos_grpc: String,
     flag_ethereum_rpc: String,
     flag_contract_address: String,
+    flag_gas_limit_multiplier: Option<f64>,
+    flag_gas_oracle_url: Option<String>,
+    flag_gas_price_tier: Option<String>,
+    flag_gas_price_smoothing: Option<f64>,
+    flag_eth_balance_reserve: Option<String>,
+    flag_batch_strategy: Option<String>,
+    flag_logic_call_gas_stipend: Vec<String>,
+    flag_grpc_connect_timeout: Option<u64>,
+    flag_grpc_request_timeout: Option<u64>,
+    flag_control_socket: Option<String>,
+    flag_estimate_gas_cap: Option<String>,
+    flag_halt_on_valset_divergence: bool,
+    flag_max_batch_age: Option<u64>,
+    flag_price_feed_url: Option<String>,
+    flag_token_eth_price: Vec<String>,
+    flag_only_profitable_batches: bool,
+    flag_valset_power_drift: Option<f32>,
+    flag_contract_code_hash: Option<String>,
+    flag_recovery_log: Option<String>,
+    flag_recovery_log_max_bytes: Option<u64>,
+    flag_batch_max_gas_price: Option<String>,
+    flag_state_snapshot: Option<String>,
+    flag_valset_submit_retries: Option<u32>,
+    flag_allow_token: Vec<String>,
+    flag_deny_token: Vec<String>,
+    flag_batch_reconcile_interval: Option<u64>,
+    flag_valset_submission_key: Option<String>,
+    flag_relay_priority: Option<String>,
+    flag_gas_estimate_failure: Option<String>,
+    flag_gas_estimate_retries: Option<u32>,
+    flag_batch_fallback_gas: Option<String>,
+    flag_submit_valset: Option<String>,
+    flag_metrics_listen: Option<String>,
 }
 
 lazy_static! {
     pub static ref USAGE: String = format!(
-    "Usage: {} --ethereum-key=<key> --cosmos-legacy-rpc=<url> --cosmos-grpc=<url> --ethereum-rpc=<url> --contract-address=<addr>
+    "Usage: {} (--ethereum-key=<key> | --ethereum-keystore=<path>) --cosmos-legacy-rpc=<url> --cosmos-grpc=<url> --ethereum-rpc=<url> --contract-address=<addr> [--gas-limit-multiplier=<mult>] [--gas-oracle-url=<url>] [--gas-price-tier=<tier>] [--gas-price-smoothing=<alpha>] [--eth-balance-reserve=<wei>] [--batch-strategy=<strategy>] [--logic-call-gas-stipend=<stipend>...] [--grpc-connect-timeout=<secs>] [--grpc-request-timeout=<secs>] [--control-socket=<path>] [--estimate-gas-cap=<gas>] [--halt-on-valset-divergence] [--max-batch-age=<blocks>] [--price-feed-url=<url>] [--token-eth-price=<price>...] [--only-profitable-batches] [--valset-power-drift=<fraction>] [--contract-code-hash=<hash>] [--recovery-log=<path>] [--recovery-log-max-bytes=<n>] [--batch-max-gas-price=<wei>] [--state-snapshot=<path>] [--valset-submit-retries=<n>] [--allow-token=<addr>...] [--deny-token=<addr>...] [--batch-reconcile-interval=<secs>] [--valset-submission-key=<key>] [--relay-priority=<priority>] [--gas-estimate-failure=<policy>] [--gas-estimate-retries=<n>] [--batch-fallback-gas=<gas>] [--submit-valset=<path>] [--metrics-listen=<addr>]
         Options:
             -h --help                    Show this screen.
             --ethereum-key=<ekey>        An Ethereum private key containing non-trivial funds
+            --ethereum-keystore=<path>   A V3 keystore file holding the Ethereum key, the passphrase is read from GRAVITY_KEYSTORE_PASSPHRASE or prompted for
             --cosmos-legacy-rpc=<curl>   The Cosmos RPC url
             --cosmos-grpc=<gurl>         The Cosmos gRPC url
             --ethereum-rpc=<eurl>        The Ethereum RPC url, Geth light clients work and sync fast
             --contract-address=<addr>    The Ethereum contract address for Peggy
+            --gas-limit-multiplier=<mult> Multiplier applied to Ethereum gas estimates, defaults to 1.2
+            --gas-oracle-url=<url>       An ethgasstation compatible gas price oracle, defaults to the node gas price
+            --gas-price-tier=<tier>      The oracle gas price tier to pay, one of fast, standard, or slow
+            --gas-price-smoothing=<alpha> Weight of new samples in the gas price moving average, defaults to 0.2
+            --eth-balance-reserve=<wei>  ETH to always keep in the relayer account, defaults to 0.05 ETH
+            --batch-strategy=<strategy>  Which ready batch to relay, one of oldest, highest-fee, or highest-fee-density
+            --logic-call-gas-stipend=<stipend>  A fixed gas limit for calls to a logic contract, as <address>=<gas>
+            --grpc-connect-timeout=<secs>  Seconds allowed to connect to Cosmos gRPC, defaults to 10
+            --grpc-request-timeout=<secs>  Seconds allowed for a single Cosmos gRPC request, defaults to 30
+            --control-socket=<path>      A Unix socket accepting pause, resume, and status commands for relaying
+            --estimate-gas-cap=<gas>     The largest gas limit used when estimating valset updates, defaults to the block gas limit
+            --halt-on-valset-divergence  Pause all relaying if Ethereum holds a valset nonce Cosmos does not know of
+            --max-batch-age=<blocks>     Do not relay batches created more than this many Ethereum blocks ago
+            --price-feed-url=<url>       A CoinGecko compatible token price api used to value batch fees in ETH
+            --token-eth-price=<price>    A fixed price in ETH of one whole fee token, as <address>=<price>
+            --only-profitable-batches    Skip batches whose fees are worth less than the gas to submit them
+            --valset-power-drift=<fraction>  Only relay valsets once this fraction of the power has moved or a validator joined or left
+            --contract-code-hash=<hash>  The keccak256 of the Peggy contract's runtime bytecode, checked at startup when set
+            --recovery-log=<path>        Record every Ethereum transaction to this file before it is sent, for disaster recovery
+            --recovery-log-max-bytes=<n>  The size the recovery log is rotated at, defaults to 10MiB
+            --batch-max-gas-price=<wei>  Defer batches while the gas price is above this, batches are submitted at any gas price by default
+            --state-snapshot=<path>      Save the relayer's gas price average to this file every loop and resume from it on startup, for migrating hosts
+            --valset-submit-retries=<n>  Times a failed valset update submission is retried with a fresh nonce, defaults to 2
+            --allow-token=<addr>         Only relay batches of this token, may be given several times
+            --deny-token=<addr>          Never relay batches of this token, may be given several times
+            --batch-reconcile-interval=<secs>  Compare executed batch nonces on Ethereum and Cosmos this often
+            --valset-submission-key=<key>  Send valset updates from this Ethereum key, it pays their gas instead of the relayer key
+            --relay-priority=<priority>  valsets-first (default) or batches-first, which is relayed first each iteration
+            --gas-estimate-failure=<policy>  skip (default), retry or fixed-gas, what to do when a batch gas estimate fails
+            --gas-estimate-retries=<n>   Extra batch gas estimates made under the retry policy, defaults to 2
+            --batch-fallback-gas=<gas>   The gas limit batches are submitted with under the fixed-gas policy, which requires it, never above the estimate gas cap
+            --submit-valset=<path>       Submit the valset update prepared in this JSON file and exit, for recovery when relaying is stuck
+            --metrics-listen=<addr>      Serve Prometheus metrics at /metrics and recent log records at /logs/recent on this address, for example 127.0.0.1:9100
         About:
             The Peggy relayer component, responsible for relaying data from the Cosmos blockchain
             to the Ethereum blockchain, cosmos key and fees are optional since they are only used
@@ -63,20 +148,124 @@ async fn main() {
     let args: Args = Docopt::new(USAGE.as_str())
         .and_then(|d| d.deserialize())
         .unwrap_or_else(|e| e.exit());
-    let ethereum_key: EthPrivateKey = args
-        .flag_ethereum_key
-        .parse()
-        .expect("Invalid Ethereum private key!");
+    let ethereum_key: EthPrivateKey = match (args.flag_ethereum_key, args.flag_ethereum_keystore) {
+        (Some(key), _) => key.parse().expect("Invalid Ethereum private key!"),
+        (None, Some(path)) => load_keystore(&path).unwrap_or_else(|e| panic!("{}", e)),
+        (None, None) => panic!("An Ethereum key or keystore is required!"),
+    };
     let peggy_contract_address: EthAddress = args
         .flag_contract_address
         .parse()
         .expect("Invalid contract address!");
+    let mut relayer_config = RelayerConfig::default();
+    if let Some(multiplier) = args.flag_gas_limit_multiplier {
+        relayer_config.gas_limit_multiplier =
+            validate_gas_multiplier(multiplier).expect("Invalid gas limit multiplier!");
+    }
+    relayer_config.gas_oracle_url = args.flag_gas_oracle_url;
+    if let Some(tier) = args.flag_gas_price_tier {
+        relayer_config.gas_price_tier = tier.parse().expect("Invalid gas price tier!");
+    }
+    if let Some(smoothing) = args.flag_gas_price_smoothing {
+        relayer_config.gas_price_smoothing =
+            validate_gas_price_smoothing(smoothing).expect("Invalid gas price smoothing!");
+    }
+    if let Some(reserve) = args.flag_eth_balance_reserve {
+        relayer_config.eth_balance_reserve = reserve.parse().expect("Invalid ETH balance reserve!");
+    }
+    if let Some(strategy) = args.flag_batch_strategy {
+        relayer_config.batch_selection_strategy =
+            strategy.parse().expect("Invalid batch selection strategy!");
+    }
+    for stipend in args.flag_logic_call_gas_stipend {
+        let (logic_contract, gas) =
+            parse_logic_call_gas_stipend(&stipend).expect("Invalid logic call gas stipend!");
+        relayer_config
+            .logic_call_gas_stipends
+            .insert(logic_contract, gas);
+    }
+    relayer_config.control_socket = args.flag_control_socket;
+    if let Some(cap) = args.flag_estimate_gas_cap {
+        relayer_config.estimate_gas_limit_cap =
+            Some(cap.parse().expect("Invalid estimate gas cap!"));
+    }
+    relayer_config.halt_on_valset_divergence = args.flag_halt_on_valset_divergence;
+    relayer_config.max_batch_age_blocks = args.flag_max_batch_age;
+    relayer_config.price_feed_url = args.flag_price_feed_url;
+    for price in args.flag_token_eth_price {
+        let (token, price) = parse_token_eth_price(&price).expect("Invalid token price!");
+        relayer_config.token_eth_prices.insert(token, price);
+    }
+    relayer_config.only_relay_profitable_batches = args.flag_only_profitable_batches;
+
+    relayer_config.valset_power_drift_threshold = args.flag_valset_power_drift;
+    let contract_code_hash = args
+        .flag_contract_code_hash
+        .map(|hash| parse_bytecode_hash(&hash).expect("Invalid contract code hash!"));
+    relayer_config.recovery_log = args.flag_recovery_log;
+    if let Some(max) = args.flag_recovery_log_max_bytes {
+        relayer_config.recovery_log_max_bytes = max;
+    }
+    relayer_config.state_snapshot = args.flag_state_snapshot;
+
+    if let Some(price) = args.flag_batch_max_gas_price {
+        relayer_config.batch_max_gas_price =
+            Some(price.parse().expect("Invalid batch max gas price!"));
+    }
+    if let Some(retries) = args.flag_valset_submit_retries {
+        relayer_config.valset_submit_retries = retries;
+    }
+    for token in args.flag_allow_token {
+        relayer_config
+            .token_allowlist
+            .insert(token.parse().expect("Invalid allowed token!"));
+    }
+    for token in args.flag_deny_token {
+        relayer_config
+            .token_denylist
+            .insert(token.parse().expect("Invalid denied token!"));
+    }
+    relayer_config.batch_reconcile_interval =
+        args.flag_batch_reconcile_interval.map(Duration::from_secs);
+    relayer_config.valset_submission_key = args
+        .flag_valset_submission_key
+        .map(|key| key.parse().expect("Invalid valset submission key!"));
+    if let Some(priority) = args.flag_relay_priority {
+        relayer_config.relay_priority = priority.parse().expect("Invalid relay priority!");
+    }
+    if let Some(policy) = args.flag_gas_estimate_failure {
+        relayer_config.gas_estimate_failure_policy = policy
+            .parse()
+            .expect("Invalid gas estimate failure policy!");
+    }
+    if let Some(retries) = args.flag_gas_estimate_retries {
+        relayer_config.gas_estimate_retries = retries;
+    }
+    relayer_config.batch_fallback_gas_limit = args
+        .flag_batch_fallback_gas
+        .map(|gas| gas.parse().expect("Invalid batch fallback gas!"));
+    relayer_config
+        .validate_gas_estimate_failure_policy()
+        .unwrap_or_else(|e| panic!("{}", e));
+    relayer_config.metrics_listen = args.flag_metrics_listen;
+    if let Err(e) = load_eth_rpc_headers() {
+        error!("{}", e);
+        std::process::exit(1);
+    }
+    let mut grpc_config = GrpcChannelConfig::default();
+    if let Some(secs) = args.flag_grpc_connect_timeout {
+        grpc_config.connect_timeout = Duration::from_secs(secs);
+    }
+    if let Some(secs) = args.flag_grpc_request_timeout {
+        grpc_config.request_timeout = Duration::from_secs(secs);
+    }
 
-    let connections = create_rpc_connections(
+    let connections = create_rpc_connections_with_grpc_config(
         Some(args.flag_cosmos_grpc),
         Some(args.flag_cosmos_legacy_rpc),
         Some(args.flag_ethereum_rpc),
         LOOP_SPEED,
+        grpc_config,
     )
     .await;
 
@@ -89,17 +278,71 @@ async fn main() {
     let contact = connections.contact.clone().unwrap();
     let web3 = connections.web3.clone().unwrap();
 
+    if let Some(expected) = contract_code_hash {
+        if let Err(e) = verify_contract_bytecode(&web3, peggy_contract_address, expected).await {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(path) = args.flag_submit_valset {
+        let update = PreparedValsetUpdate::load(&path).expect("Invalid valset update file!");
+        match submit_prepared_valset(
+            &update,
+            &web3,
+            peggy_contract_address,
+            ethereum_key,
+            &relayer_config,
+            LOOP_SPEED,
+        )
+        .await
+        {
+            Ok(submission) => {
+                match submission.tx_hash {
+                    Some(tx) => info!("Valset update sent with txid {:#066x}", tx),
+                    None => info!("No valset update was sent"),
+                }
+                let confirmed = submission.confirmed(&update);
+                info!(
+                    "Valset nonce on Ethereum is {}, update to {} confirmed: {}",
+                    submission.ethereum_nonce, update.new_valset.nonce, confirmed
+                );
+                std::process::exit(if confirmed { 0 } else { 1 });
+            }
+            Err(e) => {
+                error!("Valset submission failed {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
     // check if the cosmos node is syncing, if so wait for it
     // we can't move any steps above this because they may fail on an incorrect
     // historic chain state while syncing occurs
     wait_for_cosmos_node_ready(&contact).await;
     check_for_eth(public_eth_key, &web3).await;
+    // valset updates are sent from the submission key, it needs its own ETH for their gas
+    if let Some(key) = relayer_config.valset_submission_key {
+        check_for_eth(key.to_public_key().unwrap(), &web3).await;
+    }
+
+    // tag every structured log record with the chain we are attached to
+    match web3.net_version().await {
+        Ok(chain_id) => {
+            json_logger::set_chain_id(chain_id);
+        }
+        Err(e) => warn!(
+            "Failed to get the Ethereum net version, logging without a chain id {:?}",
+            e
+        ),
+    }
 
     relayer_main_loop(
         ethereum_key,
         connections.web3.unwrap(),
         connections.grpc.unwrap(),
         peggy_contract_address,
+        relayer_config,
     )
     .await
 }
@@ -1,11 +1,30 @@
 use crate::{
-    batch_relaying::relay_batches, find_latest_valset::find_latest_valset,
-    logic_call_relaying::relay_logic_calls, valset_relaying::relay_valsets,
+    batch_reconciliation::BatchReconciler,
+    batch_relaying::relay_batches,
+    control_socket::start_control_socket,
+    find_latest_valset::find_latest_valset,
+    logic_call_relaying::relay_logic_calls,
+    participation::ParticipationTracker,
+    signature_cache::{BatchSignatureCache, BATCH_SIGNATURE_CACHE_TTL},
+    state_snapshot::{load_relayer_snapshot, save_relayer_snapshot, RelayerStateSnapshot},
+    valset_relaying::relay_valsets,
 };
 use clarity::address::Address as EthAddress;
 use clarity::PrivateKey as EthPrivateKey;
-use ethereum_peggy::utils::get_peggy_id;
+use ethereum_peggy::gas_oracle::{EthGasStationOracle, GasOracle};
+use ethereum_peggy::gas_price_tracker::GasPriceTracker;
+use ethereum_peggy::nonce_manager::NonceManager;
+use ethereum_peggy::price_feed::{CoinGeckoPriceFeed, PriceFeed, StaticPriceFeed};
+use ethereum_peggy::recovery_log::{set_recovery_log, RecoveryLog};
+use ethereum_peggy::token_metadata::TokenMetadataCache;
+use ethereum_peggy::utils::{get_peggy_id, get_power_threshold, get_valset_nonce};
+use json_logger::LOGGING;
 use peggy_proto::peggy::query_client::QueryClient as PeggyQueryClient;
+use peggy_utils::metrics::start_metrics_server;
+use peggy_utils::types::{
+    power_threshold, set_power_threshold, RelayPriority, RelayerConfig, Valset, TOTAL_PEGGY_POWER,
+};
+use slog::info as sinfo;
 use std::time::{Duration, Instant};
 use tokio::time::delay_for;
 use tonic::transport::Channel;
@@ -20,11 +39,85 @@ pub async fn relayer_main_loop(
     web3: Web3,
     grpc_client: PeggyQueryClient<Channel>,
     peggy_contract_address: EthAddress,
+    config: RelayerConfig,
 ) {
     let mut grpc_client = grpc_client;
+    if let Some(path) = config.control_socket.as_deref() {
+        if let Err(e) = start_control_socket(path) {
+            error!("Failed to open control socket {} {:?}", path, e);
+        }
+    }
+    if let Some(addr) = config.metrics_listen.as_deref() {
+        if let Err(e) = start_metrics_server(addr) {
+            error!("Failed to serve metrics on {} {:?}", addr, e);
+        }
+    }
+    if let Some(path) = config.recovery_log.as_deref() {
+        set_recovery_log(RecoveryLog::new(path, config.recovery_log_max_bytes));
+    }
+    let our_ethereum_address = ethereum_key.to_public_key().unwrap();
+    match get_power_threshold(peggy_contract_address, our_ethereum_address, &web3).await {
+        Ok(threshold) => set_power_threshold(threshold),
+        Err(e) => warn!(
+            "Failed to get the contract power threshold, assuming 66% {:?}",
+            e
+        ),
+    }
+    info!(
+        "Signatures must represent more than {}/{} power",
+        power_threshold(),
+        TOTAL_PEGGY_POWER
+    );
+    sinfo!(&LOGGING.logger, "POWER_THRESHOLD";
+        "function" => "relayer_main_loop()",
+        "power_threshold" => format!("{}",power_threshold()),
+        "total_power" => format!("{}",TOTAL_PEGGY_POWER),
+    );
+    let gas_oracle = config.gas_oracle_url.clone().map(EthGasStationOracle::new);
+    let price_feed: Option<Box<dyn PriceFeed>> = match &config.price_feed_url {
+        Some(url) => Some(Box::new(CoinGeckoPriceFeed::new(url.clone()))),
+        None if !config.token_eth_prices.is_empty() => Some(Box::new(StaticPriceFeed::new(
+            config.token_eth_prices.clone(),
+        ))),
+        None => None,
+    };
+    // valsets, batches and logic calls are submitted from the same Ethereum key by default, so
+    // they must share a nonce manager to avoid building transactions with the same nonce
+    let nonce_manager = NonceManager::new(ethereum_key.to_public_key().unwrap());
+    // a separate valset submission key sends from its own account and so tracks its own nonces
+    let valset_key = config.valset_submission_key.unwrap_or(ethereum_key);
+    let valset_nonce_manager = match config.valset_submission_key {
+        Some(key) => {
+            let address = key.to_public_key().unwrap();
+            info!("Submitting valset updates from {}", address);
+            NonceManager::new(address)
+        }
+        None => nonce_manager.clone(),
+    };
+    let mut signature_cache = BatchSignatureCache::new(BATCH_SIGNATURE_CACHE_TTL);
+    let mut token_metadata = TokenMetadataCache::new();
+    let mut participation = ParticipationTracker::new();
+    let mut gas_price_tracker = GasPriceTracker::new(config.gas_price_smoothing);
+    if let Some(path) = config.state_snapshot.as_deref() {
+        let ema = load_relayer_snapshot(path, &our_ethereum_address.to_string())
+            .and_then(|snapshot| snapshot.fresh_gas_price_ema());
+        if let Some(ema) = ema {
+            info!("Restored gas price average {} from the state snapshot", ema);
+            sinfo!(&LOGGING.logger, "RELAYER_STATE_SNAPSHOT_RESTORED";
+                "function" => "relayer_main_loop()",
+                "gas_price_ema" => format!("{}",ema),
+            );
+            gas_price_tracker.restore(&ema);
+        }
+    }
+    let mut batch_reconciler = config.batch_reconcile_interval.map(BatchReconciler::new);
     loop {
         let loop_start = Instant::now();
 
+        if let Err(e) = gas_price_tracker.sample(&web3).await {
+            warn!("Failed to sample the Ethereum gas price {:?}", e);
+        }
+
         let our_ethereum_address = ethereum_key.to_public_key().unwrap();
         let current_valset = find_latest_valset(
             &mut grpc_client,
@@ -37,7 +130,7 @@ pub async fn relayer_main_loop(
             error!("Could not get current valset! {:?}", current_valset);
             continue;
         }
-        let current_valset = current_valset.unwrap();
+        let mut current_valset = current_valset.unwrap();
 
         let peggy_id = get_peggy_id(peggy_contract_address, our_ethereum_address, &web3).await;
         if peggy_id.is_err() {
@@ -47,27 +140,75 @@ pub async fn relayer_main_loop(
         let peggy_id = peggy_id.unwrap();
         let peggy_id = String::from_utf8(peggy_id.clone()).expect("Invalid PeggyID");
 
-        relay_valsets(
-            current_valset.clone(),
-            ethereum_key,
-            &web3,
-            &mut grpc_client,
-            peggy_contract_address,
-            peggy_id.clone(),
-            LOOP_SPEED,
-        )
-        .await;
-
-        relay_batches(
-            current_valset.clone(),
-            ethereum_key,
-            &web3,
-            &mut grpc_client,
-            peggy_contract_address,
-            peggy_id.clone(),
-            LOOP_SPEED,
-        )
-        .await;
+        // see RelayPriority for why valsets go first unless configured otherwise
+        match config.relay_priority {
+            RelayPriority::ValsetsFirst => {
+                current_valset = relay_valsets_and_refresh(
+                    current_valset,
+                    valset_key,
+                    &web3,
+                    &mut grpc_client,
+                    peggy_contract_address,
+                    our_ethereum_address,
+                    peggy_id.clone(),
+                    &config,
+                    &mut participation,
+                    &valset_nonce_manager,
+                )
+                .await;
+                relay_batches(
+                    current_valset.clone(),
+                    ethereum_key,
+                    &web3,
+                    &mut grpc_client,
+                    peggy_contract_address,
+                    peggy_id.clone(),
+                    LOOP_SPEED,
+                    &config,
+                    gas_oracle.as_ref().map(|o| o as &dyn GasOracle),
+                    &gas_price_tracker,
+                    price_feed.as_deref(),
+                    &mut token_metadata,
+                    &mut signature_cache,
+                    &mut participation,
+                    &nonce_manager,
+                )
+                .await;
+            }
+            RelayPriority::BatchesFirst => {
+                relay_batches(
+                    current_valset.clone(),
+                    ethereum_key,
+                    &web3,
+                    &mut grpc_client,
+                    peggy_contract_address,
+                    peggy_id.clone(),
+                    LOOP_SPEED,
+                    &config,
+                    gas_oracle.as_ref().map(|o| o as &dyn GasOracle),
+                    &gas_price_tracker,
+                    price_feed.as_deref(),
+                    &mut token_metadata,
+                    &mut signature_cache,
+                    &mut participation,
+                    &nonce_manager,
+                )
+                .await;
+                current_valset = relay_valsets_and_refresh(
+                    current_valset,
+                    valset_key,
+                    &web3,
+                    &mut grpc_client,
+                    peggy_contract_address,
+                    our_ethereum_address,
+                    peggy_id.clone(),
+                    &config,
+                    &mut participation,
+                    &valset_nonce_manager,
+                )
+                .await;
+            }
+        }
 
         relay_logic_calls(
             current_valset,
@@ -77,9 +218,39 @@ pub async fn relayer_main_loop(
             peggy_contract_address,
             peggy_id.clone(),
             LOOP_SPEED,
+            &config,
+            &nonce_manager,
         )
         .await;
 
+        // reconciliation is only monitoring, so it only runs when relaying has left time to spare
+        // in this iteration and never delays the next round of relaying
+        if let Some(reconciler) = batch_reconciler.as_mut() {
+            if reconciler.is_due() && loop_start.elapsed() < LOOP_SPEED / 2 {
+                if let Err(e) = reconciler
+                    .reconcile(
+                        &web3,
+                        &mut grpc_client,
+                        peggy_contract_address,
+                        our_ethereum_address,
+                    )
+                    .await
+                {
+                    warn!("Failed to reconcile batch state {:?}", e);
+                }
+            }
+        }
+
+        if let Some(path) = config.state_snapshot.as_deref() {
+            let snapshot = RelayerStateSnapshot::new(
+                our_ethereum_address.to_string(),
+                gas_price_tracker.ema(),
+            );
+            if let Err(e) = save_relayer_snapshot(path, &snapshot) {
+                warn!("Failed to write relayer state snapshot {} {:?}", path, e);
+            }
+        }
+
         // a bit of logic that tires to keep things running every 5 seconds exactly
         // this is not required for any specific reason. In fact we expect and plan for
         // the timing being off significantly
@@ -89,3 +260,52 @@ pub async fn relayer_main_loop(
         }
     }
 }
+
+/// Relays any pending valset update and returns the valset in the bridge afterwards, what is
+/// relayed next must be checked against the valset the update we or another relayer sent has
+/// put in place
+#[allow(clippy::too_many_arguments)]
+async fn relay_valsets_and_refresh(
+    current_valset: Valset,
+    valset_key: EthPrivateKey,
+    web3: &Web3,
+    grpc_client: &mut PeggyQueryClient<Channel>,
+    peggy_contract_address: EthAddress,
+    our_ethereum_address: EthAddress,
+    peggy_id: String,
+    config: &RelayerConfig,
+    participation: &mut ParticipationTracker,
+    nonce_manager: &NonceManager,
+) -> Valset {
+    relay_valsets(
+        current_valset.clone(),
+        valset_key,
+        web3,
+        grpc_client,
+        peggy_contract_address,
+        peggy_id,
+        LOOP_SPEED,
+        config,
+        participation,
+        nonce_manager,
+    )
+    .await;
+    match get_valset_nonce(peggy_contract_address, our_ethereum_address, web3).await {
+        Ok(nonce) if nonce != current_valset.nonce => {
+            match find_latest_valset(
+                grpc_client,
+                our_ethereum_address,
+                peggy_contract_address,
+                web3,
+            )
+            .await
+            {
+                Ok(valset) => return valset,
+                Err(e) => error!("Could not get current valset! {:?}", e),
+            }
+        }
+        Ok(_) => {}
+        Err(e) => warn!("Failed to get the valset nonce {:?}", e),
+    }
+    current_valset
+}
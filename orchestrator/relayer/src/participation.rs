@@ -0,0 +1,133 @@
+//! Tallies which validators signed each valset and batch the relayer submits, so that validators
+//! who chronically fail to sign can be identified. Each item is only counted once, when it first
+//! becomes submittable, so repeated polls of the same batch don't inflate the tally.
+
+use clarity::Address as EthAddress;
+use json_logger::LOGGING;
+use peggy_utils::metrics::{VALIDATOR_SIGNATURES_MISSING, VALIDATOR_SIGNATURES_PRESENT};
+use peggy_utils::types::{Confirm, Valset};
+use slog::info as sinfo;
+use std::collections::HashSet;
+
+/// The valset or batch a set of signatures was collected for
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SignedItem {
+    Valset {
+        nonce: u64,
+    },
+    Batch {
+        token_contract: EthAddress,
+        nonce: u64,
+    },
+}
+
+/// Splits the members of `valset` into those who provided a signature and those who did not,
+/// members without an Ethereum key set are counted as missing
+pub fn signature_participation<T: Confirm>(
+    valset: &Valset,
+    signatures: &[T],
+) -> (Vec<EthAddress>, Vec<Option<EthAddress>>) {
+    let signers: HashSet<EthAddress> = signatures.iter().map(|s| s.get_eth_address()).collect();
+    let mut present = Vec::new();
+    let mut missing = Vec::new();
+    for member in valset.members.iter() {
+        match member.eth_address {
+            Some(address) if signers.contains(&address) => present.push(address),
+            other => missing.push(other),
+        }
+    }
+    (present, missing)
+}
+
+#[derive(Debug, Default)]
+pub struct ParticipationTracker {
+    recorded: HashSet<SignedItem>,
+}
+
+impl ParticipationTracker {
+    pub fn new() -> Self {
+        ParticipationTracker::default()
+    }
+
+    /// Adds the signatures for `item` to the participation tally, if it has not already been counted
+    pub fn record<T: Confirm>(&mut self, item: SignedItem, valset: &Valset, signatures: &[T]) {
+        if !self.recorded.insert(item.clone()) {
+            return;
+        }
+        let (present, missing) = signature_participation(valset, signatures);
+        for address in present.iter() {
+            VALIDATOR_SIGNATURES_PRESENT.inc_by(&address.to_string(), 1u8.into());
+        }
+        let missing: Vec<String> = missing
+            .iter()
+            .map(|a| match a {
+                Some(address) => address.to_string(),
+                None => "unset".to_string(),
+            })
+            .collect();
+        for address in missing.iter() {
+            VALIDATOR_SIGNATURES_MISSING.inc_by(address, 1u8.into());
+        }
+        sinfo!(&LOGGING.logger, "VALIDATOR_PARTICIPATION";
+            "function" => "ParticipationTracker::record()",
+            "item" => format!("{:?}",item),
+            "present" => format!("{}",present.len()),
+            "missing" => format!("{}",missing.join(",")),
+        );
+    }
+
+    /// Forgets items that are no longer pending so the set of recorded items doesn't grow forever
+    pub fn retain(&mut self, keep: impl Fn(&SignedItem) -> bool) {
+        self.recorded.retain(|item| keep(item));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use peggy_utils::types::{ValsetConfirmResponse, ValsetMember};
+
+    #[test]
+    fn test_signature_participation() {
+        let a: EthAddress = "0x0000000000000000000000000000000000000001"
+            .parse()
+            .unwrap();
+        let b: EthAddress = "0x0000000000000000000000000000000000000002"
+            .parse()
+            .unwrap();
+        let c: EthAddress = "0x0000000000000000000000000000000000000003"
+            .parse()
+            .unwrap();
+        let valset = Valset {
+            nonce: 1,
+            members: vec![
+                ValsetMember {
+                    power: 10,
+                    eth_address: Some(a),
+                },
+                ValsetMember {
+                    power: 10,
+                    eth_address: Some(b),
+                },
+                ValsetMember {
+                    power: 10,
+                    eth_address: None,
+                },
+            ],
+        };
+        let confirms = vec![
+            ValsetConfirmResponse {
+                eth_address: a,
+                ..Default::default()
+            },
+            // signatures from outside the valset are ignored
+            ValsetConfirmResponse {
+                eth_address: c,
+                ..Default::default()
+            },
+        ];
+        let (present, missing) = signature_participation(&valset, &confirms);
+        assert_eq!(present, vec![a]);
+        assert_eq!(missing, vec![Some(b), None]);
+    }
+}
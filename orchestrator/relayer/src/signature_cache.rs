@@ -0,0 +1,123 @@
+//! Caches the signatures collected for each transaction batch so that the relayer does not query
+//! every batch's signatures from Cosmos on every loop. Once a batch has collected enough signatures
+//! to be submitted they will not change, so only batches still waiting on signatures are refreshed.
+
+use clarity::Address as EthAddress;
+use peggy_utils::metrics::BATCH_SIGNATURE_CACHE_LOOKUPS;
+use peggy_utils::types::{BatchConfirmResponse, TransactionBatch};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How long fetched batch signatures are trusted before they are queried again
+pub const BATCH_SIGNATURE_CACHE_TTL: Duration = Duration::from_secs(60);
+
+struct CachedSignatures {
+    signatures: Vec<BatchConfirmResponse>,
+    fetched_at: Instant,
+}
+
+/// Batch signatures keyed by (token_contract, nonce)
+pub struct BatchSignatureCache {
+    ttl: Duration,
+    entries: HashMap<(EthAddress, u64), CachedSignatures>,
+}
+
+impl BatchSignatureCache {
+    pub fn new(ttl: Duration) -> Self {
+        BatchSignatureCache {
+            ttl,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Returns the cached signatures for this batch if they are still fresh, recording
+    /// the lookup as a hit or miss
+    pub fn get(
+        &mut self,
+        token_contract: EthAddress,
+        nonce: u64,
+    ) -> Option<Vec<BatchConfirmResponse>> {
+        let key = (token_contract, nonce);
+        let fresh = match self.entries.get(&key) {
+            Some(entry) => entry.fetched_at.elapsed() < self.ttl,
+            None => false,
+        };
+        if fresh {
+            BATCH_SIGNATURE_CACHE_LOOKUPS.inc_by("hit", 1u8.into());
+            self.entries.get(&key).map(|entry| entry.signatures.clone())
+        } else {
+            BATCH_SIGNATURE_CACHE_LOOKUPS.inc_by("miss", 1u8.into());
+            self.entries.remove(&key);
+            None
+        }
+    }
+
+    pub fn insert(
+        &mut self,
+        token_contract: EthAddress,
+        nonce: u64,
+        signatures: Vec<BatchConfirmResponse>,
+    ) {
+        self.entries.insert(
+            (token_contract, nonce),
+            CachedSignatures {
+                signatures,
+                fetched_at: Instant::now(),
+            },
+        );
+    }
+
+    /// Drops the cached signatures for a batch, used when the cached set does not have enough
+    /// power to submit so that the next lookup fetches the grown signature set
+    pub fn invalidate(&mut self, token_contract: EthAddress, nonce: u64) {
+        self.entries.remove(&(token_contract, nonce));
+    }
+
+    /// Removes every entry for a batch that is no longer pending on Cosmos
+    pub fn retain_batches(&mut self, batches: &[TransactionBatch]) {
+        self.entries.retain(|(token_contract, nonce), _| {
+            batches
+                .iter()
+                .any(|b| b.token_contract == *token_contract && b.nonce == *nonce)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn confirm(nonce: u64) -> BatchConfirmResponse {
+        BatchConfirmResponse {
+            nonce,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_batch_signature_cache() {
+        let token = EthAddress::default();
+        let mut cache = BatchSignatureCache::new(Duration::from_secs(60));
+        assert!(cache.get(token, 1).is_none());
+
+        cache.insert(token, 1, vec![confirm(1)]);
+        assert_eq!(cache.get(token, 1).unwrap().len(), 1);
+
+        // the signature set grew, the stale entry must not be served
+        cache.invalidate(token, 1);
+        assert!(cache.get(token, 1).is_none());
+        cache.insert(token, 1, vec![confirm(1), confirm(1)]);
+        assert_eq!(cache.get(token, 1).unwrap().len(), 2);
+
+        cache.retain_batches(&[]);
+        assert!(cache.get(token, 1).is_none());
+    }
+
+    #[test]
+    fn test_batch_signature_cache_expiry() {
+        let token = EthAddress::default();
+        let mut cache = BatchSignatureCache::new(Duration::from_secs(0));
+        cache.insert(token, 1, vec![confirm(1)]);
+        assert!(cache.get(token, 1).is_none());
+    }
+}
@@ -0,0 +1,95 @@
+//! The relayer's section of the host migration snapshot, see peggy_utils::state_snapshot for the
+//! file itself. It is saved after every relaying loop and restored on startup.
+//!
+//! The only relayer state worth carrying over is the gas price moving average, without it the
+//! new machine submits at whatever spot price its first sample lands on. An average goes stale
+//! quickly so it is only restored when the snapshot is recent.
+//!
+//! The participation tally is not saved, it only deduplicates metrics which start from zero in a
+//! new process anyway.
+
+use clarity::Uint256;
+use peggy_utils::state_snapshot::{load_snapshot_section, save_snapshot_section, RELAYER_SECTION};
+use serde_derive::{Deserialize, Serialize};
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A gas price average saved longer ago than this is discarded rather than restored
+pub const GAS_PRICE_EMA_MAX_AGE: Duration = Duration::from_secs(600);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RelayerStateSnapshot {
+    /// The Ethereum address of the relayer, a snapshot taken by another relayer is ignored
+    pub ethereum_address: String,
+    /// Seconds since the Unix epoch when the snapshot was saved
+    pub saved_at: u64,
+    pub gas_price_ema: Option<Uint256>,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl RelayerStateSnapshot {
+    pub fn new(ethereum_address: String, gas_price_ema: Option<Uint256>) -> Self {
+        RelayerStateSnapshot {
+            ethereum_address,
+            saved_at: unix_now(),
+            gas_price_ema,
+        }
+    }
+
+    /// The saved gas price average, None if there was none or it is older than
+    /// GAS_PRICE_EMA_MAX_AGE
+    pub fn fresh_gas_price_ema(&self) -> Option<Uint256> {
+        let age = unix_now().saturating_sub(self.saved_at);
+        if age > GAS_PRICE_EMA_MAX_AGE.as_secs() {
+            None
+        } else {
+            self.gas_price_ema.clone()
+        }
+    }
+}
+
+/// Loads the relayer's section of the snapshot at `path` if it was taken by this relayer
+pub fn load_relayer_snapshot(path: &str, ethereum_address: &str) -> Option<RelayerStateSnapshot> {
+    let snapshot: RelayerStateSnapshot = load_snapshot_section(path, RELAYER_SECTION)?;
+    if snapshot.ethereum_address == ethereum_address {
+        Some(snapshot)
+    } else {
+        warn!(
+            "Ignoring relayer state snapshot {} taken by a different relayer",
+            path
+        );
+        None
+    }
+}
+
+/// Replaces the relayer's section of the snapshot at `path`
+pub fn save_relayer_snapshot(path: &str, snapshot: &RelayerStateSnapshot) -> Result<(), io::Error> {
+    save_snapshot_section(path, RELAYER_SECTION, snapshot)
+}
+
+#[test]
+fn test_relayer_snapshot_round_trip() {
+    let path = std::env::temp_dir().join(format!("relayer_snapshot_{}.json", std::process::id()));
+    let path = path.to_str().unwrap();
+    let address = "0x8858eeB3DfffA017D4BCE9801D340D36Cf895CCf";
+    let snapshot = RelayerStateSnapshot::new(address.to_string(), Some(30_000_000_000u64.into()));
+    save_relayer_snapshot(path, &snapshot).unwrap();
+
+    let loaded = load_relayer_snapshot(path, address).unwrap();
+    assert_eq!(loaded, snapshot);
+    assert_eq!(loaded.fresh_gas_price_ema(), Some(30_000_000_000u64.into()));
+    assert!(load_relayer_snapshot(path, "0x0000000000000000000000000000000000000001").is_none());
+    std::fs::remove_file(path).unwrap();
+
+    let stale = RelayerStateSnapshot {
+        saved_at: loaded.saved_at - GAS_PRICE_EMA_MAX_AGE.as_secs() - 1,
+        ..loaded
+    };
+    assert_eq!(stale.fresh_gas_price_ema(), None);
+}
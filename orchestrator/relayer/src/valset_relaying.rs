@@ -3,30 +3,46 @@
 
 use std::time::Duration;
 
+use crate::balance_guard::check_eth_balance;
+use crate::participation::{ParticipationTracker, SignedItem};
 use clarity::address::Address as EthAddress;
 use clarity::PrivateKey as EthPrivateKey;
 use cosmos_peggy::query::get_latest_valsets;
 use cosmos_peggy::query::{get_all_valset_confirms, get_valset};
-use ethereum_peggy::{one_eth, utils::downcast_to_u128, valset_update::send_eth_valset_update};
+use ethereum_peggy::{
+    nonce_manager::NonceManager,
+    one_eth,
+    utils::{downcast_to_u128, get_gas_limit},
+    valset_update::send_eth_valset_update,
+};
+use json_logger::LOGGING;
 use peggy_proto::peggy::query_client::QueryClient as PeggyQueryClient;
-use peggy_utils::{message_signatures::encode_valset_confirm_hashed, types::Valset};
+use peggy_utils::relay_pause::pause_relaying;
+use peggy_utils::{
+    message_signatures::encode_valset_confirm_hashed,
+    types::{RelayerConfig, Valset},
+};
+use slog::error as serror;
+use slog::info as sinfo;
 use tonic::transport::Channel;
 use web30::client::Web3;
-use json_logger::LOGGING;
-use slog::{info as sinfo};
-use slog::{error as serror};
 
 /// Check the last validator set on Ethereum, if it's lower than our latest validator
 /// set then we should package and submit the update as an Ethereum transaction
+#[allow(clippy::too_many_arguments)]
 pub async fn relay_valsets(
     // the validator set currently in the contract on Ethereum
     current_valset: Valset,
+    // the key the update is sent from, it pays the gas but signs nothing the contract checks
     ethereum_key: EthPrivateKey,
     web3: &Web3,
     grpc_client: &mut PeggyQueryClient<Channel>,
     peggy_contract_address: EthAddress,
     peggy_id: String,
     timeout: Duration,
+    config: &RelayerConfig,
+    participation: &mut ParticipationTracker,
+    nonce_manager: &NonceManager,
 ) {
     // we have to start with the current valset, we need to know what's currently
     // in the contract in order to determine if a new validator set is valid.
@@ -48,6 +64,26 @@ pub async fn relay_valsets(
         return;
     }
 
+    // the bridge holding a valset Cosmos has never produced means one of the chains is not
+    // in the state we think it is, nothing we submit against it can be trusted
+    let latest_cosmos_nonce = latest_valsets[0].nonce;
+    if current_valset.nonce > latest_cosmos_nonce {
+        error!(
+            "Ethereum valset nonce {} is ahead of the latest Cosmos valset nonce {}, not relaying valsets",
+            current_valset.nonce, latest_cosmos_nonce
+        );
+        serror!(&LOGGING.logger, "VALSET_STATE_DIVERGENCE";
+            "function" => "relay_valsets()",
+            "ethereum_valset_nonce" => format!("{}",current_valset.nonce),
+            "cosmos_valset_nonce" => format!("{}",latest_cosmos_nonce),
+            "halting" => format!("{}",config.halt_on_valset_divergence),
+        );
+        if config.halt_on_valset_divergence && pause_relaying() {
+            error!("Relaying halted, resume it through the control socket once the divergence is resolved");
+        }
+        return;
+    }
+
     // we only use the latest valsets endpoint to get a starting point, from there we will iterate
     // backwards until we find the newest validator set that we can submit to the bridge. So if we
     // have sets A-Z and it's possible to submit only A, L, and Q before reaching Z this code will do
@@ -57,6 +93,7 @@ pub async fn relay_valsets(
     let mut latest_valset = None;
     // this is used to display the state of the last validator set to fail signature checks
     let mut last_error = None;
+    let indexed_valset = current_valset.index();
     while latest_nonce > 0 {
         let valset = get_valset(grpc_client, latest_nonce).await;
         if let Ok(Some(valset)) = valset {
@@ -66,11 +103,12 @@ pub async fn relay_valsets(
                 for confirm in confirms.iter() {
                     assert_eq!(valset.nonce, confirm.nonce);
                 }
+                let confirms = current_valset.filter_valid_confirms(&confirms);
                 let hash = encode_valset_confirm_hashed(peggy_id.clone(), valset.clone());
                 // order valset sigs prepares signatures for submission, notice we compare
                 // them to the 'current' set in the bridge, this confirms for us that the validator set
                 // we have here can be submitted to the bridge in it's current state
-                let res = current_valset.order_sigs(&hash, &confirms);
+                let res = indexed_valset.order_sigs(&hash, &confirms);
                 if res.is_ok() {
                     latest_confirmed = Some(confirms);
                     latest_valset = Some(valset);
@@ -105,6 +143,62 @@ pub async fn relay_valsets(
 
     let latest_cosmos_valset_nonce = latest_cosmos_valset.nonce;
     if latest_cosmos_valset_nonce > current_valset.nonce {
+        participation.record(
+            SignedItem::Valset {
+                nonce: latest_cosmos_valset_nonce,
+            },
+            &current_valset,
+            &latest_cosmos_confirmed,
+        );
+        // valsets at or below the one in the bridge will never be submitted again
+        let bridge_nonce = current_valset.nonce;
+        participation.retain(|item| match item {
+            SignedItem::Valset { nonce } => *nonce > bridge_nonce,
+            SignedItem::Batch { .. } => true,
+        });
+        let skipped = skipped_valset_nonces(
+            &latest_valsets,
+            current_valset.nonce,
+            latest_cosmos_valset_nonce,
+        );
+        if !skipped.is_empty() {
+            info!(
+                "Catching up from valset {} to {}, skipping intermediate valsets {:?}",
+                current_valset.nonce, latest_cosmos_valset_nonce, skipped
+            );
+            sinfo!(&LOGGING.logger, "VALSET_CATCH_UP_SKIPPED";
+                "function" => "relay_valsets()",
+                "current_valset_nonce" => format!("{}",current_valset.nonce),
+                "latest_cosmos_valset_nonce" => format!("{}",latest_cosmos_valset_nonce),
+                "skipped_nonces" => format!("{:?}",skipped),
+            );
+        }
+        if let Some(threshold) = config.valset_power_drift_threshold {
+            let diff = current_valset.diff(&latest_cosmos_valset);
+            let drift = current_valset.power_diff(&latest_cosmos_valset);
+            let urgent = valset_update_is_urgent(drift, diff.membership_changed(), threshold);
+            info!(
+                "Valset {} has moved {:.4} of the power since valset {} ({}), threshold {}, {}",
+                latest_cosmos_valset_nonce,
+                drift,
+                current_valset.nonce,
+                diff,
+                threshold,
+                if urgent { "relaying" } else { "not relaying" }
+            );
+            sinfo!(&LOGGING.logger, "VALSET_POWER_DRIFT";
+                "function" => "relay_valsets()",
+                "latest_cosmos_valset_nonce" => format!("{}",latest_cosmos_valset_nonce),
+                "current_valset_nonce" => format!("{}",current_valset.nonce),
+                "power_drift" => format!("{:.4}",drift),
+                "threshold" => format!("{}",threshold),
+                "membership_changed" => format!("{}",diff.membership_changed()),
+                "relaying" => format!("{}",urgent),
+            );
+            if !urgent {
+                return;
+            }
+        }
         let cost = ethereum_peggy::valset_update::estimate_valset_cost(
             &latest_cosmos_valset,
             &current_valset,
@@ -113,6 +207,7 @@ pub async fn relay_valsets(
             peggy_contract_address,
             peggy_id.clone(),
             ethereum_key,
+            config.estimate_gas_limit_cap.clone(),
         )
         .await;
         if cost.is_err() {
@@ -128,13 +223,20 @@ pub async fn relay_valsets(
             return;
         }
         let cost = cost.unwrap();
+        let gas_limit = get_gas_limit(cost.gas.clone(), config.gas_limit_multiplier, web3).await;
+        if gas_limit.is_err() {
+            error!("Failed to get block gas limit with {:?}", gas_limit);
+            return;
+        }
+        let gas_limit = gas_limit.unwrap();
 
         info!(
-           "We have detected latest valset {} but latest on Ethereum is {} This valset is estimated to cost {} Gas / {:.4} ETH to submit",
+           "We have detected latest valset {} but latest on Ethereum is {} This valset is estimated to cost {} Gas / {:.4} ETH to submit, submitting with gas limit {}",
             latest_cosmos_valset.nonce, current_valset.nonce,
             cost.gas_price.clone(),
             downcast_to_u128(cost.get_total()).unwrap() as f32
-                / downcast_to_u128(one_eth()).unwrap() as f32
+                / downcast_to_u128(one_eth()).unwrap() as f32,
+            gas_limit
         );
         sinfo!(&LOGGING.logger, "WE_HAVE_DETECTED_LATEST_VALSET";
             "function" => "relay_valsets()",
@@ -143,8 +245,23 @@ pub async fn relay_valsets(
             "cost_gas_price" => format!("{}",cost.gas_price.clone()),
             "per_eth" => format!("{:.4}",downcast_to_u128(cost.get_total()).unwrap() as f32
                 / downcast_to_u128(one_eth()).unwrap() as f32),
+            "gas_estimate" => format!("{}",cost.gas),
+            "gas_limit" => format!("{}",gas_limit),
         );
 
+        if !check_eth_balance(
+            web3,
+            ethereum_key.to_public_key().unwrap(),
+            &gas_limit,
+            &cost.gas_price,
+            &config.eth_balance_reserve,
+            "relay_valsets()",
+        )
+        .await
+        {
+            return;
+        }
+
         let _res = send_eth_valset_update(
             latest_cosmos_valset,
             current_valset,
@@ -154,7 +271,62 @@ pub async fn relay_valsets(
             peggy_contract_address,
             peggy_id,
             ethereum_key,
+            gas_limit,
+            nonce_manager,
+            config.valset_submit_retries,
         )
         .await;
     }
 }
+
+/// A valset update is worth its gas once enough power has moved that the set in the bridge no
+/// longer reflects the chain, or as soon as a validator joins or leaves since a departed
+/// validator's key should not keep control of the bridge
+fn valset_update_is_urgent(power_drift: f32, membership_changed: bool, threshold: f32) -> bool {
+    membership_changed || power_drift > threshold
+}
+
+/// The nonces of the valsets in `latest_valsets` between the one in the bridge and the one we
+/// are submitting, these intermediate sets never need to be relayed since the bridge only
+/// requires that the new valset is signed by enough of the power in the current one. Valset
+/// nonces are the block heights the sets were created at so they are not contiguous
+fn skipped_valset_nonces(
+    latest_valsets: &[Valset],
+    current_nonce: u64,
+    submitted_nonce: u64,
+) -> Vec<u64> {
+    let mut skipped: Vec<u64> = latest_valsets
+        .iter()
+        .map(|valset| valset.nonce)
+        .filter(|nonce| *nonce > current_nonce && *nonce < submitted_nonce)
+        .collect();
+    skipped.sort_unstable();
+    skipped
+}
+
+#[test]
+fn test_skipped_valset_nonces() {
+    let valsets: Vec<Valset> = [1200, 950, 700, 300]
+        .iter()
+        .map(|nonce| Valset {
+            nonce: *nonce,
+            members: Vec::new(),
+        })
+        .collect();
+    assert_eq!(
+        skipped_valset_nonces(&valsets, 950, 1200),
+        Vec::<u64>::new()
+    );
+    assert_eq!(skipped_valset_nonces(&valsets, 300, 1200), vec![700, 950]);
+    assert_eq!(
+        skipped_valset_nonces(&valsets, 1200, 300),
+        Vec::<u64>::new()
+    );
+}
+
+#[test]
+fn test_valset_update_is_urgent() {
+    assert!(!valset_update_is_urgent(0.01, false, 0.05));
+    assert!(valset_update_is_urgent(0.06, false, 0.05));
+    assert!(valset_update_is_urgent(0.0, true, 0.05));
+}
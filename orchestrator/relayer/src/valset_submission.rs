@@ -0,0 +1,188 @@
+//! Submits a hand assembled valset update read from a file, run with --submit-valset. This is a
+//! recovery tool for when the automatic relaying path is stuck, the file holds the new valset, the
+//! valset currently in the bridge and the old set's confirms of the new one as JSON, in the same
+//! form they are returned by the Cosmos queries.
+
+use clarity::address::Address as EthAddress;
+use clarity::PrivateKey as EthPrivateKey;
+use clarity::Uint256;
+use ethereum_peggy::nonce_manager::NonceManager;
+use ethereum_peggy::utils::{get_gas_limit, get_peggy_id, get_valset_nonce};
+use ethereum_peggy::valset_update::{estimate_valset_cost, send_eth_valset_update};
+use peggy_utils::error::PeggyError;
+use peggy_utils::types::{RelayerConfig, Valset, ValsetConfirmResponse};
+use serde_derive::Deserialize;
+use std::fs;
+use std::time::Duration;
+use web30::client::Web3;
+
+/// The contents of a --submit-valset file
+#[derive(Debug, Clone, Deserialize)]
+pub struct PreparedValsetUpdate {
+    pub new_valset: Valset,
+    pub old_valset: Valset,
+    pub confirms: Vec<ValsetConfirmResponse>,
+}
+
+impl PreparedValsetUpdate {
+    pub fn load(path: &str) -> Result<Self, PeggyError> {
+        let contents = fs::read_to_string(path).map_err(|e| {
+            PeggyError::InvalidBridgeStateError(format!("Could not read {} {}", path, e))
+        })?;
+        serde_json::from_str(&contents).map_err(|e| {
+            PeggyError::InvalidBridgeStateError(format!("Could not parse {} {}", path, e))
+        })
+    }
+
+    /// Checks the nonces before anything is sent, the new valset must be later than the old one
+    /// and every confirm must be a signature of the new valset
+    pub fn validate(&self) -> Result<(), PeggyError> {
+        if self.new_valset.nonce <= self.old_valset.nonce {
+            return Err(PeggyError::InvalidBridgeStateError(format!(
+                "The new valset nonce {} must be greater than the old valset nonce {}",
+                self.new_valset.nonce, self.old_valset.nonce
+            )));
+        }
+        if self.confirms.is_empty() {
+            return Err(PeggyError::InvalidBridgeStateError(
+                "The update has no confirms".to_string(),
+            ));
+        }
+        if let Some(confirm) = self
+            .confirms
+            .iter()
+            .find(|c| c.nonce != self.new_valset.nonce)
+        {
+            return Err(PeggyError::InvalidBridgeStateError(format!(
+                "The confirm from {} is for valset {} not the new valset {}",
+                confirm.eth_address, confirm.nonce, self.new_valset.nonce
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// The result of a manual valset submission
+#[derive(Debug, Clone)]
+pub struct ValsetSubmission {
+    /// our mined transaction, None if nothing was sent because the valset on Ethereum had
+    /// already moved past the old valset or relaying is paused
+    pub tx_hash: Option<Uint256>,
+    /// the valset nonce on Ethereum after the submission
+    pub ethereum_nonce: u64,
+}
+
+impl ValsetSubmission {
+    /// Whether the new valset is now the one in the bridge, regardless of who submitted it
+    pub fn confirmed(&self, update: &PreparedValsetUpdate) -> bool {
+        self.ethereum_nonce == update.new_valset.nonce
+    }
+}
+
+/// Validates and submits a prepared valset update with send_eth_valset_update, the gas limit is
+/// estimated the same way the relayer estimates it for automatic updates. Like automatic updates
+/// it is sent from the valset submission key when one is configured
+pub async fn submit_prepared_valset(
+    update: &PreparedValsetUpdate,
+    web3: &Web3,
+    peggy_contract_address: EthAddress,
+    ethereum_key: EthPrivateKey,
+    config: &RelayerConfig,
+    timeout: Duration,
+) -> Result<ValsetSubmission, PeggyError> {
+    update.validate()?;
+    let ethereum_key = config.valset_submission_key.unwrap_or(ethereum_key);
+    let our_ethereum_address = ethereum_key.to_public_key().unwrap();
+
+    // an update from a valset the bridge no longer holds can only fail, don't spend gas on it
+    let ethereum_nonce =
+        get_valset_nonce(peggy_contract_address, our_ethereum_address, web3).await?;
+    if ethereum_nonce > update.old_valset.nonce {
+        info!(
+            "The bridge is already at valset {}, past the old valset {}",
+            ethereum_nonce, update.old_valset.nonce
+        );
+        return Ok(ValsetSubmission {
+            tx_hash: None,
+            ethereum_nonce,
+        });
+    }
+    if ethereum_nonce < update.old_valset.nonce {
+        return Err(PeggyError::InvalidBridgeStateError(format!(
+            "The bridge holds valset {} not the old valset {}",
+            ethereum_nonce, update.old_valset.nonce
+        )));
+    }
+
+    let peggy_id = get_peggy_id(peggy_contract_address, our_ethereum_address, web3).await?;
+    let peggy_id = String::from_utf8(peggy_id)
+        .map_err(|e| PeggyError::InvalidBridgeStateError(format!("Invalid PeggyID {}", e)))?;
+
+    let cost = estimate_valset_cost(
+        &update.new_valset,
+        &update.old_valset,
+        &update.confirms,
+        web3,
+        peggy_contract_address,
+        peggy_id.clone(),
+        ethereum_key,
+        config.estimate_gas_limit_cap.clone(),
+    )
+    .await?;
+    let gas_limit = get_gas_limit(cost.gas, config.gas_limit_multiplier, web3).await?;
+    info!(
+        "Submitting prepared valset update {} -> {} with gas limit {}",
+        update.old_valset.nonce, update.new_valset.nonce, gas_limit
+    );
+
+    let tx_hash = send_eth_valset_update(
+        update.new_valset.clone(),
+        update.old_valset.clone(),
+        &update.confirms,
+        web3,
+        timeout,
+        peggy_contract_address,
+        peggy_id,
+        ethereum_key,
+        gas_limit,
+        &NonceManager::new(our_ethereum_address),
+        config.valset_submit_retries,
+    )
+    .await?;
+    let ethereum_nonce =
+        get_valset_nonce(peggy_contract_address, our_ethereum_address, web3).await?;
+    Ok(ValsetSubmission {
+        tx_hash,
+        ethereum_nonce,
+    })
+}
+
+#[test]
+fn test_validate_prepared_valset_update() {
+    let confirm = |nonce| ValsetConfirmResponse {
+        nonce,
+        ..Default::default()
+    };
+    let mut update = PreparedValsetUpdate {
+        new_valset: Valset {
+            nonce: 5,
+            members: Vec::new(),
+        },
+        old_valset: Valset {
+            nonce: 3,
+            members: Vec::new(),
+        },
+        confirms: vec![confirm(5), confirm(5)],
+    };
+    assert!(update.validate().is_ok());
+
+    update.confirms.push(confirm(4));
+    assert!(update.validate().is_err());
+
+    update.confirms.clear();
+    assert!(update.validate().is_err());
+
+    update.confirms.push(confirm(5));
+    update.old_valset.nonce = 5;
+    assert!(update.validate().is_err());
+}
@@ -0,0 +1,189 @@
+//! Estimates what it would cost to relay everything that can currently be relayed, giving
+//! operators a budgeting view before any transactions are sent.
+
+use crate::find_latest_valset::find_latest_valset;
+use clarity::address::Address as EthAddress;
+use clarity::PrivateKey as EthPrivateKey;
+use clarity::Uint256;
+use cosmos_peggy::query::{
+    get_all_valset_confirms, get_latest_transaction_batches, get_latest_valsets,
+    get_transaction_batch_signatures, get_valset,
+};
+use ethereum_peggy::submit_batch::estimate_tx_batch_cost;
+use ethereum_peggy::utils::{get_peggy_id, get_tx_batch_nonce, GasCost};
+use ethereum_peggy::valset_update::estimate_valset_cost;
+use peggy_proto::peggy::query_client::QueryClient as PeggyQueryClient;
+use peggy_utils::error::PeggyError;
+use peggy_utils::message_signatures::{
+    encode_tx_batch_confirm_hashed, encode_valset_confirm_hashed,
+};
+use tonic::transport::Channel;
+use web30::client::Web3;
+
+/// A single relayable piece of work
+#[derive(Debug, Clone, PartialEq)]
+pub enum PendingWorkItem {
+    Valset {
+        nonce: u64,
+    },
+    Batch {
+        token_contract: EthAddress,
+        nonce: u64,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct PendingWorkloadCost {
+    /// The summed gas of every item, the gas price is the average weighted by gas
+    /// so that get_total() returns the summed cost
+    pub total: GasCost,
+    pub items: Vec<(PendingWorkItem, GasCost)>,
+}
+
+/// Sums the cost of the provided items, see PendingWorkloadCost::total
+pub fn aggregate_gas_cost(items: &[(PendingWorkItem, GasCost)]) -> GasCost {
+    let zero: Uint256 = 0u8.into();
+    let mut gas = zero.clone();
+    let mut total_cost = zero.clone();
+    for (_, cost) in items {
+        gas = gas + cost.gas.clone();
+        total_cost = total_cost + cost.get_total();
+    }
+    let gas_price = if gas == zero {
+        zero
+    } else {
+        total_cost / gas.clone()
+    };
+    GasCost { gas, gas_price }
+}
+
+/// Estimates the cost of submitting the newest valset that can be submitted and every batch that
+/// has enough signatures, has not been executed, and has not timed out. Work that can't be submitted
+/// yet is skipped, as is any item whose estimate fails since it would revert if submitted.
+/// `gas_limit_cap` is RelayerConfig::estimate_gas_limit_cap, applied to the valset estimate
+pub async fn estimate_pending_workload_cost(
+    grpc_client: &mut PeggyQueryClient<Channel>,
+    web3: &Web3,
+    peggy_contract_address: EthAddress,
+    ethereum_key: EthPrivateKey,
+    gas_limit_cap: Option<Uint256>,
+) -> Result<PendingWorkloadCost, PeggyError> {
+    let our_ethereum_address = ethereum_key.to_public_key().unwrap();
+    let current_valset = find_latest_valset(
+        grpc_client,
+        our_ethereum_address,
+        peggy_contract_address,
+        web3,
+    )
+    .await?;
+    let peggy_id = get_peggy_id(peggy_contract_address, our_ethereum_address, web3).await?;
+    let peggy_id = String::from_utf8(peggy_id)
+        .map_err(|e| PeggyError::InvalidBridgeStateError(format!("Invalid PeggyID {}", e)))?;
+    let mut items = Vec::new();
+    let indexed_valset = current_valset.index();
+
+    // only the newest valset the current one can validate needs to be submitted
+    let latest_valsets = get_latest_valsets(grpc_client).await?;
+    if let Some(latest) = latest_valsets.first() {
+        let mut nonce = latest.nonce;
+        while nonce > current_valset.nonce {
+            if let Some(valset) = get_valset(grpc_client, nonce).await? {
+                let confirms = get_all_valset_confirms(grpc_client, nonce).await?;
+                let hash = encode_valset_confirm_hashed(peggy_id.clone(), valset.clone());
+                if indexed_valset.order_sigs(&hash, &confirms).is_ok() {
+                    match estimate_valset_cost(
+                        &valset,
+                        &current_valset,
+                        &confirms,
+                        web3,
+                        peggy_contract_address,
+                        peggy_id.clone(),
+                        ethereum_key,
+                        gas_limit_cap.clone(),
+                    )
+                    .await
+                    {
+                        Ok(cost) => items.push((PendingWorkItem::Valset { nonce }, cost)),
+                        Err(e) => warn!("Valset {} cost estimate failed {}", nonce, e),
+                    }
+                    break;
+                }
+            }
+            nonce -= 1;
+        }
+    }
+
+    let gas_price = web3.eth_gas_price().await?;
+    let current_block_height = web3.eth_block_number().await?;
+    let latest_batches = get_latest_transaction_batches(grpc_client).await?;
+    for batch in latest_batches {
+        let latest_ethereum_nonce = get_tx_batch_nonce(
+            peggy_contract_address,
+            batch.token_contract,
+            our_ethereum_address,
+            web3,
+        )
+        .await?;
+        let timed_out = batch.is_timed_out(current_block_height.clone());
+        if latest_ethereum_nonce >= batch.nonce || timed_out {
+            continue;
+        }
+        let sigs = get_transaction_batch_signatures(grpc_client, batch.nonce, batch.token_contract)
+            .await?;
+        let hash = encode_tx_batch_confirm_hashed(peggy_id.clone(), batch.clone());
+        if indexed_valset.order_sigs(&hash, &sigs).is_err() {
+            continue;
+        }
+        let item = PendingWorkItem::Batch {
+            token_contract: batch.token_contract,
+            nonce: batch.nonce,
+        };
+        match estimate_tx_batch_cost(
+            current_valset.clone(),
+            batch,
+            &sigs,
+            web3,
+            peggy_contract_address,
+            peggy_id.clone(),
+            ethereum_key,
+            gas_price.clone(),
+        )
+        .await
+        {
+            Ok(cost) => items.push((item, cost)),
+            Err(e) => warn!("{:?} cost estimate failed {}", item, e),
+        }
+    }
+
+    Ok(PendingWorkloadCost {
+        total: aggregate_gas_cost(&items),
+        items,
+    })
+}
+
+#[test]
+fn test_aggregate_gas_cost() {
+    let items = vec![
+        (
+            PendingWorkItem::Valset { nonce: 2 },
+            GasCost {
+                gas: 100u8.into(),
+                gas_price: 10u8.into(),
+            },
+        ),
+        (
+            PendingWorkItem::Batch {
+                token_contract: EthAddress::default(),
+                nonce: 5,
+            },
+            GasCost {
+                gas: 300u16.into(),
+                gas_price: 30u8.into(),
+            },
+        ),
+    ];
+    let total = aggregate_gas_cost(&items);
+    assert_eq!(total.gas, 400u16.into());
+    assert_eq!(total.get_total(), 10_000u16.into());
+    assert_eq!(aggregate_gas_cost(&[]).get_total(), 0u8.into());
+}
@@ -18,7 +18,7 @@ use crate::ETH_NODE;
 use crate::MINER_PRIVATE_KEY;
 use crate::TOTAL_TIMEOUT;
 use json_logger::LOGGING;
-use slog::{info as sinfo};
+use slog::info as sinfo;
 // use chrono;
 
 /// Ethereum keys are generated for every validator inside
@@ -131,8 +131,8 @@ pub async fn deploy_contracts(
         let paths = return_existing(A, B);
         Command::new(paths[0])
             .args(&[
-                &format!("--cosmos-node={}", COSMOS_NODE_ABCI),
-                &format!("--eth-node={}", ETH_NODE),
+                &format!("--cosmos-node={}", *COSMOS_NODE_ABCI),
+                &format!("--eth-node={}", *ETH_NODE),
                 &format!("--eth-privkey={:#x}", *MINER_PRIVATE_KEY),
                 &format!("--contract={}", paths[1]),
                 "--test-mode=true",
@@ -144,8 +144,8 @@ pub async fn deploy_contracts(
             .args(&[
                 "ts-node",
                 C[0],
-                &format!("--cosmos-node={}", COSMOS_NODE_ABCI),
-                &format!("--eth-node={}", ETH_NODE),
+                &format!("--cosmos-node={}", *COSMOS_NODE_ABCI),
+                &format!("--eth-node={}", *ETH_NODE),
                 &format!("--eth-privkey={:#x}", *MINER_PRIVATE_KEY),
                 &format!("--contract={}", C[1]),
                 "--test-mode=true",
@@ -236,4 +236,4 @@ fn return_existing<'a>(a: [&'a str; 2], b: [&'a str; 2]) -> [&'a str; 2] {
 //     fn it_works() {
 //         sinfo!(&LOGGING.logger, "TEST");
 //     }
-// }
\ No newline at end of file
+// }
@@ -0,0 +1,94 @@
+//! An end to end test of the oracle, a deposit made on Ethereum has to be claimed by every
+//! orchestrator and credited on Cosmos. Unlike the happy path this runs nothing but the deposit,
+//! so it is quick enough to point at a forked Ethereum node, see ETH_NODE, where signature and
+//! encoding bugs against a real contract deployment show up that unit tests can't catch.
+
+use crate::happy_path::test_erc20_deposit;
+use crate::{get_test_token_name, COSMOS_NODE_GRPC, TOTAL_TIMEOUT};
+use actix::Arbiter;
+use clarity::Address as EthAddress;
+use clarity::PrivateKey as EthPrivateKey;
+use contact::client::Contact;
+use cosmos_peggy::query::get_last_event_nonce;
+use deep_space::private_key::PrivateKey as CosmosPrivateKey;
+use orchestrator::main_loop::orchestrator_main_loop;
+use peggy_proto::peggy::query_client::QueryClient as PeggyQueryClient;
+use peggy_utils::types::{OrchestratorConfig, RelayerConfig};
+use rand::Rng;
+use std::time::{Duration, Instant};
+use tokio::time::delay_for;
+use tonic::transport::Channel;
+use web30::client::Web3;
+
+pub async fn deposit_claim_test(
+    web30: &Web3,
+    grpc_client: PeggyQueryClient<Channel>,
+    contact: &Contact,
+    keys: Vec<(CosmosPrivateKey, EthPrivateKey)>,
+    peggy_address: EthAddress,
+    erc20_address: EthAddress,
+) {
+    let mut grpc_client = grpc_client;
+    let mut start_nonces = Vec::new();
+    for (c_key, e_key) in keys.iter() {
+        let address = c_key.to_public_key().unwrap().to_address();
+        let nonce = get_last_event_nonce(&mut grpc_client, address)
+            .await
+            .unwrap();
+        start_nonces.push((address, nonce));
+
+        info!("Spawning Orchestrator");
+        let grpc_client = PeggyQueryClient::connect(COSMOS_NODE_GRPC.as_str())
+            .await
+            .unwrap();
+        Arbiter::spawn(orchestrator_main_loop(
+            *c_key,
+            *e_key,
+            web30.clone(),
+            contact.clone(),
+            grpc_client,
+            peggy_address,
+            get_test_token_name(),
+            RelayerConfig::default(),
+            OrchestratorConfig::default(),
+        ));
+    }
+
+    // a fresh destination so that the balance check can't be satisfied by an earlier run
+    let secret: [u8; 32] = rand::thread_rng().gen();
+    let dest = CosmosPrivateKey::from_secret(&secret)
+        .to_public_key()
+        .unwrap()
+        .to_address();
+    test_erc20_deposit(
+        web30,
+        contact,
+        dest,
+        peggy_address,
+        erc20_address,
+        100u64.into(),
+    )
+    .await;
+
+    // the deposit is credited once enough power has claimed it, every orchestrator should still
+    // submit its own claim
+    let start = Instant::now();
+    for (address, start_nonce) in start_nonces {
+        loop {
+            let nonce = get_last_event_nonce(&mut grpc_client, address)
+                .await
+                .unwrap();
+            if nonce > start_nonce {
+                info!(
+                    "Orchestrator {} claimed up to event nonce {}",
+                    address, nonce
+                );
+                break;
+            }
+            if Instant::now() - start > TOTAL_TIMEOUT {
+                panic!("Orchestrator {} did not claim the deposit", address);
+            }
+            delay_for(Duration::from_secs(1)).await;
+        }
+    }
+}
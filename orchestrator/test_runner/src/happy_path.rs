@@ -9,6 +9,7 @@ use actix::Arbiter;
 use clarity::PrivateKey as EthPrivateKey;
 use clarity::{Address as EthAddress, Uint256};
 use contact::client::Contact;
+use cosmos_peggy::fees::static_claims_fee;
 use cosmos_peggy::send::{send_request_batch, send_to_eth};
 use cosmos_peggy::utils::wait_for_next_cosmos_block;
 use cosmos_peggy::{query::get_oldest_unsigned_transaction_batch, send::send_ethereum_claims};
@@ -17,18 +18,19 @@ use deep_space::coin::Coin;
 use deep_space::private_key::PrivateKey as CosmosPrivateKey;
 use ethereum_peggy::utils::get_valset_nonce;
 use ethereum_peggy::{send_to_cosmos::send_to_cosmos, utils::get_tx_batch_nonce};
+use json_logger::LOGGING;
 use orchestrator::main_loop::orchestrator_main_loop;
 use peggy_proto::peggy::query_client::QueryClient as PeggyQueryClient;
 use peggy_utils::connection_prep::check_delegate_addresses;
-use peggy_utils::types::SendToCosmosEvent;
+use peggy_utils::types::{OrchestratorConfig, RelayerConfig};
+use peggy_utils::types::{SendToCosmosEvent, DEFAULT_COSMOS_PREFIX};
 use rand::Rng;
+use slog::info as sinfo;
 use std::{env, process::Command, time::Duration};
 use std::{process::ExitStatus, time::Instant};
 use tokio::time::delay_for;
 use tonic::transport::Channel;
 use web30::client::Web3;
-use json_logger::LOGGING;
-use slog::{info as sinfo};
 
 pub async fn happy_path_test(
     web30: &Web3,
@@ -51,7 +53,9 @@ pub async fn happy_path_test(
     for (c_key, e_key) in keys.iter() {
         info!("Spawning Orchestrator");
         sinfo!(&LOGGING.logger, "SPAWNING_ORCHESTRATOR";"function" => "happy_path_test()");
-        let mut grpc_client = PeggyQueryClient::connect(COSMOS_NODE_GRPC).await.unwrap();
+        let mut grpc_client = PeggyQueryClient::connect(COSMOS_NODE_GRPC.as_str())
+            .await
+            .unwrap();
         // we have only one actual futures executor thread (see the actix runtime tag on our main function)
         // but that will execute all the orchestrators in our test in parallel
         Arbiter::spawn(orchestrator_main_loop(
@@ -62,6 +66,8 @@ pub async fn happy_path_test(
             grpc_client.clone(),
             peggy_address,
             get_test_token_name(),
+            RelayerConfig::default(),
+            OrchestratorConfig::default(),
         ));
 
         // this function is just to test normal startup
@@ -310,7 +316,7 @@ pub async fn test_valset_update(
 }
 
 /// this function tests Ethereum -> Cosmos
-async fn test_erc20_deposit(
+pub async fn test_erc20_deposit(
     web30: &Web3,
     contact: &Contact,
     dest: CosmosAddress,
@@ -572,7 +578,8 @@ async fn submit_duplicate_erc20_send(
             vec![],
             vec![],
             vec![],
-            get_fee(),
+            static_claims_fee(get_fee()),
+            DEFAULT_COSMOS_PREFIX,
         )
         .await
         .unwrap();
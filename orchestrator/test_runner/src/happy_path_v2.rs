@@ -16,6 +16,7 @@ use deep_space::{coin::Coin, private_key::PrivateKey as CosmosPrivateKey};
 use ethereum_peggy::{deploy_erc20::deploy_erc20, utils::get_event_nonce};
 use orchestrator::main_loop::orchestrator_main_loop;
 use peggy_proto::peggy::{query_client::QueryClient as PeggyQueryClient, QueryDenomToErc20Request};
+use peggy_utils::types::{OrchestratorConfig, RelayerConfig};
 use tokio::time::delay_for;
 use tonic::transport::Channel;
 use web30::client::Web3;
@@ -70,7 +71,9 @@ pub async fn happy_path_test_v2(
     #[allow(clippy::explicit_counter_loop)]
     for (c_key, e_key) in keys.iter() {
         info!("Spawning Orchestrator");
-        let grpc_client = PeggyQueryClient::connect(COSMOS_NODE_GRPC).await.unwrap();
+        let grpc_client = PeggyQueryClient::connect(COSMOS_NODE_GRPC.as_str())
+            .await
+            .unwrap();
         // we have only one actual futures executor thread (see the actix runtime tag on our main function)
         // but that will execute all the orchestrators in our test in parallel
         Arbiter::spawn(orchestrator_main_loop(
@@ -81,6 +84,8 @@ pub async fn happy_path_test_v2(
             grpc_client,
             peggy_address,
             get_test_token_name(),
+            RelayerConfig::default(),
+            OrchestratorConfig::default(),
         ));
 
         // used to break out of the loop early to simulate one validator
@@ -16,6 +16,7 @@ use clarity::{Address as EthAddress, Uint256};
 use contact::client::Contact;
 use cosmos_peggy::utils::wait_for_cosmos_online;
 use deep_space::coin::Coin;
+use deposit_claim::deposit_claim_test;
 use happy_path::happy_path_test;
 use happy_path_v2::happy_path_test_v2;
 use peggy_proto::peggy::query_client::QueryClient as PeggyQueryClient;
@@ -25,24 +26,20 @@ use valset_stress::validator_set_stress_test;
 
 mod arbitrary_logic;
 mod bootstrapping;
+mod deposit_claim;
 mod happy_path;
 mod happy_path_v2;
 mod transaction_stress_test;
 mod utils;
 mod valset_stress;
 use json_logger::LOGGING;
-use slog::{info as sinfo};
+use slog::info as sinfo;
 
 /// the timeout for individual requests
 const OPERATION_TIMEOUT: Duration = Duration::from_secs(30);
 /// the timeout for the total system
 const TOTAL_TIMEOUT: Duration = Duration::from_secs(300);
 
-pub const COSMOS_NODE: &str = "http://localhost:1317";
-pub const COSMOS_NODE_GRPC: &str = "http://localhost:9090";
-pub const COSMOS_NODE_ABCI: &str = "http://localhost:26657";
-pub const ETH_NODE: &str = "http://localhost:8545";
-
 /// this value reflects the contents of /tests/container-scripts/setup-validator.sh
 /// and is used to compute if a stake change is big enough to trigger a validator set
 /// update since we want to make several such changes intentionally
@@ -50,7 +47,18 @@ pub const STAKE_SUPPLY_PER_VALIDATOR: u128 = 1000000000;
 /// this is the amount each validator bonds at startup
 pub const STARTING_STAKE_PER_VALIDATOR: u128 = STAKE_SUPPLY_PER_VALIDATOR / 2;
 
+/// Reads an endpoint from the environment variable of the same name, so that the tests can be
+/// pointed at chains other than the ones started in the test container, such as a forked
+/// Ethereum node with the Peggy contract deployed
+fn endpoint(name: &str, default: &str) -> String {
+    env::var(name).unwrap_or_else(|_| default.to_string())
+}
+
 lazy_static! {
+    pub static ref COSMOS_NODE: String = endpoint("COSMOS_NODE", "http://localhost:1317");
+    pub static ref COSMOS_NODE_GRPC: String = endpoint("COSMOS_NODE_GRPC", "http://localhost:9090");
+    pub static ref COSMOS_NODE_ABCI: String = endpoint("COSMOS_NODE_ABCI", "http://localhost:26657");
+    pub static ref ETH_NODE: String = endpoint("ETH_NODE", "http://localhost:8545");
     // this key is the private key for the public key defined in tests/assets/ETHGenesis.json
     // where the full node / miner sends its rewards. Therefore it's always going
     // to have a lot of ETH to pay for things like contract deployments
@@ -100,14 +108,16 @@ pub async fn main() {
     env_logger::init();
     info!("Staring Peggy test-runner");
     sinfo!(&LOGGING.logger, "STARING_PEGGY_TEST_RUNNER";"function" => "main()");
-    let contact = Contact::new(COSMOS_NODE, OPERATION_TIMEOUT);
+    let contact = Contact::new(&COSMOS_NODE, OPERATION_TIMEOUT);
 
     info!("Waiting for Cosmos chain to come online");
     sinfo!(&LOGGING.logger, "WAITING_FOR_COSMOS_CHAIN_TO_COME_ONLINE";"function" => "main()");
     wait_for_cosmos_online(&contact, TOTAL_TIMEOUT).await;
 
-    let grpc_client = PeggyQueryClient::connect(COSMOS_NODE_GRPC).await.unwrap();
-    let web30 = web30::client::Web3::new(ETH_NODE, OPERATION_TIMEOUT);
+    let grpc_client = PeggyQueryClient::connect(COSMOS_NODE_GRPC.as_str())
+        .await
+        .unwrap();
+    let web30 = web30::client::Web3::new(&ETH_NODE, OPERATION_TIMEOUT);
     let keys = get_keys();
 
     // if we detect this env var we are only deploying contracts, do that then exit.
@@ -147,6 +157,8 @@ pub async fn main() {
     // V2_HAPPY_PATH runs the happy path tests but focusing on moving Cosmos assets to Ethereum
     // ARBITRARY_LOGIC tests the arbitrary logic functionality, where an arbitrary contract call
     //                 is created and deployed vai the bridge.
+    // DEPOSIT_CLAIM runs only a deposit through the oracle, with an ALCHEMY_ID it runs against a
+    //               fork of Ethereum mainnet
     let test_type = env::var("TEST_TYPE");
     info!("Starting tests with {:?}", test_type);
     sinfo!(&LOGGING.logger, "TEST_TYPE";
@@ -169,7 +181,7 @@ pub async fn main() {
             .await;
             return;
         } else if test_type == "BATCH_STRESS" {
-            let contact = Contact::new(COSMOS_NODE, TOTAL_TIMEOUT);
+            let contact = Contact::new(&COSMOS_NODE, TOTAL_TIMEOUT);
             transaction_stress_test(&web30, &contact, keys, peggy_address, erc20_addresses).await;
             return;
         } else if test_type == "VALSET_STRESS" {
@@ -180,6 +192,18 @@ pub async fn main() {
             info!("Starting happy path for Gravity v2");
             happy_path_test_v2(&web30, grpc_client, &contact, keys, peggy_address, false).await;
             return;
+        } else if test_type == "DEPOSIT_CLAIM" {
+            info!("Starting deposit to claim test");
+            deposit_claim_test(
+                &web30,
+                grpc_client,
+                &contact,
+                keys,
+                peggy_address,
+                erc20_addresses[0],
+            )
+            .await;
+            return;
         } else if test_type == "ARBITRARY_LOGIC" {
             info!("Starting arbitrary logic tests!");
             arbitrary_logic_test(&web30, grpc_client, &contact, keys, peggy_address).await;
@@ -13,6 +13,7 @@ use ethereum_peggy::{send_to_cosmos::send_to_cosmos, utils::get_tx_batch_nonce};
 use futures::future::join_all;
 use orchestrator::main_loop::orchestrator_main_loop;
 use peggy_proto::peggy::query_client::QueryClient as PeggyQueryClient;
+use peggy_utils::types::{OrchestratorConfig, RelayerConfig};
 use std::{
     collections::HashSet,
     time::{Duration, Instant},
@@ -45,7 +46,9 @@ pub async fn transaction_stress_test(
     // start orchestrators
     for (c_key, e_key) in keys.iter() {
         info!("Spawning Orchestrator");
-        let grpc_client = PeggyQueryClient::connect(COSMOS_NODE_GRPC).await.unwrap();
+        let grpc_client = PeggyQueryClient::connect(COSMOS_NODE_GRPC.as_str())
+            .await
+            .unwrap();
         // we have only one actual futures executor thread (see the actix runtime tag on our main function)
         // but that will execute all the orchestrators in our test in parallel
         Arbiter::spawn(orchestrator_main_loop(
@@ -56,6 +59,8 @@ pub async fn transaction_stress_test(
             grpc_client,
             peggy_address,
             get_test_token_name(),
+            RelayerConfig::default(),
+            OrchestratorConfig::default(),
         ));
     }
 
@@ -6,6 +6,7 @@ use contact::client::Contact;
 use deep_space::private_key::PrivateKey as CosmosPrivateKey;
 use orchestrator::main_loop::orchestrator_main_loop;
 use peggy_proto::peggy::query_client::QueryClient as PeggyQueryClient;
+use peggy_utils::types::{OrchestratorConfig, RelayerConfig};
 use web30::client::Web3;
 
 #[allow(clippy::too_many_arguments)]
@@ -18,7 +19,9 @@ pub async fn validator_set_stress_test(
     // start orchestrators
     for (c_key, e_key) in keys.iter() {
         info!("Spawning Orchestrator");
-        let grpc_client = PeggyQueryClient::connect(COSMOS_NODE_GRPC).await.unwrap();
+        let grpc_client = PeggyQueryClient::connect(COSMOS_NODE_GRPC.as_str())
+            .await
+            .unwrap();
         // we have only one actual futures executor thread (see the actix runtime tag on our main function)
         // but that will execute all the orchestrators in our test in parallel
         Arbiter::spawn(orchestrator_main_loop(
@@ -29,6 +32,8 @@ pub async fn validator_set_stress_test(
             grpc_client,
             peggy_address,
             get_test_token_name(),
+            RelayerConfig::default(),
+            OrchestratorConfig::default(),
         ));
     }
 
@@ -42,7 +42,7 @@ done
 # let the cosmos chain settle before starting eth as it
 # consumes a lot of processing power
 sleep 10
-if [[ $TEST_TYPE == *"ARBITRARY_LOGIC"* ]]; then
+if [[ $TEST_TYPE == *"ARBITRARY_LOGIC"* ]] || [[ $TEST_TYPE == *"DEPOSIT_CLAIM"* && -n "$ALCHEMY_ID" ]]; then
 bash /peggy/tests/container-scripts/run-eth-fork.sh $ALCHEMY_ID &
 else
 bash /peggy/tests/container-scripts/run-eth.sh &